@@ -41,6 +41,7 @@ use std::{env, fmt, fmt::Debug, sync::Arc};
 use anyhow::{bail, Result};
 use chrono::{offset::Utc, DateTime};
 use reqwest::{header, Client, Method, Request, StatusCode, Url};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware};
 use schemars::JsonSchema;
 use serde::{
     de::{DeserializeOwned, MapAccess, SeqAccess, Visitor},
@@ -55,10 +56,26 @@ pub struct Airtable {
     key: String,
     base_id: String,
     enterprise_account_id: String,
+    endpoint: String,
 
-    client: Arc<Client>,
+    client: Arc<ClientWithMiddleware>,
 }
 
+/// An error status code from the Airtable API, with the body it came with.
+#[derive(Debug)]
+pub struct StatusError {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "status code: {}, body: {}", self.status, self.body)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
 /// Get the API key from the AIRTABLE_API_KEY env variable.
 pub fn api_key_from_env() -> String {
     env::var("AIRTABLE_API_KEY").unwrap_or_default()
@@ -82,8 +99,9 @@ impl Airtable {
                 key: key.to_string(),
                 base_id: base_id.to_string(),
                 enterprise_account_id: enterprise_account_id.to_string(),
+                endpoint: ENDPOINT.to_string(),
 
-                client: Arc::new(c),
+                client: Arc::new(ClientWithMiddleware::from(c)),
             },
             Err(e) => panic!("creating client failed: {:?}", e),
         }
@@ -93,11 +111,45 @@ impl Airtable {
     /// takes a type that can convert into
     /// an &str (`String` or `Vec<u8>` for example). As long as the function is
     /// given a valid API Key and Base ID your requests will work.
+    /// If AIRTABLE_ENDPOINT is set, the requests go there instead of the Airtable API.
     pub fn new_from_env() -> Self {
         let base_id = env::var("AIRTABLE_BASE_ID").unwrap_or_default();
         let enterprise_account_id = env::var("AIRTABLE_ENTERPRISE_ACCOUNT_ID").unwrap_or_default();
 
-        Airtable::new(api_key_from_env(), base_id, enterprise_account_id)
+        let airtable = Airtable::new(api_key_from_env(), base_id, enterprise_account_id);
+        match env::var("AIRTABLE_ENDPOINT") {
+            Ok(endpoint) if !endpoint.is_empty() => airtable.with_endpoint(endpoint),
+            _ => airtable,
+        }
+    }
+
+    /// Send the requests through `middleware`, for example to count or retry them.
+    pub fn with_middleware<M>(mut self, middleware: M) -> Self
+    where
+        M: Middleware,
+    {
+        self.client = Arc::new(ClientBuilder::new(Client::new()).with(middleware).build());
+        self
+    }
+
+    /// Send the requests to a different endpoint, for example a local server in tests.
+    /// The endpoint should include the version, like `http://127.0.0.1:8080/v0/`.
+    pub fn with_endpoint<S>(mut self, endpoint: S) -> Self
+    where
+        S: ToString,
+    {
+        let mut endpoint = endpoint.to_string();
+        // Make sure the base id gets joined onto the path, not in place of the version.
+        if !endpoint.ends_with('/') {
+            endpoint.push('/');
+        }
+        self.endpoint = endpoint;
+        self
+    }
+
+    /// Get the endpoint the requests are sent to.
+    pub fn get_endpoint(&self) -> &str {
+        &self.endpoint
     }
 
     /// Get the currently set API key.
@@ -109,7 +161,7 @@ impl Airtable {
     where
         B: Serialize,
     {
-        let base = Url::parse(ENDPOINT)?;
+        let base = Url::parse(&self.endpoint)?;
         let url = base.join(&(self.base_id.to_string() + "/" + &path))?;
 
         let bt = format!("Bearer {}", self.key);
@@ -160,7 +212,11 @@ impl Airtable {
         match resp.status() {
             StatusCode::OK => (),
             s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
+                return Err(StatusError {
+                    status: s,
+                    body: resp.text().await?,
+                }
+                .into());
             }
         };
 
@@ -189,7 +245,11 @@ impl Airtable {
             match resp.status() {
                 StatusCode::OK => (),
                 s => {
-                    bail!("status code: {}, body: {}", s, resp.text().await?);
+                    return Err(StatusError {
+                        status: s,
+                        body: resp.text().await?,
+                    }
+                    .into());
                 }
             };
 
@@ -213,7 +273,11 @@ impl Airtable {
         match resp.status() {
             StatusCode::OK => (),
             s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
+                return Err(StatusError {
+                    status: s,
+                    body: resp.text().await?,
+                }
+                .into());
             }
         };
 
@@ -237,7 +301,11 @@ impl Airtable {
         match resp.status() {
             StatusCode::OK => (),
             s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
+                return Err(StatusError {
+                    status: s,
+                    body: resp.text().await?,
+                }
+                .into());
             }
         };
 
@@ -269,7 +337,11 @@ impl Airtable {
         match resp.status() {
             StatusCode::OK => (),
             s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
+                return Err(StatusError {
+                    status: s,
+                    body: resp.text().await?,
+                }
+                .into());
             }
         };
 
@@ -304,7 +376,11 @@ impl Airtable {
         match resp.status() {
             StatusCode::OK => (),
             s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
+                return Err(StatusError {
+                    status: s,
+                    body: resp.text().await?,
+                }
+                .into());
             }
         };
 
@@ -339,7 +415,11 @@ impl Airtable {
         match resp.status() {
             StatusCode::OK => (),
             s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
+                return Err(StatusError {
+                    status: s,
+                    body: resp.text().await?,
+                }
+                .into());
             }
         };
 
@@ -374,7 +454,11 @@ impl Airtable {
         match resp.status() {
             StatusCode::OK => (),
             s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
+                return Err(StatusError {
+                    status: s,
+                    body: resp.text().await?,
+                }
+                .into());
             }
         };
 
@@ -423,7 +507,11 @@ impl Airtable {
         match resp.status() {
             StatusCode::OK => (),
             s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
+                return Err(StatusError {
+                    status: s,
+                    body: resp.text().await?,
+                }
+                .into());
             }
         };
 
@@ -452,7 +540,11 @@ impl Airtable {
         match resp.status() {
             StatusCode::OK => (),
             s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
+                return Err(StatusError {
+                    status: s,
+                    body: resp.text().await?,
+                }
+                .into());
             }
         };
 
@@ -26,6 +26,7 @@ use std::{env, error, fmt, sync::Arc};
 
 use chrono::{offset::Utc, DateTime};
 use reqwest::{header, Client, Method, Request, StatusCode, Url};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -36,7 +37,7 @@ const ENDPOINT: &str = "https://api.checkr.com/v1/";
 pub struct Checkr {
     key: String,
 
-    client: Arc<Client>,
+    client: Arc<ClientWithMiddleware>,
 }
 
 impl Checkr {
@@ -52,7 +53,7 @@ impl Checkr {
             Ok(c) => Self {
                 key: key.to_string(),
 
-                client: Arc::new(c),
+                client: Arc::new(ClientWithMiddleware::from(c)),
             },
             Err(e) => panic!("creating client failed: {:?}", e),
         }
@@ -68,6 +69,15 @@ impl Checkr {
         Checkr::new(key)
     }
 
+    /// Send the requests through `middleware`, for example to count or retry them.
+    pub fn with_middleware<M>(mut self, middleware: M) -> Self
+    where
+        M: Middleware,
+    {
+        self.client = Arc::new(ClientBuilder::new(Client::new()).with(middleware).build());
+        self
+    }
+
     fn request<B>(&self, method: Method, path: &str, body: B, query: Option<Vec<(&str, String)>>) -> Request
     where
         B: Serialize,
@@ -0,0 +1 @@
+DROP TABLE airtable_outbox_entries
@@ -0,0 +1,16 @@
+CREATE TABLE airtable_outbox_entries (
+    id SERIAL PRIMARY KEY,
+    record_type VARCHAR NOT NULL,
+    record_id INTEGER NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    operation VARCHAR NOT NULL,
+    status VARCHAR NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+    next_attempt_at TIMESTAMPTZ NOT NULL,
+    cio_company_id INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX airtable_outbox_entries_status_next_attempt_at ON airtable_outbox_entries (status, next_attempt_at)
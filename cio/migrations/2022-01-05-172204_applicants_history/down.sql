@@ -0,0 +1 @@
+DROP TABLE applicants_history
\ No newline at end of file
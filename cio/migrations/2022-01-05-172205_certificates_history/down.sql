@@ -0,0 +1 @@
+DROP TABLE certificates_history
\ No newline at end of file
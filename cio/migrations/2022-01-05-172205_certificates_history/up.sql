@@ -0,0 +1,13 @@
+CREATE TABLE certificates_history (
+    id SERIAL PRIMARY KEY,
+    record_id INTEGER NOT NULL,
+    action VARCHAR NOT NULL,
+    before JSONB NOT NULL DEFAULT 'null',
+    after JSONB NOT NULL DEFAULT 'null',
+    diff JSONB NOT NULL DEFAULT '{}',
+    component VARCHAR NOT NULL DEFAULT '',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    cio_company_id INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX certificates_history_record_id ON certificates_history (record_id)
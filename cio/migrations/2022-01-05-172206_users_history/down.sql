@@ -0,0 +1 @@
+DROP TABLE users_history
\ No newline at end of file
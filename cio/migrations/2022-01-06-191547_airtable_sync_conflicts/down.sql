@@ -0,0 +1 @@
+DROP TABLE airtable_sync_conflicts
@@ -0,0 +1,16 @@
+CREATE TABLE airtable_sync_conflicts (
+    id SERIAL PRIMARY KEY,
+    record_type VARCHAR NOT NULL,
+    record_id INTEGER NOT NULL,
+    field VARCHAR NOT NULL,
+    base_value JSONB NOT NULL,
+    db_value JSONB NOT NULL,
+    airtable_value JSONB NOT NULL,
+    winner VARCHAR NOT NULL,
+    db_updated_at TIMESTAMPTZ,
+    airtable_modified_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL,
+    cio_company_id INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX airtable_sync_conflicts_record_type_record_id ON airtable_sync_conflicts (record_type, record_id)
@@ -0,0 +1,11 @@
+CREATE TABLE airtable_sync_states (
+    id SERIAL PRIMARY KEY,
+    record_type VARCHAR NOT NULL,
+    record_id INTEGER NOT NULL,
+    fields JSONB NOT NULL,
+    db_updated_at TIMESTAMPTZ,
+    synced_at TIMESTAMPTZ,
+    cio_company_id INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE UNIQUE INDEX airtable_sync_states_record_type_record_id ON airtable_sync_states (record_type, record_id)
@@ -0,0 +1,2 @@
+DROP INDEX rfds_search;
+DROP INDEX rfd_sections_search
\ No newline at end of file
@@ -0,0 +1,3 @@
+CREATE INDEX rfds_search ON rfds USING GIN (to_tsvector('english', title || ' ' || content));
+
+CREATE INDEX rfd_sections_search ON rfd_sections USING GIN (to_tsvector('english', name || ' ' || content))
\ No newline at end of file
@@ -0,0 +1,5 @@
+DROP INDEX rfd_sections_anchor;
+
+ALTER TABLE rfd_sections DROP COLUMN position;
+
+ALTER TABLE rfd_sections DROP COLUMN level
\ No newline at end of file
@@ -0,0 +1,5 @@
+ALTER TABLE rfd_sections ADD COLUMN level INTEGER NOT NULL DEFAULT 2;
+
+ALTER TABLE rfd_sections ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+
+CREATE UNIQUE INDEX rfd_sections_anchor ON rfd_sections (rfds_id, anchor)
\ No newline at end of file
@@ -0,0 +1,3 @@
+ALTER TABLE rfds DROP COLUMN backlinks;
+
+DROP TABLE rfd_references
\ No newline at end of file
@@ -0,0 +1,14 @@
+CREATE TABLE rfd_references (
+    id SERIAL PRIMARY KEY,
+    rfds_id INTEGER NOT NULL,
+    kind VARCHAR NOT NULL,
+    target VARCHAR NOT NULL,
+    link VARCHAR NOT NULL,
+    cio_company_id INTEGER NOT NULL DEFAULT 0
+);
+
+ALTER TABLE "rfd_references" ADD FOREIGN KEY ("rfds_id") REFERENCES "rfds"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+CREATE INDEX rfd_references_target ON rfd_references (kind, target);
+
+ALTER TABLE rfds ADD COLUMN backlinks TEXT [] NOT NULL DEFAULT '{}'
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE rfd_state_transitions
\ No newline at end of file
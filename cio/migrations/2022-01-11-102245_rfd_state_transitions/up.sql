@@ -0,0 +1,10 @@
+CREATE TABLE rfd_state_transitions (
+    id SERIAL PRIMARY KEY,
+    rfds_id INTEGER NOT NULL,
+    from_state VARCHAR NOT NULL,
+    to_state VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    cio_company_id INTEGER NOT NULL DEFAULT 0
+);
+
+ALTER TABLE "rfd_state_transitions" ADD FOREIGN KEY ("rfds_id") REFERENCES "rfds"("id") ON DELETE CASCADE ON UPDATE CASCADE
\ No newline at end of file
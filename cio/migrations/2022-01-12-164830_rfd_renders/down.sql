@@ -0,0 +1 @@
+DROP TABLE rfd_renders
\ No newline at end of file
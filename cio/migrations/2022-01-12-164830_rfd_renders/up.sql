@@ -0,0 +1,15 @@
+CREATE TABLE rfd_renders (
+    id SERIAL PRIMARY KEY,
+    rfds_id INTEGER NOT NULL,
+    html_cache_key VARCHAR NOT NULL,
+    html TEXT NOT NULL,
+    pdf_cache_key VARCHAR NOT NULL,
+    pdf_file_name VARCHAR NOT NULL,
+    pdf_link_google_drive VARCHAR NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    cio_company_id INTEGER NOT NULL DEFAULT 0
+);
+
+ALTER TABLE "rfd_renders" ADD FOREIGN KEY ("rfds_id") REFERENCES "rfds"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+CREATE UNIQUE INDEX rfd_renders_rfds_id ON rfd_renders (rfds_id)
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE rfd_subscriptions
\ No newline at end of file
@@ -0,0 +1,12 @@
+CREATE TABLE rfd_subscriptions (
+    id SERIAL PRIMARY KEY,
+    slack_user_id VARCHAR NOT NULL,
+    email VARCHAR NOT NULL DEFAULT '',
+    notify_by VARCHAR NOT NULL,
+    rfd_number INTEGER NOT NULL DEFAULT 0,
+    label VARCHAR NOT NULL DEFAULT '',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    cio_company_id INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE UNIQUE INDEX rfd_subscriptions_follow ON rfd_subscriptions (cio_company_id, slack_user_id, rfd_number, label)
\ No newline at end of file
@@ -0,0 +1,7 @@
+ALTER TABLE users DROP COLUMN rfds_authored;
+
+ALTER TABLE rfds DROP COLUMN unresolved_authors;
+
+ALTER TABLE rfds DROP COLUMN link_to_authors;
+
+DROP TABLE rfd_authors
\ No newline at end of file
@@ -0,0 +1,21 @@
+CREATE TABLE rfd_authors (
+    id SERIAL PRIMARY KEY,
+    rfds_id INTEGER NOT NULL,
+    users_id INTEGER,
+    name VARCHAR NOT NULL,
+    email VARCHAR NOT NULL,
+    github VARCHAR NOT NULL,
+    cio_company_id INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX rfd_authors_users_id ON rfd_authors (users_id);
+
+ALTER TABLE "rfd_authors" ADD FOREIGN KEY ("rfds_id") REFERENCES "rfds"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE "rfd_authors" ADD FOREIGN KEY ("users_id") REFERENCES "users"("id") ON DELETE SET NULL ON UPDATE CASCADE;
+
+ALTER TABLE rfds ADD COLUMN link_to_authors TEXT [] NOT NULL DEFAULT '{}';
+
+ALTER TABLE rfds ADD COLUMN unresolved_authors TEXT [] NOT NULL DEFAULT '{}';
+
+ALTER TABLE users ADD COLUMN rfds_authored TEXT [] NOT NULL DEFAULT '{}'
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE webhook_secrets
\ No newline at end of file
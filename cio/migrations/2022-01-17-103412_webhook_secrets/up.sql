@@ -0,0 +1,9 @@
+CREATE TABLE webhook_secrets (
+    id SERIAL PRIMARY KEY,
+    provider VARCHAR NOT NULL,
+    secret VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    cio_company_id INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE UNIQUE INDEX webhook_secrets_provider ON webhook_secrets (cio_company_id, provider)
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE webhook_events
\ No newline at end of file
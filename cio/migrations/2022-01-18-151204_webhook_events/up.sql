@@ -0,0 +1,15 @@
+CREATE TABLE webhook_events (
+    id SERIAL PRIMARY KEY,
+    provider VARCHAR NOT NULL,
+    endpoint VARCHAR NOT NULL,
+    headers JSONB NOT NULL,
+    body BYTEA NOT NULL,
+    status VARCHAR NOT NULL,
+    error TEXT NOT NULL DEFAULT '',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    processed_at TIMESTAMPTZ,
+    cio_company_id INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX webhook_events_status ON webhook_events (status, provider)
\ No newline at end of file
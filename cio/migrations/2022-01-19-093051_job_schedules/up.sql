@@ -0,0 +1,11 @@
+CREATE TABLE job_schedules (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    cron VARCHAR NOT NULL,
+    timezone VARCHAR NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    cio_company_id INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE UNIQUE INDEX job_schedules_name ON job_schedules (name, cio_company_id)
\ No newline at end of file
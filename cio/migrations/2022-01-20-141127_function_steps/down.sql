@@ -0,0 +1 @@
+DROP TABLE function_steps
\ No newline at end of file
@@ -0,0 +1,12 @@
+CREATE TABLE function_steps (
+    id SERIAL PRIMARY KEY,
+    saga_id VARCHAR NOT NULL,
+    name VARCHAR NOT NULL,
+    status VARCHAR NOT NULL,
+    error TEXT NOT NULL DEFAULT '',
+    started_at TIMESTAMPTZ,
+    completed_at TIMESTAMPTZ,
+    cio_company_id INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE UNIQUE INDEX function_steps_saga_id ON function_steps (saga_id, name)
\ No newline at end of file
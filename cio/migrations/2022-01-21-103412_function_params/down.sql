@@ -0,0 +1 @@
+ALTER TABLE functions DROP COLUMN params
\ No newline at end of file
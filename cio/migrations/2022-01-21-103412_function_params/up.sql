@@ -0,0 +1 @@
+ALTER TABLE functions ADD COLUMN params JSONB NOT NULL DEFAULT '{}'
\ No newline at end of file
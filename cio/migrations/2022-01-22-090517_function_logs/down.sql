@@ -0,0 +1 @@
+DROP TABLE function_logs
\ No newline at end of file
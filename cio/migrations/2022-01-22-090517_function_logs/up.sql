@@ -0,0 +1,9 @@
+CREATE TABLE function_logs (
+    id SERIAL PRIMARY KEY,
+    saga_id VARCHAR NOT NULL,
+    logs TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    cio_company_id INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX function_logs_saga_id ON function_logs (saga_id, id)
\ No newline at end of file
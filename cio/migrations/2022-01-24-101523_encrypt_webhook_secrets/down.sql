@@ -0,0 +1,3 @@
+DELETE FROM webhook_secrets;
+
+ALTER TABLE webhook_secrets RENAME COLUMN encrypted_secret TO secret
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- The secrets were stored as they are, they have to be set again with
+-- `webhooky set-webhook-secret` so they are encrypted.
+DELETE FROM webhook_secrets;
+
+ALTER TABLE webhook_secrets RENAME COLUMN secret TO encrypted_secret
\ No newline at end of file
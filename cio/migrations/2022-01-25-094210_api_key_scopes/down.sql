@@ -0,0 +1 @@
+UPDATE api_keys SET scopes = '{}' WHERE scopes = '{*}'
\ No newline at end of file
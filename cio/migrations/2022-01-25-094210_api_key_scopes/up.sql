@@ -0,0 +1,3 @@
+-- Keys without scopes used to be able to read everything, now they need the
+-- `*` scope for that.
+UPDATE api_keys SET scopes = '{*}' WHERE scopes = '{}'
\ No newline at end of file
@@ -0,0 +1,3 @@
+UPDATE job_schedules c SET cron = j.cron, timezone = j.timezone
+FROM job_schedules j
+WHERE j.name = c.name AND j.cio_company_id = 0 AND c.cio_company_id != 0
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- The schedules for companies only turned a job off or on, their cron expressions and
+-- timezones were copies of the job's. Now a company can have its own, so empty them to follow
+-- the job's.
+UPDATE job_schedules SET cron = '', timezone = '' WHERE cio_company_id != 0
\ No newline at end of file
@@ -0,0 +1,2 @@
+UPDATE job_schedules SET cron = '0 8 * * Mon', timezone = 'America/Los_Angeles'
+WHERE name = 'send-rfd-changelog' AND cio_company_id = 0 AND cron = '0 17 * * Mon' AND timezone = 'UTC';
\ No newline at end of file
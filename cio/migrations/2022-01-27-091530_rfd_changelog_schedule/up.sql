@@ -0,0 +1,3 @@
+-- The changelog goes out on Monday at 17:00 UTC, unless someone already changed when it runs.
+UPDATE job_schedules SET cron = '0 17 * * Mon', timezone = 'UTC'
+WHERE name = 'send-rfd-changelog' AND cio_company_id = 0 AND cron = '0 8 * * Mon' AND timezone = 'America/Los_Angeles';
\ No newline at end of file
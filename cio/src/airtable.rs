@@ -3,7 +3,6 @@ pub static AIRTABLE_RACK_LINE_SIGNUPS_TABLE: &str = "Rack Line Signups";
 pub static AIRTABLE_CUSTOMER_INTERACTIONS_TABLE: &str = "Interactions";
 pub static AIRTABLE_AUTH_USERS_TABLE: &str = "Auth Users";
 pub static AIRTABLE_AUTH_USER_LOGINS_TABLE: &str = "Auth User Logins";
-pub static AIRTABLE_PAGE_VIEWS_TABLE: &str = "Page Views";
 
 pub static AIRTABLE_EMPLOYEES_TABLE: &str = "Employees";
 pub static AIRTABLE_GROUPS_TABLE: &str = "Groups";
@@ -42,9 +41,7 @@ pub static AIRTABLE_SWAG_ITEMS_TABLE: &str = "Items";
 
 pub static AIRTABLE_ASSET_ITEMS_TABLE: &str = "Items";
 
-pub static AIRTABLE_API_TOKENS_TABLE: &str = "API Tokens";
 pub static AIRTABLE_COMPANIES_TABLE: &str = "Companies";
-pub static AIRTABLE_FUNCTIONS_TABLE: &str = "Functions";
 
 pub static AIRTABLE_BOOKINGS_TABLE: &str = "Bookings";
 
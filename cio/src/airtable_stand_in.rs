@@ -0,0 +1,675 @@
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+};
+
+use airtable_api::{Airtable, Record};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use dropshot::{
+    endpoint, ApiDescription, ConfigDropshot, ConfigLogging, ConfigLoggingLevel, HttpError, HttpResponseOk, HttpServer,
+    HttpServerStarter, Path, RequestContext, TypedBody,
+};
+use reqwest::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::airtable::AIRTABLE_GRID_VIEW;
+
+/// The most records Airtable returns in a page.
+const MAX_PAGE_SIZE: usize = 100;
+/// The most records Airtable lets you create, update or delete at once.
+const MAX_RECORDS_PER_REQUEST: usize = 10;
+
+/// A filter for the records in a view.
+pub type ViewFilter = Box<dyn Fn(&Map<String, Value>) -> bool + Send + Sync>;
+
+/// A local, in-process server that speaks enough of the Airtable API for our clients:
+/// listing records (with views and offset pagination), getting, creating, updating and
+/// deleting them.
+///
+/// The records only live in memory, so every stand-in starts out empty. Point a client at it
+/// with `AirtableStandIn::client`, and give that to the generated `*_in_airtable_with`
+/// functions.
+pub struct AirtableStandIn {
+    server: HttpServer<Arc<StandInState>>,
+    state: Arc<StandInState>,
+}
+
+impl AirtableStandIn {
+    /// Start the stand-in on a random local port.
+    pub async fn start() -> Result<Self> {
+        let config_dropshot = ConfigDropshot {
+            bind_address: "127.0.0.1:0".parse()?,
+            request_body_max_bytes: 100000000,
+        };
+
+        // Only log errors, the tests are noisy enough.
+        let config_logging = ConfigLogging::StderrTerminal {
+            level: ConfigLoggingLevel::Error,
+        };
+        let log = config_logging.to_logger("airtable-stand-in")?;
+
+        let mut api = ApiDescription::new();
+        api.register(api_list_records).map_err(|e| anyhow!(e))?;
+        api.register(api_get_record).map_err(|e| anyhow!(e))?;
+        api.register(api_create_records).map_err(|e| anyhow!(e))?;
+        api.register(api_update_records).map_err(|e| anyhow!(e))?;
+        api.register(api_delete_records).map_err(|e| anyhow!(e))?;
+
+        let state = Arc::new(StandInState::default());
+        let server = HttpServerStarter::new(&config_dropshot, api, state.clone(), &log)
+            .map_err(|e| anyhow!("failed to start airtable stand-in: {}", e))?
+            .start();
+
+        Ok(AirtableStandIn { server, state })
+    }
+
+    /// Returns the address the stand-in is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+
+    /// Returns the endpoint to give to `Airtable::with_endpoint`.
+    pub fn endpoint(&self) -> String {
+        format!("http://{}/v0/", self.local_addr())
+    }
+
+    /// Returns a client for a base in the stand-in.
+    pub fn client(&self, base_id: &str) -> Airtable {
+        Airtable::new("key", base_id, "").with_endpoint(self.endpoint())
+    }
+
+    /// Add a view to a table. Records show up in the view if the filter returns true for
+    /// their fields.
+    ///
+    /// Listing a view that was never added returns every record, like the default
+    /// "Grid view" does.
+    pub fn add_view(&self, base_id: &str, table: &str, view: &str, filter: ViewFilter) {
+        self.state
+            .tables()
+            .entry(table_key(base_id, table))
+            .or_default()
+            .views
+            .insert(view.to_string(), filter);
+    }
+
+    /// Add a record to a table directly, as if someone had added it in Airtable.
+    /// Returns the id of the new record.
+    pub fn insert(&self, base_id: &str, table: &str, fields: Value) -> String {
+        let record = self.state.new_record(fields);
+        let id = record.id.to_string();
+        self.state
+            .tables()
+            .entry(table_key(base_id, table))
+            .or_default()
+            .records
+            .push(record);
+
+        id
+    }
+
+    /// Returns every record in a table, in the order they were created.
+    pub fn records(&self, base_id: &str, table: &str) -> Vec<Record<Value>> {
+        match self.state.tables().get(&table_key(base_id, table)) {
+            Some(t) => t
+                .records
+                .iter()
+                .map(|r| Record {
+                    id: r.id.to_string(),
+                    fields: Value::Object(r.fields.clone()),
+                    created_time: Some(r.created_time),
+                })
+                .collect(),
+            None => Default::default(),
+        }
+    }
+
+    /// Stop the stand-in.
+    pub async fn stop(self) -> Result<()> {
+        self.server.close().await.map_err(|e| anyhow!(e))
+    }
+}
+
+/// The tables in the stand-in, keyed by the base id and the table name.
+#[derive(Default)]
+struct StandInState {
+    tables: Mutex<BTreeMap<(String, String), Table>>,
+    next_id: AtomicU64,
+}
+
+impl StandInState {
+    fn tables(&self) -> MutexGuard<'_, BTreeMap<(String, String), Table>> {
+        // A panic in another request does not leave the tables in a state we care about.
+        self.tables.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn new_record(&self, fields: Value) -> StoredRecord {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut record = StoredRecord {
+            id: format!("rec{:014}", id),
+            fields: Default::default(),
+            created_time: Utc::now(),
+        };
+        record.merge(fields);
+        record
+    }
+}
+
+#[derive(Default)]
+struct Table {
+    records: Vec<StoredRecord>,
+    views: BTreeMap<String, ViewFilter>,
+}
+
+impl Table {
+    fn get_mut(&mut self, record_id: &str) -> Result<&mut StoredRecord, HttpError> {
+        self.records
+            .iter_mut()
+            .find(|r| r.id == record_id)
+            .ok_or_else(|| not_found(record_id))
+    }
+}
+
+struct StoredRecord {
+    id: String,
+    fields: Map<String, Value>,
+    created_time: DateTime<Utc>,
+}
+
+impl StoredRecord {
+    /// Update the fields that were given, like a PATCH to Airtable does.
+    /// Airtable does not store empty fields, so a null clears the field.
+    fn merge(&mut self, fields: Value) {
+        if let Value::Object(fields) = fields {
+            for (k, v) in fields {
+                if v.is_null() {
+                    self.fields.remove(&k);
+                } else {
+                    self.fields.insert(k, v);
+                }
+            }
+        }
+    }
+
+    /// Returns the record the way Airtable returns it, optionally with only some fields.
+    fn to_json(&self, only: &[String]) -> Value {
+        let fields: Map<String, Value> = self
+            .fields
+            .iter()
+            .filter(|(k, _)| only.is_empty() || only.contains(k))
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect();
+
+        json!({
+            "id": self.id,
+            "fields": fields,
+            "createdTime": self.created_time,
+        })
+    }
+}
+
+fn table_key(base_id: &str, table: &str) -> (String, String) {
+    (base_id.to_string(), table.to_string())
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct TablePath {
+    base_id: String,
+    table: String,
+}
+
+impl TablePath {
+    fn key(&self) -> (String, String) {
+        table_key(&decode(&self.base_id), &decode(&self.table))
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct RecordPath {
+    base_id: String,
+    table: String,
+    record_id: String,
+}
+
+impl RecordPath {
+    fn key(&self) -> (String, String) {
+        table_key(&decode(&self.base_id), &decode(&self.table))
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct RecordsRequest {
+    records: Vec<RecordRequest>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct RecordRequest {
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    fields: Value,
+}
+
+impl RecordsRequest {
+    fn check(&self) -> Result<(), HttpError> {
+        if self.records.is_empty() || self.records.len() > MAX_RECORDS_PER_REQUEST {
+            return Err(invalid_request(&format!(
+                "you can send between 1 and {} records at a time, got {}",
+                MAX_RECORDS_PER_REQUEST,
+                self.records.len()
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/**
+ * List the records in a table.
+ */
+#[endpoint {
+    method = GET,
+    path = "/v0/{base_id}/{table}",
+}]
+async fn api_list_records(
+    rqctx: Arc<RequestContext<Arc<StandInState>>>,
+    path_params: Path<TablePath>,
+) -> Result<HttpResponseOk<Value>, HttpError> {
+    let query = query_params(&rqctx).await;
+    let key = path_params.into_inner().key();
+
+    let view = query_param(&query, "view").unwrap_or_else(|| AIRTABLE_GRID_VIEW.to_string());
+    let fields: Vec<String> = query
+        .iter()
+        .filter(|(k, _)| k == "fields[]")
+        .map(|(_, v)| v.to_string())
+        .collect();
+    let page_size = match query_param(&query, "pageSize") {
+        Some(s) => s
+            .parse::<usize>()
+            .ok()
+            .filter(|s| (1..=MAX_PAGE_SIZE).contains(s))
+            .ok_or_else(|| invalid_request(&format!("invalid pageSize: {}", s)))?,
+        None => MAX_PAGE_SIZE,
+    };
+    // We hand out the index of the next record as the offset.
+    let start = match query_param(&query, "offset") {
+        Some(s) => s
+            .parse::<usize>()
+            .map_err(|_| invalid_request(&format!("invalid offset: {}", s)))?,
+        None => 0,
+    };
+
+    let tables = rqctx.context().tables();
+    let (records, views) = match tables.get(&key) {
+        Some(t) => (&t.records[..], Some(&t.views)),
+        None => (&[][..], None),
+    };
+    let filter = views.and_then(|v| v.get(&view));
+    let in_view: Vec<&StoredRecord> = records
+        .iter()
+        .filter(|r| filter.map(|f| f(&r.fields)).unwrap_or(true))
+        .collect();
+
+    let page: Vec<Value> = in_view
+        .iter()
+        .skip(start)
+        .take(page_size)
+        .map(|r| r.to_json(&fields))
+        .collect();
+
+    let mut resp = json!({ "records": page });
+    if start + page_size < in_view.len() {
+        resp["offset"] = json!((start + page_size).to_string());
+    }
+
+    Ok(HttpResponseOk(resp))
+}
+
+/**
+ * Get a record from a table.
+ */
+#[endpoint {
+    method = GET,
+    path = "/v0/{base_id}/{table}/{record_id}",
+}]
+async fn api_get_record(
+    rqctx: Arc<RequestContext<Arc<StandInState>>>,
+    path_params: Path<RecordPath>,
+) -> Result<HttpResponseOk<Value>, HttpError> {
+    let path = path_params.into_inner();
+    let key = path.key();
+
+    let tables = rqctx.context().tables();
+    let record = tables
+        .get(&key)
+        .and_then(|t| t.records.iter().find(|r| r.id == path.record_id))
+        .ok_or_else(|| not_found(&path.record_id))?;
+
+    Ok(HttpResponseOk(record.to_json(&[])))
+}
+
+/**
+ * Create records in a table.
+ */
+#[endpoint {
+    method = POST,
+    path = "/v0/{base_id}/{table}",
+}]
+async fn api_create_records(
+    rqctx: Arc<RequestContext<Arc<StandInState>>>,
+    path_params: Path<TablePath>,
+    body_param: TypedBody<RecordsRequest>,
+) -> Result<HttpResponseOk<Value>, HttpError> {
+    let body = body_param.into_inner();
+    body.check()?;
+
+    let state = rqctx.context();
+    let new_records: Vec<StoredRecord> = body.records.into_iter().map(|r| state.new_record(r.fields)).collect();
+    let created: Vec<Value> = new_records.iter().map(|r| r.to_json(&[])).collect();
+
+    state
+        .tables()
+        .entry(path_params.into_inner().key())
+        .or_default()
+        .records
+        .extend(new_records);
+
+    Ok(HttpResponseOk(json!({ "records": created })))
+}
+
+/**
+ * Update records in a table, only the fields that are given are changed.
+ */
+#[endpoint {
+    method = PATCH,
+    path = "/v0/{base_id}/{table}",
+}]
+async fn api_update_records(
+    rqctx: Arc<RequestContext<Arc<StandInState>>>,
+    path_params: Path<TablePath>,
+    body_param: TypedBody<RecordsRequest>,
+) -> Result<HttpResponseOk<Value>, HttpError> {
+    let body = body_param.into_inner();
+    body.check()?;
+
+    let mut tables = rqctx.context().tables();
+    let table = tables
+        .get_mut(&path_params.into_inner().key())
+        .ok_or_else(|| not_found("table"))?;
+
+    // Make sure every record exists before we change any of them, Airtable does not
+    // update some of the records in a request.
+    for r in &body.records {
+        table.get_mut(&r.id)?;
+    }
+
+    let mut updated = Vec::new();
+    for r in body.records {
+        let record = table.get_mut(&r.id)?;
+        record.merge(r.fields);
+        updated.push(record.to_json(&[]));
+    }
+
+    Ok(HttpResponseOk(json!({ "records": updated })))
+}
+
+/**
+ * Delete records from a table, the ids are given as `records[]` query parameters.
+ */
+#[endpoint {
+    method = DELETE,
+    path = "/v0/{base_id}/{table}",
+}]
+async fn api_delete_records(
+    rqctx: Arc<RequestContext<Arc<StandInState>>>,
+    path_params: Path<TablePath>,
+) -> Result<HttpResponseOk<Value>, HttpError> {
+    let query = query_params(&rqctx).await;
+    let ids: Vec<String> = query
+        .into_iter()
+        .filter(|(k, _)| k == "records[]")
+        .map(|(_, v)| v)
+        .collect();
+    if ids.is_empty() || ids.len() > MAX_RECORDS_PER_REQUEST {
+        return Err(invalid_request(&format!(
+            "you can delete between 1 and {} records at a time, got {}",
+            MAX_RECORDS_PER_REQUEST,
+            ids.len()
+        )));
+    }
+
+    let mut tables = rqctx.context().tables();
+    let table = tables
+        .get_mut(&path_params.into_inner().key())
+        .ok_or_else(|| not_found("table"))?;
+
+    for id in &ids {
+        table.get_mut(id)?;
+    }
+    table.records.retain(|r| !ids.contains(&r.id));
+
+    let deleted: Vec<Value> = ids.iter().map(|id| json!({ "id": id, "deleted": true })).collect();
+
+    Ok(HttpResponseOk(json!({ "records": deleted })))
+}
+
+/// Returns the query parameters of a request, in order.
+/// We parse these ourselves since the client repeats `fields[]` and `records[]`.
+async fn query_params(rqctx: &Arc<RequestContext<Arc<StandInState>>>) -> Vec<(String, String)> {
+    let request = rqctx.request.lock().await;
+    let query = request.uri().query().unwrap_or_default();
+
+    url::form_urlencoded::parse(query.as_bytes()).into_owned().collect()
+}
+
+fn query_param(query: &[(String, String)], name: &str) -> Option<String> {
+    query.iter().find(|(k, _)| k == name).map(|(_, v)| v.to_string())
+}
+
+/// Percent-decode a path segment, table names usually have spaces in them.
+fn decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(b) = std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+            {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Airtable returns a 404 with `NOT_FOUND` for records that do not exist.
+fn not_found(what: &str) -> HttpError {
+    HttpError::for_client_error(
+        Some("NOT_FOUND".to_string()),
+        StatusCode::NOT_FOUND,
+        format!("could not find {}", what),
+    )
+}
+
+/// Airtable returns a 422 for requests it does not understand.
+fn invalid_request(message: &str) -> HttpError {
+    HttpError::for_client_error(
+        Some("INVALID_REQUEST_UNKNOWN".to_string()),
+        StatusCode::UNPROCESSABLE_ENTITY,
+        message.to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::{
+        airtable::AIRTABLE_SWAG_INVENTORY_ITEMS_TABLE,
+        airtable_stand_in::{decode, AirtableStandIn},
+        db::Database,
+        swag_inventory::SwagInventoryItem,
+    };
+
+    const BASE: &str = "appStandIn";
+    const TABLE: &str = "Swag Items";
+
+    #[test]
+    fn test_airtable_stand_in_decode() {
+        assert_eq!(decode("Swag%20Items"), "Swag Items");
+        assert_eq!(decode("RFDs"), "RFDs");
+        assert_eq!(decode("100%"), "100%");
+        assert_eq!(decode("%zz"), "%zz");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_airtable_stand_in_records() {
+        let stand_in = AirtableStandIn::start().await.unwrap();
+        let airtable = stand_in.client(BASE);
+
+        let created = airtable
+            .create_records::<Value>(
+                TABLE,
+                vec![airtable_api::Record {
+                    id: "".to_string(),
+                    fields: json!({"name": "Hoodie", "count": 3, "notes": null}),
+                    created_time: None,
+                }],
+            )
+            .await
+            .unwrap();
+        assert_eq!(created.len(), 1);
+        let id = created[0].id.to_string();
+        assert!(id.starts_with("rec"));
+        // Empty fields are not stored.
+        assert_eq!(created[0].fields, json!({"name": "Hoodie", "count": 3}));
+
+        let mut record = airtable.get_record::<Value>(TABLE, &id).await.unwrap();
+        assert_eq!(record.fields["name"], "Hoodie");
+
+        // Only the fields we send are changed.
+        record.fields = json!({"count": 2});
+        let updated = airtable.update_records(TABLE, vec![record]).await.unwrap();
+        assert_eq!(updated[0].fields, json!({"name": "Hoodie", "count": 2}));
+
+        airtable.delete_record(TABLE, &id).await.unwrap();
+        assert!(stand_in.records(BASE, TABLE).is_empty());
+
+        let e = airtable.get_record::<Value>(TABLE, &id).await.unwrap_err();
+        assert!(crate::error::CioError::is_not_found(&e));
+        assert!(airtable.delete_record(TABLE, &id).await.is_err());
+
+        stand_in.stop().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_airtable_stand_in_list() {
+        let stand_in = AirtableStandIn::start().await.unwrap();
+        for i in 0..250 {
+            stand_in.insert(BASE, TABLE, json!({"name": format!("Sticker {}", i), "count": i}));
+        }
+        stand_in.add_view(
+            BASE,
+            TABLE,
+            "In Stock",
+            Box::new(|fields| fields.get("count").and_then(|c| c.as_i64()).unwrap_or_default() >= 200),
+        );
+
+        let airtable = stand_in.client(BASE);
+
+        // This takes three pages.
+        let records = airtable
+            .list_records::<Value>(TABLE, "Grid view", vec![])
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 250);
+        assert_eq!(records[249].fields["name"], "Sticker 249");
+
+        let records = airtable
+            .list_records::<Value>(TABLE, "In Stock", vec!["count"])
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 50);
+        assert_eq!(records[0].fields, json!({"count": 200}));
+
+        // Tables nobody wrote to are empty.
+        let records = airtable
+            .list_records::<Value>("Other", "Grid view", vec![])
+            .await
+            .unwrap();
+        assert!(records.is_empty());
+
+        stand_in.stop().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_airtable_stand_in_sync() {
+        let stand_in = AirtableStandIn::start().await.unwrap();
+        let airtable = stand_in.client(BASE);
+        // The Airtable hooks for swag inventory items don't touch the database.
+        let db = Database::unconnected();
+
+        let mut item: SwagInventoryItem = serde_json::from_value(json!({
+            "id": 1,
+            "item": "Hoodie",
+            "size": "L",
+            "current_stock": 3,
+            "airtable_record_id": "",
+            "cio_company_id": 1,
+        }))
+        .unwrap();
+
+        // Create the row.
+        let mut record = item.create_in_airtable_with(&db, &airtable).await.unwrap();
+        let rows = stand_in.records(BASE, AIRTABLE_SWAG_INVENTORY_ITEMS_TABLE);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].id, record.id);
+        assert_eq!(rows[0].fields["id"], item.id);
+        assert_eq!(rows[0].fields["current_stock"], 3);
+
+        // Update the row.
+        item.airtable_record_id = record.id.to_string();
+        item.current_stock = 5;
+        let updated = item.update_in_airtable_with(&db, &airtable, &mut record).await.unwrap();
+        assert_eq!(updated.fields.current_stock, 5);
+        let rows = stand_in.records(BASE, AIRTABLE_SWAG_INVENTORY_ITEMS_TABLE);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].fields["current_stock"], 5);
+
+        // The record we have matches, so this does not send an update, or it would undo the
+        // change made in Airtable since.
+        airtable
+            .update_records::<Value>(
+                AIRTABLE_SWAG_INVENTORY_ITEMS_TABLE,
+                vec![airtable_api::Record {
+                    id: record.id.to_string(),
+                    fields: json!({"current_stock": 9}),
+                    created_time: None,
+                }],
+            )
+            .await
+            .unwrap();
+        let mut existing = updated.clone();
+        item.update_in_airtable_with(&db, &airtable, &mut existing)
+            .await
+            .unwrap();
+        let rows = stand_in.records(BASE, AIRTABLE_SWAG_INVENTORY_ITEMS_TABLE);
+        assert_eq!(rows[0].fields["current_stock"], 9);
+
+        stand_in.stop().await.unwrap();
+    }
+}
@@ -0,0 +1,269 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use reqwest::header::{HeaderMap, AUTHORIZATION};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{db::Database, schema::api_keys};
+
+/// All our keys start with this, so they are easy to spot if they leak.
+static KEY_PREFIX: &str = "cio_";
+
+/// The header we also accept a key in, for clients that can't set a bearer token.
+static API_KEY_HEADER: &str = "x-api-key";
+
+/// What an API key is allowed to read.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum ApiScope {
+    /// Everything the key's company has, this has to be given to a key on purpose.
+    All,
+    ReadApplicants,
+    ReadAuthUsers,
+    /// Users, groups, buildings, conference rooms and links.
+    ReadDirectory,
+    ReadJournalClubs,
+    ReadMailingList,
+    ReadRepos,
+    ReadRFDs,
+}
+
+impl fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiScope::All => write!(f, "*"),
+            ApiScope::ReadApplicants => write!(f, "read:applicants"),
+            ApiScope::ReadAuthUsers => write!(f, "read:auth_users"),
+            ApiScope::ReadDirectory => write!(f, "read:directory"),
+            ApiScope::ReadJournalClubs => write!(f, "read:journal_clubs"),
+            ApiScope::ReadMailingList => write!(f, "read:mailing_list"),
+            ApiScope::ReadRepos => write!(f, "read:repos"),
+            ApiScope::ReadRFDs => write!(f, "read:rfds"),
+        }
+    }
+}
+
+impl FromStr for ApiScope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "*" => Ok(ApiScope::All),
+            "read:applicants" => Ok(ApiScope::ReadApplicants),
+            "read:auth_users" => Ok(ApiScope::ReadAuthUsers),
+            "read:directory" => Ok(ApiScope::ReadDirectory),
+            "read:journal_clubs" => Ok(ApiScope::ReadJournalClubs),
+            "read:mailing_list" => Ok(ApiScope::ReadMailingList),
+            "read:repos" => Ok(ApiScope::ReadRepos),
+            "read:rfds" => Ok(ApiScope::ReadRFDs),
+            _ => bail!("invalid api scope: `{}`", s),
+        }
+    }
+}
+
+/// A key for the cio-api server, it can only read the records of its company.
+///
+/// We only store a hash of the key, the key itself is shown once when it is created.
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "api_keys"]
+pub struct NewApiKey {
+    /// What the key is for, so we know who to ask before revoking it.
+    pub name: String,
+    /// The start of the key, to tell keys apart without storing them.
+    pub key_prefix: String,
+    pub key_hash: String,
+    /// The key can read everything for its company only if this has `*`, a key without
+    /// scopes can't read anything.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<String>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Queryable, Identifiable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "api_keys"]
+pub struct ApiKey {
+    pub id: i32,
+    pub name: String,
+    pub key_prefix: String,
+    pub key_hash: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<String>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+impl ApiKey {
+    /// Create a key for a company, with at least one scope.
+    /// Returns the key, this is the only time we have it.
+    pub fn create(db: &Database, cio_company_id: i32, name: &str, scopes: &[ApiScope]) -> Result<(ApiKey, String)> {
+        if scopes.is_empty() {
+            bail!("an api key needs at least one scope, use `*` for a key that can read everything");
+        }
+
+        let random: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(40)
+            .map(char::from)
+            .collect();
+        let key = format!("{}{}", KEY_PREFIX, random);
+
+        let new_key = NewApiKey {
+            name: name.to_string(),
+            key_prefix: key[..KEY_PREFIX.len() + 6].to_string(),
+            key_hash: hash_key(&key),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            last_used_at: None,
+            revoked_at: None,
+            created_at: Utc::now(),
+            cio_company_id,
+        };
+
+        let api_key = diesel::insert_into(api_keys::table)
+            .values(&new_key)
+            .get_result::<ApiKey>(&db.conn()?)?;
+
+        Ok((api_key, key))
+    }
+
+    /// Returns the key that was given in the headers of a request, if it is valid.
+    pub async fn authenticate(db: &Database, headers: &HeaderMap) -> Result<Option<ApiKey>> {
+        let key = match key_from_headers(headers) {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+
+        db.run(move |db| {
+            let api_key = api_keys::dsl::api_keys
+                .filter(api_keys::dsl::key_hash.eq(hash_key(&key)))
+                .filter(api_keys::dsl::revoked_at.is_null())
+                .first::<ApiKey>(&db.conn()?)
+                .optional()?;
+
+            if let Some(api_key) = &api_key {
+                diesel::update(api_key)
+                    .set(api_keys::dsl::last_used_at.eq(Utc::now()))
+                    .execute(&db.conn()?)?;
+            }
+
+            Ok(api_key)
+        })
+        .await
+    }
+
+    /// Returns if the key can read what the scope covers.
+    pub fn allows(&self, scope: ApiScope) -> bool {
+        self.scopes
+            .iter()
+            .any(|s| *s == ApiScope::All.to_string() || *s == scope.to_string())
+    }
+
+    /// Revoke the key, it can't be used after this.
+    pub fn revoke(&self, db: &Database) -> Result<()> {
+        diesel::update(self)
+            .set(api_keys::dsl::revoked_at.eq(Utc::now()))
+            .execute(&db.conn()?)?;
+
+        Ok(())
+    }
+}
+
+/// Returns the key from either an `Authorization: Bearer` or an `X-Api-Key` header.
+fn key_from_headers(headers: &HeaderMap) -> Option<String> {
+    if let Some(value) = headers.get(AUTHORIZATION).and_then(|v| v.to_str().ok()) {
+        let mut parts = value.splitn(2, ' ');
+        if let (Some(scheme), Some(key)) = (parts.next(), parts.next()) {
+            if scheme.eq_ignore_ascii_case("bearer") && !key.trim().is_empty() {
+                return Some(key.trim().to_string());
+            }
+        }
+    }
+
+    headers
+        .get(API_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// Returns the hex encoded SHA-256 of a key.
+fn hash_key(key: &str) -> String {
+    let digest = sodiumoxide::crypto::hash::sha256::hash(key.as_bytes());
+    digest.0.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use chrono::Utc;
+    use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+
+    use crate::api_keys::{hash_key, key_from_headers, ApiKey, ApiScope};
+
+    #[test]
+    fn test_api_keys_key_from_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(key_from_headers(&headers), None);
+
+        headers.insert("x-api-key", HeaderValue::from_static("cio_other"));
+        assert_eq!(key_from_headers(&headers), Some("cio_other".to_string()));
+
+        // The bearer token wins.
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer cio_abc123"));
+        assert_eq!(key_from_headers(&headers), Some("cio_abc123".to_string()));
+
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Basic dXNlcjpwYXNz"));
+        assert_eq!(key_from_headers(&headers), Some("cio_other".to_string()));
+    }
+
+    #[test]
+    fn test_api_keys_scopes() {
+        for scope in &[
+            ApiScope::All,
+            ApiScope::ReadApplicants,
+            ApiScope::ReadDirectory,
+            ApiScope::ReadRFDs,
+        ] {
+            assert_eq!(ApiScope::from_str(&scope.to_string()).unwrap(), *scope);
+        }
+        assert!(ApiScope::from_str("write:rfds").is_err());
+
+        let mut key = ApiKey {
+            id: 1,
+            name: "dashboard".to_string(),
+            key_prefix: "cio_abc123".to_string(),
+            key_hash: hash_key("cio_abc123"),
+            scopes: vec![],
+            last_used_at: None,
+            revoked_at: None,
+            created_at: Utc::now(),
+            cio_company_id: 1,
+        };
+        // No scopes means nothing.
+        assert!(!key.allows(ApiScope::ReadApplicants));
+        assert!(!key.allows(ApiScope::ReadRFDs));
+
+        key.scopes = vec!["read:rfds".to_string()];
+        assert!(key.allows(ApiScope::ReadRFDs));
+        assert!(!key.allows(ApiScope::ReadApplicants));
+
+        key.scopes = vec!["*".to_string()];
+        assert!(key.allows(ApiScope::ReadApplicants));
+        assert!(key.allows(ApiScope::ReadRFDs));
+
+        assert_eq!(
+            hash_key("cio_abc123"),
+            "f4aad8b83cae56b55816b9762859fd9e4f8604781b7541832bf75b188c07700a"
+        );
+    }
+}
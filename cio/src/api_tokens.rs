@@ -1,22 +1,18 @@
 use anyhow::Result;
-use async_trait::async_trait;
 use chrono::{DateTime, Duration, Utc};
 use macros::db;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    airtable::AIRTABLE_API_TOKENS_TABLE,
     companies::Company,
-    core::UpdateAirtableRecord,
     db::Database,
     schema::{api_tokens as a_p_i_tokens, api_tokens},
 };
 
 #[db {
     new_struct_name = "APIToken",
-    airtable_base = "cio",
-    airtable_table = "AIRTABLE_API_TOKENS_TABLE",
+    mirror = "none",
     match_on = {
         "auth_company_id" = "i32",
         "product" = "String",
@@ -68,19 +64,6 @@ pub struct NewAPIToken {
     pub auth_company_id: i32,
 }
 
-/// Implement updating the Airtable record for a APIToken.
-#[async_trait]
-impl UpdateAirtableRecord<APIToken> for APIToken {
-    async fn update_airtable_record(&mut self, _record: APIToken) -> Result<()> {
-        // Link to the correct company.
-        let db = Database::new();
-        let company = Company::get_by_id(&db, self.auth_company_id)?;
-        self.company = vec![company.airtable_record_id];
-
-        Ok(())
-    }
-}
-
 impl NewAPIToken {
     pub fn expand(&mut self) {
         if self.expires_in > 0 {
@@ -137,7 +120,7 @@ impl APIToken {
 }
 
 pub async fn refresh_api_tokens(db: &Database, company: &Company) -> Result<()> {
-    APITokens::get_from_db(db, company.id)?.update_airtable(db).await?;
+    APITokens::get_from_db(db, company.id)?.update_mirror(db).await?;
 
     Ok(())
 }
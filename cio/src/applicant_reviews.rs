@@ -106,7 +106,7 @@ pub struct NewApplicantReview {
 /// Implement updating the Airtable record for a ApplicantReview.
 #[async_trait]
 impl UpdateAirtableRecord<ApplicantReview> for ApplicantReview {
-    async fn update_airtable_record(&mut self, _record: ApplicantReview) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, _record: ApplicantReview) -> Result<()> {
         // Set name to empty since it is a function we cannot update it.
         self.name = "".to_string();
 
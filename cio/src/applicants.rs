@@ -4,6 +4,7 @@ use std::{
     io::{copy, Write},
     process::Command,
     str::FromStr,
+    sync::Arc,
 };
 
 use anyhow::{bail, Result};
@@ -37,7 +38,9 @@ use crate::{
     configs::{User, Users},
     core::UpdateAirtableRecord,
     db::Database,
+    error::CioError,
     interviews::ApplicantInterview,
+    sagas::{run_saga, run_step, undo_nothing, undo_step, SagaContext, StepSaga},
     schema::{applicant_interviews, applicant_reviewers, applicants, users},
     utils::{check_if_github_issue_exists, truncate},
 };
@@ -67,6 +70,8 @@ pub static DOCUSIGN_PIIA_SUBJECT: &str = "Sign your Oxide Computer Company Emplo
     new_struct_name = "Applicant",
     airtable_base = "hiring",
     airtable_table = "AIRTABLE_APPLICATIONS_TABLE",
+    history = true,
+    reconcile = true,
     match_on = {
         "email" = "String",
         "sheet_id" = "String",
@@ -660,12 +665,12 @@ impl Applicant {
     }
 
     /// Update the interviews start and end time, if we have it.
-    pub fn update_interviews_start_end_time(&mut self, db: &Database) {
+    pub fn update_interviews_start_end_time(&mut self, db: &Database) -> Result<()> {
         // If we have interviews for them, let's update the interviews_started and
         // interviews_completed times.
         if self.interviews.is_empty() || self.airtable_record_id.is_empty() {
             // Return early we don't care.
-            return;
+            return Ok(());
         }
 
         // Since our interviews length is at least one, we must have at least one interview.
@@ -673,8 +678,7 @@ impl Applicant {
         let data = applicant_interviews::dsl::applicant_interviews
             .filter(applicant_interviews::dsl::applicant.contains(vec![self.airtable_record_id.to_string()]))
             .order_by(applicant_interviews::dsl::start_time.asc())
-            .load::<ApplicantInterview>(&db.conn())
-            .unwrap();
+            .load::<ApplicantInterview>(&db.conn()?)?;
         // Probably a better way to do this using first and last, but whatever.
         for (index, r) in data.iter().enumerate() {
             if index == 0 {
@@ -692,6 +696,8 @@ impl Applicant {
                 break;
             }
         }
+
+        Ok(())
     }
 
     /// Update applicant reviews counts.
@@ -1361,7 +1367,7 @@ fn parse_question(q1: &str, q2: &str, materials_contents: &str) -> String {
 /// Implement updating the Airtable record for an Applicant.
 #[async_trait]
 impl UpdateAirtableRecord<Applicant> for Applicant {
-    async fn update_airtable_record(&mut self, record: Applicant) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, record: Applicant) -> Result<()> {
         self.interviews = record.interviews;
         self.geocode_cache = record.geocode_cache;
         self.link_to_reviews = record.link_to_reviews;
@@ -1800,7 +1806,7 @@ pub struct NewApplicantReviewer {
 /// Implement updating the Airtable record for an ApplicantReviewer.
 #[async_trait]
 impl UpdateAirtableRecord<ApplicantReviewer> for ApplicantReviewer {
-    async fn update_airtable_record(&mut self, _record: ApplicantReviewer) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, _record: ApplicantReviewer) -> Result<()> {
         Ok(())
     }
 }
@@ -1814,7 +1820,7 @@ pub async fn refresh_docusign_for_applicants(db: &Database, company: &Company) -
     // Authenticate DocuSign.
     let dsa = company.authenticate_docusign(db).await;
     if let Err(e) = dsa {
-        if e.to_string().contains("no token") {
+        if CioError::is_not_connected(&e) {
             // Return early, this company does not use Zoom.
             return Ok(());
         }
@@ -1853,6 +1859,169 @@ pub async fn get_docusign_template_id(ds: &DocuSign, name: &str) -> String {
     "".to_string()
 }
 
+#[derive(Debug)]
+pub struct SendOffer;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SendOfferParams {
+    company_id: i32,
+    applicant_id: i32,
+    template_id: String,
+}
+
+impl steno::SagaType for SendOffer {
+    type SagaParamsType = SendOfferParams;
+    type ExecContextType = Arc<SagaContext>;
+}
+
+/// Send the offer for an applicant in DocuSign. The envelope is created as a draft and only
+/// sent once we saved it, so if saving it fails the applicant does not get an offer we lost
+/// track of.
+pub async fn send_offer(db: &Database, company: &Company, applicant_id: i32, template_id: &str) -> Result<()> {
+    run_saga::<SendOffer>(
+        db,
+        &uuid::Uuid::new_v4(),
+        SendOfferParams {
+            company_id: company.id,
+            applicant_id,
+            template_id: template_id.to_string(),
+        },
+    )
+    .await
+}
+
+impl StepSaga for SendOffer {
+    const NAME: &'static str = "send-offer";
+    const STEPS: &'static [&'static str] = &["envelope", "database", "send"];
+
+    /// The saga for sending an offer.
+    fn template() -> steno::SagaTemplate<SendOffer> {
+        let mut builder = steno::SagaTemplateBuilder::new();
+        builder.append(
+            "envelope",
+            "envelope",
+            steno::ActionFunc::new_action(action_offer_envelope, undo_nothing::<SendOffer>),
+        );
+        builder.append(
+            "database",
+            "database",
+            steno::ActionFunc::new_action(action_offer_database, undo_offer_database),
+        );
+        builder.append(
+            "send",
+            "send",
+            steno::ActionFunc::new_action(action_offer_send, undo_nothing::<SendOffer>),
+        );
+
+        builder.build()
+    }
+}
+
+// We leave the draft envelope if a later step fails, nobody gets an email for a draft and the
+// next sync creates a new one.
+async fn action_offer_envelope(action_context: steno::ActionContext<SendOffer>) -> Result<String, steno::ActionError> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+
+    run_step(context, "envelope", async {
+        let db = &context.db;
+        let company = Company::get_by_id(db, params.company_id)?;
+        let applicant = Applicant::get_by_id(db, params.applicant_id)?;
+
+        let ds = company.authenticate_docusign(db).await?;
+        let envelope = ds
+            .create_envelope(applicant.offer_envelope(&params.template_id, &company))
+            .await?;
+
+        Ok(envelope.envelope_id)
+    })
+    .await
+}
+
+async fn action_offer_database(action_context: steno::ActionContext<SendOffer>) -> Result<(), steno::ActionError> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+    let envelope_id = action_context.lookup::<String>("envelope")?;
+
+    run_step(context, "database", async {
+        let db = &context.db;
+        let mut applicant = Applicant::get_by_id(db, params.applicant_id)?;
+
+        // Set the id of the envelope.
+        applicant.docusign_envelope_id = envelope_id;
+        // Set the status of the envelope.
+        applicant.docusign_envelope_status = "created".to_string();
+
+        // Update the applicant in the database.
+        applicant.update(db).await?;
+
+        Ok(())
+    })
+    .await
+}
+
+async fn undo_offer_database(action_context: steno::ActionContext<SendOffer>) -> Result<()> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+
+    undo_step(context, "database", async {
+        let db = &context.db;
+        let mut applicant = Applicant::get_by_id(db, params.applicant_id)?;
+
+        // Clear the envelope so the next sync sends the offer again.
+        applicant.docusign_envelope_id = Default::default();
+        applicant.docusign_envelope_status = Default::default();
+        applicant.update(db).await?;
+
+        Ok(())
+    })
+    .await
+}
+
+async fn action_offer_send(action_context: steno::ActionContext<SendOffer>) -> Result<(), steno::ActionError> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+
+    run_step(context, "send", async {
+        let db = &context.db;
+        let company = Company::get_by_id(db, params.company_id)?;
+        let mut applicant = Applicant::get_by_id(db, params.applicant_id)?;
+
+        let ds = company.authenticate_docusign(db).await?;
+        ds.send_envelope(&applicant.docusign_envelope_id).await?;
+
+        // The offer is out, from here on nothing should fail the step. If it did, the database
+        // step would be undone and the next sync would send them a second offer.
+        applicant.docusign_envelope_status = "sent".to_string();
+        for attempt in 1..=3 {
+            match applicant.update(db).await {
+                Ok(_) => break,
+                Err(e) => {
+                    warn!(
+                        "saving the sent offer for applicant `{}` failed, attempt {} of 3: {}",
+                        applicant.email, attempt, e
+                    );
+                    tokio::time::sleep(std::time::Duration::from_secs(2 * attempt)).await;
+                }
+            }
+        }
+
+        // A failed notification should not undo saving it either.
+        if let Err(e) = applicant
+            .send_slack_notification_docusign_offer_status_changed(db, &company)
+            .await
+        {
+            warn!(
+                "sending the slack notification for the offer of applicant `{}` failed: {}",
+                applicant.email, e
+            );
+        }
+
+        Ok(())
+    })
+    .await
+}
+
 impl Applicant {
     pub fn cleanup_linkedin(&mut self) {
         if self.linkedin.trim().is_empty() {
@@ -2444,88 +2613,13 @@ Sincerely,
                 self.name
             );
             // We haven't sent their offer yet, so let's do that.
-            // Let's create a new envelope for the user.
-            let mut new_envelope: docusign::Envelope = Default::default();
-
-            // Sent the status to `sent` so it sends.
-            // To save it as a draft set the status as `created`.
-            new_envelope.status = "sent".to_string();
-
-            // Set the email subject.
-            new_envelope.email_subject = DOCUSIGN_OFFER_SUBJECT.to_string();
-
-            // Set the template id to that of our template.
-            new_envelope.template_id = template_id.to_string();
-
-            // Set the recipients of the template.
-            // The first recipient needs to be the CEO (or whoever is going to do the mad lib for
-            // the offer.
-            // The second recipient needs to be the Applicant.
-            new_envelope.template_roles = vec![
-                docusign::TemplateRole {
-                    name: "Steve Tuck".to_string(),
-                    role_name: "CEO".to_string(),
-                    email: format!("steve@{}", company.gsuite_domain),
-                    signer_name: "Steve Tuck".to_string(),
-                    routing_order: "1".to_string(),
-                    // Make Steve's email notification different than the actual applicant.
-                    email_notification: docusign::EmailNotification {
-                        email_subject: format!("Complete the offer letter for {}", self.name),
-                        email_body: format!(
-                            "The status for the applicant, {}, has been changed to `Giving \
-                             offer`. Therefore, we are sending you an offer letter to complete, \
-                             as Jess calls, the 'Mad Libs'. GO COMPLETE THE MAD LIBS! After you \
-                             finish, we will send the offer letter to {} at {} to sign and date! \
-                             Thanks!",
-                            self.name, self.name, self.email
-                        ),
-                        language: Default::default(),
-                    },
-                },
-                docusign::TemplateRole {
-                    name: self.name.to_string(),
-                    role_name: "Applicant".to_string(),
-                    email: self.email.to_string(),
-                    signer_name: self.name.to_string(),
-                    routing_order: "2".to_string(),
-                    email_notification: docusign::EmailNotification {
-                        email_subject: DOCUSIGN_OFFER_SUBJECT.to_string(),
-                        email_body: "We are very excited to offer you a position at the Oxide \
-                                     Computer Company!"
-                            .to_string(),
-                        language: Default::default(),
-                    },
-                },
-                docusign::TemplateRole {
-                    name: "Ruth Alexander".to_string(),
-                    role_name: "HR".to_string(),
-                    email: "ruth@mindsharegroup.com".to_string(),
-                    signer_name: "Ruth Alexander".to_string(),
-                    routing_order: "3".to_string(),
-                    email_notification: docusign::EmailNotification {
-                        email_subject: "Oxide Computer Company Offer Letter Signed".to_string(),
-                        email_body: "Attached is a newly signed offer letter, please set up \
-                                     benefits. Thank you!"
-                            .to_string(),
-                        language: Default::default(),
-                    },
-                },
-            ];
-
-            // Let's create the envelope.
-            let envelope = ds.create_envelope(new_envelope.clone()).await?;
-
-            // Set the id of the envelope.
-            self.docusign_envelope_id = envelope.envelope_id.to_string();
-            // Set the status of the envelope.
-            self.docusign_envelope_status = envelope.status.to_string();
-
-            // Update the applicant in the database.
+            // Save what we have so far, sending the offer works from the database.
             self.update(db).await?;
 
-            // Send a slack notification that the docusign status changed.
-            self.send_slack_notification_docusign_offer_status_changed(db, company)
-                .await?;
+            send_offer(db, company, self.id, template_id).await?;
+
+            // Get the envelope the offer was sent in.
+            *self = Applicant::get_by_id(db, self.id)?;
         } else if !self.docusign_envelope_id.is_empty() {
             // We have sent their offer.
             // Let's get the status of the envelope in Docusign.
@@ -2538,6 +2632,78 @@ Sincerely,
         Ok(())
     }
 
+    /// Returns the envelope for the offer of the applicant, as a draft.
+    fn offer_envelope(&self, template_id: &str, company: &Company) -> docusign::Envelope {
+        // Let's create a new envelope for the user.
+        let mut new_envelope: docusign::Envelope = Default::default();
+
+        // Save it as a draft, it is sent once we saved it.
+        new_envelope.status = "created".to_string();
+
+        // Set the email subject.
+        new_envelope.email_subject = DOCUSIGN_OFFER_SUBJECT.to_string();
+
+        // Set the template id to that of our template.
+        new_envelope.template_id = template_id.to_string();
+
+        // Set the recipients of the template.
+        // The first recipient needs to be the CEO (or whoever is going to do the mad lib for
+        // the offer.
+        // The second recipient needs to be the Applicant.
+        new_envelope.template_roles = vec![
+            docusign::TemplateRole {
+                name: "Steve Tuck".to_string(),
+                role_name: "CEO".to_string(),
+                email: format!("steve@{}", company.gsuite_domain),
+                signer_name: "Steve Tuck".to_string(),
+                routing_order: "1".to_string(),
+                // Make Steve's email notification different than the actual applicant.
+                email_notification: docusign::EmailNotification {
+                    email_subject: format!("Complete the offer letter for {}", self.name),
+                    email_body: format!(
+                        "The status for the applicant, {}, has been changed to `Giving \
+                         offer`. Therefore, we are sending you an offer letter to complete, \
+                         as Jess calls, the 'Mad Libs'. GO COMPLETE THE MAD LIBS! After you \
+                         finish, we will send the offer letter to {} at {} to sign and date! \
+                         Thanks!",
+                        self.name, self.name, self.email
+                    ),
+                    language: Default::default(),
+                },
+            },
+            docusign::TemplateRole {
+                name: self.name.to_string(),
+                role_name: "Applicant".to_string(),
+                email: self.email.to_string(),
+                signer_name: self.name.to_string(),
+                routing_order: "2".to_string(),
+                email_notification: docusign::EmailNotification {
+                    email_subject: DOCUSIGN_OFFER_SUBJECT.to_string(),
+                    email_body: "We are very excited to offer you a position at the Oxide \
+                                 Computer Company!"
+                        .to_string(),
+                    language: Default::default(),
+                },
+            },
+            docusign::TemplateRole {
+                name: "Ruth Alexander".to_string(),
+                role_name: "HR".to_string(),
+                email: "ruth@mindsharegroup.com".to_string(),
+                signer_name: "Ruth Alexander".to_string(),
+                routing_order: "3".to_string(),
+                email_notification: docusign::EmailNotification {
+                    email_subject: "Oxide Computer Company Offer Letter Signed".to_string(),
+                    email_body: "Attached is a newly signed offer letter, please set up \
+                                 benefits. Thank you!"
+                        .to_string(),
+                    language: Default::default(),
+                },
+            },
+        ];
+
+        new_envelope
+    }
+
     pub async fn update_applicant_from_docusign_offer_envelope(
         &mut self,
         db: &Database,
@@ -2643,7 +2809,7 @@ Sincerely,
                     .eq(self.email.to_string())
                     .and(users::dsl::cio_company_id.eq(company.id)),
             )
-            .first::<User>(&db.conn());
+            .first::<User>(&db.conn()?);
         if result.is_ok() {
             let mut employee = result?;
             // Only do this if we don't have the employee's home address or start date.
@@ -3006,7 +3172,7 @@ pub async fn refresh_new_applicants_and_reviews(db: &Database, company: &Company
     // about. Everything else came from Google Sheets and therefore uses the old system.
     let applicants = applicants::dsl::applicants
         .filter(applicants::dsl::sheet_id.eq("".to_string()))
-        .load::<Applicant>(&db.conn())?;
+        .load::<Applicant>(&db.conn()?)?;
 
     // Iterate over the applicants and update them.
     for mut applicant in applicants {
@@ -3034,7 +3200,7 @@ pub async fn refresh_new_applicants_and_reviews(db: &Database, company: &Company
             .await?;
 
         // Update the interviews start and end time if we have interviews.
-        applicant.update_interviews_start_end_time(db);
+        applicant.update_interviews_start_end_time(db)?;
 
         // Update airtable and the database again, we want to save our status just in
         // case there is an error.
@@ -3066,10 +3232,10 @@ mod tests {
     fn test_serialize_deserialize_applicants() {
         crate::utils::setup_logger();
 
-        let db = Database::new();
+        let db = Database::new().unwrap();
         let applicant = applicants::dsl::applicants
             .filter(applicants::dsl::id.eq(318))
-            .first::<Applicant>(&db.conn())
+            .first::<Applicant>(&db.conn().unwrap())
             .unwrap();
 
         // Let's test that serializing this is going to give us an array of Airtable users.
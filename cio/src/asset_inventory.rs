@@ -99,7 +99,7 @@ pub struct NewAssetItem {
 /// Implement updating the Airtable record for a AssetItem.
 #[async_trait]
 impl UpdateAirtableRecord<AssetItem> for AssetItem {
-    async fn update_airtable_record(&mut self, _record: AssetItem) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, _record: AssetItem) -> Result<()> {
         Ok(())
     }
 }
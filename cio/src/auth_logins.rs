@@ -81,7 +81,7 @@ pub struct NewAuthUser {
 /// Implement updating the Airtable record for a AuthUser.
 #[async_trait]
 impl UpdateAirtableRecord<AuthUser> for AuthUser {
-    async fn update_airtable_record(&mut self, record: AuthUser) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, record: AuthUser) -> Result<()> {
         // Set the link_to_people and link_to_auth_user_logins from the original so it stays intact.
         self.link_to_people = record.link_to_people.clone();
         self.link_to_auth_user_logins = record.link_to_auth_user_logins;
@@ -163,11 +163,10 @@ pub struct NewAuthUserLogin {
 /// Implement updating the Airtable record for a AuthUserLogin.
 #[async_trait]
 impl UpdateAirtableRecord<AuthUserLogin> for AuthUserLogin {
-    async fn update_airtable_record(&mut self, _record: AuthUserLogin) -> Result<()> {
+    async fn update_airtable_record(&mut self, db: &Database, _record: AuthUserLogin) -> Result<()> {
         // Get the current auth users in Airtable so we can link to it.
         // TODO: make this more dry so we do not call it every single damn time.
-        let db = Database::new();
-        let auth_users = AuthUsers::get_from_airtable(&db, self.cio_company_id).await?;
+        let auth_users = AuthUsers::get_from_airtable(db, self.cio_company_id).await?;
 
         // Iterate over the auth_users and see if we find a match.
         for (_id, auth_user_record) in auth_users {
@@ -37,6 +37,7 @@ use crate::{
     new_struct_name = "Certificate",
     airtable_base = "misc",
     airtable_table = "AIRTABLE_CERTIFICATES_TABLE",
+    history = true,
     match_on = {
         "cio_company_id" = "i32",
         "domain" = "String",
@@ -469,7 +470,7 @@ impl NewCertificate {
 /// Implement updating the Airtable record for a Certificate.
 #[async_trait]
 impl UpdateAirtableRecord<Certificate> for Certificate {
-    async fn update_airtable_record(&mut self, _record: Certificate) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, _record: Certificate) -> Result<()> {
         Ok(())
     }
 }
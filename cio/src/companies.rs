@@ -40,6 +40,8 @@ use crate::{
     configs::{Building, Buildings},
     core::UpdateAirtableRecord,
     db::Database,
+    error::CioError,
+    metrics::ApiCallMetrics,
     schema::{api_tokens, companys},
 };
 
@@ -148,7 +150,7 @@ pub struct NewCompany {
 /// Implement updating the Airtable record for a Company.
 #[async_trait]
 impl UpdateAirtableRecord<Company> for Company {
-    async fn update_airtable_record(&mut self, _record: Company) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, _record: Company) -> Result<()> {
         Ok(())
     }
 }
@@ -185,7 +187,7 @@ impl Company {
         // Create the Slack client.
         let r = self.authenticate_slack(db);
         if let Err(e) = r {
-            if e.to_string().contains("no token") {
+            if CioError::is_not_connected(&e) {
                 // Return early, this company does not use Slack.
                 return Ok(());
             }
@@ -216,7 +218,7 @@ impl Company {
                     .eq(team_id.to_string())
                     .and(api_tokens::dsl::product.eq("slack".to_lowercase())),
             )
-            .first::<APIToken>(&db.conn())?;
+            .first::<APIToken>(&db.conn()?)?;
 
         // Now we can get the company.
         Company::get_by_id(db, token.auth_company_id)
@@ -229,7 +231,7 @@ impl Company {
                     .eq(org.to_string())
                     .or(companys::dsl::github_org.eq(org.to_lowercase())),
             )
-            .first::<Company>(&db.conn())?)
+            .first::<Company>(&db.conn()?)?)
     }
 
     pub fn get_from_shipbob_channel_id(db: &Database, channel_id: &str) -> Result<Self> {
@@ -239,7 +241,7 @@ impl Company {
                     .eq(channel_id.to_string())
                     .and(api_tokens::dsl::product.eq("shipbob".to_string())),
             )
-            .first::<APIToken>(&db.conn())?;
+            .first::<APIToken>(&db.conn()?)?;
 
         Company::get_by_id(db, token.auth_company_id)
     }
@@ -247,7 +249,7 @@ impl Company {
     pub fn get_from_mailchimp_list_id(db: &Database, list_id: &str) -> Result<Self> {
         Ok(companys::dsl::companys
             .filter(companys::dsl::mailchimp_list_id.eq(list_id.to_string()))
-            .first::<Company>(&db.conn())?)
+            .first::<Company>(&db.conn()?)?)
     }
 
     pub fn get_from_domain(db: &Database, domain: &str) -> Result<Self> {
@@ -257,14 +259,14 @@ impl Company {
                     .eq(domain.to_string())
                     .or(companys::dsl::gsuite_domain.eq(domain.to_string())),
             )
-            .first::<Company>(&db.conn())?)
+            .first::<Company>(&db.conn()?)?)
     }
 
     /// Authenticate with Cloudflare.
     pub fn authenticate_cloudflare(&self) -> Result<CloudflareClient> {
         if self.cloudflare_api_key.is_empty() || self.gsuite_subject.is_empty() {
             // Return early.
-            bail!("no token");
+            return Err(CioError::NotConnected("cloudflare".to_string()).into());
         }
 
         // Create the Cloudflare client.
@@ -282,7 +284,7 @@ impl Company {
             // Return early.
             return None;
         }
-        Some(Checkr::new(&self.checkr_api_key))
+        Some(Checkr::new(&self.checkr_api_key).with_middleware(ApiCallMetrics::new("checkr")))
     }
 
     /// Authenticate with Okta.
@@ -308,7 +310,15 @@ impl Company {
 
     /// Authenticate with Airtable.
     pub fn authenticate_airtable(&self, base_id: &str) -> Airtable {
-        Airtable::new(&self.airtable_api_key, base_id, &self.airtable_enterprise_account_id)
+        let airtable = Airtable::new(&self.airtable_api_key, base_id, &self.airtable_enterprise_account_id)
+            .with_middleware(ApiCallMetrics::new("airtable"));
+
+        // Point at a different endpoint if we were told to, this is how the tests talk to
+        // the local stand-in.
+        match env::var("AIRTABLE_ENDPOINT") {
+            Ok(endpoint) if !endpoint.is_empty() => airtable.with_endpoint(endpoint),
+            _ => airtable,
+        }
     }
 
     /// Authenticate with ShipBob.
@@ -345,14 +355,14 @@ impl Company {
             return Ok(shipbob);
         }
 
-        bail!("no token");
+        Err(CioError::NotConnected("shipbob".to_string()).into())
     }
 
     /// Ensure the company has ShipBob webhooks setup.
     pub async fn ensure_shipbob_webhooks(&self, db: &Database) -> Result<()> {
         let shipbob_auth = self.authenticate_shipbob(db).await;
         if let Err(e) = shipbob_auth {
-            if e.to_string().contains("no token") {
+            if CioError::is_not_connected(&e) {
                 // Return early, they don't use ShipBob.
                 return Ok(());
             }
@@ -420,7 +430,8 @@ impl Company {
                 t.access_token.to_string(),
                 t.refresh_token.to_string(),
                 t.endpoint.to_string(),
-            );
+            )
+            .with_middleware(ApiCallMetrics::new("mailchimp"));
 
             // MailChimp does not give you a refresh token so we should never refresh.
             // But just in case in the future they do, we will leave this here.
@@ -448,7 +459,7 @@ impl Company {
             return Ok(mailchimp);
         }
 
-        bail!("no token");
+        Err(CioError::NotConnected("mailchimp".to_string()).into())
     }
 
     /// Authenticate with Slack.
@@ -461,7 +472,7 @@ impl Company {
                     .and(api_tokens::dsl::product.eq("slack".to_string()))
                     .and(api_tokens::dsl::token_type.eq("bot".to_string())),
             )
-            .first::<APIToken>(&db.conn())
+            .first::<APIToken>(&db.conn()?)
         {
             if let Ok(user_token) = api_tokens::dsl::api_tokens
                 .filter(
@@ -470,14 +481,15 @@ impl Company {
                         .and(api_tokens::dsl::product.eq("slack".to_string()))
                         .and(api_tokens::dsl::token_type.eq("user".to_string())),
                 )
-                .first::<APIToken>(&db.conn())
+                .first::<APIToken>(&db.conn()?)
             {
                 // Initialize the Slack client.
                 let slack = Slack::new_from_env(
                     bot_token.company_id.to_string(),
                     bot_token.access_token,
                     user_token.access_token,
-                );
+                )
+                .with_middleware(ApiCallMetrics::new("slack"));
                 // Slack does not give you refresh tokens.
                 // So we don't need to do any song and dance to refresh.
 
@@ -485,7 +497,7 @@ impl Company {
             }
         }
 
-        bail!("no token");
+        Err(CioError::NotConnected("slack".to_string()).into())
     }
 
     /// Authenticate with Ramp.
@@ -519,7 +531,7 @@ impl Company {
             return Ok(ramp);
         }
 
-        bail!("no token");
+        Err(CioError::NotConnected("ramp".to_string()).into())
     }
 
     /// Authenticate with Zoom.
@@ -553,7 +565,7 @@ impl Company {
             return Ok(zoom);
         }
 
-        bail!("no token");
+        Err(CioError::NotConnected("zoom".to_string()).into())
     }
 
     /// Authenticate with DocuSign.
@@ -566,7 +578,8 @@ impl Company {
                 t.refresh_token.to_string(),
                 t.company_id.to_string(),
                 t.endpoint.to_string(),
-            );
+            )
+            .with_middleware(ApiCallMetrics::new("docusign"));
 
             if t.is_expired() {
                 // Only refresh the token if it is expired.
@@ -592,7 +605,7 @@ impl Company {
             return Ok(ds);
         }
 
-        bail!("no token");
+        Err(CioError::NotConnected("docusign".to_string()).into())
     }
 
     /// Authenticate with Gusto.
@@ -626,19 +639,19 @@ impl Company {
             return Ok((gusto, t.company_id.to_string()));
         }
 
-        bail!("no token");
+        Err(CioError::NotConnected("gusto".to_string()).into())
     }
 
     /// Authenticate with Tailscale.
     pub fn authenticate_tailscale(&self) -> Tailscale {
-        Tailscale::new(&self.tailscale_api_key, &self.gsuite_domain)
+        Tailscale::new(&self.tailscale_api_key, &self.gsuite_domain).with_middleware(ApiCallMetrics::new("tailscale"))
     }
 
     /// Authenticate with TripActions.
     pub async fn authenticate_tripactions(&self, db: &Database) -> Result<TripActions> {
         if self.tripactions_client_id.is_empty() || self.tripactions_client_secret.is_empty() {
             // bail early we don't have a token.
-            bail!("no token");
+            return Err(CioError::NotConnected("tripactions".to_string()).into());
         }
 
         // Get the APIToken from the database.
@@ -716,7 +729,8 @@ impl Company {
                 t.company_id.to_string(),
                 t.access_token.to_string(),
                 t.refresh_token.to_string(),
-            );
+            )
+            .with_middleware(ApiCallMetrics::new("quickbooks"));
 
             if t.is_expired() {
                 // Only refresh the token if it is expired.
@@ -742,7 +756,7 @@ impl Company {
             return Ok(qb);
         }
 
-        bail!("no token");
+        Err(CioError::NotConnected("quickbooks".to_string()).into())
     }
 
     /// Authenticate Google Admin.
@@ -776,7 +790,7 @@ impl Company {
             return Ok(g);
         }
 
-        bail!("no token");
+        Err(CioError::NotConnected("google_admin".to_string()).into())
     }
 
     /// Authenticate Google Calendar.
@@ -810,7 +824,7 @@ impl Company {
             return Ok(g);
         }
 
-        bail!("no token");
+        Err(CioError::NotConnected("google_calendar".to_string()).into())
     }
 
     /// Authenticate Google Calendar with Service Account.
@@ -854,7 +868,7 @@ impl Company {
             return Ok(g);
         }
 
-        bail!("no token");
+        Err(CioError::NotConnected("google_drive".to_string()).into())
     }
 
     /// Authenticate Google Drive with Service Account.
@@ -946,7 +960,7 @@ impl Company {
             return Ok(g);
         }
 
-        bail!("no token");
+        Err(CioError::NotConnected("google_sheets".to_string()).into())
     }
 
     /// Authenticate Google Groups Settings.
@@ -980,7 +994,7 @@ impl Company {
             return Ok(g);
         }
 
-        bail!("no token");
+        Err(CioError::NotConnected("google_groups_settings".to_string()).into())
     }
 
     /// Authenticate GitHub with JSON web token credentials, for an application installation.
@@ -1016,11 +1030,9 @@ impl Company {
     }
 }
 
-pub async fn refresh_companies() -> Result<()> {
-    let db = Database::new();
-
+pub async fn refresh_companies(db: &Database) -> Result<()> {
     // This should forever only be Oxide.
-    let oxide = Company::get_from_db(&db, "Oxide".to_string()).unwrap();
+    let oxide = Company::get_from_db(db, "Oxide".to_string()).unwrap();
 
     let is: Vec<airtable_api::Record<Company>> = oxide
         .authenticate_airtable(&oxide.airtable_base_id_cio)
@@ -1035,15 +1047,15 @@ pub async fn refresh_companies() -> Result<()> {
 
         let new_company: NewCompany = record.fields.into();
 
-        let mut company = new_company.upsert_in_db(&db)?;
+        let mut company = new_company.upsert_in_db(db)?;
         if company.airtable_record_id.is_empty() {
             company.airtable_record_id = record.id;
         }
         company.cio_company_id = oxide.id;
-        company.update(&db).await?;
+        company.update(db).await?;
     }
     // Companies are only stored with Oxide.
-    Companys::get_from_db(&db, 1)?.update_airtable(&db).await?;
+    Companys::get_from_db(db, 1)?.update_airtable(db).await?;
 
     Ok(())
 }
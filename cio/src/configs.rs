@@ -2,6 +2,7 @@
 use std::{
     collections::{BTreeMap, HashMap},
     str::from_utf8,
+    sync::Arc,
 };
 
 use anyhow::{bail, Result};
@@ -30,8 +31,10 @@ use crate::{
     companies::Company,
     core::UpdateAirtableRecord,
     db::Database,
+    error::CioError,
     gsuite::{update_gsuite_building, update_gsuite_calendar_resource},
     providers::ProviderOps,
+    sagas::{run_optional_step, run_saga, run_step, undo_nothing, undo_step, SagaContext, StepSaga},
     schema::{applicants, buildings, conference_rooms, groups, links, users},
     shipments::NewOutboundShipment,
     utils::{get_file_content_from_repo, get_github_user_public_ssh_keys},
@@ -69,6 +72,7 @@ pub struct Config {
     new_struct_name = "User",
     airtable_base = "directory",
     airtable_table = "AIRTABLE_EMPLOYEES_TABLE",
+    history = true,
     match_on = {
         "cio_company_id" = "i32",
         "username" = "String",
@@ -210,6 +214,11 @@ pub struct UserConfig {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub working_on: Vec<String>,
 
+    /// The following field does not exist in the config files, it is the names of the
+    /// RFDs the user is an author of and is set when the RFDs are synced.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rfds_authored: Vec<String>,
+
     /// The CIO company ID.
     #[serde(default)]
     pub cio_company_id: i32,
@@ -487,12 +496,12 @@ impl UserConfig {
         self.work_address_formatted = self.work_address_formatted.replace('\n', "\\n");
     }
 
-    pub fn populate_start_date(&mut self, db: &Database) {
+    pub fn populate_start_date(&mut self, db: &Database) -> Result<()> {
         // Only populate the start date, if we could not update it from Gusto.
         if self.start_date == crate::utils::default_date() {
             if let Ok(a) = applicants::dsl::applicants
                 .filter(applicants::dsl::email.eq(self.recovery_email.to_string()))
-                .first::<Applicant>(&db.conn())
+                .first::<Applicant>(&db.conn()?)
             {
                 // Get their start date.
                 if a.start_date.is_some() {
@@ -500,6 +509,8 @@ impl UserConfig {
                 }
             }
         }
+
+        Ok(())
     }
 
     pub fn populate_type(&mut self) {
@@ -565,7 +576,7 @@ impl UserConfig {
         self.populate_home_address().await?;
         self.populate_work_address(db).await;
 
-        self.populate_start_date(db);
+        self.populate_start_date(db)?;
 
         // Create the link to the manager.
         if !self.manager.is_empty() {
@@ -924,11 +935,10 @@ xoxo,
 /// Implement updating the Airtable record for a User.
 #[async_trait]
 impl UpdateAirtableRecord<User> for User {
-    async fn update_airtable_record(&mut self, record: User) -> Result<()> {
+    async fn update_airtable_record(&mut self, db: &Database, record: User) -> Result<()> {
         // Get the current groups in Airtable so we can link to them.
         // TODO: make this more dry so we do not call it every single damn time.
-        let db = Database::new();
-        let groups = Groups::get_from_airtable(&db, self.cio_company_id).await?;
+        let groups = Groups::get_from_airtable(db, self.cio_company_id).await?;
 
         let mut links: Vec<String> = Default::default();
         // Iterate over the group names in our record and match it against the
@@ -1141,7 +1151,7 @@ impl GroupConfig {
 /// Implement updating the Airtable record for a Group.
 #[async_trait]
 impl UpdateAirtableRecord<Group> for Group {
-    async fn update_airtable_record(&mut self, record: Group) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, record: Group) -> Result<()> {
         // Make sure we don't mess with the members since that is populated by the Users table.
         self.members = record.members;
         Ok(())
@@ -1208,7 +1218,7 @@ impl BuildingConfig {
 /// Implement updating the Airtable record for a Building.
 #[async_trait]
 impl UpdateAirtableRecord<Building> for Building {
-    async fn update_airtable_record(&mut self, record: Building) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, record: Building) -> Result<()> {
         // Make sure we don't mess with the employees since that is populated by the Users table.
         self.employees = record.employees.clone();
         // Make sure we don't mess with the conference_rooms since that is populated by the Conference Rooms table.
@@ -1256,12 +1266,11 @@ pub struct ResourceConfig {
 /// Implement updating the Airtable record for a ConferenceRoom.
 #[async_trait]
 impl UpdateAirtableRecord<ConferenceRoom> for ConferenceRoom {
-    async fn update_airtable_record(&mut self, _record: ConferenceRoom) -> Result<()> {
+    async fn update_airtable_record(&mut self, db: &Database, _record: ConferenceRoom) -> Result<()> {
         // Set the building to right building link.
         // Get the current buildings in Airtable so we can link to it.
         // TODO: make this more dry so we do not call it every single damn time.
-        let db = Database::new();
-        let buildings = Buildings::get_from_airtable(&db, self.cio_company_id).await?;
+        let buildings = Buildings::get_from_airtable(db, self.cio_company_id).await?;
         // Iterate over the buildings to get the ID.
         for building in buildings.values() {
             if self.building == building.fields.name {
@@ -1307,7 +1316,7 @@ pub struct LinkConfig {
 /// Implement updating the Airtable record for a Link.
 #[async_trait]
 impl UpdateAirtableRecord<Link> for Link {
-    async fn update_airtable_record(&mut self, _record: Link) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, _record: Link) -> Result<()> {
         Ok(())
     }
 }
@@ -1648,6 +1657,7 @@ pub async fn sync_users(
         // Update or create the user in the database.
         if let Some(e) = existing.clone() {
             user.google_anniversary_event_id = e.google_anniversary_event_id.to_string();
+            user.rfds_authored = e.rfds_authored;
         }
 
         // See if we have a gsuite user for the user.
@@ -1721,6 +1731,16 @@ pub async fn sync_users(
         // Expand the user.
         user.expand(db, company).await?;
 
+        if existing.is_none() {
+            // This is a new user, onboard them one service at a time. If one of the services
+            // fails the ones before it are undone and the next sync starts over.
+            if let Err(e) = onboard_user(db, company, &user).await {
+                warn!("onboarding user `{}` failed: {}", user.username, e);
+            }
+
+            continue;
+        }
+
         let mut new_user = user.upsert(db).await?;
 
         if let Some(ref okta) = okta_auth {
@@ -1849,6 +1869,292 @@ pub async fn sync_users(
     Ok(())
 }
 
+/// Onboarding a new user, with a step for each service they get an account on.
+#[derive(Debug)]
+pub struct OnboardUser;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OnboardUserParams {
+    company_id: i32,
+    username: String,
+}
+
+impl steno::SagaType for OnboardUser {
+    type SagaParamsType = OnboardUserParams;
+    type ExecContextType = Arc<SagaContext>;
+}
+
+/// Onboard a new user. If it fails, what was done for them is undone.
+pub async fn onboard_user(db: &Database, company: &Company, user: &UserConfig) -> Result<()> {
+    // Save them first, the steps work from what is in the database.
+    user.upsert(db).await?;
+
+    run_saga::<OnboardUser>(
+        db,
+        &uuid::Uuid::new_v4(),
+        OnboardUserParams {
+            company_id: company.id,
+            username: user.username.to_string(),
+        },
+    )
+    .await
+}
+
+impl StepSaga for OnboardUser {
+    const NAME: &'static str = "onboard-user";
+    const STEPS: &'static [&'static str] = &["database", "gsuite", "okta", "github", "zoom", "ramp", "airtable"];
+
+    /// The saga for onboarding a user, with what undoes each step.
+    fn template() -> steno::SagaTemplate<OnboardUser> {
+        let mut builder = steno::SagaTemplateBuilder::new();
+        builder.append(
+            "database",
+            "database",
+            steno::ActionFunc::new_action(action_onboard_database, undo_onboard_database),
+        );
+        builder.append(
+            "gsuite",
+            "gsuite",
+            steno::ActionFunc::new_action(action_onboard_gsuite, undo_nothing::<OnboardUser>),
+        );
+        builder.append(
+            "okta",
+            "okta",
+            steno::ActionFunc::new_action(action_onboard_okta, undo_nothing::<OnboardUser>),
+        );
+        builder.append(
+            "github",
+            "github",
+            steno::ActionFunc::new_action(action_onboard_github, undo_onboard_github),
+        );
+        builder.append(
+            "zoom",
+            "zoom",
+            steno::ActionFunc::new_action(action_onboard_zoom, undo_onboard_zoom),
+        );
+        builder.append(
+            "ramp",
+            "ramp",
+            steno::ActionFunc::new_action(action_onboard_ramp, undo_nothing::<OnboardUser>),
+        );
+        builder.append(
+            "airtable",
+            "airtable",
+            steno::ActionFunc::new_action(action_onboard_airtable, undo_nothing::<OnboardUser>),
+        );
+
+        builder.build()
+    }
+}
+
+/// Get the user being onboarded from the database, with their company.
+fn get_onboarding_user(db: &Database, params: &OnboardUserParams) -> Result<(Company, User)> {
+    let company = Company::get_by_id(db, params.company_id)?;
+    match User::get_from_db(db, company.id, params.username.to_string()) {
+        Some(user) => Ok((company, user)),
+        None => bail!("user `{}` is not in the database", params.username),
+    }
+}
+
+async fn action_onboard_database(action_context: steno::ActionContext<OnboardUser>) -> Result<i32, steno::ActionError> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+
+    // The user was saved before the saga started, this step is here to take them out again
+    // if onboarding them fails.
+    run_step(context, "database", async {
+        let (_, user) = get_onboarding_user(&context.db, params)?;
+        Ok(user.id)
+    })
+    .await
+}
+
+async fn undo_onboard_database(action_context: steno::ActionContext<OnboardUser>) -> Result<()> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+
+    undo_step(context, "database", async {
+        // Take them out of the database so the next sync onboards them from the start.
+        let (_, user) = get_onboarding_user(&context.db, params)?;
+        user.delete(&context.db).await
+    })
+    .await
+}
+
+// We don't undo the GSuite or Okta accounts, they were emailed to the user and suspending
+// them would not be undone by the next sync. Ensuring them again is a no-op.
+async fn action_onboard_gsuite(
+    action_context: steno::ActionContext<OnboardUser>,
+) -> Result<String, steno::ActionError> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+
+    run_optional_step(context, "gsuite", async {
+        let db = &context.db;
+        let (company, mut user) = get_onboarding_user(db, params)?;
+        if company.authenticate_okta().is_some() {
+            // Okta creates the GSuite account for companies that use it.
+            return Ok(None);
+        }
+
+        let gsuite = company.authenticate_google_admin(db).await?;
+        user.google_id = gsuite.ensure_user(db, &company, &user).await?;
+        user.update(db).await?;
+
+        Ok(Some(user.google_id))
+    })
+    .await
+}
+
+async fn action_onboard_okta(action_context: steno::ActionContext<OnboardUser>) -> Result<String, steno::ActionError> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+
+    run_optional_step(context, "okta", async {
+        let db = &context.db;
+        let (company, mut user) = get_onboarding_user(db, params)?;
+        let okta = match company.authenticate_okta() {
+            Some(okta) => okta,
+            None => return Ok(None),
+        };
+
+        user.okta_id = okta.ensure_user(db, &company, &user).await?;
+        user.update(db).await?;
+
+        Ok(Some(user.okta_id))
+    })
+    .await
+}
+
+async fn action_onboard_github(
+    action_context: steno::ActionContext<OnboardUser>,
+) -> Result<String, steno::ActionError> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+
+    run_optional_step(context, "github", async {
+        let db = &context.db;
+        let (company, user) = get_onboarding_user(db, params)?;
+        if user.github.is_empty() {
+            return Ok(None);
+        }
+
+        // Add them to the org and any teams they need to be added to.
+        let github = company.authenticate_github()?;
+        github.ensure_user(db, &company, &user).await?;
+
+        Ok(Some(user.github))
+    })
+    .await
+}
+
+async fn undo_onboard_github(action_context: steno::ActionContext<OnboardUser>) -> Result<()> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+
+    undo_step(context, "github", async {
+        let db = &context.db;
+        let (company, user) = get_onboarding_user(db, params)?;
+
+        // Take them out of the org again, the next sync invites them back.
+        let github = company.authenticate_github()?;
+        github.delete_user(db, &company, &user).await
+    })
+    .await
+}
+
+async fn action_onboard_zoom(action_context: steno::ActionContext<OnboardUser>) -> Result<String, steno::ActionError> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+
+    run_optional_step(context, "zoom", async {
+        let db = &context.db;
+        let (company, mut user) = get_onboarding_user(db, params)?;
+        if company.authenticate_okta().is_some() {
+            // Okta manages the Zoom account for companies that use it.
+            return Ok(None);
+        }
+
+        let zoom = match company.authenticate_zoom(db).await {
+            Ok(zoom) => zoom,
+            // The company does not use Zoom.
+            Err(e) if CioError::is_not_connected(&e) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        user.zoom_id = zoom.ensure_user(db, &company, &user).await?;
+        user.update(db).await?;
+
+        Ok(Some(user.zoom_id))
+    })
+    .await
+}
+
+async fn undo_onboard_zoom(action_context: steno::ActionContext<OnboardUser>) -> Result<()> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+
+    undo_step(context, "zoom", async {
+        let db = &context.db;
+        let (company, mut user) = get_onboarding_user(db, params)?;
+        if user.zoom_id.is_empty() {
+            return Ok(());
+        }
+
+        let zoom = company.authenticate_zoom(db).await?;
+        zoom.delete_user(db, &company, &user).await?;
+
+        user.zoom_id = Default::default();
+        user.update(db).await?;
+
+        Ok(())
+    })
+    .await
+}
+
+// We don't undo the Ramp user, Ramp doesn't let us remove them.
+async fn action_onboard_ramp(action_context: steno::ActionContext<OnboardUser>) -> Result<String, steno::ActionError> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+
+    run_optional_step(context, "ramp", async {
+        let db = &context.db;
+        let (company, mut user) = get_onboarding_user(db, params)?;
+        let ramp = match company.authenticate_ramp(db).await {
+            Ok(ramp) => ramp,
+            // The company does not use Ramp.
+            Err(e) if CioError::is_not_connected(&e) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        user.ramp_id = ramp.ensure_user(db, &company, &user).await?;
+        user.update(db).await?;
+
+        Ok(Some(user.ramp_id))
+    })
+    .await
+}
+
+async fn action_onboard_airtable(
+    action_context: steno::ActionContext<OnboardUser>,
+) -> Result<String, steno::ActionError> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+
+    run_step(context, "airtable", async {
+        let db = &context.db;
+        let (company, mut user) = get_onboarding_user(db, params)?;
+
+        // We don't need a base id here since we are only using the enterprise api features.
+        let airtable = company.authenticate_airtable("");
+        user.airtable_id = airtable.ensure_user(db, &company, &user).await?;
+        user.update(db).await?;
+
+        Ok(user.airtable_id)
+    })
+    .await
+}
+
 /// Sync our buildings with our database and then update Airtable from the database.
 pub async fn sync_buildings(
     db: &Database,
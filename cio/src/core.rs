@@ -5,12 +5,12 @@ use chrono::{naive::NaiveDate, DateTime, Utc};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::repos::FromUrl;
+use crate::{db::Database, repos::FromUrl};
 
 /// Define the trait for doing logic in updating Airtable.
 #[async_trait]
 pub trait UpdateAirtableRecord<T> {
-    async fn update_airtable_record(&mut self, _: T) -> Result<()>;
+    async fn update_airtable_record(&mut self, db: &Database, _: T) -> Result<()>;
 }
 
 /// The data type for customer interactions.
@@ -1,30 +1,110 @@
-use std::{env, fmt, sync::Arc};
+use std::{env, fmt, str::FromStr, sync::Arc, time::Duration};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use async_trait::async_trait;
 use diesel::{pg::PgConnection, r2d2};
+use log::warn;
+
+use crate::error::CioError;
 
 #[derive(Debug, Clone)]
 pub struct Database {
     pool: DB,
+
+    /// The component making changes through this handle, this is recorded in the
+    /// history of records.
+    component: String,
 }
 
 #[derive(Clone)]
 struct DB(Arc<r2d2::Pool<r2d2::ConnectionManager<PgConnection>>>);
 
-impl Default for Database {
-    fn default() -> Self {
-        let database_url = env::var("CIO_DATABASE_URL").expect("CIO_DATABASE_URL must be set");
+/// The settings for the database connection pool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatabaseConfig {
+    pub url: String,
+    /// The maximum number of connections in the pool.
+    pub max_size: u32,
+    /// The minimum number of idle connections the pool keeps open, if `None` this is `max_size`.
+    pub min_idle: Option<u32>,
+    /// How long to wait for a connection from the pool before giving up.
+    pub connection_timeout: Duration,
+    /// How long a connection can be idle before it is closed.
+    pub idle_timeout: Option<Duration>,
+}
 
-        let manager = r2d2::ConnectionManager::new(&database_url);
-        // TODO: once we remove creating new databases for each of the cron tests, then
-        // make this max something like 100+
-        let pool = r2d2::Pool::builder().max_size(5).build(manager).unwrap();
+impl DatabaseConfig {
+    /// Read the settings from the environment:
+    ///   - `CIO_DATABASE_URL` (required)
+    ///   - `CIO_DATABASE_POOL_SIZE`, defaults to 10
+    ///   - `CIO_DATABASE_POOL_MIN_IDLE`, defaults to 1
+    ///   - `CIO_DATABASE_CONNECTION_TIMEOUT`, in seconds, defaults to 10
+    ///   - `CIO_DATABASE_IDLE_TIMEOUT`, in seconds, defaults to 600
+    pub fn from_env() -> Result<Self> {
+        let url = env::var("CIO_DATABASE_URL").map_err(|_| anyhow!("CIO_DATABASE_URL must be set"))?;
 
-        Database {
-            pool: DB(Arc::new(pool)),
+        Ok(DatabaseConfig {
+            url,
+            max_size: parse_setting("CIO_DATABASE_POOL_SIZE", env::var("CIO_DATABASE_POOL_SIZE").ok(), 10)?,
+            min_idle: Some(parse_setting(
+                "CIO_DATABASE_POOL_MIN_IDLE",
+                env::var("CIO_DATABASE_POOL_MIN_IDLE").ok(),
+                1,
+            )?),
+            connection_timeout: Duration::from_secs(parse_setting(
+                "CIO_DATABASE_CONNECTION_TIMEOUT",
+                env::var("CIO_DATABASE_CONNECTION_TIMEOUT").ok(),
+                10,
+            )?),
+            idle_timeout: Some(Duration::from_secs(parse_setting(
+                "CIO_DATABASE_IDLE_TIMEOUT",
+                env::var("CIO_DATABASE_IDLE_TIMEOUT").ok(),
+                600,
+            )?)),
+        })
+    }
+}
+
+/// Parse the value of a setting, falling back to the default if it is not set.
+fn parse_setting<T: FromStr>(name: &str, value: Option<String>, default: T) -> Result<T> {
+    Ok(parse_optional_setting(name, value)?.unwrap_or(default))
+}
+
+/// Parse the value of a setting that is optional.
+fn parse_optional_setting<T: FromStr>(name: &str, value: Option<String>) -> Result<Option<T>> {
+    match value {
+        Some(v) if !v.trim().is_empty() => match v.trim().parse() {
+            Ok(v) => Ok(Some(v)),
+            Err(_) => bail!("invalid value for {}: `{}`", name, v),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Returns the default component for a database handle, this is the `CIO_COMPONENT`
+/// environment variable if set, otherwise the binary name and subcommand,
+/// ie. `webhooky sync-rfds`.
+fn default_component() -> String {
+    if let Ok(c) = env::var("CIO_COMPONENT") {
+        return c;
+    }
+
+    let mut args = env::args();
+    let mut component = args
+        .next()
+        .and_then(|a| {
+            std::path::Path::new(&a)
+                .file_name()
+                .map(|f| f.to_string_lossy().to_string())
+        })
+        .unwrap_or_default();
+    if let Some(cmd) = args.next() {
+        if !cmd.starts_with('-') {
+            component = format!("{} {}", component, cmd);
         }
     }
+
+    component
 }
 
 // This is a workaround so we can implement Debug for PgConnection.
@@ -35,17 +115,79 @@ impl fmt::Debug for DB {
 }
 
 impl Database {
-    /// Establish a connection to the database.
-    pub fn new() -> Database {
-        Default::default()
+    /// Establish a connection to the database, with the pool configured from the environment.
+    pub fn new() -> Result<Database> {
+        Database::with_config(&DatabaseConfig::from_env()?)
+    }
+
+    /// Establish a connection to the database with the given pool settings.
+    pub fn with_config(config: &DatabaseConfig) -> Result<Database> {
+        let manager = r2d2::ConnectionManager::new(&config.url);
+        let pool = r2d2::Pool::builder()
+            .max_size(config.max_size)
+            .min_idle(config.min_idle)
+            .connection_timeout(config.connection_timeout)
+            .idle_timeout(config.idle_timeout)
+            .build(manager)
+            .map_err(|e| anyhow!("creating the database connection pool failed: {}", e))?;
+
+        Ok(Database {
+            pool: DB(Arc::new(pool)),
+            component: default_component(),
+        })
+    }
+
+    /// A handle that never connects to a database, for tests of code that takes a
+    /// `Database` but does not use it. Getting a connection from it fails.
+    #[cfg(test)]
+    pub fn unconnected() -> Database {
+        let manager = r2d2::ConnectionManager::new("postgres://127.0.0.1:1/unconnected");
+        let pool = r2d2::Pool::builder()
+            .max_size(1)
+            .min_idle(Some(0))
+            .connection_timeout(Duration::from_millis(10))
+            .build_unchecked(manager);
+
+        Database {
+            pool: DB(Arc::new(pool)),
+            component: "test".to_string(),
+        }
+    }
+
+    /// Set the component making changes through this handle.
+    pub fn with_component(mut self, component: &str) -> Database {
+        self.component = component.to_string();
+        self
+    }
+
+    /// Returns the component making changes through this handle.
+    pub fn component(&self) -> &str {
+        &self.component
     }
 
     /// Returns a connection from the pool.
-    pub fn conn(&self) -> r2d2::PooledConnection<r2d2::ConnectionManager<PgConnection>> {
-        self.pool
-            .0
-            .get()
-            .unwrap_or_else(|e| panic!("getting a connection from the pool failed: {}", e))
+    /// This fails if no connection is free before the connection timeout.
+    pub fn conn(&self) -> Result<r2d2::PooledConnection<r2d2::ConnectionManager<PgConnection>>> {
+        self.pool.0.get().map_err(|e| {
+            CioError::DatabaseUnavailable(format!("getting a connection from the pool failed: {}", e)).into()
+        })
+    }
+
+    /// Returns the connections in the pool, how many of them are idle, and the most it can have.
+    pub fn pool_state(&self) -> (u32, u32, u32) {
+        let state = self.pool.0.state();
+        (state.connections, state.idle_connections, self.pool.0.max_size())
+    }
+
+    /// Run blocking database work on the blocking thread pool, so waiting on the
+    /// database does not stall the other tasks on the async runtime.
+    pub async fn run<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&Database) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || f(&db)).await?
     }
 }
 
@@ -57,15 +199,39 @@ impl steno::SecStore for Database {
         Ok(())
     }
 
+    // The saga executor has no way to handle an error from these, so we log it and keep
+    // the saga running rather than panicking.
     async fn record_event(&self, event: steno::SagaNodeEvent) {
-        crate::functions::Function::from_saga_node_event(self, &event)
-            .await
-            .unwrap();
+        if let Err(e) = crate::functions::Function::from_saga_node_event(self, &event).await {
+            warn!("recording event for saga {} failed: {:?}", event.saga_id, e);
+        }
     }
 
     async fn saga_update(&self, id: steno::SagaId, update: steno::SagaCachedState) {
-        crate::functions::Function::from_saga_cached_state(self, &id, &update)
-            .await
-            .unwrap();
+        if let Err(e) = crate::functions::Function::from_saga_cached_state(self, &id, &update).await {
+            warn!("updating saga {} failed: {:?}", id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{parse_optional_setting, parse_setting};
+
+    #[test]
+    fn test_db_parse_setting() {
+        assert_eq!(parse_setting::<u32>("POOL_SIZE", None, 10).unwrap(), 10);
+        assert_eq!(parse_setting::<u32>("POOL_SIZE", Some("".to_string()), 10).unwrap(), 10);
+        assert_eq!(
+            parse_setting::<u32>("POOL_SIZE", Some(" 50 ".to_string()), 10).unwrap(),
+            50
+        );
+        assert!(parse_setting::<u32>("POOL_SIZE", Some("lots".to_string()), 10).is_err());
+
+        assert_eq!(parse_optional_setting::<u32>("MIN_IDLE", None).unwrap(), None);
+        assert_eq!(
+            parse_optional_setting::<u32>("MIN_IDLE", Some("2".to_string())).unwrap(),
+            Some(2)
+        );
     }
 }
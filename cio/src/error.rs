@@ -0,0 +1,224 @@
+use std::fmt;
+
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use reqwest::StatusCode;
+
+use crate::mirror::MirrorBackend;
+
+/// The errors from database, mirror and provider operations that callers act on.
+///
+/// These are returned inside an `anyhow::Error`, use `CioError::find` to get them back out
+/// rather than matching on the message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CioError {
+    /// The record or resource does not exist.
+    NotFound(String),
+    /// A record with the same unique key already exists.
+    UniqueViolation(String),
+    /// The company has not connected the provider, so we have no token for it.
+    NotConnected(String),
+    /// We could not get a connection to the database.
+    DatabaseUnavailable(String),
+    /// The backend we mirror records to could not be reached.
+    MirrorUnavailable(MirrorBackend),
+    /// The provider is rate limiting us.
+    RateLimited(String),
+    /// The provider returned an error status code.
+    Upstream(String, StatusCode),
+    /// The request is for something the caller is not allowed to touch.
+    Forbidden(String),
+    /// The record is not in a state where the request can be done, like retrying something
+    /// that is still running.
+    Conflict(String),
+}
+
+impl fmt::Display for CioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CioError::NotFound(what) => write!(f, "{} not found", what),
+            CioError::UniqueViolation(m) => write!(f, "unique violation: {}", m),
+            // Keep the "no token" wording, it is what shows up in our logs.
+            CioError::NotConnected(provider) => write!(f, "no token for {}", provider),
+            CioError::DatabaseUnavailable(m) => write!(f, "database unavailable: {}", m),
+            CioError::MirrorUnavailable(backend) => write!(f, "{} mirror unavailable", backend),
+            CioError::RateLimited(provider) => write!(f, "rate limited by {}", provider),
+            CioError::Upstream(provider, status) => {
+                write!(f, "{} returned status code {}", provider, status.as_u16())
+            }
+            CioError::Forbidden(m) => write!(f, "forbidden: {}", m),
+            CioError::Conflict(m) => write!(f, "conflict: {}", m),
+        }
+    }
+}
+
+impl std::error::Error for CioError {}
+
+impl CioError {
+    /// Returns the `CioError` in the chain of an error, if there is one.
+    pub fn find(e: &anyhow::Error) -> Option<&CioError> {
+        // This finds it if it was added as context, anywhere in the chain.
+        e.downcast_ref::<CioError>()
+            .or_else(|| e.chain().find_map(|c| c.downcast_ref::<CioError>()))
+    }
+
+    /// Returns if the error means the record or resource does not exist.
+    /// This also works for errors from the API clients that are not wrapped with
+    /// `CioError::from_upstream`.
+    pub fn is_not_found(e: &anyhow::Error) -> bool {
+        match CioError::find(e) {
+            Some(CioError::NotFound(_)) => true,
+            Some(_) => false,
+            None => status_code(e) == Some(StatusCode::NOT_FOUND),
+        }
+    }
+
+    /// Returns if the error means the company has not connected the provider.
+    pub fn is_not_connected(e: &anyhow::Error) -> bool {
+        matches!(CioError::find(e), Some(CioError::NotConnected(_)))
+    }
+
+    /// Convert an error from diesel.
+    pub fn from_db(e: DieselError, what: &str) -> anyhow::Error {
+        match e {
+            DieselError::NotFound => CioError::NotFound(what.to_string()).into(),
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+                CioError::UniqueViolation(format!("{}: {}", what, info.message())).into()
+            }
+            e => e.into(),
+        }
+    }
+
+    /// Classify an error from a provider's API by its status code.
+    /// The original error is kept in the chain.
+    pub fn from_upstream(provider: &str, e: anyhow::Error) -> anyhow::Error {
+        let kind = match status_code(&e) {
+            Some(StatusCode::NOT_FOUND) => CioError::NotFound(provider.to_string()),
+            Some(StatusCode::TOO_MANY_REQUESTS) => CioError::RateLimited(provider.to_string()),
+            Some(status) => CioError::Upstream(provider.to_string(), status),
+            None => return e,
+        };
+
+        e.context(kind)
+    }
+
+    /// Classify an error from a mirror. Besides what `from_upstream` does, server errors and
+    /// errors connecting to the mirror mean it is unavailable.
+    pub fn from_mirror(backend: MirrorBackend, e: anyhow::Error) -> anyhow::Error {
+        let unreachable = e
+            .chain()
+            .filter_map(|c| c.downcast_ref::<reqwest::Error>())
+            .any(|r| r.is_connect() || r.is_timeout());
+        if unreachable || status_code(&e).map(|s| s.is_server_error()).unwrap_or(false) {
+            return e.context(CioError::MirrorUnavailable(backend));
+        }
+
+        CioError::from_upstream(&backend.to_string(), e)
+    }
+}
+
+/// Returns the HTTP status code of an error from an API client.
+///
+/// The clients that give us their status code as a type, `reqwest` and `airtable_api`, are
+/// asked for it. The others only put it in their message, ie. `code: 404 Not Found, body: ...`,
+/// so for those we look for a status code followed by its reason.
+fn status_code(e: &anyhow::Error) -> Option<StatusCode> {
+    for c in e.chain() {
+        if let Some(status) = c.downcast_ref::<reqwest::Error>().and_then(|r| r.status()) {
+            return Some(status);
+        }
+        if let Some(s) = c.downcast_ref::<airtable_api::StatusError>() {
+            return Some(s.status);
+        }
+    }
+
+    // Use the alternate format so we see the whole chain.
+    parse_status_code(&format!("{:#}", e))
+}
+
+/// Returns the status code from an error message, where it is followed by its reason, ie.
+/// `404 Not Found`. A number that is not followed by the reason for that status code is not
+/// taken as one.
+fn parse_status_code(message: &str) -> Option<StatusCode> {
+    message.match_indices(char::is_numeric).find_map(|(i, _)| {
+        let status = StatusCode::from_bytes(message.get(i..i + 3)?.as_bytes()).ok()?;
+        let after_number = message[..i].chars().last().map(|c| !c.is_numeric()).unwrap_or(true);
+        let reason = status.canonical_reason()?;
+        if after_number && message[i + 3..].starts_with(&format!(" {}", reason)) {
+            Some(status)
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use crate::{
+        error::{parse_status_code, CioError},
+        mirror::MirrorBackend,
+    };
+
+    #[test]
+    fn test_error_parse_status_code() {
+        assert_eq!(
+            parse_status_code("status code: 404 Not Found, body: {\"error\":\"NOT_FOUND\"}"),
+            Some(StatusCode::NOT_FOUND)
+        );
+        assert_eq!(
+            parse_status_code("code: 429 Too Many Requests"),
+            Some(StatusCode::TOO_MANY_REQUESTS)
+        );
+        assert_eq!(parse_status_code("updated 404 records"), None);
+        assert_eq!(parse_status_code("file 1404 Not Found"), None);
+        assert_eq!(parse_status_code("Not Found"), None);
+        assert_eq!(parse_status_code("no token"), None);
+    }
+
+    #[test]
+    fn test_error_classify() {
+        let e = CioError::from_upstream("github", anyhow::anyhow!("status code: 404 Not Found, body: "));
+        assert_eq!(CioError::find(&e), Some(&CioError::NotFound("github".to_string())));
+        assert!(CioError::is_not_found(&e));
+
+        let e = CioError::from_mirror(
+            MirrorBackend::Airtable,
+            anyhow::anyhow!("status code: 503 Service Unavailable, body: "),
+        );
+        assert_eq!(
+            CioError::find(&e),
+            Some(&CioError::MirrorUnavailable(MirrorBackend::Airtable))
+        );
+
+        let e = CioError::from_mirror(
+            MirrorBackend::Airtable,
+            anyhow::anyhow!("status code: 422 Unprocessable Entity, body: "),
+        );
+        assert_eq!(
+            CioError::find(&e),
+            Some(&CioError::Upstream(
+                "airtable".to_string(),
+                StatusCode::UNPROCESSABLE_ENTITY
+            ))
+        );
+
+        // The Airtable client gives us the status code as a type.
+        let e: anyhow::Error = airtable_api::StatusError {
+            status: StatusCode::NOT_FOUND,
+            body: "{}".to_string(),
+        }
+        .into();
+        assert!(CioError::is_not_found(&e));
+        let e = CioError::from_upstream("airtable", e.context("getting record failed"));
+        assert_eq!(CioError::find(&e), Some(&CioError::NotFound("airtable".to_string())));
+
+        // Without a status code, it isn't a not found, whatever the message says.
+        assert!(!CioError::is_not_found(&anyhow::anyhow!("Not Found")));
+
+        let e: anyhow::Error = CioError::NotConnected("shipbob".to_string()).into();
+        assert!(CioError::is_not_connected(&e));
+        assert!(!CioError::is_not_found(&e));
+        assert_eq!(e.to_string(), "no token for shipbob");
+    }
+}
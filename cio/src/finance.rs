@@ -20,6 +20,7 @@ use crate::{
     configs::{Group, User},
     core::UpdateAirtableRecord,
     db::Database,
+    error::CioError,
     providers::ProviderOps,
     repos::FromUrl,
     schema::{accounts_payables, credit_card_transactions, expensed_items, software_vendors, users},
@@ -87,7 +88,7 @@ fn is_zero(num: &f32) -> bool {
 /// Implement updating the Airtable record for a SoftwareVendor.
 #[async_trait]
 impl UpdateAirtableRecord<SoftwareVendor> for SoftwareVendor {
-    async fn update_airtable_record(&mut self, record: SoftwareVendor) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, record: SoftwareVendor) -> Result<()> {
         // This is a function so we can't change it through the API.
         self.total_cost_per_month = 0.0;
         // Keep this the same, we update it from the transactions.
@@ -380,7 +381,7 @@ pub struct NewCreditCardTransaction {
 /// Implement updating the Airtable record for a CreditCardTransaction.
 #[async_trait]
 impl UpdateAirtableRecord<CreditCardTransaction> for CreditCardTransaction {
-    async fn update_airtable_record(&mut self, _record: CreditCardTransaction) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, _record: CreditCardTransaction) -> Result<()> {
         Ok(())
     }
 }
@@ -389,7 +390,7 @@ pub async fn refresh_ramp_transactions(db: &Database, company: &Company) -> Resu
     // Create the Ramp client.
     let r = company.authenticate_ramp(db).await;
     if let Err(e) = r {
-        if e.to_string().contains("no token") {
+        if CioError::is_not_connected(&e) {
             // Return early, this company does not use Zoom.
             return Ok(());
         }
@@ -485,7 +486,7 @@ pub async fn refresh_ramp_reimbursements(db: &Database, company: &Company) -> Re
     // Create the Ramp client.
     let r = company.authenticate_ramp(db).await;
     if let Err(e) = r {
-        if e.to_string().contains("no token") {
+        if CioError::is_not_connected(&e) {
             // Return early, this company does not use Zoom.
             return Ok(());
         }
@@ -896,7 +897,7 @@ pub async fn refresh_brex_transactions(db: &Database, company: &Company) -> Resu
                     .eq(last_name.to_string())
                     .and(users::dsl::cio_company_id.eq(company.id)),
             )
-            .first::<User>(&db.conn())
+            .first::<User>(&db.conn()?)
         {
             Ok(user) => {
                 // Set the user's email.
@@ -992,7 +993,7 @@ pub struct NewAccountsPayable {
 /// Implement updating the Airtable record for a AccountsPayable.
 #[async_trait]
 impl UpdateAirtableRecord<AccountsPayable> for AccountsPayable {
-    async fn update_airtable_record(&mut self, _record: AccountsPayable) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, _record: AccountsPayable) -> Result<()> {
         Ok(())
     }
 }
@@ -1118,7 +1119,7 @@ pub struct NewExpensedItem {
 /// Implement updating the Airtable record for a ExpensedItem.
 #[async_trait]
 impl UpdateAirtableRecord<ExpensedItem> for ExpensedItem {
-    async fn update_airtable_record(&mut self, _record: ExpensedItem) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, _record: ExpensedItem) -> Result<()> {
         Ok(())
     }
 }
@@ -1168,7 +1169,7 @@ pub async fn refresh_expensify_transactions(db: &Database, company: &Company) ->
                     .or(users::dsl::username.eq(last_name.to_string())),
             )
             .filter(users::dsl::cio_company_id.eq(company.id))
-            .first::<User>(&db.conn())
+            .first::<User>(&db.conn()?)
         {
             Ok(user) => {
                 // Set the user's email.
@@ -1298,7 +1299,7 @@ pub async fn sync_quickbooks(db: &Database, company: &Company) -> Result<()> {
     // Authenticate QuickBooks.
     let qba = company.authenticate_quickbooks(db).await;
     if let Err(e) = qba {
-        if e.to_string().contains("no token") {
+        if CioError::is_not_connected(&e) {
             // Return early, this company does not use QuickBooks.
             return Ok(());
         }
@@ -1325,7 +1326,7 @@ pub async fn sync_quickbooks(db: &Database, company: &Company) -> Result<()> {
                     .and(accounts_payables::dsl::amount.eq(bill_payment.total_amt))
                     .and(accounts_payables::dsl::date.eq(bill_payment.txn_date)),
             )
-            .first::<AccountsPayable>(&db.conn())
+            .first::<AccountsPayable>(&db.conn()?)
         {
             Ok(mut transaction) => {
                 // Add the receipt.
@@ -1390,7 +1391,7 @@ pub async fn sync_quickbooks(db: &Database, company: &Company) -> Result<()> {
                         .and(credit_card_transactions::dsl::time.ge(DateTime::<Utc>::from_utc(sdt, Utc)))
                         .and(credit_card_transactions::dsl::time.le(DateTime::<Utc>::from_utc(edt, Utc))),
                 )
-                .first::<CreditCardTransaction>(&db.conn())
+                .first::<CreditCardTransaction>(&db.conn()?)
             {
                 Ok(mut transaction) => {
                     // Add the receipt.
@@ -1478,7 +1479,7 @@ mod tests {
     async fn test_finance_bill_com() {
         crate::utils::setup_logger();
 
-        let db = Database::new();
+        let db = Database::new().unwrap();
 
         // Get the company id for Oxide.
         // TODO: split this out per company.
@@ -1492,7 +1493,7 @@ mod tests {
     async fn test_finance_expensify() {
         crate::utils::setup_logger();
 
-        let db = Database::new();
+        let db = Database::new().unwrap();
 
         // Get the company id for Oxide.
         // TODO: split this out per company.
@@ -1506,7 +1507,7 @@ mod tests {
     async fn test_finance_brex() {
         crate::utils::setup_logger();
 
-        let db = Database::new();
+        let db = Database::new().unwrap();
 
         // Get the company id for Oxide.
         // TODO: split this out per company.
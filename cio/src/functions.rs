@@ -1,5 +1,6 @@
-use anyhow::Result;
-use async_trait::async_trait;
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Result};
 use chrono::{DateTime, Utc};
 use chrono_humanize::HumanTime;
 use macros::db;
@@ -11,14 +12,15 @@ use slack_chat_api::{
 };
 
 use crate::{
-    airtable::AIRTABLE_FUNCTIONS_TABLE, companies::Company, core::UpdateAirtableRecord, db::Database,
-    schema::functions, utils::truncate,
+    companies::Company,
+    db::Database,
+    error::CioError,
+    schema::{function_logs, function_steps, functions},
 };
 
 #[db {
     new_struct_name = "Function",
-    airtable_base = "cio",
-    airtable_table = "AIRTABLE_FUNCTIONS_TABLE",
+    mirror = "none",
     match_on = {
         "saga_id" = "String",
     },
@@ -39,21 +41,15 @@ pub struct NewFunction {
     pub logs: String,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub saga_id: String,
+    /// The parameters the saga was started with, so it can be retried.
+    #[serde(default)]
+    pub params: serde_json::Value,
 
     /// The CIO company ID.
     #[serde(default)]
     pub cio_company_id: i32,
 }
 
-/// Implement updating the Airtable record for a Function.
-#[async_trait]
-impl UpdateAirtableRecord<Function> for Function {
-    async fn update_airtable_record(&mut self, _record: Function) -> Result<()> {
-        self.logs = truncate(&self.logs, 100000);
-        Ok(())
-    }
-}
-
 fn get_color_based_from_status_and_conclusion(status: &str, conclusion: &str) -> String {
     if status == octorust::types::JobStatus::InProgress.to_string() {
         return crate::colors::Colors::Blue.to_string();
@@ -135,7 +131,8 @@ impl From<NewFunction> for FormattedMessage {
                     text: format!("Re-run {}", item.name),
                 }),
                 action_id: "function".to_string(),
-                value: item.name.to_string(),
+                // Retry this run, so sagas get the same parameters.
+                value: item.saga_id.to_string(),
                 image_url: Default::default(),
                 alt_text: Default::default(),
             };
@@ -213,7 +210,55 @@ impl Function {
         n.send_slack_notification(db, company).await
     }
 
-    /// Add logs to a running saga.
+    /// Get the functions, the newest first. We only return the last 100, with the filters
+    /// applied.
+    pub fn get_by_name_and_status(db: &Database, name: Option<&str>, status: Option<&str>) -> Result<Vec<Function>> {
+        let mut query = functions::dsl::functions
+            .order_by(functions::dsl::created_at.desc())
+            .limit(100)
+            .into_boxed();
+        if let Some(name) = name {
+            query = query.filter(functions::dsl::name.eq(name.to_string()));
+        }
+        if let Some(status) = status {
+            query = query.filter(functions::dsl::status.eq(status.to_string()));
+        }
+
+        let functions = query.load::<Function>(&db.conn()?)?;
+
+        Ok(functions)
+    }
+
+    /// Get the status of a function, without loading its logs.
+    pub fn get_status(db: &Database, saga_id: &str) -> Result<Option<String>> {
+        let status = functions::dsl::functions
+            .filter(functions::dsl::saga_id.eq(saga_id.to_string()))
+            .select(functions::dsl::status)
+            .first::<String>(&db.conn()?)
+            .optional()?;
+
+        Ok(status)
+    }
+
+    /// Returns if someone asked for the function to be cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.conclusion == octorust::types::Conclusion::Cancelled.to_string()
+    }
+
+    /// Mark a running function as cancelled. Sagas stop before their next step, what runs
+    /// the function has to stop it otherwise.
+    pub async fn cancel(&mut self, db: &Database) -> Result<()> {
+        if self.status == octorust::types::JobStatus::Completed.to_string() {
+            bail!("function `{}` `{}` already finished", self.name, self.saga_id);
+        }
+
+        self.conclusion = octorust::types::Conclusion::Cancelled.to_string();
+        *self = self.update(db).await?;
+
+        Ok(())
+    }
+
+    /// Add the logs of a step of a saga to the ones of the steps before it.
     pub async fn add_logs(db: &Database, saga_id: &uuid::Uuid, logs: &str) -> Result<()> {
         if logs.is_empty() {
             // Return early.
@@ -221,14 +266,16 @@ impl Function {
         }
 
         // Get the saga from it's id.
-        let mut nf = Function::get_from_db(db, saga_id.to_string()).unwrap();
-        nf.logs = logs.to_string();
+        let mut nf = Function::get_from_db(db, saga_id.to_string())
+            .ok_or_else(|| CioError::NotFound(format!("function `{}`", saga_id)))?;
+
+        nf.logs = append_logs(&nf.logs, logs);
         nf.update(db).await?;
 
         Ok(())
     }
 
-    /// Add logs with a conclusion saga.
+    /// Add the logs of the last step of a saga, with its conclusion.
     pub async fn add_logs_with_conclusion(
         db: &Database,
         saga_id: &uuid::Uuid,
@@ -241,14 +288,15 @@ impl Function {
         }
 
         // Get the saga from it's id.
-        let mut nf = Function::get_from_db(db, saga_id.to_string()).unwrap();
+        let mut nf = Function::get_from_db(db, saga_id.to_string())
+            .ok_or_else(|| CioError::NotFound(format!("function `{}`", saga_id)))?;
 
         let mut send_notification = false;
         if conclusion.to_string() != nf.conclusion && nf.status == octorust::types::JobStatus::Completed.to_string() {
             send_notification = true;
         }
 
-        nf.logs = logs.to_string();
+        nf.logs = append_logs(&nf.logs, logs);
         nf.conclusion = conclusion.to_string();
         let new = nf.update(db).await?;
 
@@ -276,6 +324,7 @@ impl Function {
             completed_at: None,
             logs: "".to_string(),
             saga_id: saga.id.to_string(),
+            params: saga.saga_params.clone(),
             cio_company_id: 1, // This is always 1 because these are meta and tied to Oxide.
         };
 
@@ -294,7 +343,8 @@ impl Function {
         state: &steno::SagaCachedState,
     ) -> Result<Self> {
         // Get the saga from it's id.
-        let mut nf = Function::get_from_db(db, saga_id.to_string()).unwrap();
+        let mut nf = Function::get_from_db(db, saga_id.to_string())
+            .ok_or_else(|| CioError::NotFound(format!("function `{}`", saga_id)))?;
 
         let status = match state {
             steno::SagaCachedState::Running => octorust::types::JobStatus::InProgress,
@@ -327,7 +377,8 @@ impl Function {
     /// Update a job from SagaNodeEvent.
     pub async fn from_saga_node_event(db: &Database, event: &steno::SagaNodeEvent) -> Result<Self> {
         // Get the saga from it's id.
-        let nf = Function::get_from_db(db, event.saga_id.to_string()).unwrap();
+        let nf = Function::get_from_db(db, event.saga_id.to_string())
+            .ok_or_else(|| CioError::NotFound(format!("function `{}`", event.saga_id)))?;
 
         match &event.event_type {
             steno::SagaNodeEventType::Started => {}
@@ -342,9 +393,245 @@ impl Function {
     }
 }
 
-pub async fn refresh_functions() -> Result<()> {
-    let db = Database::new();
-    let company = Company::get_by_id(&db, 1)?;
+/// Returns the logs of a function with the logs of another of its steps after them.
+fn append_logs(logs: &str, more: &str) -> String {
+    format!("{}\n{}", logs.trim_end(), more).trim().to_string()
+}
+
+/// The status of a step of a saga.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum FunctionStepStatus {
+    /// The step has not run yet.
+    Queued,
+
+    /// The step is running.
+    InProgress,
+
+    /// The step finished.
+    Succeeded,
+
+    /// The step failed, the steps before it are undone.
+    Failed,
+
+    /// The step finished but a later one failed, so what it did was undone.
+    Undone,
+
+    /// The step had nothing to do, like adding someone to a service their company does not use.
+    Skipped,
+}
+
+impl fmt::Display for FunctionStepStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FunctionStepStatus::Queued => write!(f, "queued"),
+            FunctionStepStatus::InProgress => write!(f, "in_progress"),
+            FunctionStepStatus::Succeeded => write!(f, "succeeded"),
+            FunctionStepStatus::Failed => write!(f, "failed"),
+            FunctionStepStatus::Undone => write!(f, "undone"),
+            FunctionStepStatus::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+impl FromStr for FunctionStepStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queued" => Ok(FunctionStepStatus::Queued),
+            "in_progress" => Ok(FunctionStepStatus::InProgress),
+            "succeeded" => Ok(FunctionStepStatus::Succeeded),
+            "failed" => Ok(FunctionStepStatus::Failed),
+            "undone" => Ok(FunctionStepStatus::Undone),
+            "skipped" => Ok(FunctionStepStatus::Skipped),
+            _ => bail!("invalid function step status: `{}`", s),
+        }
+    }
+}
+
+/// A step of a function that runs as a saga with more than one step.
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "function_steps"]
+pub struct NewFunctionStep {
+    /// The saga the step is part of, this is the `saga_id` of the function.
+    pub saga_id: String,
+    pub name: String,
+    pub status: String,
+    /// The error the step failed with, or the one undoing it failed with.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub error: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<DateTime<Utc>>,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Queryable, Identifiable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "function_steps"]
+pub struct FunctionStep {
+    pub id: i32,
+    pub saga_id: String,
+    pub name: String,
+    pub status: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub error: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+impl FunctionStep {
+    /// Add the steps of a saga before it runs, so the ones that haven't run yet show up too.
+    pub fn queue(db: &Database, saga_id: &uuid::Uuid, names: &[&str], cio_company_id: i32) -> Result<()> {
+        let steps: Vec<NewFunctionStep> = names
+            .iter()
+            .map(|name| NewFunctionStep {
+                saga_id: saga_id.to_string(),
+                name: name.to_string(),
+                status: FunctionStepStatus::Queued.to_string(),
+                error: Default::default(),
+                started_at: None,
+                completed_at: None,
+                cio_company_id,
+            })
+            .collect();
+
+        diesel::insert_into(function_steps::table)
+            .values(&steps)
+            .on_conflict_do_nothing()
+            .execute(&db.conn()?)?;
+
+        Ok(())
+    }
+
+    /// Get the steps of a saga, in the order they were added.
+    pub fn get_for_saga(db: &Database, saga_id: &str) -> Result<Vec<FunctionStep>> {
+        let steps = function_steps::dsl::function_steps
+            .filter(function_steps::dsl::saga_id.eq(saga_id.to_string()))
+            .order_by(function_steps::dsl::id.asc())
+            .load::<FunctionStep>(&db.conn()?)?;
+
+        Ok(steps)
+    }
+
+    /// Set the status of a step of a saga.
+    pub fn set_status(
+        db: &Database,
+        saga_id: &uuid::Uuid,
+        name: &str,
+        status: FunctionStepStatus,
+        error: &str,
+    ) -> Result<()> {
+        let now = Utc::now();
+        let step = diesel::update(
+            function_steps::dsl::function_steps
+                .filter(function_steps::dsl::saga_id.eq(saga_id.to_string()))
+                .filter(function_steps::dsl::name.eq(name.to_string())),
+        );
+
+        match status {
+            FunctionStepStatus::InProgress => step
+                .set((
+                    function_steps::dsl::status.eq(status.to_string()),
+                    function_steps::dsl::error.eq(error.to_string()),
+                    function_steps::dsl::started_at.eq(Some(now)),
+                    function_steps::dsl::completed_at.eq(None::<DateTime<Utc>>),
+                ))
+                .execute(&db.conn()?)?,
+            FunctionStepStatus::Queued => step
+                .set((
+                    function_steps::dsl::status.eq(status.to_string()),
+                    function_steps::dsl::error.eq(error.to_string()),
+                ))
+                .execute(&db.conn()?)?,
+            _ => step
+                .set((
+                    function_steps::dsl::status.eq(status.to_string()),
+                    function_steps::dsl::error.eq(error.to_string()),
+                    function_steps::dsl::completed_at.eq(Some(now)),
+                ))
+                .execute(&db.conn()?)?,
+        };
+
+        Ok(())
+    }
+}
+
+/// A chunk of the logs of a function, saved while it runs so they can be followed.
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "function_logs"]
+pub struct NewFunctionLog {
+    pub saga_id: String,
+    pub logs: String,
+    pub created_at: DateTime<Utc>,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Queryable, Identifiable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "function_logs"]
+pub struct FunctionLog {
+    pub id: i32,
+    pub saga_id: String,
+    pub logs: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+impl FunctionLog {
+    /// Save the logs a function wrote since the last time we saved them, for the company of
+    /// the function.
+    pub async fn append(db: &Database, saga_id: &uuid::Uuid, cio_company_id: i32, logs: &str) -> Result<()> {
+        if logs.is_empty() {
+            // Return early.
+            return Ok(());
+        }
+
+        let new_log = NewFunctionLog {
+            saga_id: saga_id.to_string(),
+            logs: logs.to_string(),
+            created_at: Utc::now(),
+            cio_company_id,
+        };
+        db.run(move |db| {
+            diesel::insert_into(function_logs::table)
+                .values(&new_log)
+                .execute(&db.conn()?)?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Get the chunks of the logs of a function after the one with the id, in order.
+    pub fn get_after(db: &Database, saga_id: &str, id: i32) -> Result<Vec<FunctionLog>> {
+        let logs = function_logs::dsl::function_logs
+            .filter(function_logs::dsl::saga_id.eq(saga_id.to_string()))
+            .filter(function_logs::dsl::id.gt(id))
+            .order_by(function_logs::dsl::id.asc())
+            .load::<FunctionLog>(&db.conn()?)?;
+
+        Ok(logs)
+    }
+
+    /// Get the logs of a function that were saved so far.
+    pub fn get_for_saga(db: &Database, saga_id: &str) -> Result<String> {
+        let logs = FunctionLog::get_after(db, saga_id, 0)?;
+
+        Ok(logs.into_iter().map(|l| l.logs).collect())
+    }
+}
+
+pub async fn refresh_functions(db: &Database) -> Result<()> {
+    let company = Company::get_by_id(db, 1)?;
 
     let hours_ago = Utc::now().checked_sub_signed(chrono::Duration::days(1)).unwrap();
 
@@ -352,34 +639,46 @@ pub async fn refresh_functions() -> Result<()> {
     let fns = functions::dsl::functions
         .filter(functions::dsl::status.eq(octorust::types::JobStatus::InProgress.to_string()))
         .filter(functions::dsl::created_at.lt(hours_ago))
-        .load::<Function>(&db.conn())?;
+        .load::<Function>(&db.conn()?)?;
 
     for mut f in fns {
         // Set the function as TimedOut.
         f.status = octorust::types::JobStatus::Completed.to_string();
         f.conclusion = octorust::types::Conclusion::TimedOut.to_string();
+        // It never got to save its logs, keep what it wrote before we prune the chunks.
+        if f.logs.is_empty() {
+            f.logs = FunctionLog::get_for_saga(db, &f.saga_id)?;
+        }
 
-        let new = f.update(&db).await?;
+        let new = f.update(db).await?;
 
-        new.send_slack_notification(&db, &company).await?;
+        new.send_slack_notification(db, &company).await?;
     }
 
     // List all functions that are "Completed", but have no conclusion.
     let fns = functions::dsl::functions
         .filter(functions::dsl::status.eq(octorust::types::JobStatus::Completed.to_string()))
         .filter(functions::dsl::conclusion.eq("".to_string()))
-        .load::<Function>(&db.conn())?;
+        .load::<Function>(&db.conn()?)?;
 
     for mut f in fns {
         // Set the function as Neutral.
         f.conclusion = octorust::types::Conclusion::Neutral.to_string();
 
-        let new = f.update(&db).await?;
+        let new = f.update(db).await?;
 
-        new.send_slack_notification(&db, &company).await?;
+        new.send_slack_notification(db, &company).await?;
     }
 
-    Functions::get_from_db(&db, 1)?.update_airtable(&db).await?;
+    // The chunks of the logs are only there to follow the functions while they run, the
+    // functions have all their logs once they are done.
+    let completed = functions::dsl::functions
+        .filter(functions::dsl::status.eq(octorust::types::JobStatus::Completed.to_string()))
+        .select(functions::dsl::saga_id);
+    diesel::delete(function_logs::dsl::function_logs.filter(function_logs::dsl::saga_id.eq_any(completed)))
+        .execute(&db.conn()?)?;
+
+    Functions::get_from_db(db, 1)?.update_mirror(db).await?;
 
     Ok(())
 }
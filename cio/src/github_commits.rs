@@ -144,10 +144,8 @@ impl FromTagger for Option<octorust::types::Tagger> {
     }
 }
 
-pub async fn refresh_commits() -> Result<()> {
-    let db = Database::new();
-
-    let companies = Companys::get_from_db(&db, 1)?;
+pub async fn refresh_commits(db: &Database) -> Result<()> {
+    let companies = Companys::get_from_db(db, 1)?;
 
     for company in companies {
         let github = company.authenticate_github()?;
@@ -189,6 +187,7 @@ mod tests {
     #[ignore]
     #[tokio::test(flavor = "multi_thread")]
     async fn test_commits() {
-        refresh_commits().await.unwrap();
+        let db = Database::new().unwrap();
+        refresh_commits(&db).await.unwrap();
     }
 }
@@ -224,10 +224,8 @@ impl FromVecPullRequestSimpleLinks for octorust::types::PullRequestSimpleLinks {
     }
 }
 
-pub async fn refresh_pulls() -> Result<()> {
-    let db = Database::new();
-
-    let companies = Companys::get_from_db(&db, 1)?;
+pub async fn refresh_pulls(db: &Database) -> Result<()> {
+    let companies = Companys::get_from_db(db, 1)?;
 
     for company in companies {
         let github = company.authenticate_github()?;
@@ -269,6 +267,7 @@ mod tests {
     #[ignore]
     #[tokio::test(flavor = "multi_thread")]
     async fn test_pulls() {
-        refresh_pulls().await.unwrap();
+        let db = Database::new().unwrap();
+        refresh_pulls(&db).await.unwrap();
     }
 }
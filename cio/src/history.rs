@@ -0,0 +1,171 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::db::Database;
+
+/// The change that was made to a record.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum HistoryAction {
+    Create,
+    Update,
+    Delete,
+}
+
+impl fmt::Display for HistoryAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryAction::Create => write!(f, "create"),
+            HistoryAction::Update => write!(f, "update"),
+            HistoryAction::Delete => write!(f, "delete"),
+        }
+    }
+}
+
+impl FromStr for HistoryAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "create" => Ok(HistoryAction::Create),
+            "update" => Ok(HistoryAction::Update),
+            "delete" => Ok(HistoryAction::Delete),
+            _ => bail!("invalid history action: `{}`", s),
+        }
+    }
+}
+
+/// A single change to a record, as stored in the history table for a `db` struct.
+///
+/// Every history table has the same columns, so this type is shared by all of them.
+#[derive(Debug, Queryable, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    pub id: i32,
+    /// The id of the record that changed.
+    pub record_id: i32,
+    pub action: String,
+    /// The record before the change, this is null for creates.
+    #[serde(default)]
+    pub before: Value,
+    /// The record after the change, this is null for deletes.
+    #[serde(default)]
+    pub after: Value,
+    /// The fields that changed, as a map of field name to `{"before": ..., "after": ...}`.
+    #[serde(default)]
+    pub diff: Value,
+    /// The component that made the change, ie. `webhooky sync-rfds`.
+    pub component: String,
+    pub created_at: DateTime<Utc>,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+/// The values for a new row in a history table.
+#[derive(Debug, PartialEq, Clone)]
+pub struct NewHistoryEntry {
+    pub record_id: i32,
+    pub action: String,
+    pub before: Value,
+    pub after: Value,
+    pub diff: Value,
+    pub component: String,
+    pub created_at: DateTime<Utc>,
+    pub cio_company_id: i32,
+}
+
+impl NewHistoryEntry {
+    /// Build the history entry for a change to a record.
+    /// Returns `None` for updates that did not change anything.
+    pub fn new<T: Serialize>(
+        db: &Database,
+        action: HistoryAction,
+        record_id: i32,
+        before: Option<&T>,
+        after: Option<&T>,
+        cio_company_id: i32,
+    ) -> Result<Option<Self>> {
+        let before = match before {
+            Some(b) => serde_json::to_value(b)?,
+            None => Value::Null,
+        };
+        let after = match after {
+            Some(a) => serde_json::to_value(a)?,
+            None => Value::Null,
+        };
+
+        let diff = diff(&before, &after);
+        if action == HistoryAction::Update && diff.as_object().map(|d| d.is_empty()).unwrap_or(true) {
+            return Ok(None);
+        }
+
+        Ok(Some(NewHistoryEntry {
+            record_id,
+            action: action.to_string(),
+            before,
+            after,
+            diff,
+            component: db.component().to_string(),
+            created_at: Utc::now(),
+            cio_company_id,
+        }))
+    }
+}
+
+/// Returns the fields that differ between two JSON objects, as a map of field name to
+/// `{"before": ..., "after": ...}`. A missing side is treated as null.
+pub fn diff(before: &Value, after: &Value) -> Value {
+    let empty = Map::new();
+    let b = before.as_object().unwrap_or(&empty);
+    let a = after.as_object().unwrap_or(&empty);
+
+    let mut changes = Map::new();
+    for key in b.keys().chain(a.keys()) {
+        if changes.contains_key(key) {
+            continue;
+        }
+
+        let old = b.get(key).unwrap_or(&Value::Null);
+        let new = a.get(key).unwrap_or(&Value::Null);
+        if old != new {
+            let mut change = Map::new();
+            change.insert("before".to_string(), old.clone());
+            change.insert("after".to_string(), new.clone());
+            changes.insert(key.to_string(), Value::Object(change));
+        }
+    }
+
+    Value::Object(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::history::diff;
+
+    #[test]
+    fn test_history_diff() {
+        let before = json!({"name": "Jess", "status": "Onboarding", "id": 1});
+        let after = json!({"name": "Jess", "status": "Hired", "id": 1, "phone": "555"});
+
+        assert_eq!(
+            diff(&before, &after),
+            json!({
+                "status": {"before": "Onboarding", "after": "Hired"},
+                "phone": {"before": null, "after": "555"},
+            })
+        );
+
+        assert_eq!(diff(&before, &before), json!({}));
+
+        assert_eq!(
+            diff(&serde_json::Value::Null, &json!({"id": 1})),
+            json!({"id": {"before": null, "after": 1}})
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use airtable_api::{Airtable, Record};
+use airtable_api::Record;
 use anyhow::Result;
 use chrono::{Duration, NaiveDate, Utc};
 use google_calendar::types::Event;
@@ -32,7 +32,7 @@ pub async fn sync_changes_to_google_events(db: &Database, company: &Company) ->
     // Iterate over the huddle meetings.
     for (slug, huddle) in configs.huddles {
         // Initialize the Airtable client.
-        let airtable = Airtable::new(&company.airtable_api_key, huddle.airtable_base_id, "");
+        let airtable = company.authenticate_airtable(&huddle.airtable_base_id);
 
         // Get the meeting schedule table from airtable.
         let records: Vec<Record<Meeting>> = airtable
@@ -185,7 +185,7 @@ pub async fn send_huddle_reminders(db: &Database, company: &Company) -> Result<(
         let mut email_data: MeetingReminderEmailData = Default::default();
 
         // Initialize the Airtable client.
-        let airtable = Airtable::new(&company.airtable_api_key, huddle.airtable_base_id, "");
+        let airtable = company.authenticate_airtable(&huddle.airtable_base_id);
 
         // Get the meeting schedule table from airtable.
         let records: Vec<Record<Meeting>> = airtable
@@ -417,7 +417,7 @@ pub async fn sync_huddle_meeting_notes(company: &Company) -> Result<()> {
     // Iterate over the huddle meetings.
     for (name, huddle) in configs.huddles {
         // Initialize the Airtable client.
-        let airtable = Airtable::new(&company.airtable_api_key, huddle.airtable_base_id, "");
+        let airtable = company.authenticate_airtable(&huddle.airtable_base_id);
 
         // Get the meeting schedule table from airtable.
         let records: Vec<Record<Meeting>> = airtable
@@ -564,7 +564,7 @@ pub async fn sync_huddles(db: &Database, company: &Company) -> Result<()> {
         );
 
         // Now let's get the Airtable records.
-        let airtable = Airtable::new(&company.airtable_api_key, huddle.airtable_base_id.to_string(), "");
+        let airtable = company.authenticate_airtable(&huddle.airtable_base_id);
         let records: Vec<Record<Meeting>> = airtable
             .list_records(AIRTABLE_MEETING_SCHEDULE_TABLE, "All Meetings", vec![])
             .await?;
@@ -69,7 +69,7 @@ pub struct NewApplicantInterview {
 /// Implement updating the Airtable record for a ApplicantInterview.
 #[async_trait]
 impl UpdateAirtableRecord<ApplicantInterview> for ApplicantInterview {
-    async fn update_airtable_record(&mut self, _record: ApplicantInterview) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, _record: ApplicantInterview) -> Result<()> {
         Ok(())
     }
 }
@@ -175,7 +175,7 @@ pub async fn refresh_interviews(db: &Database, company: &Company) -> Result<()>
                                     .and(users::dsl::cio_company_id.eq(company.id)),
                             )
                             .limit(1)
-                            .load::<User>(&db.conn())
+                            .load::<User>(&db.conn()?)
                         {
                             Ok(r) => {
                                 if !r.is_empty() {
@@ -206,7 +206,7 @@ pub async fn refresh_interviews(db: &Database, company: &Company) -> Result<()>
                             )
                             .filter(users::dsl::cio_company_id.eq(company.id))
                             .limit(1)
-                            .load::<User>(&db.conn())
+                            .load::<User>(&db.conn()?)
                         {
                             Ok(r) => {
                                 if !r.is_empty() {
@@ -236,7 +236,7 @@ pub async fn refresh_interviews(db: &Database, company: &Company) -> Result<()>
 
             if let Ok(mut a) = applicants::dsl::applicants
                 .filter(applicants::dsl::email.eq(interview.email.to_string()))
-                .first::<Applicant>(&db.conn())
+                .first::<Applicant>(&db.conn()?)
             {
                 // Set the applicant to interviewing.
                 if a.status != crate::applicant_status::Status::Interviewing.to_string()
@@ -318,7 +318,7 @@ pub async fn compile_packets(db: &Database, company: &Company) -> Result<()> {
         let mut materials_url = "".to_string();
         if let Ok(a) = applicants::dsl::applicants
             .filter(applicants::dsl::email.eq(employee.recovery_email.to_string()))
-            .first::<Applicant>(&db.conn())
+            .first::<Applicant>(&db.conn()?)
         {
             materials_url = a.materials;
         }
@@ -355,7 +355,7 @@ pub async fn compile_packets(db: &Database, company: &Company) -> Result<()> {
                         .or(users::dsl::aliases.contains(vec![username.to_string()])),
                 )
                 .filter(users::dsl::cio_company_id.eq(company.id))
-                .first::<User>(&db.conn())
+                .first::<User>(&db.conn()?)
             {
                 existing.push((
                     user,
@@ -379,7 +379,7 @@ pub async fn compile_packets(db: &Database, company: &Company) -> Result<()> {
     for (email, itrs) in interviewers {
         if let Ok(applicant) = applicants::dsl::applicants
             .filter(applicants::dsl::email.eq(email.to_string()))
-            .first::<Applicant>(&db.conn())
+            .first::<Applicant>(&db.conn()?)
         {
             // Create the cover page.
             let mut user_html = "".to_string();
@@ -0,0 +1,614 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use cron::Schedule;
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use log::warn;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    companies::{Company, Companys},
+    db::Database,
+    functions::Function,
+    schema::{functions, job_schedules},
+};
+
+/// The schedules the jobs start with, before anyone changes them in the database.
+/// These are in `DEFAULT_TIMEZONE`, unless `DEFAULT_SCHEDULE_TIMEZONES` has another one for
+/// the job.
+///
+/// The jobs used to run every so many hours from when the server started. A cron expression
+/// can only repeat by a number of hours that divides a day, so the jobs that ran every 5, 9,
+/// 14, 16, 18, 20 or 23 hours now run every 4, 8 or 12 hours, a little more often than they
+/// did. The minutes are spread out so they don't all start at once.
+pub static DEFAULT_SCHEDULES: &[(&str, &str)] = &[
+    ("send-rfd-changelog", "0 17 * * Mon"),
+    ("sync-analytics", "0 3 * * *"),
+    // Was every 23 hours.
+    ("sync-api-tokens", "5 */12 * * *"),
+    ("sync-applications", "10 */6 * * *"),
+    ("sync-asset-inventory", "15 */2 * * *"),
+    ("sync-companies", "20 */12 * * *"),
+    ("sync-configs", "25 */4 * * *"),
+    ("sync-finance", "30 */6 * * *"),
+    ("sync-functions", "35 */2 * * *"),
+    ("sync-huddles", "40 * * * *"),
+    ("sync-interviews", "45 */4 * * *"),
+    ("sync-journal-clubs", "50 */12 * * *"),
+    // Was every 20 hours.
+    ("sync-mailing-lists", "55 */12 * * *"),
+    // Was every 18 hours.
+    ("sync-other", "0 */12 * * *"),
+    ("sync-recorded-meetings", "5 */2 * * *"),
+    // Was every 16 hours.
+    ("sync-repos", "10 */12 * * *"),
+    // Was every 14 hours.
+    ("sync-rfds", "15 */12 * * *"),
+    ("sync-shipments", "20 */2 * * *"),
+    ("sync-shorturls", "25 */3 * * *"),
+    // Was every 9 hours.
+    ("sync-swag-inventory", "30 */8 * * *"),
+    // Was every 5 hours.
+    ("sync-travel", "35 */4 * * *"),
+];
+
+pub static DEFAULT_TIMEZONE: &str = "America/Los_Angeles";
+
+/// The jobs whose default schedule is in another timezone than `DEFAULT_TIMEZONE`.
+pub static DEFAULT_SCHEDULE_TIMEZONES: &[(&str, &str)] = &[
+    // The changelog goes out at the same time all year, it doesn't move with daylight saving.
+    ("send-rfd-changelog", "UTC"),
+];
+
+/// Returns the timezone of the default schedule of a job.
+pub fn default_timezone(name: &str) -> &'static str {
+    DEFAULT_SCHEDULE_TIMEZONES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, tz)| *tz)
+        .unwrap_or(DEFAULT_TIMEZONE)
+}
+
+/// The jobs that run once for everyone rather than for each company, so they can't be turned
+/// off for a company.
+pub static JOBS_NOT_PER_COMPANY: &[&str] = &["sync-companies", "sync-functions"];
+
+/// When a job runs.
+///
+/// The schedule with a `cio_company_id` of 0 is the one for the job, it sets when the job runs
+/// and if it runs at all. A schedule for a company turns the job off or on for that company,
+/// and can give it its own cron expression or timezone. An empty one follows the job's, and
+/// a company with either of its own runs on its own schedule, not with the others.
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "job_schedules"]
+pub struct NewJobSchedule {
+    /// The name of the job, ie. `sync-rfds`.
+    pub name: String,
+    /// When the job runs, as a cron expression with five fields, ie. `0 */6 * * *`. Days of the
+    /// week are numbered from 0 for Sunday, or use their names, ie. `Mon`. Empty for a company
+    /// that follows the job's.
+    pub cron: String,
+    /// The timezone the cron expression is in, ie. `America/Los_Angeles`. Empty for a company
+    /// that follows the job's.
+    pub timezone: String,
+    pub enabled: bool,
+    pub updated_at: DateTime<Utc>,
+    /// The CIO company ID, or 0 for the schedule of the job.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Queryable, Identifiable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "job_schedules"]
+pub struct JobSchedule {
+    pub id: i32,
+    pub name: String,
+    pub cron: String,
+    pub timezone: String,
+    pub enabled: bool,
+    pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+impl JobSchedule {
+    /// Add the default schedule for any job that does not have one yet.
+    pub fn seed_defaults(db: &Database) -> Result<()> {
+        let schedules: Vec<NewJobSchedule> = DEFAULT_SCHEDULES
+            .iter()
+            .map(|(name, cron)| NewJobSchedule {
+                name: name.to_string(),
+                cron: cron.to_string(),
+                timezone: default_timezone(name).to_string(),
+                enabled: true,
+                updated_at: Utc::now(),
+                cio_company_id: 0,
+            })
+            .collect();
+
+        diesel::insert_into(job_schedules::table)
+            .values(&schedules)
+            .on_conflict((job_schedules::dsl::name, job_schedules::dsl::cio_company_id))
+            .do_nothing()
+            .execute(&db.conn()?)?;
+
+        Ok(())
+    }
+
+    /// Set the schedule of a job, or of a job for a company. Anything not given is left as it
+    /// was, or is the default for a new schedule.
+    pub fn set(
+        db: &Database,
+        name: &str,
+        cio_company_id: i32,
+        cron: Option<&str>,
+        timezone: Option<&str>,
+        enabled: Option<bool>,
+    ) -> Result<JobSchedule> {
+        if cio_company_id != 0 && JOBS_NOT_PER_COMPANY.contains(&name) {
+            bail!("`{}` does not run for each company, it can't be set for one", name);
+        }
+
+        let existing = job_schedules::dsl::job_schedules
+            .filter(job_schedules::dsl::name.eq(name.to_string()))
+            .filter(job_schedules::dsl::cio_company_id.eq(cio_company_id))
+            .first::<JobSchedule>(&db.conn()?)
+            .optional()?;
+
+        let mut schedule = match existing {
+            Some(s) => NewJobSchedule {
+                name: s.name,
+                cron: s.cron,
+                timezone: s.timezone,
+                enabled: s.enabled,
+                updated_at: s.updated_at,
+                cio_company_id,
+            },
+            // A new schedule for a company follows the job's until it is given its own.
+            None if cio_company_id != 0 => NewJobSchedule {
+                name: name.to_string(),
+                cron: String::new(),
+                timezone: String::new(),
+                enabled: true,
+                updated_at: Utc::now(),
+                cio_company_id,
+            },
+            None => NewJobSchedule {
+                name: name.to_string(),
+                cron: DEFAULT_SCHEDULES
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, c)| c.to_string())
+                    .unwrap_or_default(),
+                timezone: default_timezone(name).to_string(),
+                enabled: true,
+                updated_at: Utc::now(),
+                cio_company_id,
+            },
+        };
+        if let Some(cron) = cron {
+            schedule.cron = cron.to_string();
+        }
+        if let Some(timezone) = timezone {
+            schedule.timezone = timezone.to_string();
+        }
+        if let Some(enabled) = enabled {
+            schedule.enabled = enabled;
+        }
+        schedule.updated_at = Utc::now();
+
+        // Make sure we can run it before we save it. A company can set either back to empty
+        // to follow the job's again.
+        if cio_company_id == 0 || !schedule.cron.is_empty() {
+            parse_cron(&schedule.cron)?;
+        }
+        if cio_company_id == 0 || !schedule.timezone.is_empty() {
+            parse_timezone(&schedule.timezone)?;
+        }
+
+        let schedule = diesel::insert_into(job_schedules::table)
+            .values(&schedule)
+            .on_conflict((job_schedules::dsl::name, job_schedules::dsl::cio_company_id))
+            .do_update()
+            .set(&schedule)
+            .get_result::<JobSchedule>(&db.conn()?)?;
+
+        Ok(schedule)
+    }
+
+    /// Get the schedules of the jobs, not the ones for companies.
+    pub fn get_for_jobs(db: &Database) -> Result<Vec<JobSchedule>> {
+        let schedules = job_schedules::dsl::job_schedules
+            .filter(job_schedules::dsl::cio_company_id.eq(0))
+            .order_by(job_schedules::dsl::name.asc())
+            .load::<JobSchedule>(&db.conn()?)?;
+
+        Ok(schedules)
+    }
+
+    /// Returns if this is a company's schedule with its own cron expression or timezone.
+    pub fn has_own_schedule(&self) -> bool {
+        self.cio_company_id != 0 && (!self.cron.is_empty() || !self.timezone.is_empty())
+    }
+
+    /// Returns the schedule a company runs the job on, filling in what it doesn't set from the
+    /// schedule of the job. The company only runs while the job is enabled.
+    pub fn for_company(&self, job: &JobSchedule) -> JobSchedule {
+        JobSchedule {
+            cron: if self.cron.is_empty() {
+                job.cron.to_string()
+            } else {
+                self.cron.to_string()
+            },
+            timezone: if self.timezone.is_empty() {
+                job.timezone.to_string()
+            } else {
+                self.timezone.to_string()
+            },
+            enabled: job.enabled && self.enabled,
+            ..self.clone()
+        }
+    }
+
+    /// Returns the next time the job runs after a time, if it is enabled.
+    pub fn next_run_after(&self, after: DateTime<Utc>) -> Result<Option<DateTime<Utc>>> {
+        if !self.enabled {
+            return Ok(None);
+        }
+
+        let tz = parse_timezone(&self.timezone)?;
+        let cron = parse_cron(&self.cron)?;
+
+        Ok(cron
+            .after(&after.with_timezone(&tz))
+            .next()
+            .map(|next| next.with_timezone(&Utc)))
+    }
+
+    /// Returns the schedules that were due to run after `after`, up to and including `now`.
+    /// These are the jobs, and the companies that run a job on their own schedule, with what
+    /// they don't set filled in from the job. A schedule we can't parse is skipped, so it
+    /// doesn't hold up the others.
+    pub fn get_due(db: &Database, after: DateTime<Utc>, now: DateTime<Utc>) -> Result<Vec<JobSchedule>> {
+        let companies = job_schedules::dsl::job_schedules
+            .filter(job_schedules::dsl::cio_company_id.ne(0))
+            .load::<JobSchedule>(&db.conn()?)?;
+
+        let mut due = Vec::new();
+        for job in JobSchedule::get_for_jobs(db)? {
+            let mut schedules = vec![job.clone()];
+            schedules.extend(
+                companies
+                    .iter()
+                    .filter(|c| c.name == job.name && c.has_own_schedule())
+                    .map(|c| c.for_company(&job)),
+            );
+
+            for schedule in schedules {
+                match schedule.next_run_after(after) {
+                    Ok(Some(next)) if next <= now => due.push(schedule),
+                    Ok(_) => (),
+                    Err(e) => warn!(
+                        "the schedule for `{}` for company `{}` is invalid: {}",
+                        schedule.name, schedule.cio_company_id, e
+                    ),
+                }
+            }
+        }
+
+        Ok(due)
+    }
+}
+
+/// Returns the companies a job should run for, this leaves out the ones the job was turned off
+/// for. Given a company, it is only that one, for running the job on the company's own
+/// schedule. Otherwise the companies with their own schedule are left out too, they run the
+/// job when their schedule says so.
+pub fn companies_for_job(db: &Database, name: &str, cio_company_id: Option<i32>) -> Result<Vec<Company>> {
+    let schedules = job_schedules::dsl::job_schedules
+        .filter(job_schedules::dsl::name.eq(name.to_string()))
+        .filter(job_schedules::dsl::cio_company_id.ne(0))
+        .load::<JobSchedule>(&db.conn()?)?;
+
+    Ok(Companys::get_from_db(db, 1)?
+        .into_iter()
+        .filter(|c| runs_for_company(&schedules, c.id, cio_company_id))
+        .collect())
+}
+
+/// Returns if a job runs for a company, given the company schedules of the job and the company
+/// the job was started for, if any.
+fn runs_for_company(schedules: &[JobSchedule], company_id: i32, only: Option<i32>) -> bool {
+    let schedule = schedules.iter().find(|s| s.cio_company_id == company_id);
+    if schedule.map(|s| !s.enabled).unwrap_or(false) {
+        return false;
+    }
+
+    match only {
+        Some(only) => only == company_id,
+        None => !schedule.map(|s| s.has_own_schedule()).unwrap_or(false),
+    }
+}
+
+/// The schedule of a job, with when it runs next and how it last ran.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct JobScheduleStatus {
+    pub name: String,
+    pub cron: String,
+    pub timezone: String,
+    pub enabled: bool,
+    /// The companies the job is turned off for.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disabled_company_ids: Vec<i32>,
+    /// The schedules of the companies that run the job on their own, with what they don't set
+    /// filled in from the job.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub company_schedules: Vec<JobSchedule>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_run_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub last_status: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub last_conclusion: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_success_at: Option<DateTime<Utc>>,
+}
+
+/// Get the schedule of every job, with its next and last runs from the functions.
+pub fn get_job_schedule_statuses(db: &Database) -> Result<Vec<JobScheduleStatus>> {
+    let now = Utc::now();
+    let overrides = job_schedules::dsl::job_schedules
+        .filter(job_schedules::dsl::cio_company_id.ne(0))
+        .load::<JobSchedule>(&db.conn()?)?;
+
+    let mut statuses = Vec::new();
+    for schedule in JobSchedule::get_for_jobs(db)? {
+        let last_run = functions::dsl::functions
+            .filter(functions::dsl::name.eq(schedule.name.to_string()))
+            .order_by(functions::dsl::created_at.desc())
+            .first::<Function>(&db.conn()?)
+            .optional()?;
+        let last_success = functions::dsl::functions
+            .filter(functions::dsl::name.eq(schedule.name.to_string()))
+            .filter(functions::dsl::conclusion.eq(octorust::types::Conclusion::Success.to_string()))
+            .order_by(functions::dsl::created_at.desc())
+            .first::<Function>(&db.conn()?)
+            .optional()?;
+
+        statuses.push(JobScheduleStatus {
+            next_run_at: schedule.next_run_after(now)?,
+            disabled_company_ids: overrides
+                .iter()
+                .filter(|o| o.name == schedule.name && !o.enabled)
+                .map(|o| o.cio_company_id)
+                .collect(),
+            company_schedules: overrides
+                .iter()
+                .filter(|o| o.name == schedule.name && o.enabled && o.has_own_schedule())
+                .map(|o| o.for_company(&schedule))
+                .collect(),
+            last_run_at: last_run.as_ref().map(|f| f.created_at),
+            last_status: last_run.as_ref().map(|f| f.status.to_string()).unwrap_or_default(),
+            last_conclusion: last_run.map(|f| f.conclusion).unwrap_or_default(),
+            last_success_at: last_success.and_then(|f| f.completed_at),
+            name: schedule.name,
+            cron: schedule.cron,
+            timezone: schedule.timezone,
+            enabled: schedule.enabled,
+        });
+    }
+
+    Ok(statuses)
+}
+
+fn parse_timezone(timezone: &str) -> Result<Tz> {
+    match timezone.parse::<Tz>() {
+        Ok(tz) => Ok(tz),
+        Err(_) => bail!("invalid timezone: `{}`", timezone),
+    }
+}
+
+/// Returns the schedule for a cron expression with five fields: minute, hour, day of the
+/// month, month and day of the week, ie. `0 */6 * * *`. The `cron` crate wants the seconds too,
+/// the jobs always start on the minute.
+pub fn parse_cron(expression: &str) -> Result<Schedule> {
+    let fields: Vec<&str> = expression.split_whitespace().collect();
+    if fields.len() != 5 {
+        bail!("invalid cron expression: `{}`, it needs five fields", expression);
+    }
+
+    let days_of_week = days_of_week_to_names(fields[4])?;
+    let cron = format!("0 {} {}", fields[..4].join(" "), days_of_week);
+    match Schedule::from_str(&cron) {
+        Ok(schedule) => Ok(schedule),
+        Err(e) => bail!("invalid cron expression: `{}`: {}", expression, e),
+    }
+}
+
+/// The names of the days of the week, from 0 for Sunday like in a crontab. 7 is Sunday too.
+static DAYS_OF_WEEK: &[&str] = &["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// The `cron` crate numbers the days of the week from 1 for Sunday, where a crontab numbers them
+/// from 0, so `1-5` would run from Sunday to Thursday. We swap the numbers for the names of the
+/// days, which mean the same to both. The steps, after a `/`, are left alone.
+fn days_of_week_to_names(field: &str) -> Result<String> {
+    let mut items = Vec::new();
+    for item in field.split(',') {
+        let (range, step) = match item.find('/') {
+            Some(i) => item.split_at(i),
+            None => (item, ""),
+        };
+
+        let mut days = Vec::new();
+        for day in range.split('-') {
+            match day.parse::<usize>() {
+                Ok(n) if n < DAYS_OF_WEEK.len() => days.push(DAYS_OF_WEEK[n].to_string()),
+                Ok(n) => bail!("invalid day of the week: `{}`, it is 0 to 7", n),
+                Err(_) => days.push(day.to_string()),
+            }
+        }
+        items.push(format!("{}{}", days.join("-"), step));
+    }
+
+    Ok(items.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::job_schedules::{
+        default_timezone, parse_cron, parse_timezone, runs_for_company, JobSchedule, DEFAULT_SCHEDULES,
+        DEFAULT_SCHEDULE_TIMEZONES, DEFAULT_TIMEZONE,
+    };
+
+    #[test]
+    fn test_job_schedules_cron() {
+        for (name, cron) in DEFAULT_SCHEDULES {
+            assert!(parse_cron(cron).is_ok(), "{}", name);
+            assert!(parse_timezone(default_timezone(name)).is_ok(), "{}", name);
+        }
+        for (name, _) in DEFAULT_SCHEDULE_TIMEZONES {
+            assert!(DEFAULT_SCHEDULES.iter().any(|(n, _)| n == name), "{}", name);
+        }
+        assert_eq!(default_timezone("sync-rfds"), DEFAULT_TIMEZONE);
+        assert!(parse_cron("* * * *").is_err());
+        assert!(parse_cron("0 * * * * *").is_err());
+        assert!(parse_cron("60 * * * *").is_err());
+
+        let pacific = chrono_tz::US::Pacific;
+        let after = pacific.ymd(2022, 1, 17).and_hms(9, 12, 30);
+
+        let every_six_hours = parse_cron("10 */6 * * *").unwrap();
+        assert_eq!(
+            every_six_hours.after(&after).next().unwrap(),
+            pacific.ymd(2022, 1, 17).and_hms(12, 10, 0)
+        );
+
+        let mondays = parse_cron("0 8 * * Mon").unwrap();
+        assert_eq!(
+            mondays.after(&after).next().unwrap(),
+            pacific.ymd(2022, 1, 24).and_hms(8, 0, 0)
+        );
+
+        // The changelog goes out on Monday at 17:00 UTC.
+        assert_eq!(default_timezone("send-rfd-changelog"), "UTC");
+        let changelog = parse_cron("0 17 * * Mon").unwrap();
+        assert_eq!(
+            changelog
+                .after(&chrono_tz::UTC.ymd(2022, 1, 17).and_hms(9, 0, 0))
+                .next()
+                .unwrap(),
+            chrono_tz::UTC.ymd(2022, 1, 17).and_hms(17, 0, 0)
+        );
+
+        let twice = parse_cron("15 7,19 1-3 * *").unwrap();
+        assert_eq!(
+            twice.after(&after).next().unwrap(),
+            pacific.ymd(2022, 2, 1).and_hms(7, 15, 0)
+        );
+
+        // The days of the week are numbered like in a crontab, from 0 for Sunday.
+        let weekdays = parse_cron("0 8 * * 1-5").unwrap();
+        assert_eq!(
+            weekdays
+                .after(&pacific.ymd(2022, 1, 21).and_hms(9, 0, 0))
+                .next()
+                .unwrap(),
+            pacific.ymd(2022, 1, 24).and_hms(8, 0, 0)
+        );
+        let sundays = parse_cron("0 8 * * 0").unwrap();
+        assert_eq!(
+            sundays.after(&after).next().unwrap(),
+            pacific.ymd(2022, 1, 23).and_hms(8, 0, 0)
+        );
+        assert_eq!(
+            parse_cron("0 8 * * 7").unwrap().after(&after).next().unwrap(),
+            pacific.ymd(2022, 1, 23).and_hms(8, 0, 0)
+        );
+        let weekends = parse_cron("0 8 * * 6,0").unwrap();
+        assert_eq!(
+            weekends.after(&after).next().unwrap(),
+            pacific.ymd(2022, 1, 22).and_hms(8, 0, 0)
+        );
+        assert!(parse_cron("0 8 * * 8").is_err());
+
+        // It is the same instant in any timezone.
+        let utc = Utc.ymd(2022, 1, 17).and_hms(17, 12, 30);
+        assert_eq!(
+            every_six_hours
+                .after(&utc.with_timezone(&pacific))
+                .next()
+                .unwrap()
+                .with_timezone(&Utc),
+            Utc.ymd(2022, 1, 17).and_hms(20, 10, 0)
+        );
+    }
+
+    #[test]
+    fn test_job_schedules_for_company() {
+        let job = JobSchedule {
+            id: 1,
+            name: "sync-rfds".to_string(),
+            cron: "15 */12 * * *".to_string(),
+            timezone: "America/Los_Angeles".to_string(),
+            enabled: true,
+            updated_at: Utc::now(),
+            cio_company_id: 0,
+        };
+        let follows = JobSchedule {
+            id: 2,
+            cron: String::new(),
+            timezone: String::new(),
+            cio_company_id: 2,
+            ..job.clone()
+        };
+        let own = JobSchedule {
+            id: 3,
+            cron: "0 6 * * *".to_string(),
+            cio_company_id: 3,
+            ..follows.clone()
+        };
+        let disabled = JobSchedule {
+            id: 4,
+            enabled: false,
+            cio_company_id: 4,
+            ..own.clone()
+        };
+
+        assert!(!follows.has_own_schedule());
+        assert!(own.has_own_schedule());
+        assert!(!job.has_own_schedule());
+
+        // What the company doesn't set comes from the job.
+        let effective = own.for_company(&job);
+        assert_eq!(effective.cron, "0 6 * * *");
+        assert_eq!(effective.timezone, "America/Los_Angeles");
+        assert_eq!(effective.cio_company_id, 3);
+        assert!(effective.enabled);
+        let job_disabled = JobSchedule {
+            enabled: false,
+            ..job.clone()
+        };
+        assert!(!own.for_company(&job_disabled).enabled);
+
+        // The run of the job is for everyone but the companies with their own schedule, or
+        // that turned it off.
+        let schedules = vec![follows, own, disabled];
+        assert!(runs_for_company(&schedules, 1, None));
+        assert!(runs_for_company(&schedules, 2, None));
+        assert!(!runs_for_company(&schedules, 3, None));
+        assert!(!runs_for_company(&schedules, 4, None));
+
+        // The run for a company is only for that one, unless it turned the job off.
+        assert!(runs_for_company(&schedules, 3, Some(3)));
+        assert!(!runs_for_company(&schedules, 1, Some(3)));
+        assert!(runs_for_company(&schedules, 1, Some(1)));
+        assert!(!runs_for_company(&schedules, 4, Some(4)));
+    }
+}
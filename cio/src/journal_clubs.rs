@@ -14,6 +14,7 @@ use crate::{
     companies::Company,
     core::UpdateAirtableRecord,
     db::Database,
+    error::CioError,
     schema::{journal_club_meetings, journal_club_papers},
     utils::get_file_content_from_repo,
 };
@@ -147,7 +148,7 @@ impl From<JournalClubMeeting> for FormattedMessage {
 /// Implement updating the Airtable record for a JournalClubMeeting.
 #[async_trait]
 impl UpdateAirtableRecord<JournalClubMeeting> for JournalClubMeeting {
-    async fn update_airtable_record(&mut self, record: JournalClubMeeting) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, record: JournalClubMeeting) -> Result<()> {
         // Set the papers field, since it is pre-populated as table links.
         self.papers = record.papers;
 
@@ -183,11 +184,10 @@ pub struct NewJournalClubPaper {
 /// Implement updating the Airtable record for a JournalClubPaper.
 #[async_trait]
 impl UpdateAirtableRecord<JournalClubPaper> for JournalClubPaper {
-    async fn update_airtable_record(&mut self, _record: JournalClubPaper) -> Result<()> {
+    async fn update_airtable_record(&mut self, db: &Database, _record: JournalClubPaper) -> Result<()> {
         // Get the current journal club meetings in Airtable so we can link to it.
         // TODO: make this more dry so we do not call it every single damn time.
-        let db = Database::new();
-        let journal_club_meetings = JournalClubMeetings::get_from_airtable(&db, self.cio_company_id).await?;
+        let journal_club_meetings = JournalClubMeetings::get_from_airtable(db, self.cio_company_id).await?;
 
         // Iterate over the journal_club_meetings and see if we find a match.
         for (_id, meeting_record) in journal_club_meetings {
@@ -300,7 +300,7 @@ pub async fn get_meetings_from_repo(github: &octorust::Client, company: &Company
     let r = github.repos().get(owner, repo).await;
 
     if let Err(e) = r {
-        if e.to_string().contains("404 Not Found") {
+        if CioError::is_not_found(&e) {
             // They don't have a paper's repo, let's just return early.
             return Ok(Default::default());
         }
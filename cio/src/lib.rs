@@ -3,7 +3,10 @@
 #![allow(clippy::nonstandard_macro_braces)]
 
 pub mod airtable;
+#[cfg(test)]
+mod airtable_stand_in;
 pub mod analytics;
+pub mod api_keys;
 pub mod api_tokens;
 pub mod applicant_reviews;
 pub mod applicant_status;
@@ -19,20 +22,36 @@ pub mod core;
 pub mod customers;
 pub mod db;
 pub mod dns_providers;
+pub mod error;
 pub mod finance;
 pub mod functions;
 pub mod github_commits;
 pub mod github_prs;
 pub mod gsuite;
+pub mod history;
 pub mod huddles;
 pub mod interviews;
+pub mod job_schedules;
 pub mod journal_clubs;
 pub mod mailing_list;
+pub mod metrics;
+pub mod mirror;
+pub mod outbox;
+pub mod pagination;
 pub mod providers;
 pub mod rack_line;
+pub mod reconcile;
 pub mod recorded_meetings;
 pub mod repos;
+pub mod rfd_archive;
+pub mod rfd_authors;
+pub mod rfd_references;
+pub mod rfd_renders;
+pub mod rfd_sections;
+pub mod rfd_states;
+pub mod rfd_subscriptions;
 pub mod rfds;
+pub mod sagas;
 pub mod schema;
 pub mod shipment_status;
 pub mod shipments;
@@ -44,6 +63,8 @@ pub mod tailscale;
 pub mod templates;
 pub mod travel;
 pub mod utils;
+pub mod webhook_events;
+pub mod webhook_secrets;
 
 #[macro_use]
 extern crate diesel;
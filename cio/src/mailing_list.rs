@@ -11,7 +11,7 @@ use slack_chat_api::{FormattedMessage, MessageBlock, MessageBlockText, MessageBl
 
 use crate::{
     airtable::AIRTABLE_MAILING_LIST_SIGNUPS_TABLE, companies::Company, core::UpdateAirtableRecord, db::Database,
-    schema::mailing_list_subscribers,
+    error::CioError, schema::mailing_list_subscribers,
 };
 
 /// The data type for a MailingListSubscriber.
@@ -247,7 +247,7 @@ impl Default for NewMailingListSubscriber {
 /// Implement updating the Airtable record for a MailingListSubscriber.
 #[async_trait]
 impl UpdateAirtableRecord<MailingListSubscriber> for MailingListSubscriber {
-    async fn update_airtable_record(&mut self, record: MailingListSubscriber) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, record: MailingListSubscriber) -> Result<()> {
         // Set the link_to_people from the original so it stays intact.
         self.link_to_people = record.link_to_people;
 
@@ -264,7 +264,7 @@ pub async fn refresh_db_mailing_list_subscribers(db: &Database, company: &Compan
 
     let mailchimp_auth = company.authenticate_mailchimp(db).await;
     if let Err(e) = mailchimp_auth {
-        if e.to_string().contains("no token") {
+        if CioError::is_not_connected(&e) {
             // Return early, this company does not use MailChimp.
             return Ok(());
         }
@@ -1,19 +1,32 @@
 use std::{fs::File, sync::Arc};
 
+use chrono::{DateTime, NaiveDate, Utc};
 use cio_api::{
+    api_keys::{ApiKey, ApiScope},
     applicants::{Applicant, Applicants},
     auth_logins::{AuthUser, AuthUsers},
     configs::{Building, Buildings, ConferenceRoom, ConferenceRooms, Group, Groups, Link, Links, User, Users},
     db::Database,
     journal_clubs::{JournalClubMeeting, JournalClubMeetings},
     mailing_list::{MailingListSubscriber, MailingListSubscribers},
+    metrics,
+    pagination::{ListParams, ListQuery, Page},
     repos::{GithubRepo, GithubRepos},
-    rfds::{RFDs, RFD},
+    rfd_authors::RFDAuthor,
+    rfd_sections::RFDSection,
+    rfds::{search_rfds, RFDSearchResult, RFDs, RFD},
+    schema::{applicants, journal_club_meetings, mailing_list_subscribers, rfds, users},
 };
+use diesel::ExpressionMethods;
 use dropshot::{
     endpoint, ApiDescription, ConfigDropshot, ConfigLogging, ConfigLoggingLevel, HttpError, HttpResponseOk,
-    HttpServerStarter, RequestContext,
+    HttpServerStarter, Path, Query, RequestContext,
 };
+use hyper::{header::CONTENT_TYPE, Body, Response};
+use macros::request_metrics;
+use reqwest::StatusCode;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
 #[tokio::main]
 async fn main() -> Result<(), String> {
@@ -54,9 +67,12 @@ async fn main() -> Result<(), String> {
     api.register(api_get_journal_club_meetings).unwrap();
     api.register(api_get_links).unwrap();
     api.register(api_get_mailing_list_subscribers).unwrap();
+    api.register(api_get_metrics).unwrap();
+    api.register(api_get_rfd).unwrap();
     api.register(api_get_rfds).unwrap();
     api.register(api_get_schema).unwrap();
     api.register(api_get_users).unwrap();
+    api.register(api_search_rfds).unwrap();
 
     // Print the OpenAPI Spec to stdout.
     let mut api_definition = &mut api.openapi(&"CIO API", &"0.0.1");
@@ -73,7 +89,7 @@ async fn main() -> Result<(), String> {
     /*
      * The functions that implement our API endpoints will share this context.
      */
-    let api_context = Context::new(schema).await;
+    let api_context = Context::new(schema).await?;
 
     /*
      * Set up the server.
@@ -97,12 +113,174 @@ impl Context {
     /**
      * Return a new Context.
      */
-    pub async fn new(schema: String) -> Context {
-        Context {
+    pub async fn new(schema: String) -> Result<Context, String> {
+        Ok(Context {
             schema,
-            db: Database::new(),
+            db: Database::new().map_err(|e| e.to_string())?,
+        })
+    }
+}
+
+/**
+ * Authenticate a request with the API key in its headers, and make sure the key has the
+ * scope the endpoint needs. The endpoints only return the records for the key's company.
+ */
+async fn authorize(rqctx: &Arc<RequestContext<Context>>, scope: Option<ApiScope>) -> Result<ApiKey, HttpError> {
+    let headers = rqctx.request.lock().await.headers().clone();
+    let db = &rqctx.context().db;
+
+    let api_key = ApiKey::authenticate(db, &headers)
+        .await
+        .map_err(|e| HttpError::for_internal_error(format!("authenticating the request failed: {:?}", e)))?
+        .ok_or_else(|| {
+            HttpError::for_client_error(
+                None,
+                StatusCode::UNAUTHORIZED,
+                "a valid API key is required".to_string(),
+            )
+        })?;
+
+    if let Some(scope) = scope {
+        if !api_key.allows(scope) {
+            return Err(HttpError::for_client_error(
+                None,
+                StatusCode::FORBIDDEN,
+                format!("the API key does not have the `{}` scope", scope),
+            ));
         }
     }
+
+    Ok(api_key)
+}
+
+/**
+ * The query parameters for listing applicants.
+ */
+#[derive(Deserialize, JsonSchema)]
+struct ApplicantsQuery {
+    #[serde(flatten)]
+    list: ListQuery,
+    /// Only return the applicants with this status, ie. `interviewing`.
+    status: Option<String>,
+    /// Only return the applicants for this role.
+    role: Option<String>,
+    /// Only return the applicants who applied at or after this time.
+    submitted_after: Option<DateTime<Utc>>,
+    /// Only return the applicants who applied before this time.
+    submitted_before: Option<DateTime<Utc>>,
+}
+
+/**
+ * The query parameters for listing journal club meetings.
+ */
+#[derive(Deserialize, JsonSchema)]
+struct JournalClubMeetingsQuery {
+    #[serde(flatten)]
+    list: ListQuery,
+    /// Only return the meetings in this state, ie. `open`.
+    state: Option<String>,
+    /// Only return the meetings on or after this date.
+    meeting_after: Option<NaiveDate>,
+    /// Only return the meetings before this date.
+    meeting_before: Option<NaiveDate>,
+}
+
+/**
+ * The query parameters for listing mailing list subscribers.
+ */
+#[derive(Deserialize, JsonSchema)]
+struct MailingListSubscribersQuery {
+    #[serde(flatten)]
+    list: ListQuery,
+    /// Only return the subscribers added at or after this time.
+    added_after: Option<DateTime<Utc>>,
+    /// Only return the subscribers added before this time.
+    added_before: Option<DateTime<Utc>>,
+}
+
+/**
+ * The query parameters for listing RFDs.
+ */
+#[derive(Deserialize, JsonSchema)]
+struct RFDsQuery {
+    #[serde(flatten)]
+    list: ListQuery,
+    /// Only return the RFDs in this state, ie. `published`.
+    state: Option<String>,
+    /// Only return the RFDs changed at or after this time.
+    updated_after: Option<DateTime<Utc>>,
+    /// Only return the RFDs changed before this time.
+    updated_before: Option<DateTime<Utc>>,
+    /// Only return the RFDs the user with this username is an author of.
+    author: Option<String>,
+}
+
+/**
+ * The query parameters for listing users.
+ */
+#[derive(Deserialize, JsonSchema)]
+struct UsersQuery {
+    #[serde(flatten)]
+    list: ListQuery,
+    /// Only return the users in this department.
+    department: Option<String>,
+    /// Only return the users who started on or after this date.
+    started_after: Option<NaiveDate>,
+    /// Only return the users who started before this date.
+    started_before: Option<NaiveDate>,
+}
+
+/**
+ * The path parameters for an RFD.
+ */
+#[derive(Deserialize, JsonSchema)]
+struct RFDPathParams {
+    number: i32,
+}
+
+/**
+ * The query parameters for searching RFDs.
+ */
+#[derive(Deserialize, JsonSchema)]
+struct SearchQuery {
+    /// The words to search for.
+    q: String,
+    /// How many results to return, at most 100. The default is 20.
+    limit: Option<u32>,
+}
+
+/**
+ * An RFD with its sections.
+ */
+#[derive(Serialize, JsonSchema)]
+struct RFDWithSections {
+    #[serde(flatten)]
+    rfd: RFD,
+    /// The sections of the RFD, in the order they appear.
+    sections: Vec<LinkedRFDSection>,
+}
+
+/**
+ * A section of an RFD, with the link to it in the rendered RFD.
+ */
+#[derive(Serialize, JsonSchema)]
+struct LinkedRFDSection {
+    #[serde(flatten)]
+    section: RFDSection,
+    link: String,
+}
+
+/**
+ * Check the parameters every list endpoint takes.
+ */
+fn list_params<T: JsonSchema>(query: ListQuery) -> Result<ListParams, HttpError> {
+    query
+        .parse::<T>()
+        .map_err(|e| HttpError::for_bad_request(None, e.to_string()))
+}
+
+fn internal_error(e: anyhow::Error) -> HttpError {
+    HttpError::for_internal_error(format!("{:?}", e))
 }
 
 /*
@@ -112,175 +290,405 @@ impl Context {
 /**
  * Return the OpenAPI schema in JSON format.
  */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/",
 }]
 async fn api_get_schema(rqctx: Arc<RequestContext<Context>>) -> Result<HttpResponseOk<String>, HttpError> {
+    authorize(&rqctx, None).await?;
     let api_context = rqctx.context();
 
     Ok(HttpResponseOk(api_context.schema.to_string()))
 }
 
 /**
- * Fetch all auth users.
+ * Return the metrics for the server in the Prometheus text format. This is for our scraper,
+ * so unlike the other endpoints it takes the `METRICS_TOKEN` instead of an API key.
  */
+#[request_metrics]
+#[endpoint {
+    method = GET,
+    path = "/metrics",
+    unpublished = true,
+}]
+async fn api_get_metrics(rqctx: Arc<RequestContext<Context>>) -> Result<Response<Body>, HttpError> {
+    let api_context = rqctx.context();
+
+    if !metrics::authorized(rqctx.request.lock().await.headers()) {
+        return Err(HttpError::for_client_error(
+            None,
+            StatusCode::UNAUTHORIZED,
+            "a valid metrics token is required".to_string(),
+        ));
+    }
+
+    let body = metrics::render(&api_context.db)
+        .map_err(|e| HttpError::for_internal_error(format!("rendering the metrics failed: {:?}", e)))?;
+
+    Response::builder()
+        .header(CONTENT_TYPE, metrics::TEXT_FORMAT)
+        .body(body.into())
+        .map_err(|e| HttpError::for_internal_error(format!("building the response failed: {}", e)))
+}
+
+/**
+ * Fetch auth users, the newest first.
+ */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/users",
 }]
-async fn api_get_auth_users(rqctx: Arc<RequestContext<Context>>) -> Result<HttpResponseOk<Vec<AuthUser>>, HttpError> {
+async fn api_get_auth_users(
+    rqctx: Arc<RequestContext<Context>>,
+    query_params: Query<ListQuery>,
+) -> Result<HttpResponseOk<Page<AuthUser>>, HttpError> {
+    let api_key = authorize(&rqctx, Some(ApiScope::ReadAuthUsers)).await?;
+    let list = list_params::<AuthUser>(query_params.into_inner())?;
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    Ok(HttpResponseOk(AuthUsers::get_from_db(db, 1).unwrap().0))
+    let query = AuthUsers::query(api_key.cio_company_id);
+
+    Ok(HttpResponseOk(query.list(db, &list).map_err(internal_error)?))
 }
 
 /**
- * Fetch all applicants.
+ * Fetch applicants, the newest first.
  */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/applicants",
 }]
-async fn api_get_applicants(rqctx: Arc<RequestContext<Context>>) -> Result<HttpResponseOk<Vec<Applicant>>, HttpError> {
+async fn api_get_applicants(
+    rqctx: Arc<RequestContext<Context>>,
+    query_params: Query<ApplicantsQuery>,
+) -> Result<HttpResponseOk<Page<Applicant>>, HttpError> {
+    let api_key = authorize(&rqctx, Some(ApiScope::ReadApplicants)).await?;
+    let params = query_params.into_inner();
+    let list = list_params::<Applicant>(params.list)?;
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    Ok(HttpResponseOk(Applicants::get_from_db(db, 1).unwrap().0))
+    let mut query = Applicants::query(api_key.cio_company_id);
+    if let Some(status) = params.status {
+        query = query.filter(applicants::dsl::status.eq(status));
+    }
+    if let Some(role) = params.role {
+        query = query.filter(applicants::dsl::role.eq(role));
+    }
+    if let Some(submitted_after) = params.submitted_after {
+        query = query.filter(applicants::dsl::submitted_time.ge(submitted_after));
+    }
+    if let Some(submitted_before) = params.submitted_before {
+        query = query.filter(applicants::dsl::submitted_time.lt(submitted_before));
+    }
+
+    Ok(HttpResponseOk(query.list(db, &list).map_err(internal_error)?))
 }
 
 /**
  * Fetch a list of office buildings.
  */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/buildings",
 }]
-async fn api_get_buildings(rqctx: Arc<RequestContext<Context>>) -> Result<HttpResponseOk<Vec<Building>>, HttpError> {
+async fn api_get_buildings(
+    rqctx: Arc<RequestContext<Context>>,
+    query_params: Query<ListQuery>,
+) -> Result<HttpResponseOk<Page<Building>>, HttpError> {
+    let api_key = authorize(&rqctx, Some(ApiScope::ReadDirectory)).await?;
+    let list = list_params::<Building>(query_params.into_inner())?;
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    Ok(HttpResponseOk(Buildings::get_from_db(db, 1).unwrap().0))
+    let query = Buildings::query(api_key.cio_company_id);
+
+    Ok(HttpResponseOk(query.list(db, &list).map_err(internal_error)?))
 }
 
 /**
  * Fetch a list of conference rooms.
  */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/conference_rooms",
 }]
-#[inline]
 async fn api_get_conference_rooms(
     rqctx: Arc<RequestContext<Context>>,
-) -> Result<HttpResponseOk<Vec<ConferenceRoom>>, HttpError> {
+    query_params: Query<ListQuery>,
+) -> Result<HttpResponseOk<Page<ConferenceRoom>>, HttpError> {
+    let api_key = authorize(&rqctx, Some(ApiScope::ReadDirectory)).await?;
+    let list = list_params::<ConferenceRoom>(query_params.into_inner())?;
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    Ok(HttpResponseOk(ConferenceRooms::get_from_db(db, 1).unwrap().0))
+    let query = ConferenceRooms::query(api_key.cio_company_id);
+
+    Ok(HttpResponseOk(query.list(db, &list).map_err(internal_error)?))
 }
 
 /**
  * Fetch a list of our GitHub repositories.
  */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/github/repos",
 }]
 async fn api_get_github_repos(
     rqctx: Arc<RequestContext<Context>>,
-) -> Result<HttpResponseOk<Vec<GithubRepo>>, HttpError> {
+    query_params: Query<ListQuery>,
+) -> Result<HttpResponseOk<Page<GithubRepo>>, HttpError> {
+    let api_key = authorize(&rqctx, Some(ApiScope::ReadRepos)).await?;
+    let list = list_params::<GithubRepo>(query_params.into_inner())?;
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    Ok(HttpResponseOk(GithubRepos::get_from_db(db, 1).unwrap().0))
+    let query = GithubRepos::query(api_key.cio_company_id);
+
+    Ok(HttpResponseOk(query.list(db, &list).map_err(internal_error)?))
 }
 
 /**
  * Fetch a list of Google groups.
  */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/groups",
 }]
-async fn api_get_groups(rqctx: Arc<RequestContext<Context>>) -> Result<HttpResponseOk<Vec<Group>>, HttpError> {
+async fn api_get_groups(
+    rqctx: Arc<RequestContext<Context>>,
+    query_params: Query<ListQuery>,
+) -> Result<HttpResponseOk<Page<Group>>, HttpError> {
+    let api_key = authorize(&rqctx, Some(ApiScope::ReadDirectory)).await?;
+    let list = list_params::<Group>(query_params.into_inner())?;
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    Ok(HttpResponseOk(Groups::get_from_db(db, 1).unwrap().0))
+    let query = Groups::query(api_key.cio_company_id);
+
+    Ok(HttpResponseOk(query.list(db, &list).map_err(internal_error)?))
 }
 
 /**
  * Fetch a list of journal club meetings.
  */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/journal_club_meetings",
 }]
 async fn api_get_journal_club_meetings(
     rqctx: Arc<RequestContext<Context>>,
-) -> Result<HttpResponseOk<Vec<JournalClubMeeting>>, HttpError> {
+    query_params: Query<JournalClubMeetingsQuery>,
+) -> Result<HttpResponseOk<Page<JournalClubMeeting>>, HttpError> {
+    let api_key = authorize(&rqctx, Some(ApiScope::ReadJournalClubs)).await?;
+    let params = query_params.into_inner();
+    let list = list_params::<JournalClubMeeting>(params.list)?;
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    Ok(HttpResponseOk(JournalClubMeetings::get_from_db(db, 1).unwrap().0))
+    let mut query = JournalClubMeetings::query(api_key.cio_company_id);
+    if let Some(state) = params.state {
+        query = query.filter(journal_club_meetings::dsl::state.eq(state));
+    }
+    if let Some(meeting_after) = params.meeting_after {
+        query = query.filter(journal_club_meetings::dsl::meeting_date.ge(meeting_after));
+    }
+    if let Some(meeting_before) = params.meeting_before {
+        query = query.filter(journal_club_meetings::dsl::meeting_date.lt(meeting_before));
+    }
+
+    Ok(HttpResponseOk(query.list(db, &list).map_err(internal_error)?))
 }
 
 /**
  * Fetch a list of internal links.
  */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/links",
 }]
-async fn api_get_links(rqctx: Arc<RequestContext<Context>>) -> Result<HttpResponseOk<Vec<Link>>, HttpError> {
+async fn api_get_links(
+    rqctx: Arc<RequestContext<Context>>,
+    query_params: Query<ListQuery>,
+) -> Result<HttpResponseOk<Page<Link>>, HttpError> {
+    let api_key = authorize(&rqctx, Some(ApiScope::ReadDirectory)).await?;
+    let list = list_params::<Link>(query_params.into_inner())?;
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    Ok(HttpResponseOk(Links::get_from_db(db, 1).unwrap().0))
+    let query = Links::query(api_key.cio_company_id);
+
+    Ok(HttpResponseOk(query.list(db, &list).map_err(internal_error)?))
 }
 
 /**
  * Fetch a list of mailing list subscribers.
  */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/mailing_list_subscribers",
 }]
 async fn api_get_mailing_list_subscribers(
     rqctx: Arc<RequestContext<Context>>,
-) -> Result<HttpResponseOk<Vec<MailingListSubscriber>>, HttpError> {
+    query_params: Query<MailingListSubscribersQuery>,
+) -> Result<HttpResponseOk<Page<MailingListSubscriber>>, HttpError> {
+    let api_key = authorize(&rqctx, Some(ApiScope::ReadMailingList)).await?;
+    let params = query_params.into_inner();
+    let list = list_params::<MailingListSubscriber>(params.list)?;
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    Ok(HttpResponseOk(MailingListSubscribers::get_from_db(db, 1).unwrap().0))
+    let mut query = MailingListSubscribers::query(api_key.cio_company_id);
+    if let Some(added_after) = params.added_after {
+        query = query.filter(mailing_list_subscribers::dsl::date_added.ge(added_after));
+    }
+    if let Some(added_before) = params.added_before {
+        query = query.filter(mailing_list_subscribers::dsl::date_added.lt(added_before));
+    }
+
+    Ok(HttpResponseOk(query.list(db, &list).map_err(internal_error)?))
 }
 
 /**
- * Fetch all RFDs.
+ * Fetch RFDs, the newest first.
  */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/rfds",
 }]
-async fn api_get_rfds(rqctx: Arc<RequestContext<Context>>) -> Result<HttpResponseOk<Vec<RFD>>, HttpError> {
+async fn api_get_rfds(
+    rqctx: Arc<RequestContext<Context>>,
+    query_params: Query<RFDsQuery>,
+) -> Result<HttpResponseOk<Page<RFD>>, HttpError> {
+    let api_key = authorize(&rqctx, Some(ApiScope::ReadRFDs)).await?;
+    let params = query_params.into_inner();
+    let list = list_params::<RFD>(params.list)?;
+    let api_context = rqctx.context();
+    let db = &api_context.db;
+
+    let mut query = RFDs::query(api_key.cio_company_id);
+    if let Some(state) = params.state {
+        query = query.filter(rfds::dsl::state.eq(state));
+    }
+    if let Some(updated_after) = params.updated_after {
+        query = query.filter(rfds::dsl::commit_date.ge(updated_after));
+    }
+    if let Some(updated_before) = params.updated_before {
+        query = query.filter(rfds::dsl::commit_date.lt(updated_before));
+    }
+    if let Some(author) = params.author {
+        let user = User::get_from_db(db, api_key.cio_company_id, author.to_string())
+            .ok_or_else(|| HttpError::for_not_found(None, format!("user {} not found", author)))?;
+        let ids: Vec<i32> = RFDAuthor::get_rfds_for_user(db, user.id)
+            .map_err(internal_error)?
+            .iter()
+            .map(|rfd| rfd.id)
+            .collect();
+        query = query.filter(rfds::dsl::id.eq_any(ids));
+    }
+
+    Ok(HttpResponseOk(query.list(db, &list).map_err(internal_error)?))
+}
+
+/**
+ * Fetch an RFD by its number, with its rendered HTML, PDF links and sections.
+ */
+#[request_metrics]
+#[endpoint {
+    method = GET,
+    path = "/rfds/{number}",
+}]
+async fn api_get_rfd(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<RFDPathParams>,
+) -> Result<HttpResponseOk<RFDWithSections>, HttpError> {
+    let api_key = authorize(&rqctx, Some(ApiScope::ReadRFDs)).await?;
+    let number = path_params.into_inner().number;
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    Ok(HttpResponseOk(RFDs::get_from_db(db, 1).unwrap().0))
+    let rfd = RFDs::query(api_key.cio_company_id)
+        .filter(rfds::dsl::number.eq(number))
+        .first(db)
+        .map_err(internal_error)?
+        .ok_or_else(|| HttpError::for_not_found(None, format!("RFD {} not found", number)))?;
+    let sections = RFDSection::get_for_rfd(db, rfd.id)
+        .map_err(internal_error)?
+        .into_iter()
+        .map(|section| LinkedRFDSection {
+            link: section.link(&rfd.rendered_link),
+            section,
+        })
+        .collect();
+
+    Ok(HttpResponseOk(RFDWithSections { rfd, sections }))
 }
 
 /**
  * Fetch a list of employees.
  */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/users",
 }]
-async fn api_get_users(rqctx: Arc<RequestContext<Context>>) -> Result<HttpResponseOk<Vec<User>>, HttpError> {
+async fn api_get_users(
+    rqctx: Arc<RequestContext<Context>>,
+    query_params: Query<UsersQuery>,
+) -> Result<HttpResponseOk<Page<User>>, HttpError> {
+    let api_key = authorize(&rqctx, Some(ApiScope::ReadDirectory)).await?;
+    let params = query_params.into_inner();
+    let list = list_params::<User>(params.list)?;
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    Ok(HttpResponseOk(Users::get_from_db(db, 1).unwrap().0))
+    let mut query = Users::query(api_key.cio_company_id);
+    if let Some(department) = params.department {
+        query = query.filter(users::dsl::department.eq(department));
+    }
+    if let Some(started_after) = params.started_after {
+        query = query.filter(users::dsl::start_date.ge(started_after));
+    }
+    if let Some(started_before) = params.started_before {
+        query = query.filter(users::dsl::start_date.lt(started_before));
+    }
+
+    Ok(HttpResponseOk(query.list(db, &list).map_err(internal_error)?))
+}
+
+/**
+ * Search the RFDs, the best matches first.
+ *
+ * This is not under `/rfds` since its path would clash with `/rfds/{number}`.
+ */
+#[request_metrics]
+#[endpoint {
+    method = GET,
+    path = "/search/rfds",
+}]
+async fn api_search_rfds(
+    rqctx: Arc<RequestContext<Context>>,
+    query_params: Query<SearchQuery>,
+) -> Result<HttpResponseOk<Vec<RFDSearchResult>>, HttpError> {
+    let api_key = authorize(&rqctx, Some(ApiScope::ReadRFDs)).await?;
+    let params = query_params.into_inner();
+    let api_context = rqctx.context();
+    let db = &api_context.db;
+
+    let limit = params.limit.unwrap_or(20) as i64;
+    let results = search_rfds(db, api_key.cio_company_id, &params.q, limit).map_err(internal_error)?;
+
+    Ok(HttpResponseOk(results))
 }
@@ -0,0 +1,225 @@
+use std::{env, time::Duration};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use lazy_static::lazy_static;
+pub use prometheus::TEXT_FORMAT;
+use prometheus::{
+    exponential_buckets, register_histogram_vec, register_int_counter_vec, register_int_gauge, register_int_gauge_vec,
+    Encoder, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, TextEncoder,
+};
+use reqwest::header::{HeaderMap, AUTHORIZATION};
+use reqwest_middleware::{Middleware, Next};
+use task_local_extensions::Extensions;
+
+use crate::db::Database;
+
+lazy_static! {
+    static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "http_requests_total",
+        "The requests we handled, by endpoint and status code.",
+        &["method", "endpoint", "status"]
+    )
+    .unwrap();
+    static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "http_request_duration_seconds",
+        "How long we took to handle requests, by endpoint.",
+        &["method", "endpoint"]
+    )
+    .unwrap();
+    static ref WEBHOOK_EVENTS: IntCounterVec = register_int_counter_vec!(
+        "webhook_events_total",
+        "The webhooks we received, by provider and how handling them went.",
+        &["provider", "outcome"]
+    )
+    .unwrap();
+    static ref JOB_RUNS: IntCounterVec = register_int_counter_vec!(
+        "job_runs_total",
+        "The functions we ran, by name and conclusion.",
+        &["name", "conclusion"]
+    )
+    .unwrap();
+    // The jobs take from a few seconds to the better part of an hour.
+    static ref JOB_DURATION: HistogramVec = register_histogram_vec!(
+        "job_duration_seconds",
+        "How long the functions we ran took, by name.",
+        &["name"],
+        exponential_buckets(1.0, 2.0, 13).unwrap()
+    )
+    .unwrap();
+    static ref JOB_LAST_SUCCESS: IntGaugeVec = register_int_gauge_vec!(
+        "job_last_success_timestamp_seconds",
+        "When the functions last succeeded, by name.",
+        &["name"]
+    )
+    .unwrap();
+    static ref DB_POOL_CONNECTIONS: IntGauge =
+        register_int_gauge!("db_pool_connections", "The connections in the database pool.").unwrap();
+    static ref DB_POOL_IDLE_CONNECTIONS: IntGauge = register_int_gauge!(
+        "db_pool_idle_connections",
+        "The connections in the database pool that are not in use."
+    )
+    .unwrap();
+    static ref DB_POOL_MAX_CONNECTIONS: IntGauge = register_int_gauge!(
+        "db_pool_max_connections",
+        "The most connections the database pool can have."
+    )
+    .unwrap();
+    static ref UPSTREAM_API_CALLS: IntCounterVec = register_int_counter_vec!(
+        "upstream_api_calls_total",
+        "The calls we made to the APIs of other services, by client.",
+        &["client"]
+    )
+    .unwrap();
+    static ref UPSTREAM_API_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "upstream_api_errors_total",
+        "The calls to the APIs of other services that failed, by client.",
+        &["client"]
+    )
+    .unwrap();
+}
+
+/// Returns the metrics in the Prometheus text format, `TEXT_FORMAT` is its content type.
+pub fn render(db: &Database) -> Result<String> {
+    // The pool is only looked at when we are asked for the metrics.
+    let (connections, idle_connections, max_connections) = db.pool_state();
+    DB_POOL_CONNECTIONS.set(connections.into());
+    DB_POOL_IDLE_CONNECTIONS.set(idle_connections.into());
+    DB_POOL_MAX_CONNECTIONS.set(max_connections.into());
+
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+
+    Ok(String::from_utf8(buffer)?)
+}
+
+/// Returns if the request may read the metrics. The scraper sends the `METRICS_TOKEN`
+/// environment variable as a bearer token, if it is not set nobody can read them.
+pub fn authorized(headers: &HeaderMap) -> bool {
+    has_token(headers, &env::var("METRICS_TOKEN").unwrap_or_default())
+}
+
+fn has_token(headers: &HeaderMap, token: &str) -> bool {
+    if token.is_empty() {
+        return false;
+    }
+
+    let value = match headers.get(AUTHORIZATION).and_then(|v| v.to_str().ok()) {
+        Some(v) => v,
+        None => return false,
+    };
+    let mut parts = value.splitn(2, ' ');
+    match (parts.next(), parts.next()) {
+        (Some(scheme), Some(given)) if scheme.eq_ignore_ascii_case("bearer") => {
+            sodiumoxide::utils::memcmp(given.trim().as_bytes(), token.as_bytes())
+        }
+        _ => false,
+    }
+}
+
+/// Count a webhook we received, the outcome is `rejected` if we could not verify it or the
+/// status of the event once we handled it.
+pub fn record_webhook_event(provider: &str, outcome: &str) {
+    WEBHOOK_EVENTS.with_label_values(&[provider, outcome]).inc();
+}
+
+/// Count a run of a function and how long it took.
+pub fn record_job_run(name: &str, conclusion: &str, duration: Duration) {
+    JOB_RUNS.with_label_values(&[name, conclusion]).inc();
+    JOB_DURATION.with_label_values(&[name]).observe(duration.as_secs_f64());
+
+    if conclusion == octorust::types::Conclusion::Success.to_string() {
+        JOB_LAST_SUCCESS.with_label_values(&[name]).set(Utc::now().timestamp());
+    }
+}
+
+/// Count a request to one of our endpoints by its method and path, with the status code we
+/// returned and how long it took. The `#[request_metrics]` attribute on the endpoints calls it.
+pub fn record_request(method: &str, endpoint: &str, status: u16, duration: Duration) {
+    HTTP_REQUESTS
+        .with_label_values(&[method, endpoint, &status.to_string()])
+        .inc();
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[method, endpoint])
+        .observe(duration.as_secs_f64());
+}
+
+/// Counts the calls made to the API of another service, and the ones that failed, by the
+/// client they were made with, ie. `airtable` or `shippo`. Our API clients send their
+/// requests through it, see `with_middleware` on the clients.
+pub struct ApiCallMetrics {
+    client: &'static str,
+}
+
+impl ApiCallMetrics {
+    pub fn new(client: &'static str) -> Self {
+        ApiCallMetrics { client }
+    }
+}
+
+#[async_trait]
+impl Middleware for ApiCallMetrics {
+    async fn handle(
+        &self,
+        req: reqwest::Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<reqwest::Response> {
+        UPSTREAM_API_CALLS.with_label_values(&[self.client]).inc();
+
+        let result = next.run(req, extensions).await;
+        let failed = match &result {
+            Ok(resp) => !resp.status().is_success(),
+            Err(_) => true,
+        };
+        if failed {
+            UPSTREAM_API_ERRORS.with_label_values(&[self.client]).inc();
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+
+    use crate::metrics::{has_token, record_request, HTTP_REQUESTS, HTTP_REQUEST_DURATION};
+
+    #[test]
+    fn test_metrics_has_token() {
+        let mut headers = HeaderMap::new();
+        assert!(!has_token(&headers, "s3cret"));
+
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer s3cret"));
+        assert!(has_token(&headers, "s3cret"));
+        assert!(!has_token(&headers, "other"));
+        // Without a token set, nobody can read the metrics.
+        assert!(!has_token(&headers, ""));
+
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Basic s3cret"));
+        assert!(!has_token(&headers, "s3cret"));
+    }
+
+    #[test]
+    fn test_metrics_record_request() {
+        let requests = || {
+            HTTP_REQUESTS
+                .with_label_values(&["GET", "/functions/{uuid}", "404"])
+                .get()
+        };
+        let before = requests();
+
+        record_request("GET", "/functions/{uuid}", 404, Duration::from_millis(20));
+        assert_eq!(requests(), before + 1);
+        assert!(
+            HTTP_REQUEST_DURATION
+                .with_label_values(&["GET", "/functions/{uuid}"])
+                .get_sample_count()
+                > 0
+        );
+    }
+}
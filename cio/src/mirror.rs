@@ -0,0 +1,336 @@
+use std::fmt;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+use sheets::Client as GoogleSheets;
+
+use crate::{companies::Company, db::Database};
+
+/// The backends we can mirror records in the database to.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum MirrorBackend {
+    Airtable,
+    GoogleSheets,
+    /// The records only live in the database.
+    None,
+}
+
+impl fmt::Display for MirrorBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MirrorBackend::Airtable => write!(f, "airtable"),
+            MirrorBackend::GoogleSheets => write!(f, "google_sheets"),
+            MirrorBackend::None => write!(f, "none"),
+        }
+    }
+}
+
+/// A record in the database that is mirrored to another backend.
+/// The `db` macro implements this for every struct, based on the `mirror` it was given.
+#[async_trait]
+pub trait Mirror: Sized {
+    /// Returns the backend the records are mirrored to.
+    fn mirror_backend() -> MirrorBackend;
+
+    /// Create or update the record in the mirror.
+    /// Returns the id of the record in the mirror, we store this in the `airtable_record_id`
+    /// column for every backend.
+    async fn upsert_in_mirror(&mut self, db: &Database) -> Result<String>;
+
+    /// Delete a record from the mirror by its id in the mirror.
+    async fn delete_from_mirror(db: &Database, cio_company_id: i32, mirror_record_id: &str) -> Result<()>;
+}
+
+/// A tab in a Google Sheets spreadsheet that we mirror records to.
+///
+/// The first row has the field names and every other row is a record. The id of a record in
+/// the mirror is the number of its row, so updating or deleting it is a single write. Only a
+/// new record has to look for the end of the tab.
+pub struct GoogleSheetsMirror {
+    tab: Box<dyn SheetTab>,
+}
+
+impl GoogleSheetsMirror {
+    /// Create the mirror for a company.
+    pub async fn new(db: &Database, cio_company_id: i32, spreadsheet_id: &str, tab: &str) -> Result<Self> {
+        let company = Company::get_by_id(db, cio_company_id)?;
+
+        Ok(GoogleSheetsMirror {
+            tab: Box::new(GoogleSheetsTab {
+                client: company.authenticate_google_sheets(db).await?,
+                spreadsheet_id: spreadsheet_id.to_string(),
+                tab: tab.to_string(),
+            }),
+        })
+    }
+
+    /// Create or update the row for a record, given the id it has in the mirror if it has one.
+    /// Returns the id of the record in the mirror, the number of its row.
+    pub async fn upsert<T: JsonSchema + Serialize>(&self, mirror_record_id: &str, record: &T) -> Result<String> {
+        let (header, row) = to_row(record)?;
+
+        if let Some(row_number) = row_number(mirror_record_id)? {
+            self.tab.write_row(row_number, row).await?;
+            return Ok(mirror_record_id.to_string());
+        }
+
+        // Add a new row to the end. The header is written with it, in case the fields changed.
+        let row_number = self.tab.rows().await?.max(1) + 1;
+        self.tab.write_row(1, header).await?;
+        self.tab.write_row(row_number, row).await?;
+
+        Ok(row_number.to_string())
+    }
+
+    /// Clear the row for a record.
+    pub async fn delete(&self, mirror_record_id: &str) -> Result<()> {
+        if let Some(row_number) = row_number(mirror_record_id)? {
+            self.tab.clear_row(row_number).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the row of a record from its id in the mirror, or `None` if it has no row yet.
+fn row_number(mirror_record_id: &str) -> Result<Option<usize>> {
+    if mirror_record_id.is_empty() {
+        return Ok(None);
+    }
+
+    match mirror_record_id.parse::<usize>() {
+        // The first row is the header.
+        Ok(n) if n > 1 => Ok(Some(n)),
+        _ => bail!("invalid Google Sheets row `{}`", mirror_record_id),
+    }
+}
+
+/// The reads and writes the mirror makes to a tab.
+#[async_trait]
+trait SheetTab: Send + Sync {
+    /// Returns the number of rows in use, this includes the header.
+    async fn rows(&self) -> Result<usize>;
+
+    async fn write_row(&self, row_number: usize, row: Vec<String>) -> Result<()>;
+
+    async fn clear_row(&self, row_number: usize) -> Result<()>;
+}
+
+struct GoogleSheetsTab {
+    client: GoogleSheets,
+    spreadsheet_id: String,
+    tab: String,
+}
+
+#[async_trait]
+impl SheetTab for GoogleSheetsTab {
+    async fn rows(&self) -> Result<usize> {
+        // The first column always has the id of the record.
+        let range = format!("{}!A:A", self.tab);
+        let values = self
+            .client
+            .spreadsheets()
+            .values_get(
+                &self.spreadsheet_id,
+                &range,
+                sheets::types::DateTimeRenderOption::FormattedString,
+                sheets::types::Dimension::Rows,
+                sheets::types::ValueRenderOption::FormattedValue,
+            )
+            .await?;
+
+        Ok(values.values.len())
+    }
+
+    async fn write_row(&self, row_number: usize, row: Vec<String>) -> Result<()> {
+        let range = format!("{}!A{}", self.tab, row_number);
+        self.client
+            .spreadsheets()
+            .values_update(
+                &self.spreadsheet_id,
+                &range,
+                false,
+                sheets::types::DateTimeRenderOption::FormattedString,
+                sheets::types::ValueRenderOption::FormattedValue,
+                sheets::types::ValueInputOption::Raw,
+                &sheets::types::ValueRange {
+                    major_dimension: Some(sheets::types::Dimension::Rows),
+                    range: range.to_string(),
+                    values: vec![row],
+                },
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn clear_row(&self, row_number: usize) -> Result<()> {
+        let range = format!("{}!A{}:ZZ{}", self.tab, row_number, row_number);
+        self.client
+            .spreadsheets()
+            .values_clear(&self.spreadsheet_id, &range, &sheets::types::ClearValuesRequest {})
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Returns the header and the row for a record.
+/// The columns come from the schema of the type, since fields that are skipped when empty
+/// are missing from the record. The `id` field always comes first, since that is how we
+/// find the row again.
+fn to_row<T: JsonSchema + Serialize>(record: &T) -> Result<(Vec<String>, Vec<String>)> {
+    let mut header = vec!["id".to_string()];
+    if let Some(object) = schemars::schema_for!(T).schema.object {
+        for k in object.properties.keys() {
+            if k != "id" {
+                header.push(k.to_string());
+            }
+        }
+    }
+
+    let value = serde_json::to_value(record)?;
+    let row = header
+        .iter()
+        .map(|k| value.get(k).map(to_cell).unwrap_or_default())
+        .collect();
+
+    Ok((header, row))
+}
+
+/// Returns the value of a cell for a field.
+fn to_cell(value: &Value) -> String {
+    match value {
+        Value::Null => "".to_string(),
+        Value::String(s) => s.to_string(),
+        Value::Array(a) => a.iter().map(to_cell).collect::<Vec<String>>().join(","),
+        v => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use schemars::JsonSchema;
+    use serde::Serialize;
+
+    use crate::mirror::{to_row, GoogleSheetsMirror, SheetTab};
+
+    #[derive(JsonSchema, Serialize)]
+    struct Record {
+        authors: Vec<String>,
+        id: i32,
+        #[serde(skip_serializing_if = "String::is_empty")]
+        link: String,
+        name: String,
+        number: i32,
+    }
+
+    #[test]
+    fn test_mirror_to_row() {
+        let (header, row) = to_row(&Record {
+            authors: vec!["Jess".to_string(), "Steve".to_string()],
+            id: 4,
+            link: "".to_string(),
+            name: "RFD 1".to_string(),
+            number: 1,
+        })
+        .unwrap();
+
+        assert_eq!(header, vec!["id", "authors", "link", "name", "number"]);
+        assert_eq!(row, vec!["4", "Jess,Steve", "", "RFD 1", "1"]);
+    }
+
+    /// A tab that lives in memory, it counts the writes so we know we didn't read or rewrite
+    /// more than we had to.
+    #[derive(Default)]
+    struct MemoryTab {
+        rows: Mutex<Vec<Vec<String>>>,
+        reads: Mutex<usize>,
+        writes: Mutex<usize>,
+    }
+
+    #[async_trait]
+    impl SheetTab for Arc<MemoryTab> {
+        async fn rows(&self) -> Result<usize> {
+            *self.reads.lock().unwrap() += 1;
+            let rows = self.rows.lock().unwrap();
+            // Like Google Sheets, the empty rows at the end don't count.
+            Ok(rows.iter().rposition(|r| !r.is_empty()).map(|i| i + 1).unwrap_or(0))
+        }
+
+        async fn write_row(&self, row_number: usize, row: Vec<String>) -> Result<()> {
+            *self.writes.lock().unwrap() += 1;
+            let mut rows = self.rows.lock().unwrap();
+            if rows.len() < row_number {
+                rows.resize(row_number, vec![]);
+            }
+            rows[row_number - 1] = row;
+            Ok(())
+        }
+
+        async fn clear_row(&self, row_number: usize) -> Result<()> {
+            *self.writes.lock().unwrap() += 1;
+            self.rows.lock().unwrap()[row_number - 1] = vec![];
+            Ok(())
+        }
+    }
+
+    fn record(id: i32, name: &str) -> Record {
+        Record {
+            authors: vec![],
+            id,
+            link: "".to_string(),
+            name: name.to_string(),
+            number: id,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mirror_google_sheets_upsert_and_delete() {
+        let tab = Arc::new(MemoryTab::default());
+        let mirror = GoogleSheetsMirror {
+            tab: Box::new(tab.clone()),
+        };
+
+        // New records go after the header, one after the other.
+        assert_eq!(mirror.upsert("", &record(4, "RFD 4")).await.unwrap(), "2");
+        assert_eq!(mirror.upsert("", &record(7, "RFD 7")).await.unwrap(), "3");
+        assert_eq!(
+            *tab.rows.lock().unwrap(),
+            vec![
+                vec!["id", "authors", "link", "name", "number"],
+                vec!["4", "", "", "RFD 4", "4"],
+                vec!["7", "", "", "RFD 7", "7"],
+            ]
+        );
+
+        // An update is one write to its row, without looking for it.
+        let (reads, writes) = (*tab.reads.lock().unwrap(), *tab.writes.lock().unwrap());
+        assert_eq!(mirror.upsert("2", &record(4, "RFD 4 again")).await.unwrap(), "2");
+        assert_eq!(*tab.reads.lock().unwrap(), reads);
+        assert_eq!(*tab.writes.lock().unwrap(), writes + 1);
+        assert_eq!(tab.rows.lock().unwrap()[1][3], "RFD 4 again");
+
+        // A delete clears the row, the rows after it stay where they are.
+        mirror.delete("2").await.unwrap();
+        assert!(tab.rows.lock().unwrap()[1].is_empty());
+        assert_eq!(tab.rows.lock().unwrap()[2][0], "7");
+        mirror.delete("").await.unwrap();
+
+        // A cleared row at the end is used again.
+        mirror.delete("3").await.unwrap();
+        assert_eq!(mirror.upsert("", &record(9, "RFD 9")).await.unwrap(), "2");
+
+        // We never touch the header with a record.
+        assert!(mirror.upsert("1", &record(1, "RFD 1")).await.is_err());
+        assert!(mirror.delete("1").await.is_err());
+        assert!(mirror.delete("recXYZ").await.is_err());
+    }
+}
@@ -0,0 +1,377 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Duration, Utc};
+use diesel::{BoolExpressionMethods, Connection, ExpressionMethods, QueryDsl, RunQueryDsl};
+use futures_util::future::BoxFuture;
+use log::{info, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{db::Database, error::CioError, schema::airtable_outbox_entries};
+
+/// The number of attempts we make before we give up on an entry and mark it as failed.
+/// Failed entries can be replayed.
+pub const MAX_ATTEMPTS: i32 = 10;
+
+/// The number of entries we process on every run of the worker.
+const BATCH_SIZE: i64 = 100;
+
+/// How long a worker has to run the entries it claimed before another one can claim them, in
+/// case the first one went away.
+const CLAIM_MINUTES: i64 = 10;
+
+/// The operation to perform in the mirror.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum OutboxOperation {
+    /// Create or update the record in the mirror.
+    Upsert,
+
+    /// Delete the record from the mirror.
+    Delete,
+}
+
+impl fmt::Display for OutboxOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutboxOperation::Upsert => write!(f, "upsert"),
+            OutboxOperation::Delete => write!(f, "delete"),
+        }
+    }
+}
+
+impl FromStr for OutboxOperation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "upsert" => Ok(OutboxOperation::Upsert),
+            "delete" => Ok(OutboxOperation::Delete),
+            _ => bail!("invalid outbox operation: `{}`", s),
+        }
+    }
+}
+
+/// The status of an entry in the outbox.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum OutboxStatus {
+    /// The entry is waiting to be run, or to be retried.
+    Pending,
+
+    /// The entry was mirrored.
+    Completed,
+
+    /// The entry ran out of attempts and needs to be replayed by hand.
+    Failed,
+}
+
+impl fmt::Display for OutboxStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutboxStatus::Pending => write!(f, "pending"),
+            OutboxStatus::Completed => write!(f, "completed"),
+            OutboxStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// A pending mutation of a record in its mirror, this is Airtable for most types.
+/// Every write through the `db` macro enqueues one of these, so a failure to talk to
+/// the mirror never fails the database write.
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "airtable_outbox_entries"]
+pub struct NewAirtableOutboxEntry {
+    /// The name of the struct generated by the `db` macro, ie. `Applicant`.
+    pub record_type: String,
+    /// The id of the record in the database.
+    pub record_id: i32,
+    /// The id of the record in the mirror, if we know it.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub airtable_record_id: String,
+    pub operation: String,
+    pub status: String,
+    #[serde(default)]
+    pub attempts: i32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub last_error: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub next_attempt_at: DateTime<Utc>,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Queryable, Identifiable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "airtable_outbox_entries"]
+pub struct AirtableOutboxEntry {
+    pub id: i32,
+    pub record_type: String,
+    pub record_id: i32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub airtable_record_id: String,
+    pub operation: String,
+    pub status: String,
+    #[serde(default)]
+    pub attempts: i32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub last_error: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub next_attempt_at: DateTime<Utc>,
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+impl AirtableOutboxEntry {
+    /// Add an entry to the outbox.
+    ///
+    /// The entry is not due until a minute from now, since the caller tries to run it
+    /// right away and we don't want the worker to race it.
+    pub fn enqueue(
+        db: &Database,
+        operation: OutboxOperation,
+        record_type: &str,
+        record_id: i32,
+        airtable_record_id: &str,
+        cio_company_id: i32,
+    ) -> Result<Self> {
+        let now = Utc::now();
+        let entry = NewAirtableOutboxEntry {
+            record_type: record_type.to_string(),
+            record_id,
+            airtable_record_id: airtable_record_id.to_string(),
+            operation: operation.to_string(),
+            status: OutboxStatus::Pending.to_string(),
+            attempts: 0,
+            last_error: Default::default(),
+            created_at: now,
+            updated_at: now,
+            next_attempt_at: now + Duration::minutes(1),
+            cio_company_id,
+        };
+
+        let r = diesel::insert_into(airtable_outbox_entries::table)
+            .values(&entry)
+            .get_result(&db.conn()?)?;
+
+        Ok(r)
+    }
+
+    /// Get the operation for the entry.
+    pub fn operation(&self) -> Result<OutboxOperation> {
+        OutboxOperation::from_str(&self.operation)
+    }
+
+    /// Mark the entry as completed.
+    /// Any older pending entries for the same record and operation are completed as
+    /// well, since they would only do the same work again.
+    pub fn complete(&self, db: &Database) -> Result<()> {
+        diesel::update(
+            airtable_outbox_entries::dsl::airtable_outbox_entries.filter(
+                airtable_outbox_entries::dsl::id
+                    .eq(self.id)
+                    .or(airtable_outbox_entries::dsl::record_type
+                        .eq(self.record_type.to_string())
+                        .and(airtable_outbox_entries::dsl::record_id.eq(self.record_id))
+                        .and(airtable_outbox_entries::dsl::operation.eq(self.operation.to_string()))
+                        .and(airtable_outbox_entries::dsl::status.eq(OutboxStatus::Pending.to_string()))
+                        .and(airtable_outbox_entries::dsl::created_at.le(self.created_at))),
+            ),
+        )
+        .set((
+            airtable_outbox_entries::dsl::status.eq(OutboxStatus::Completed.to_string()),
+            airtable_outbox_entries::dsl::updated_at.eq(Utc::now()),
+        ))
+        .execute(&db.conn()?)?;
+
+        Ok(())
+    }
+
+    /// Record a failed attempt for the entry and schedule the next one.
+    /// After `MAX_ATTEMPTS` the entry is marked as failed and needs to be replayed.
+    pub fn fail(&self, db: &Database, err: &anyhow::Error) -> Result<Self> {
+        let mut entry = self.clone();
+        let now = Utc::now();
+
+        entry.attempts += 1;
+        entry.last_error = format!("{:?}", err);
+        entry.updated_at = now;
+        entry.next_attempt_at = now + backoff(entry.attempts);
+        if entry.attempts >= MAX_ATTEMPTS {
+            entry.status = OutboxStatus::Failed.to_string();
+        }
+
+        let r = diesel::update(&entry).set(entry.clone()).get_result(&db.conn()?)?;
+
+        Ok(r)
+    }
+
+    /// Replay an entry, this resets the attempts and makes it due right away.
+    pub fn replay(db: &Database, id: i32) -> Result<Self> {
+        let mut entry = airtable_outbox_entries::dsl::airtable_outbox_entries
+            .find(id)
+            .first::<AirtableOutboxEntry>(&db.conn()?)
+            .map_err(|e| CioError::from_db(e, "outbox entry"))?;
+
+        let now = Utc::now();
+        entry.status = OutboxStatus::Pending.to_string();
+        entry.attempts = 0;
+        entry.updated_at = now;
+        entry.next_attempt_at = now;
+
+        let r = diesel::update(&entry).set(entry.clone()).get_result(&db.conn()?)?;
+
+        Ok(r)
+    }
+
+    /// Get all the entries that have not been completed, the newest first.
+    pub fn get_unfinished(db: &Database) -> Result<Vec<Self>> {
+        let r = airtable_outbox_entries::dsl::airtable_outbox_entries
+            .filter(airtable_outbox_entries::dsl::status.ne(OutboxStatus::Completed.to_string()))
+            .order_by(airtable_outbox_entries::dsl::id.desc())
+            .load::<AirtableOutboxEntry>(&db.conn()?)?;
+
+        Ok(r)
+    }
+
+    /// Claim the entries that are due, at most `BATCH_SIZE` of them. The rows are locked while
+    /// we claim them and skipped if they are already locked, and a claimed entry is not due again
+    /// for `CLAIM_MINUTES`, so workers running next to each other never run the same entry.
+    pub fn claim_due(db: &Database) -> Result<Vec<Self>> {
+        let conn = db.conn()?;
+        conn.transaction::<_, anyhow::Error, _>(|| {
+            let now = Utc::now();
+            let entries = airtable_outbox_entries::dsl::airtable_outbox_entries
+                .filter(
+                    airtable_outbox_entries::dsl::status
+                        .eq(OutboxStatus::Pending.to_string())
+                        .and(airtable_outbox_entries::dsl::next_attempt_at.le(now)),
+                )
+                .order_by(airtable_outbox_entries::dsl::id.asc())
+                .limit(BATCH_SIZE)
+                .for_update()
+                .skip_locked()
+                .load::<AirtableOutboxEntry>(&conn)?;
+
+            let ids: Vec<i32> = entries.iter().map(|e| e.id).collect();
+            diesel::update(
+                airtable_outbox_entries::dsl::airtable_outbox_entries
+                    .filter(airtable_outbox_entries::dsl::id.eq_any(ids)),
+            )
+            .set(airtable_outbox_entries::dsl::next_attempt_at.eq(now + Duration::minutes(CLAIM_MINUTES)))
+            .execute(&conn)?;
+
+            Ok(entries)
+        })
+    }
+
+    /// Run the entry against the type it was enqueued for.
+    async fn process(&self, db: &Database) -> Result<()> {
+        match processor(&self.record_type) {
+            Some(p) => (p.process)(db, self).await,
+            None => bail!("unknown record type `{}` in Airtable outbox", self.record_type),
+        }
+    }
+}
+
+/// How to run the outbox entries of a type. The `db` macro submits one of these for every type
+/// it generates.
+pub struct OutboxProcessor {
+    /// The name of the struct generated by the `db` macro, ie. `Applicant`.
+    pub record_type: &'static str,
+    pub process: for<'a> fn(&'a Database, &'a AirtableOutboxEntry) -> BoxFuture<'a, Result<()>>,
+}
+
+inventory::collect!(OutboxProcessor);
+
+/// Returns how to run the outbox entries of a type, by the name of the type.
+fn processor(record_type: &str) -> Option<&'static OutboxProcessor> {
+    inventory::iter::<OutboxProcessor>
+        .into_iter()
+        .find(|p| p.record_type == record_type)
+}
+
+/// Returns how long to wait before the next attempt, this doubles on every attempt
+/// and is capped at six hours.
+pub fn backoff(attempts: i32) -> Duration {
+    let minutes = 2_i64.saturating_pow(attempts.max(0) as u32);
+    Duration::minutes(minutes.min(6 * 60))
+}
+
+/// Run all the entries in the Airtable outbox that are due.
+pub async fn process_airtable_outbox(db: &Database) -> Result<()> {
+    let entries = db.run(AirtableOutboxEntry::claim_due).await?;
+
+    for entry in entries {
+        match entry.process(db).await {
+            Ok(_) => {
+                let e = entry.clone();
+                db.run(move |db| e.complete(db)).await?;
+                info!(
+                    "[outbox] {} `{}` id={} mirrored",
+                    entry.operation, entry.record_type, entry.record_id
+                );
+            }
+            Err(e) => {
+                let reason = e.to_string();
+                let entry = db.run(move |db| entry.fail(db, &e)).await?;
+                warn!(
+                    "[outbox] {} `{}` id={} failed (attempt {}/{}): {}",
+                    entry.operation, entry.record_type, entry.record_id, entry.attempts, MAX_ATTEMPTS, reason
+                );
+            }
+        }
+    }
+
+    // Cleanup the completed entries we no longer need.
+    let week_ago = Utc::now() - Duration::days(7);
+    db.run(move |db| {
+        diesel::delete(
+            airtable_outbox_entries::dsl::airtable_outbox_entries.filter(
+                airtable_outbox_entries::dsl::status
+                    .eq(OutboxStatus::Completed.to_string())
+                    .and(airtable_outbox_entries::dsl::updated_at.lt(week_ago)),
+            ),
+        )
+        .execute(&db.conn()?)?;
+
+        Ok(())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use crate::outbox::{backoff, processor, OutboxProcessor};
+
+    #[test]
+    fn test_outbox_backoff() {
+        assert_eq!(backoff(0), Duration::minutes(1));
+        assert_eq!(backoff(1), Duration::minutes(2));
+        assert_eq!(backoff(5), Duration::minutes(32));
+        assert_eq!(backoff(9), Duration::hours(6));
+        assert_eq!(backoff(100), Duration::hours(6));
+    }
+
+    #[test]
+    fn test_outbox_processors() {
+        // Every type the `db` macro generates can be found by its name, once.
+        for record_type in &["APIToken", "Applicant", "BarcodeScan", "Function", "RFD", "User"] {
+            let p = processor(record_type).unwrap();
+            assert_eq!(p.record_type, *record_type);
+        }
+        assert!(processor("Nope").is_none());
+
+        let mut names: Vec<&str> = inventory::iter::<OutboxProcessor>
+            .into_iter()
+            .map(|p| p.record_type)
+            .collect();
+        let count = names.len();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), count);
+    }
+}
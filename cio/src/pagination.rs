@@ -0,0 +1,230 @@
+use std::marker::PhantomData;
+
+use anyhow::{bail, Result};
+use schemars::JsonSchema;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+/// How many records are in a page if the limit isn't given.
+pub const DEFAULT_PAGE_LIMIT: u32 = 100;
+/// The most records we return in a page.
+pub const MAX_PAGE_LIMIT: u32 = 1000;
+
+/// A page of records from a list endpoint, the newest first.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Page<T> {
+    /// The records. If `fields` was given, they only have those fields and their id.
+    #[schemars(with = "Vec<T>")]
+    pub items: Vec<Value>,
+    /// Pass this as the `cursor` to get the next page. It is not set on the last page.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub next_cursor: String,
+    #[serde(skip)]
+    #[schemars(skip)]
+    record: PhantomData<T>,
+}
+
+impl<T: Serialize> Page<T> {
+    /// Create a page from the records the query returned.
+    pub fn new(records: &[T], next_id: Option<i32>) -> Result<Self> {
+        let mut items = Vec::new();
+        for record in records {
+            items.push(serde_json::to_value(record)?);
+        }
+
+        Ok(Page::from_items(items, next_id))
+    }
+}
+
+impl<T> Page<T> {
+    /// Create a page from records that were loaded with only some of their fields.
+    pub fn from_items(items: Vec<Value>, next_id: Option<i32>) -> Self {
+        Page {
+            items,
+            next_cursor: next_id.map(|id| id.to_string()).unwrap_or_default(),
+            record: PhantomData,
+        }
+    }
+}
+
+/// The query parameters every list endpoint takes. Add them to the query parameters of an
+/// endpoint with `#[serde(flatten)]`.
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+pub struct ListQuery {
+    /// The `next_cursor` from the page before, leave it out for the first page.
+    pub cursor: Option<String>,
+    /// How many records to return, at most 1000. The default is 100.
+    #[serde(default, deserialize_with = "deserialize_limit")]
+    pub limit: Option<u32>,
+    /// The fields to return, separated by commas, ie. `title,state`. The default is all of them.
+    pub fields: Option<String>,
+}
+
+impl ListQuery {
+    /// Check the parameters for a list of `T`.
+    pub fn parse<T: JsonSchema>(self) -> Result<ListParams> {
+        ListParams::parse::<T>(self.cursor, self.limit, self.fields)
+    }
+}
+
+/// Deserialize the limit from a number or a string. Once serde buffers the query parameters
+/// for a flattened struct they are all strings.
+fn deserialize_limit<'de, D>(deserializer: D) -> std::result::Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Limit {
+        Number(u32),
+        String(String),
+    }
+
+    match Option::<Limit>::deserialize(deserializer)? {
+        Some(Limit::Number(n)) => Ok(Some(n)),
+        Some(Limit::String(s)) => match s.trim().parse() {
+            Ok(n) => Ok(Some(n)),
+            Err(_) => Err(de::Error::custom(format!("invalid limit: `{}`", s))),
+        },
+        None => Ok(None),
+    }
+}
+
+/// The parameters every list endpoint takes, after we checked them. See `ListQuery`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ListParams {
+    /// Only return the records older than this id.
+    pub after_id: Option<i32>,
+    pub limit: i64,
+    /// The fields to return, all of them if this is empty.
+    pub fields: Vec<String>,
+}
+
+impl ListParams {
+    /// Check the `cursor`, `limit` and `fields` query parameters for a list of `T`.
+    pub fn parse<T: JsonSchema>(cursor: Option<String>, limit: Option<u32>, fields: Option<String>) -> Result<Self> {
+        let after_id = match cursor.filter(|c| !c.is_empty()) {
+            Some(c) => match c.parse::<i32>() {
+                Ok(id) => Some(id),
+                Err(_) => bail!("invalid cursor: `{}`", c),
+            },
+            None => None,
+        };
+
+        let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+        if limit == 0 || limit > MAX_PAGE_LIMIT {
+            bail!("limit must be between 1 and {}, got {}", MAX_PAGE_LIMIT, limit);
+        }
+
+        let known = field_names::<T>();
+        let mut selected = Vec::new();
+        for f in fields.unwrap_or_default().split(',').map(|f| f.trim()) {
+            if f.is_empty() {
+                continue;
+            }
+            if !known.iter().any(|k| k == f) {
+                bail!("unknown field: `{}`", f);
+            }
+            selected.push(f.to_string());
+        }
+
+        Ok(ListParams {
+            after_id,
+            limit: limit as i64,
+            fields: selected,
+        })
+    }
+}
+
+/// Returns the names of the fields of a type, from its schema.
+fn field_names<T: JsonSchema>() -> Vec<String> {
+    match schemars::schema_for!(T).schema.object {
+        Some(object) => object.properties.keys().cloned().collect(),
+        None => Default::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    use crate::pagination::{ListParams, ListQuery, Page};
+
+    #[derive(JsonSchema, Serialize)]
+    struct Record {
+        content: String,
+        id: i32,
+        state: String,
+        title: String,
+    }
+
+    #[test]
+    fn test_pagination_list_params() {
+        let params =
+            ListParams::parse::<Record>(Some("42".to_string()), Some(10), Some("title, state".to_string())).unwrap();
+        assert_eq!(
+            params,
+            ListParams {
+                after_id: Some(42),
+                limit: 10,
+                fields: vec!["title".to_string(), "state".to_string()],
+            }
+        );
+
+        let params = ListParams::parse::<Record>(None, None, None).unwrap();
+        assert_eq!(params.after_id, None);
+        assert_eq!(params.limit, 100);
+        assert!(params.fields.is_empty());
+
+        assert!(ListParams::parse::<Record>(Some("abc".to_string()), None, None).is_err());
+        assert!(ListParams::parse::<Record>(None, Some(0), None).is_err());
+        assert!(ListParams::parse::<Record>(None, Some(5000), None).is_err());
+        assert!(ListParams::parse::<Record>(None, None, Some("html".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_pagination_page() {
+        let records = vec![Record {
+            content: "a lot of text".to_string(),
+            id: 7,
+            state: "published".to_string(),
+            title: "RFD 7".to_string(),
+        }];
+
+        let page = Page::new(&records, Some(7)).unwrap();
+        assert_eq!(page.items[0]["content"], "a lot of text");
+        assert_eq!(page.next_cursor, "7");
+
+        let page = Page::<Record>::from_items(vec![json!({"id": 7, "title": "RFD 7"})], None);
+        assert_eq!(page.items, vec![json!({"id": 7, "title": "RFD 7"})]);
+        assert_eq!(page.next_cursor, "");
+    }
+
+    #[derive(Deserialize)]
+    struct RecordsQuery {
+        #[serde(flatten)]
+        list: ListQuery,
+        state: Option<String>,
+    }
+
+    #[test]
+    fn test_pagination_list_query() {
+        // Flattened, the limit comes in as a string.
+        let query: RecordsQuery =
+            serde_json::from_value(json!({"cursor": "42", "limit": "10", "state": "published"})).unwrap();
+        assert_eq!(query.state.as_deref(), Some("published"));
+        let params = query.list.parse::<Record>().unwrap();
+        assert_eq!(params.after_id, Some(42));
+        assert_eq!(params.limit, 10);
+
+        let query: ListQuery = serde_json::from_value(json!({"limit": 10})).unwrap();
+        assert_eq!(query.limit, Some(10));
+
+        let query: ListQuery = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(query.limit, None);
+
+        assert!(serde_json::from_value::<RecordsQuery>(json!({"limit": "ten"})).is_err());
+    }
+}
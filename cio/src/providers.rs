@@ -6,6 +6,7 @@ use crate::{
     companies::Company,
     configs::{Group, User},
     db::Database,
+    error::CioError,
 };
 
 /// This trait defines how to implement a provider for a vendor that manages users
@@ -213,7 +214,7 @@ impl ProviderOps<octorust::types::SimpleUser, octorust::types::Team> for octorus
             }
             Err(e) => {
                 // If the error is Not Found we need to add them.
-                if !e.to_string().contains("404") {
+                if !CioError::is_not_found(&e) {
                     // Otherwise bail.
                     bail!(
                         "checking if user `{}` is a member of the github org `{}` failed: {}",
@@ -308,7 +309,7 @@ impl ProviderOps<octorust::types::SimpleUser, octorust::types::Team> for octorus
             }
             Err(e) => {
                 // If the error is Not Found we need to add the team.
-                if !e.to_string().contains("404") {
+                if !CioError::is_not_found(&e) {
                     // Otherwise bail.
                     bail!(
                         "checking if team `{}` exists in github org `{}` failed: {}",
@@ -367,7 +368,7 @@ impl ProviderOps<octorust::types::SimpleUser, octorust::types::Team> for octorus
             }
             Err(e) => {
                 // If the error is Not Found we need to add them.
-                if !e.to_string().contains("404") {
+                if !CioError::is_not_found(&e) {
                     // Otherwise bail.
                     bail!(
                         "checking if user `{}` is a member of the github team `{}` failed: {}",
@@ -504,7 +505,7 @@ impl ProviderOps<gsuite_api::types::User, gsuite_api::types::Group> for gsuite_a
             }
             Err(e) => {
                 // If the error is Not Found we need to add them.
-                if !e.to_string().contains("404") {
+                if !CioError::is_not_found(&e) {
                     // Otherwise bail.
                     bail!("checking if user `{}` exists in GSuite failed: {}", user.email, e);
                 }
@@ -565,7 +566,7 @@ impl ProviderOps<gsuite_api::types::User, gsuite_api::types::Group> for gsuite_a
             }
             Err(e) => {
                 // If the error is Not Found we need to add them.
-                if !e.to_string().contains("404") {
+                if !CioError::is_not_found(&e) {
                     // Otherwise bail.
                     bail!("checking if group `{}` exists in GSuite failed: {}", group.name, e);
                 }
@@ -622,7 +623,7 @@ impl ProviderOps<gsuite_api::types::User, gsuite_api::types::Group> for gsuite_a
                 }
             }
             Err(e) => {
-                if !e.to_string().contains("404") {
+                if !CioError::is_not_found(&e) {
                     // Otherwise bail.
                     bail!(
                         "checking if user `{}` is a member of the GSuite group `{}` failed: {}",
@@ -852,7 +853,7 @@ impl ProviderOps<okta::types::User, okta::types::Group> for okta::Client {
                 okta_user.id
             }
             Err(e) => {
-                if !e.to_string().contains("404") {
+                if !CioError::is_not_found(&e) {
                     // Otherwise bail.
                     bail!("checking if user `{}` exists in Okta failed: {}", user.email, e);
                 }
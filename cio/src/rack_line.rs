@@ -12,7 +12,7 @@ use slack_chat_api::{FormattedMessage, MessageBlock, MessageBlockText, MessageBl
 
 use crate::{
     airtable::AIRTABLE_RACK_LINE_SIGNUPS_TABLE, companies::Company, core::UpdateAirtableRecord, db::Database,
-    schema::rack_line_subscribers,
+    error::CioError, schema::rack_line_subscribers,
 };
 
 /// The data type for a RackLineSubscriber.
@@ -172,7 +172,7 @@ impl Default for NewRackLineSubscriber {
 /// Implement updating the Airtable record for a RackLineSubscriber.
 #[async_trait]
 impl UpdateAirtableRecord<RackLineSubscriber> for RackLineSubscriber {
-    async fn update_airtable_record(&mut self, record: RackLineSubscriber) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, record: RackLineSubscriber) -> Result<()> {
         // Set the link_to_people from the original so it stays intact.
         self.link_to_people = record.link_to_people;
 
@@ -184,7 +184,7 @@ impl UpdateAirtableRecord<RackLineSubscriber> for RackLineSubscriber {
 pub async fn refresh_db_rack_line_subscribers(db: &Database, company: &Company) -> Result<()> {
     let mailchimp_auth = company.authenticate_mailchimp(db).await;
     if let Err(e) = mailchimp_auth {
-        if e.to_string().contains("no token") {
+        if CioError::is_not_connected(&e) {
             // Return early, this company does not use MailChimp.
             return Ok(());
         }
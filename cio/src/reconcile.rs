@@ -0,0 +1,457 @@
+use std::fmt;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use log::warn;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    db::Database,
+    schema::{airtable_sync_conflicts, airtable_sync_states},
+};
+
+/// The name of the "Last modified time" field in the Airtable tables we reconcile.
+pub static AIRTABLE_LAST_MODIFIED_FIELD: &str = "Last Modified";
+
+/// The fields we never pull from Airtable, since they are ours.
+static IGNORED_FIELDS: &[&str] = &[
+    "id",
+    "airtable_record_id",
+    "cio_company_id",
+    AIRTABLE_LAST_MODIFIED_FIELD,
+];
+
+/// The side whose value we keep for a field.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Winner {
+    Database,
+    Airtable,
+}
+
+impl fmt::Display for Winner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Winner::Database => write!(f, "database"),
+            Winner::Airtable => write!(f, "airtable"),
+        }
+    }
+}
+
+/// A field that was changed on both sides since they last agreed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldConflict {
+    pub field: String,
+    pub base: Value,
+    pub database: Value,
+    pub airtable: Value,
+    pub winner: Winner,
+}
+
+/// The result of comparing a record in the database with its row in Airtable.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Merge {
+    /// The fields to take from Airtable.
+    pub pull: Vec<String>,
+    /// The fields to push to Airtable.
+    pub push: Vec<String>,
+    /// The fields that changed on both sides.
+    pub conflicts: Vec<FieldConflict>,
+}
+
+/// Decide per field which side wins.
+///
+/// `base` is what both sides last agreed on. If only one side changed a field since then,
+/// that side wins. If both changed it, the side that was modified last wins, falling back to
+/// the database if we don't know. If we have no base yet, the database wins, pushing it to
+/// Airtable gives us a base for the next time.
+pub fn merge(
+    base: Option<&Value>,
+    database: &Value,
+    airtable: &Value,
+    db_updated_at: Option<DateTime<Utc>>,
+    airtable_modified_at: Option<DateTime<Utc>>,
+) -> Merge {
+    let newer = match (db_updated_at, airtable_modified_at) {
+        (Some(d), Some(a)) if a > d => Winner::Airtable,
+        (None, Some(_)) => Winner::Airtable,
+        _ => Winner::Database,
+    };
+
+    let empty = Map::new();
+    let d = database.as_object().unwrap_or(&empty);
+    let a = airtable.as_object().unwrap_or(&empty);
+
+    let mut keys: Vec<&String> = d.keys().chain(a.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    let mut m = Merge::default();
+    for key in keys {
+        if IGNORED_FIELDS.contains(&key.as_str()) {
+            continue;
+        }
+
+        let dv = d.get(key).unwrap_or(&Value::Null);
+        let av = a.get(key).unwrap_or(&Value::Null);
+        if dv == av {
+            continue;
+        }
+
+        let winner = match base {
+            Some(b) => {
+                let bv = b.get(key).unwrap_or(&Value::Null);
+                if dv == bv {
+                    // Only Airtable changed.
+                    Winner::Airtable
+                } else if av == bv {
+                    // Only the database changed.
+                    Winner::Database
+                } else {
+                    m.conflicts.push(FieldConflict {
+                        field: key.to_string(),
+                        base: bv.clone(),
+                        database: dv.clone(),
+                        airtable: av.clone(),
+                        winner: newer,
+                    });
+                    newer
+                }
+            }
+            // We can't tell which side changed, so we don't let Airtable clobber the database.
+            None => Winner::Database,
+        };
+
+        match winner {
+            Winner::Airtable => m.pull.push(key.to_string()),
+            Winner::Database => m.push.push(key.to_string()),
+        }
+    }
+
+    m
+}
+
+/// Returns when the row in Airtable was last modified, from its "Last Modified" field.
+pub fn airtable_modified_at(record: &airtable_api::Record<Value>) -> Option<DateTime<Utc>> {
+    let modified = record.fields.get(AIRTABLE_LAST_MODIFIED_FIELD)?.as_str()?;
+
+    DateTime::parse_from_rfc3339(modified)
+        .ok()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+/// The counts from a reconcile run.
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct ReconcileReport {
+    pub records: usize,
+    pub fields_pulled: usize,
+    pub fields_pushed: usize,
+    pub conflicts: usize,
+    /// The records that failed, ie. `id=4 failed: ...`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+impl ReconcileReport {
+    /// Add the result of reconciling a record.
+    pub fn add(&mut self, m: &Merge) {
+        self.records += 1;
+        self.fields_pulled += m.pull.len();
+        self.fields_pushed += m.push.len();
+        self.conflicts += m.conflicts.len();
+    }
+
+    /// Add a record that failed, so the run can go on with the others.
+    pub fn fail(&mut self, record_id: i32, err: &anyhow::Error) {
+        self.errors.push(format!("id={} failed: {}", record_id, err));
+    }
+}
+
+/// A record after reconciling it with Airtable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reconciled {
+    /// The record from the database with the fields that Airtable won.
+    pub fields: Value,
+    pub merge: Merge,
+}
+
+/// Reconcile a record in the database with its row in Airtable, logging any conflicts.
+/// Both `database` and `airtable` must be serialized from the same type, so that fields
+/// Airtable leaves out compare equal to their defaults.
+pub fn reconcile(
+    db: &Database,
+    record_type: &str,
+    record_id: i32,
+    cio_company_id: i32,
+    database: &Value,
+    airtable: &Value,
+    airtable_modified_at: Option<DateTime<Utc>>,
+) -> Result<Reconciled> {
+    let state = AirtableSyncState::get(db, record_type, record_id)?;
+    // A state without a snapshot means we only know when the database last changed.
+    let base = state.as_ref().map(|s| &s.fields).filter(|f| !f.is_null());
+    let db_updated_at = state.as_ref().and_then(|s| s.db_updated_at);
+    let m = merge(base, database, airtable, db_updated_at, airtable_modified_at);
+
+    for c in &m.conflicts {
+        warn!(
+            "[reconcile] {} id={} field `{}` changed in both the database and Airtable, keeping the {} value",
+            record_type, record_id, c.field, c.winner
+        );
+
+        let conflict = NewAirtableSyncConflict {
+            record_type: record_type.to_string(),
+            record_id,
+            field: c.field.to_string(),
+            base_value: c.base.clone(),
+            db_value: c.database.clone(),
+            airtable_value: c.airtable.clone(),
+            winner: c.winner.to_string(),
+            db_updated_at,
+            airtable_modified_at,
+            created_at: Utc::now(),
+            cio_company_id,
+        };
+        diesel::insert_into(airtable_sync_conflicts::table)
+            .values(&conflict)
+            .execute(&db.conn()?)?;
+    }
+
+    let mut fields = database.clone();
+    if let (Some(f), Some(a)) = (fields.as_object_mut(), airtable.as_object()) {
+        for key in &m.pull {
+            f.insert(key.to_string(), a.get(key).cloned().unwrap_or(Value::Null));
+        }
+    }
+
+    Ok(Reconciled { fields, merge: m })
+}
+
+/// What we know about the last time a record agreed with Airtable.
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "airtable_sync_states"]
+pub struct NewAirtableSyncState {
+    pub record_type: String,
+    pub record_id: i32,
+    /// The fields of the record as they were in Airtable when both sides last agreed.
+    pub fields: Value,
+    /// When the record last changed in the database.
+    pub db_updated_at: Option<DateTime<Utc>>,
+    /// When both sides last agreed.
+    pub synced_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Queryable, Identifiable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "airtable_sync_states"]
+pub struct AirtableSyncState {
+    pub id: i32,
+    pub record_type: String,
+    pub record_id: i32,
+    pub fields: Value,
+    pub db_updated_at: Option<DateTime<Utc>>,
+    pub synced_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+impl AirtableSyncState {
+    /// Get the state for a record.
+    pub fn get(db: &Database, record_type: &str, record_id: i32) -> Result<Option<Self>> {
+        let r = airtable_sync_states::dsl::airtable_sync_states
+            .filter(airtable_sync_states::dsl::record_type.eq(record_type.to_string()))
+            .filter(airtable_sync_states::dsl::record_id.eq(record_id))
+            .first::<AirtableSyncState>(&db.conn()?)
+            .optional()?;
+
+        Ok(r)
+    }
+
+    /// Record that the record changed in the database.
+    pub fn touch(db: &Database, record_type: &str, record_id: i32, cio_company_id: i32) -> Result<()> {
+        let now = Utc::now();
+        let state = NewAirtableSyncState {
+            record_type: record_type.to_string(),
+            record_id,
+            // We don't know what Airtable has yet.
+            fields: Value::Null,
+            db_updated_at: Some(now),
+            synced_at: None,
+            cio_company_id,
+        };
+
+        diesel::insert_into(airtable_sync_states::table)
+            .values(&state)
+            .on_conflict((
+                airtable_sync_states::dsl::record_type,
+                airtable_sync_states::dsl::record_id,
+            ))
+            .do_update()
+            .set(airtable_sync_states::dsl::db_updated_at.eq(now))
+            .execute(&db.conn()?)?;
+
+        Ok(())
+    }
+
+    /// Record what both sides agree on after a sync.
+    pub fn save_snapshot(
+        db: &Database,
+        record_type: &str,
+        record_id: i32,
+        cio_company_id: i32,
+        fields: &Value,
+    ) -> Result<()> {
+        let now = Utc::now();
+        let state = NewAirtableSyncState {
+            record_type: record_type.to_string(),
+            record_id,
+            fields: fields.clone(),
+            db_updated_at: None,
+            synced_at: Some(now),
+            cio_company_id,
+        };
+
+        diesel::insert_into(airtable_sync_states::table)
+            .values(&state)
+            .on_conflict((
+                airtable_sync_states::dsl::record_type,
+                airtable_sync_states::dsl::record_id,
+            ))
+            .do_update()
+            .set((
+                airtable_sync_states::dsl::fields.eq(fields.clone()),
+                airtable_sync_states::dsl::synced_at.eq(Some(now)),
+            ))
+            .execute(&db.conn()?)?;
+
+        Ok(())
+    }
+
+    /// Forget the state for a record, when it is deleted.
+    pub fn delete(db: &Database, record_type: &str, record_id: i32) -> Result<()> {
+        diesel::delete(
+            airtable_sync_states::dsl::airtable_sync_states
+                .filter(airtable_sync_states::dsl::record_type.eq(record_type.to_string()))
+                .filter(airtable_sync_states::dsl::record_id.eq(record_id)),
+        )
+        .execute(&db.conn()?)?;
+
+        Ok(())
+    }
+}
+
+/// A field that was changed in both the database and Airtable.
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "airtable_sync_conflicts"]
+pub struct NewAirtableSyncConflict {
+    pub record_type: String,
+    pub record_id: i32,
+    pub field: String,
+    /// The value both sides last agreed on.
+    pub base_value: Value,
+    pub db_value: Value,
+    pub airtable_value: Value,
+    /// The side whose value we kept.
+    pub winner: String,
+    pub db_updated_at: Option<DateTime<Utc>>,
+    pub airtable_modified_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Queryable, Identifiable, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "airtable_sync_conflicts"]
+pub struct AirtableSyncConflict {
+    pub id: i32,
+    pub record_type: String,
+    pub record_id: i32,
+    pub field: String,
+    pub base_value: Value,
+    pub db_value: Value,
+    pub airtable_value: Value,
+    pub winner: String,
+    pub db_updated_at: Option<DateTime<Utc>>,
+    pub airtable_modified_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+
+    use crate::reconcile::{merge, ReconcileReport, Winner};
+
+    #[test]
+    fn test_reconcile_merge() {
+        let base = json!({"id": 1, "status": "Interviewing", "notes": "", "name": "Jess"});
+        let database = json!({"id": 1, "status": "Interviewing", "notes": "", "name": "Jessie"});
+        let airtable = json!({"id": 1, "status": "Giving offer", "notes": "", "name": "Jess"});
+
+        // Each side changed a different field.
+        let m = merge(Some(&base), &database, &airtable, None, None);
+        assert_eq!(m.pull, vec!["status"]);
+        assert_eq!(m.push, vec!["name"]);
+        assert!(m.conflicts.is_empty());
+
+        // Both sides changed the same field, the newest wins.
+        let now = Utc::now();
+        let airtable = json!({"id": 1, "status": "Interviewing", "notes": "", "name": "Jessica"});
+        let m = merge(
+            Some(&base),
+            &database,
+            &airtable,
+            Some(now - Duration::hours(1)),
+            Some(now),
+        );
+        assert_eq!(m.pull, vec!["name"]);
+        assert!(m.push.is_empty());
+        assert_eq!(m.conflicts.len(), 1);
+        assert_eq!(m.conflicts[0].winner, Winner::Airtable);
+
+        let m = merge(
+            Some(&base),
+            &database,
+            &airtable,
+            Some(now),
+            Some(now - Duration::hours(1)),
+        );
+        assert!(m.pull.is_empty());
+        assert_eq!(m.push, vec!["name"]);
+        assert_eq!(m.conflicts[0].winner, Winner::Database);
+
+        // Without a base, the database wins, even if Airtable is newer.
+        let m = merge(None, &database, &airtable, None, None);
+        assert_eq!(m.push, vec!["name"]);
+        assert!(m.conflicts.is_empty());
+        let m = merge(None, &database, &airtable, None, Some(now));
+        assert!(m.pull.is_empty());
+        assert_eq!(m.push, vec!["name"]);
+
+        // Our own fields are never pulled.
+        let m = merge(Some(&base), &database, &json!({"id": 2, "name": "Jessie"}), None, None);
+        assert_eq!(m.pull, vec!["notes", "status"]);
+    }
+
+    #[test]
+    fn test_reconcile_report() {
+        let base = json!({"id": 1, "status": "Interviewing", "name": "Jess"});
+        let airtable = json!({"id": 1, "status": "Giving offer", "name": "Jess"});
+
+        let mut report = ReconcileReport::default();
+        report.add(&merge(Some(&base), &base, &airtable, None, None));
+        report.fail(2, &anyhow::anyhow!("Airtable is down"));
+        report.add(&merge(Some(&base), &base, &base, None, None));
+
+        // The failure doesn't stop the records after it.
+        assert_eq!(report.records, 2);
+        assert_eq!(report.fields_pulled, 1);
+        assert_eq!(report.errors, vec!["id=2 failed: Airtable is down"]);
+    }
+}
@@ -27,6 +27,7 @@ use crate::{
     configs::User,
     core::UpdateAirtableRecord,
     db::Database,
+    error::CioError,
     schema::{recorded_meetings, users},
     utils::truncate,
 };
@@ -77,7 +78,7 @@ pub struct NewRecordedMeeting {
 /// Implement updating the Airtable record for a RecordedMeeting.
 #[async_trait]
 impl UpdateAirtableRecord<RecordedMeeting> for RecordedMeeting {
-    async fn update_airtable_record(&mut self, record: RecordedMeeting) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, record: RecordedMeeting) -> Result<()> {
         if !record.transcript_id.is_empty() {
             self.transcript_id = record.transcript_id;
         }
@@ -206,7 +207,7 @@ impl RecordedMeeting {
 pub async fn refresh_zoom_recorded_meetings(db: &Database, company: &Company) -> Result<()> {
     let zoom_auth = company.authenticate_zoom(db).await;
     if let Err(e) = zoom_auth {
-        if e.to_string().contains("no token") {
+        if CioError::is_not_connected(&e) {
             // Return early, this company does not use Zoom.
             return Ok(());
         }
@@ -359,7 +360,7 @@ pub async fn refresh_zoom_recorded_meetings(db: &Database, company: &Company) ->
                     .eq(meeting.host_id.to_string())
                     .and(users::dsl::cio_company_id.eq(company.id)),
             )
-            .first::<User>(&db.conn())?;
+            .first::<User>(&db.conn()?)?;
 
         // Create the meeting in the database.
         let m = NewRecordedMeeting {
@@ -817,7 +818,7 @@ pub async fn refresh_google_recorded_meetings(db: &Database, company: &Company)
                 {
                     Ok(t) => t,
                     Err(e) => {
-                        if e.to_string().contains("404") {
+                        if CioError::is_not_found(&e) {
                             // Reset the transcript ID to be blank, so that we can re-request
                             // a transcript.
                             db_meeting.transcript_id = String::new();
@@ -16,7 +16,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     airtable::AIRTABLE_GITHUB_REPOS_TABLE, companies::Company, core::UpdateAirtableRecord, db::Database,
-    github_prs::FromSimpleUser, schema::github_repos,
+    error::CioError, github_prs::FromSimpleUser, schema::github_repos,
 };
 
 /// The data type for a GitHub user.
@@ -232,7 +232,7 @@ pub struct NewRepo {
 /// Implement updating the Airtable record for a GithubRepo.
 #[async_trait]
 impl UpdateAirtableRecord<GithubRepo> for GithubRepo {
-    async fn update_airtable_record(&mut self, _record: GithubRepo) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, _record: GithubRepo) -> Result<()> {
         Ok(())
     }
 }
@@ -415,7 +415,7 @@ impl GithubRepo {
             .get_branch(&company.github_org, &self.name, &self.default_branch)
             .await;
         if let Err(e) = branch {
-            if !e.to_string().contains("404") && !e.to_string().contains("Not Found") {
+            if !CioError::is_not_found(&e) {
                 bail!("could not get branch {} repo {}: {}", self.default_branch, self.name, e);
             } else {
                 // Return early. Likely the repo no longer exists.
@@ -462,7 +462,7 @@ impl GithubRepo {
             Err(e) => {
                 // If we get a 404 for teams then likely the repo is new, we can just move on and
                 // add the teams.
-                if !e.to_string().contains("404") && !e.to_string().contains("Not Found") {
+                if !CioError::is_not_found(&e) {
                     bail!("could not list teams for repo {}: {}", self.name, e);
                 }
             }
@@ -0,0 +1,390 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    str::{from_utf8, FromStr},
+};
+
+use anyhow::{bail, Result};
+use chrono::Utc;
+use flate2::{write::GzEncoder, Compression};
+use google_drive::traits::{DriveOps, FileOps};
+use log::{info, warn};
+use regex::{Captures, Regex};
+use serde::Serialize;
+
+use crate::{
+    companies::Company,
+    db::Database,
+    interviews::combine_pdfs,
+    rfd_sections::is_markdown,
+    rfd_states::RFDState,
+    rfds::{get_images_in_branch, RFDs, RFD},
+    utils::{decode_base64, write_file},
+};
+
+/// What an export of the RFDs made.
+#[derive(Debug, Clone, Default)]
+pub struct RFDArchive {
+    /// The directory with the static site, the EPUB and the combined PDF.
+    pub dir: PathBuf,
+    /// The tarball of the directory.
+    pub tarball: PathBuf,
+    /// The link to the tarball in Google Drive, this is empty if it was not uploaded.
+    pub link_google_drive: String,
+    /// How many RFDs are in the archive.
+    pub rfds: usize,
+}
+
+/// An RFD in the search index of the archive.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ArchiveSearchEntry {
+    pub number_string: String,
+    pub name: String,
+    pub state: String,
+    pub authors: String,
+    /// The page of the RFD, relative to the root of the archive.
+    pub path: String,
+    pub content: String,
+}
+
+/// Returns the page of an RFD, relative to the root of the archive.
+pub fn archive_path(number_string: &str) -> String {
+    format!("rfd/{}/index.html", number_string)
+}
+
+/// Rewrite the links in the HTML of an RFD so they work from its page in the archive.
+///
+/// Links to RFDs in the archive go to their pages, links to the other RFDs go to the
+/// rendered site. Images are in `static/images` at the root of the archive.
+pub fn relative_links(html: &str, numbers: &BTreeSet<String>) -> Result<String> {
+    let rfd_link = Regex::new(r#"href="(?:https://rfd\.shared\.oxide\.computer)?/rfd/(\d{4})/?"#)?;
+    let html = rfd_link.replace_all(html, |c: &Captures| {
+        if numbers.contains(&c[1]) {
+            format!(r#"href="../../{}"#, archive_path(&c[1]))
+        } else {
+            format!(r#"href="https://rfd.shared.oxide.computer/rfd/{}"#, &c[1])
+        }
+    });
+
+    Ok(html
+        .replace(r#"src="/static/images/"#, r#"src="../../static/images/"#)
+        .replace(r#"data="/static/images/"#, r#"data="../../static/images/"#))
+}
+
+/// Escape text so it can go in HTML.
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Returns the page of an RFD in the archive.
+fn rfd_page(rfd: &RFD, html: &str, backlinks: &[(String, String)]) -> String {
+    let mut page = format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{name}</title>
+</head>
+<body>
+<nav><a href="../../index.html">All RFDs</a></nav>
+<header>
+<h1>{name}</h1>
+<p>State: <strong>{state}</strong> | Authors: {authors}"#,
+        name = escape(&rfd.name),
+        state = escape(&rfd.state),
+        authors = escape(&rfd.authors),
+    );
+    if !rfd.discussion.is_empty() {
+        page += &format!(r#" | <a href="{}">Discussion</a>"#, escape(&rfd.discussion));
+    }
+    page += "</p>\n</header>\n<main>\n";
+    page += html;
+    page += "\n</main>\n";
+
+    if !backlinks.is_empty() {
+        page += "<aside>\n<h2>Referenced by</h2>\n<ul>\n";
+        for (number_string, name) in backlinks {
+            page += &format!(
+                "<li><a href=\"../../{}\">{}</a></li>\n",
+                archive_path(number_string),
+                escape(name)
+            );
+        }
+        page += "</ul>\n</aside>\n";
+    }
+
+    page += "</body>\n</html>\n";
+    page
+}
+
+/// Returns the index of the archive, with a search of the RFDs.
+fn index_page(rfds: &[RFD]) -> String {
+    let mut page = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Requests for Discussion</title>
+<script src="search-index.js"></script>
+</head>
+<body>
+<h1>Requests for Discussion</h1>
+<input id="search" type="search" placeholder="Search">
+<ul id="rfds">
+"#
+    .to_string();
+
+    for rfd in rfds {
+        page += &format!(
+            "<li data-rfd=\"{}\"><a href=\"{}\">{}</a> ({})</li>\n",
+            rfd.number_string,
+            archive_path(&rfd.number_string),
+            escape(&rfd.name),
+            escape(&rfd.state)
+        );
+    }
+
+    page += r#"</ul>
+<script>
+document.getElementById("search").addEventListener("input", function (e) {
+  var words = e.target.value.toLowerCase().split(/\s+/).filter(Boolean);
+  RFD_SEARCH_INDEX.forEach(function (rfd) {
+    var text = (rfd.name + " " + rfd.authors + " " + rfd.content).toLowerCase();
+    var found = words.every(function (w) { return text.indexOf(w) >= 0; });
+    document.querySelector('[data-rfd="' + rfd.number_string + '"]').style.display = found ? "" : "none";
+  });
+});
+</script>
+</body>
+</html>
+"#;
+    page
+}
+
+/// Save the images of an RFD in `static/images/{number_string}` in the archive.
+async fn save_images(github: &octorust::Client, company: &Company, rfd: &RFD, dir: &Path) -> Result<()> {
+    let owner = &company.github_org;
+    let repo = github.repos().get(owner, "rfd").await?;
+
+    let mut branch = rfd.number_string.to_string();
+    if rfd.link.contains(&format!("/{}/", repo.default_branch)) {
+        branch = repo.default_branch.to_string();
+    }
+
+    let old_dir = format!("rfd/{}", rfd.number_string);
+    let images = get_images_in_branch(github, owner, "rfd", &old_dir, &branch).await?;
+    for image in images {
+        let mut path = dir.join("static/images").join(&rfd.number_string);
+        path.push(image.path.replace(&old_dir, "").trim_start_matches('/'));
+
+        write_file(&path, &decode_base64(&image.content))?;
+    }
+
+    Ok(())
+}
+
+/// Export every published RFD into `output`: a static HTML site with an index, backlinks and
+/// a search index, an EPUB and one PDF of all of them. Then make a tarball of it all and, if
+/// `upload` is set, upload the tarball to the RFDs folder in Google Drive.
+pub async fn export_rfd_archive(db: &Database, company: &Company, output: &Path, upload: bool) -> Result<RFDArchive> {
+    let github = company.authenticate_github()?;
+
+    // Committed RFDs were published before, so they go in the archive too.
+    let mut rfds: Vec<RFD> = RFDs::get_from_db(db, company.id)?
+        .into_iter()
+        .filter(|rfd| {
+            matches!(
+                RFDState::from_str(&rfd.state),
+                Ok(RFDState::Published) | Ok(RFDState::Committed)
+            )
+        })
+        .collect();
+    if rfds.is_empty() {
+        bail!("there are no published RFDs to export");
+    }
+    rfds.sort_by_key(|rfd| rfd.number);
+
+    let name = format!("rfds-{}", Utc::now().format("%Y-%m-%d"));
+    let dir = output.join(&name);
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::create_dir_all(&dir)?;
+
+    let numbers: BTreeSet<String> = rfds.iter().map(|rfd| rfd.number_string.to_string()).collect();
+    let names: BTreeMap<String, String> = rfds
+        .iter()
+        .map(|rfd| (rfd.name.to_string(), rfd.number_string.to_string()))
+        .collect();
+
+    let pdf_dir = env::temp_dir().join(format!("{}-pdfs", name));
+    fs::create_dir_all(&pdf_dir)?;
+
+    let mut search_index: Vec<ArchiveSearchEntry> = Default::default();
+    let mut pages: Vec<String> = Default::default();
+    let mut pdfs: Vec<String> = Default::default();
+    for rfd in &rfds {
+        info!("exporting RFD {} to the archive", rfd.number_string);
+
+        let mut html = rfd.html.to_string();
+        if html.is_empty() {
+            let repo = github.repos().get(&company.github_org, "rfd").await?;
+            html = rfd
+                .get_html(
+                    &github,
+                    &company.github_org,
+                    "rfd",
+                    &repo.default_branch,
+                    is_markdown(&rfd.content),
+                )
+                .await?;
+        }
+
+        // Only link to the RFDs that are in the archive.
+        let backlinks: Vec<(String, String)> = rfd
+            .backlinks
+            .iter()
+            .filter_map(|b| names.get(b).map(|n| (n.to_string(), b.to_string())))
+            .collect();
+
+        let path = archive_path(&rfd.number_string);
+        write_file(
+            &dir.join(&path),
+            rfd_page(rfd, &relative_links(&html, &numbers)?, &backlinks).as_bytes(),
+        )?;
+        pages.push(path.to_string());
+
+        if let Err(e) = save_images(&github, company, rfd, &dir).await {
+            warn!(
+                "saving the images of RFD {} to the archive failed: {}",
+                rfd.number_string, e
+            );
+        }
+
+        search_index.push(ArchiveSearchEntry {
+            number_string: rfd.number_string.to_string(),
+            name: rfd.name.to_string(),
+            state: rfd.state.to_string(),
+            authors: rfd.authors.to_string(),
+            path,
+            content: rfd.content.to_string(),
+        });
+
+        match rfd.render_pdf(&github, company).await {
+            Ok(pdf) => {
+                let pdf_path = pdf_dir.join(format!("{}.pdf", rfd.number_string));
+                write_file(&pdf_path, &pdf)?;
+                pdfs.push(pdf_path.to_str().unwrap().to_string());
+            }
+            Err(e) => warn!("rendering the PDF of RFD {} failed: {}", rfd.number_string, e),
+        }
+    }
+
+    write_file(&dir.join("index.html"), index_page(&rfds).as_bytes())?;
+    // The index loads this as a script, so the search works when the archive is opened from disk.
+    write_file(
+        &dir.join("search-index.js"),
+        format!("var RFD_SEARCH_INDEX = {};\n", serde_json::to_string(&search_index)?).as_bytes(),
+    )?;
+    write_file(
+        &dir.join("search-index.json"),
+        &serde_json::to_vec_pretty(&search_index)?,
+    )?;
+
+    // Combine the PDFs into one.
+    info!("combining the PDFs of {} RFDs", pdfs.len());
+    write_file(&dir.join("rfds.pdf"), &combine_pdfs(pdfs)?)?;
+    fs::remove_dir_all(&pdf_dir)?;
+
+    // Make the EPUB from the pages. The images are relative to the pages, which are all two
+    // directories deep, so resolving them from the first page finds them.
+    info!("converting the archive to an EPUB");
+    let cmd_output = Command::new("pandoc")
+        .current_dir(&dir)
+        .args(&["-o", "rfds.epub", "--metadata", "title=Requests for Discussion"])
+        .arg(format!(
+            "--resource-path={}",
+            Path::new(&pages[0]).parent().unwrap().display()
+        ))
+        .args(&pages)
+        .output()?;
+    if !cmd_output.status.success() {
+        bail!(
+            "running pandoc failed: {} {}",
+            from_utf8(&cmd_output.stdout)?,
+            from_utf8(&cmd_output.stderr)?
+        );
+    }
+
+    // Make the tarball.
+    let tarball = output.join(format!("{}.tar.gz", name));
+    let mut tar = tar::Builder::new(GzEncoder::new(fs::File::create(&tarball)?, Compression::default()));
+    tar.append_dir_all(&name, &dir)?;
+    tar.into_inner()?.finish()?;
+    info!("exported {} RFDs to `{}`", rfds.len(), tarball.display());
+
+    let mut archive = RFDArchive {
+        dir,
+        tarball,
+        link_google_drive: Default::default(),
+        rfds: rfds.len(),
+    };
+
+    if upload {
+        let drive_client = company.authenticate_google_drive(db).await?;
+
+        // Put it next to the PDFs of the RFDs : "Automated Documents"/"rfds"/"archives"
+        let shared_drive = drive_client.drives().get_by_name("Automated Documents").await?;
+        let drive_id = shared_drive.id.to_string();
+        let rfds_id = drive_client.files().create_folder(&drive_id, "", "rfds").await?;
+        let parent_id = drive_client
+            .files()
+            .create_folder(&drive_id, &rfds_id, "archives")
+            .await?;
+
+        let drive_file = drive_client
+            .files()
+            .create_or_update(
+                &drive_id,
+                &parent_id,
+                &format!("{}.tar.gz", name),
+                "application/gzip",
+                &fs::read(&archive.tarball)?,
+            )
+            .await?;
+        archive.link_google_drive = format!("https://drive.google.com/open?id={}", drive_file.id);
+    }
+
+    Ok(archive)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::rfd_archive::relative_links;
+
+    #[test]
+    fn test_rfd_archive_relative_links() {
+        let numbers: BTreeSet<String> = vec!["0001".to_string(), "0053".to_string()].into_iter().collect();
+
+        let html = r#"<a href="/rfd/0053#_background">RFD 53</a>
+<a href="https://rfd.shared.oxide.computer/rfd/0001">RFD 1</a>
+<a href="https://rfd.shared.oxide.computer/rfd/0099/">RFD 99</a>
+<img src="/static/images/0053/arch.svg">
+<object type="image/svg+xml" data="/static/images/0053/flow.svg"></object>"#;
+
+        assert_eq!(
+            relative_links(html, &numbers).unwrap(),
+            r#"<a href="../../rfd/0053/index.html#_background">RFD 53</a>
+<a href="../../rfd/0001/index.html">RFD 1</a>
+<a href="https://rfd.shared.oxide.computer/rfd/0099">RFD 99</a>
+<img src="../../static/images/0053/arch.svg">
+<object type="image/svg+xml" data="../../static/images/0053/flow.svg"></object>"#
+        );
+    }
+}
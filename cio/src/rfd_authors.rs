@@ -0,0 +1,259 @@
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::Result;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    companies::Company,
+    configs::{User, Users},
+    db::Database,
+    rfds::{RFDs, RFD},
+    schema::{rfd_authors, rfds},
+};
+
+/// An author of an RFD, and the user they are if we found one.
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "rfd_authors"]
+pub struct NewRFDAuthor {
+    pub rfds_id: i32,
+    /// The id of the user, this is not set if we could not find a user for the author.
+    pub users_id: Option<i32>,
+    pub name: String,
+    pub email: String,
+    pub github: String,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Queryable, Identifiable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "rfd_authors"]
+pub struct RFDAuthor {
+    pub id: i32,
+    pub rfds_id: i32,
+    pub users_id: Option<i32>,
+    pub name: String,
+    pub email: String,
+    pub github: String,
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+impl fmt::Display for RFDAuthor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let author = ParsedAuthor {
+            name: self.name.to_string(),
+            email: self.email.to_string(),
+            github: self.github.to_string(),
+        };
+        write!(f, "{}", author)
+    }
+}
+
+impl RFDAuthor {
+    /// Get the authors of an RFD.
+    pub fn get_for_rfd(db: &Database, rfds_id: i32) -> Result<Vec<RFDAuthor>> {
+        let authors = rfd_authors::dsl::rfd_authors
+            .filter(rfd_authors::dsl::rfds_id.eq(rfds_id))
+            .order_by(rfd_authors::dsl::id.asc())
+            .load::<RFDAuthor>(&db.conn()?)?;
+
+        Ok(authors)
+    }
+
+    /// Get the RFDs a user is an author of.
+    pub fn get_rfds_for_user(db: &Database, users_id: i32) -> Result<Vec<RFD>> {
+        let rfds = rfds::table
+            .inner_join(rfd_authors::table)
+            .filter(rfd_authors::dsl::users_id.eq(users_id))
+            .select(rfds::all_columns)
+            .distinct()
+            .order_by(rfds::dsl::number.asc())
+            .load::<RFD>(&db.conn()?)?;
+
+        Ok(rfds)
+    }
+
+    /// Replace the authors of an RFD with the ones in its `authors` now, and find the user
+    /// for each of them.
+    pub fn sync_for_rfd(db: &Database, rfd: &RFD, users: &[User], domain: &str) -> Result<Vec<RFDAuthor>> {
+        let conn = db.conn()?;
+        diesel::delete(rfd_authors::dsl::rfd_authors.filter(rfd_authors::dsl::rfds_id.eq(rfd.id))).execute(&conn)?;
+
+        let new_authors: Vec<NewRFDAuthor> = parse_authors(&rfd.authors)?
+            .into_iter()
+            .map(|a| NewRFDAuthor {
+                rfds_id: rfd.id,
+                users_id: users.iter().find(|u| a.is_user(u, domain)).map(|u| u.id),
+                name: a.name,
+                email: a.email,
+                github: a.github,
+                cio_company_id: rfd.cio_company_id,
+            })
+            .collect();
+        let authors = diesel::insert_into(rfd_authors::table)
+            .values(&new_authors)
+            .get_results::<RFDAuthor>(&conn)?;
+
+        Ok(authors)
+    }
+}
+
+/// An author from the `authors` of an RFD, before we look for their user.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParsedAuthor {
+    pub name: String,
+    /// The email of the author, lowercased.
+    pub email: String,
+    /// The GitHub handle of the author, without the `@`.
+    pub github: String,
+}
+
+impl fmt::Display for ParsedAuthor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.name.is_empty() {
+            write!(f, "{}", self.name)
+        } else if !self.email.is_empty() {
+            write!(f, "{}", self.email)
+        } else {
+            write!(f, "@{}", self.github)
+        }
+    }
+}
+
+impl ParsedAuthor {
+    /// Returns if the author is the user, by their email, GitHub handle or name.
+    pub fn is_user(&self, user: &User, domain: &str) -> bool {
+        if !self.email.is_empty() {
+            let emails = vec![user.email.to_string(), user.recovery_email.to_string()];
+            let aliases = user.aliases.iter().map(|a| format!("{}@{}", a, domain));
+            if emails
+                .into_iter()
+                .chain(aliases)
+                .any(|e| !e.is_empty() && e.to_lowercase() == self.email)
+            {
+                return true;
+            }
+        }
+
+        if !self.github.is_empty() && user.github.trim_start_matches('@').eq_ignore_ascii_case(&self.github) {
+            return true;
+        }
+
+        !self.name.is_empty()
+            && (self.name.eq_ignore_ascii_case(&user.full_name()) || self.name.eq_ignore_ascii_case(&user.username))
+    }
+}
+
+/// Split the `authors` of an RFD into the authors, with their name, email and GitHub handle
+/// if they are there.
+///
+/// Authors are separated by `,`, `;` or `and`, for example
+/// `Jane Doe <jane@example.com>, @jdoe and John Doe`.
+pub fn parse_authors(authors: &str) -> Result<Vec<ParsedAuthor>> {
+    let separator = Regex::new(r"[,;]|\s+and\s+|\s+&\s+")?;
+    let email = Regex::new(r"[\w.+-]+@[\w-]+(?:\.[\w-]+)+")?;
+    let github = Regex::new(r"(?:https?://)?github\.com/([\w-]+)/?|@([\w-]+)")?;
+    let markup = Regex::new(r"<\s*>|\(\s*\)|\[\s*\]")?;
+
+    let mut parsed: Vec<ParsedAuthor> = Default::default();
+    for part in separator.split(authors) {
+        let mut rest = part.to_string();
+        let mut author = ParsedAuthor::default();
+
+        if let Some(m) = email.find(&rest) {
+            author.email = m.as_str().to_lowercase();
+            rest = rest.replace(m.as_str(), "");
+        }
+        if let Some(c) = github.captures(&rest) {
+            author.github = c.get(1).or_else(|| c.get(2)).unwrap().as_str().to_string();
+            rest = rest.replace(c.get(0).unwrap().as_str(), "");
+        }
+        author.name = markup
+            .replace_all(&rest, "")
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ");
+
+        if author != ParsedAuthor::default() && !parsed.contains(&author) {
+            parsed.push(author);
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Update the RFDs each user of a company is an author of.
+pub fn refresh_rfds_authored(db: &Database, company: &Company) -> Result<()> {
+    let names: BTreeMap<i32, String> = RFDs::get_from_db(db, company.id)?
+        .into_iter()
+        .map(|rfd| (rfd.id, rfd.name))
+        .collect();
+    let authors = rfd_authors::dsl::rfd_authors
+        .filter(rfd_authors::dsl::cio_company_id.eq(company.id))
+        .order_by(rfd_authors::dsl::rfds_id.asc())
+        .load::<RFDAuthor>(&db.conn()?)?;
+
+    let mut authored: BTreeMap<i32, Vec<String>> = Default::default();
+    for author in authors {
+        if let (Some(users_id), Some(name)) = (author.users_id, names.get(&author.rfds_id)) {
+            let rfds = authored.entry(users_id).or_default();
+            if !rfds.contains(name) {
+                rfds.push(name.to_string());
+            }
+        }
+    }
+
+    for mut user in Users::get_from_db(db, company.id)? {
+        let rfds = authored.remove(&user.id).unwrap_or_default();
+        if rfds != user.rfds_authored {
+            user.rfds_authored = rfds;
+            user.update_in_db(db)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rfd_authors::{parse_authors, ParsedAuthor};
+
+    #[test]
+    fn test_rfd_authors_parse() {
+        let authors =
+            parse_authors("Jane Doe <Jane@example.com>, John Smith (@jsmith); https://github.com/ada and Bob Jones")
+                .unwrap();
+        assert_eq!(
+            authors,
+            vec![
+                ParsedAuthor {
+                    name: "Jane Doe".to_string(),
+                    email: "jane@example.com".to_string(),
+                    github: "".to_string(),
+                },
+                ParsedAuthor {
+                    name: "John Smith".to_string(),
+                    email: "".to_string(),
+                    github: "jsmith".to_string(),
+                },
+                ParsedAuthor {
+                    name: "".to_string(),
+                    email: "".to_string(),
+                    github: "ada".to_string(),
+                },
+                ParsedAuthor {
+                    name: "Bob Jones".to_string(),
+                    email: "".to_string(),
+                    github: "".to_string(),
+                },
+            ]
+        );
+        assert_eq!(authors[2].to_string(), "@ada");
+
+        assert!(parse_authors("").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    str::FromStr,
+};
+
+use anyhow::{bail, Result};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use log::warn;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    companies::Company,
+    db::Database,
+    rfds::{NewRFD, RFDs, RFD},
+    schema::rfd_references,
+};
+
+/// What an RFD refers to.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum ReferenceKind {
+    /// Another RFD.
+    RFD,
+    /// A GitHub issue or pull request.
+    Issue,
+    /// A GitHub repository.
+    Repo,
+}
+
+impl fmt::Display for ReferenceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReferenceKind::RFD => write!(f, "rfd"),
+            ReferenceKind::Issue => write!(f, "issue"),
+            ReferenceKind::Repo => write!(f, "repo"),
+        }
+    }
+}
+
+impl FromStr for ReferenceKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rfd" => Ok(ReferenceKind::RFD),
+            "issue" => Ok(ReferenceKind::Issue),
+            "repo" => Ok(ReferenceKind::Repo),
+            _ => bail!("invalid reference kind: `{}`", s),
+        }
+    }
+}
+
+/// A reference from an RFD to another RFD, a GitHub issue or a repo.
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "rfd_references"]
+pub struct NewRFDReference {
+    /// The id of the RFD the reference is in.
+    pub rfds_id: i32,
+    /// One of `rfd`, `issue` or `repo`.
+    pub kind: String,
+    /// The number of the RFD, `{owner}/{repo}#{number}` for an issue, or `{owner}/{repo}`.
+    pub target: String,
+    pub link: String,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Queryable, Identifiable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "rfd_references"]
+pub struct RFDReference {
+    pub id: i32,
+    pub rfds_id: i32,
+    pub kind: String,
+    pub target: String,
+    pub link: String,
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+impl RFDReference {
+    /// Get the references in an RFD.
+    pub fn get_for_rfd(db: &Database, rfds_id: i32) -> Result<Vec<RFDReference>> {
+        let references = rfd_references::dsl::rfd_references
+            .filter(rfd_references::dsl::rfds_id.eq(rfds_id))
+            .order_by(rfd_references::dsl::id.asc())
+            .load::<RFDReference>(&db.conn()?)?;
+
+        Ok(references)
+    }
+
+    /// Replace the references of an RFD with the ones in its contents now.
+    pub fn sync_for_rfd(db: &Database, rfd: &RFD, references: &[ParsedReference]) -> Result<()> {
+        let conn = db.conn()?;
+        diesel::delete(rfd_references::dsl::rfd_references.filter(rfd_references::dsl::rfds_id.eq(rfd.id)))
+            .execute(&conn)?;
+
+        let new_references: Vec<NewRFDReference> = references
+            .iter()
+            .map(|r| NewRFDReference {
+                rfds_id: rfd.id,
+                kind: r.kind.to_string(),
+                target: r.target.to_string(),
+                link: r.link.to_string(),
+                cio_company_id: rfd.cio_company_id,
+            })
+            .collect();
+        diesel::insert_into(rfd_references::table)
+            .values(&new_references)
+            .execute(&conn)?;
+
+        Ok(())
+    }
+}
+
+/// A reference found in the contents of an RFD, before it is saved.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ParsedReference {
+    pub kind: ReferenceKind,
+    pub target: String,
+    pub link: String,
+}
+
+/// Find the references to other RFDs, GitHub issues and repos in the contents of an RFD.
+///
+/// Every reference is only returned once, in the order they first show up. References to
+/// the RFD itself are skipped.
+pub fn parse_references(content: &str, number: i32) -> Result<Vec<ParsedReference>> {
+    let rfd = Regex::new(
+        r"\bRFD[ -]?0*(\d{1,4})\b|https?://(\d+)\.rfd\.oxide\.computer|https?://rfd\.shared\.oxide\.computer/rfd/0*(\d+)",
+    )?;
+    let github = Regex::new(r"https?://github\.com/([\w.-]+)/([\w.-]+)(?:/(issues|pull)/(\d+))?")?;
+
+    let mut references: Vec<ParsedReference> = Vec::new();
+    let mut push = |reference: ParsedReference| {
+        if !references.contains(&reference) {
+            references.push(reference);
+        }
+    };
+
+    for c in rfd.captures_iter(content) {
+        let n = c
+            .get(1)
+            .or_else(|| c.get(2))
+            .or_else(|| c.get(3))
+            .map(|m| m.as_str())
+            .unwrap_or_default()
+            .parse::<i32>()?;
+        if n == number || n == 0 {
+            continue;
+        }
+
+        push(ParsedReference {
+            kind: ReferenceKind::RFD,
+            target: n.to_string(),
+            link: NewRFD::generate_rendered_link(&NewRFD::generate_number_string(n)),
+        });
+    }
+
+    for c in github.captures_iter(content) {
+        let owner = &c[1];
+        // The repo can be at the end of a sentence or a clone URL.
+        let repo = c[2].trim_end_matches('.').trim_end_matches(".git");
+        if repo.is_empty() {
+            continue;
+        }
+
+        match (c.get(3), c.get(4)) {
+            (Some(path), Some(n)) => push(ParsedReference {
+                kind: ReferenceKind::Issue,
+                target: format!("{}/{}#{}", owner, repo, n.as_str()),
+                link: format!("https://github.com/{}/{}/{}/{}", owner, repo, path.as_str(), n.as_str()),
+            }),
+            _ => push(ParsedReference {
+                kind: ReferenceKind::Repo,
+                target: format!("{}/{}", owner, repo),
+                link: format!("https://github.com/{}/{}", owner, repo),
+            }),
+        }
+    }
+
+    Ok(references)
+}
+
+/// Save the references in every RFD of a company and update the backlinks of each RFD.
+///
+/// This has to run after all the RFDs are in the database, so references to new RFDs
+/// are not reported as missing.
+pub fn refresh_rfd_references(db: &Database, company: &Company) -> Result<()> {
+    let rfds: Vec<RFD> = RFDs::get_from_db(db, company.id)?.into();
+    let numbers: BTreeSet<i32> = rfds.iter().map(|r| r.number).collect();
+
+    let mut backlinks: BTreeMap<i32, Vec<String>> = Default::default();
+    for rfd in &rfds {
+        let references = parse_references(&rfd.content, rfd.number)?;
+        for reference in &references {
+            if reference.kind != ReferenceKind::RFD {
+                continue;
+            }
+
+            let n = reference.target.parse::<i32>()?;
+            if numbers.contains(&n) {
+                backlinks.entry(n).or_default().push(rfd.name.to_string());
+            } else {
+                warn!(
+                    "RFD {} references RFD {}, which does not exist",
+                    rfd.number_string, reference.target
+                );
+            }
+        }
+
+        RFDReference::sync_for_rfd(db, rfd, &references)?;
+    }
+
+    for mut rfd in rfds {
+        let links = backlinks.remove(&rfd.number).unwrap_or_default();
+        if links != rfd.backlinks {
+            rfd.backlinks = links;
+            rfd.update_in_db(db)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rfd_references::{parse_references, ParsedReference, ReferenceKind};
+
+    #[test]
+    fn test_rfd_references_parse() {
+        let content = r#"= RFD 43 Identity and Access Management (IAM)
+
+See RFD 53 and https://rfd.shared.oxide.computer/rfd/0053#_background, also RFD-4
+and https://12.rfd.oxide.computer.
+
+This is tracked in https://github.com/oxidecomputer/omicron/issues/123, the code is
+in https://github.com/oxidecomputer/omicron. Clone https://github.com/oxidecomputer/propolis.git
+"#;
+
+        let references = parse_references(content, 43).unwrap();
+        assert_eq!(
+            references,
+            vec![
+                ParsedReference {
+                    kind: ReferenceKind::RFD,
+                    target: "53".to_string(),
+                    link: "https://rfd.shared.oxide.computer/rfd/0053".to_string(),
+                },
+                ParsedReference {
+                    kind: ReferenceKind::RFD,
+                    target: "4".to_string(),
+                    link: "https://rfd.shared.oxide.computer/rfd/0004".to_string(),
+                },
+                ParsedReference {
+                    kind: ReferenceKind::RFD,
+                    target: "12".to_string(),
+                    link: "https://rfd.shared.oxide.computer/rfd/0012".to_string(),
+                },
+                ParsedReference {
+                    kind: ReferenceKind::Issue,
+                    target: "oxidecomputer/omicron#123".to_string(),
+                    link: "https://github.com/oxidecomputer/omicron/issues/123".to_string(),
+                },
+                ParsedReference {
+                    kind: ReferenceKind::Repo,
+                    target: "oxidecomputer/omicron".to_string(),
+                    link: "https://github.com/oxidecomputer/omicron".to_string(),
+                },
+                ParsedReference {
+                    kind: ReferenceKind::Repo,
+                    target: "oxidecomputer/propolis".to_string(),
+                    link: "https://github.com/oxidecomputer/propolis".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{db::Database, rfds::RFD, schema::rfd_renders};
+
+/// The version of how we render RFDs. Bump this when the asciidoctor options or templates
+/// change, so every RFD is rendered again on the next sync.
+pub const RFD_RENDERER_VERSION: &str = "1";
+
+/// The last HTML and PDF we rendered for an RFD, so we don't render it again if it did not change.
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "rfd_renders"]
+pub struct NewRFDRender {
+    pub rfds_id: i32,
+    /// The cache key the HTML was rendered for, empty if it was not rendered yet.
+    pub html_cache_key: String,
+    pub html: String,
+    /// The cache key the PDF was rendered for, empty if it was not rendered yet.
+    pub pdf_cache_key: String,
+    /// The name of the PDF in GitHub and Google Drive, it changes with the title.
+    pub pdf_file_name: String,
+    pub pdf_link_google_drive: String,
+    pub updated_at: DateTime<Utc>,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Queryable, Identifiable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "rfd_renders"]
+pub struct RFDRender {
+    pub id: i32,
+    pub rfds_id: i32,
+    pub html_cache_key: String,
+    pub html: String,
+    pub pdf_cache_key: String,
+    pub pdf_file_name: String,
+    pub pdf_link_google_drive: String,
+    pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+impl RFDRender {
+    /// Returns the cache key for the contents of an RFD, from the SHA of its file and the renderer version.
+    pub fn cache_key(sha: &str) -> String {
+        format!("{}:{}", sha, RFD_RENDERER_VERSION)
+    }
+
+    /// Get what we last rendered for an RFD.
+    pub fn get_for_rfd(db: &Database, rfds_id: i32) -> Result<Option<RFDRender>> {
+        let render = rfd_renders::dsl::rfd_renders
+            .filter(rfd_renders::dsl::rfds_id.eq(rfds_id))
+            .first::<RFDRender>(&db.conn()?)
+            .optional()?;
+
+        Ok(render)
+    }
+
+    /// Returns the HTML if it was rendered from the file with this SHA by the current renderer.
+    pub fn html_for(&self, sha: &str) -> Option<&str> {
+        if sha.is_empty() || self.html_cache_key != RFDRender::cache_key(sha) {
+            return None;
+        }
+
+        Some(&self.html)
+    }
+
+    /// Returns the Google Drive link to the PDF if it was rendered from the file with this SHA
+    /// by the current renderer, and uploaded with this file name.
+    pub fn pdf_link_for(&self, sha: &str, file_name: &str) -> Option<&str> {
+        if sha.is_empty()
+            || self.pdf_cache_key != RFDRender::cache_key(sha)
+            || self.pdf_file_name != file_name
+            || self.pdf_link_google_drive.is_empty()
+        {
+            return None;
+        }
+
+        Some(&self.pdf_link_google_drive)
+    }
+
+    /// Save the HTML of an RFD, it is valid until the SHA of the RFD or the renderer changes.
+    pub fn save_html(db: &Database, rfd: &RFD) -> Result<()> {
+        let now = Utc::now();
+        let cache_key = RFDRender::cache_key(&rfd.sha);
+
+        diesel::insert_into(rfd_renders::table)
+            .values(&NewRFDRender {
+                rfds_id: rfd.id,
+                html_cache_key: cache_key.to_string(),
+                html: rfd.html.to_string(),
+                pdf_cache_key: Default::default(),
+                pdf_file_name: Default::default(),
+                pdf_link_google_drive: Default::default(),
+                updated_at: now,
+                cio_company_id: rfd.cio_company_id,
+            })
+            .on_conflict(rfd_renders::dsl::rfds_id)
+            .do_update()
+            .set((
+                rfd_renders::dsl::html_cache_key.eq(cache_key),
+                rfd_renders::dsl::html.eq(rfd.html.to_string()),
+                rfd_renders::dsl::updated_at.eq(now),
+            ))
+            .execute(&db.conn()?)?;
+
+        Ok(())
+    }
+
+    /// Save where the PDF of an RFD was uploaded, it is valid until the SHA of the RFD,
+    /// its title or the renderer changes.
+    pub fn save_pdf(db: &Database, rfd: &RFD) -> Result<()> {
+        let now = Utc::now();
+        let cache_key = RFDRender::cache_key(&rfd.sha);
+        let file_name = rfd.get_pdf_filename();
+
+        diesel::insert_into(rfd_renders::table)
+            .values(&NewRFDRender {
+                rfds_id: rfd.id,
+                html_cache_key: Default::default(),
+                html: Default::default(),
+                pdf_cache_key: cache_key.to_string(),
+                pdf_file_name: file_name.to_string(),
+                pdf_link_google_drive: rfd.pdf_link_google_drive.to_string(),
+                updated_at: now,
+                cio_company_id: rfd.cio_company_id,
+            })
+            .on_conflict(rfd_renders::dsl::rfds_id)
+            .do_update()
+            .set((
+                rfd_renders::dsl::pdf_cache_key.eq(cache_key),
+                rfd_renders::dsl::pdf_file_name.eq(file_name),
+                rfd_renders::dsl::pdf_link_google_drive.eq(rfd.pdf_link_google_drive.to_string()),
+                rfd_renders::dsl::updated_at.eq(now),
+            ))
+            .execute(&db.conn()?)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use crate::rfd_renders::RFDRender;
+
+    #[test]
+    fn test_rfd_renders_cache() {
+        let render = RFDRender {
+            id: 1,
+            rfds_id: 1,
+            html_cache_key: RFDRender::cache_key("abc"),
+            html: "<h2>Background</h2>".to_string(),
+            pdf_cache_key: RFDRender::cache_key("abc"),
+            pdf_file_name: "RFD 0001 Requests for Discussion.pdf".to_string(),
+            pdf_link_google_drive: "https://drive.google.com/open?id=123".to_string(),
+            updated_at: Utc::now(),
+            cio_company_id: 1,
+        };
+
+        assert_eq!(render.html_for("abc"), Some("<h2>Background</h2>"));
+        assert_eq!(render.html_for("def"), None);
+        assert_eq!(render.html_for(""), None);
+
+        assert_eq!(
+            render.pdf_link_for("abc", "RFD 0001 Requests for Discussion.pdf"),
+            Some("https://drive.google.com/open?id=123")
+        );
+        // The title changed, so the PDF has a new name.
+        assert_eq!(render.pdf_link_for("abc", "RFD 0001 Requests for Comments.pdf"), None);
+        assert_eq!(render.pdf_link_for("def", "RFD 0001 Requests for Discussion.pdf"), None);
+    }
+}
@@ -0,0 +1,471 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{db::Database, rfds::RFD, schema::rfd_sections};
+
+/// A section of an RFD, from one heading to the next.
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "rfd_sections"]
+pub struct NewRFDSection {
+    /// The anchor of the heading, link to the section with `{rendered_link}#{anchor}`.
+    pub anchor: String,
+    /// The text of the section, without markup.
+    pub content: String,
+    /// The title of the heading.
+    pub name: String,
+    /// The id of the RFD in the database.
+    pub rfds_id: i32,
+    /// The level of the heading, `==` or `##` is 2.
+    pub level: i32,
+    /// Where the section is in the RFD, starting at 0.
+    pub position: i32,
+}
+
+/// A section of an RFD, from one heading to the next.
+#[derive(Debug, Queryable, Identifiable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "rfd_sections"]
+pub struct RFDSection {
+    pub id: i32,
+    /// The anchor of the heading, link to the section with `{rendered_link}#{anchor}`.
+    pub anchor: String,
+    /// The text of the section, without markup.
+    pub content: String,
+    /// The title of the heading.
+    pub name: String,
+    /// The id of the RFD in the database.
+    pub rfds_id: i32,
+    /// The level of the heading, `==` or `##` is 2.
+    pub level: i32,
+    /// Where the section is in the RFD, starting at 0.
+    pub position: i32,
+}
+
+impl RFDSection {
+    /// Get the sections of an RFD, in the order they appear.
+    pub fn get_for_rfd(db: &Database, rfds_id: i32) -> Result<Vec<RFDSection>> {
+        let sections = rfd_sections::dsl::rfd_sections
+            .filter(rfd_sections::dsl::rfds_id.eq(rfds_id))
+            .order_by(rfd_sections::dsl::position.asc())
+            .load::<RFDSection>(&db.conn()?)?;
+
+        Ok(sections)
+    }
+
+    /// Split the contents of an RFD into its sections and save them.
+    ///
+    /// A section keeps its id as long as its anchor stays the same, so links to it keep
+    /// working. Sections that are no longer in the RFD are deleted.
+    pub fn sync_for_rfd(db: &Database, rfd: &RFD) -> Result<Vec<RFDSection>> {
+        let parsed = parse_sections(&rfd.content, is_markdown(&rfd.content))?;
+        let conn = db.conn()?;
+
+        let mut sections = Vec::new();
+        for (position, p) in parsed.into_iter().enumerate() {
+            let new_section = NewRFDSection {
+                anchor: p.anchor,
+                content: p.content,
+                name: p.name,
+                rfds_id: rfd.id,
+                level: p.level,
+                position: position as i32,
+            };
+
+            let section = diesel::insert_into(rfd_sections::table)
+                .values(&new_section)
+                .on_conflict((rfd_sections::dsl::rfds_id, rfd_sections::dsl::anchor))
+                .do_update()
+                .set(&new_section)
+                .get_result::<RFDSection>(&conn)?;
+            sections.push(section);
+        }
+
+        let ids: Vec<i32> = sections.iter().map(|s| s.id).collect();
+        diesel::delete(
+            rfd_sections::dsl::rfd_sections
+                .filter(rfd_sections::dsl::rfds_id.eq(rfd.id))
+                .filter(rfd_sections::dsl::id.ne_all(ids)),
+        )
+        .execute(&conn)?;
+
+        Ok(sections)
+    }
+
+    /// Returns the link to the section in the rendered RFD.
+    pub fn link(&self, rendered_link: &str) -> String {
+        format!("{}#{}", rendered_link, self.anchor)
+    }
+}
+
+/// A section parsed from the contents of an RFD, before it is saved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedSection {
+    pub anchor: String,
+    pub level: i32,
+    pub name: String,
+    pub content: String,
+}
+
+/// Returns if the contents of an RFD are Markdown rather than AsciiDoc, from how the title is written.
+pub fn is_markdown(content: &str) -> bool {
+    for line in content.lines() {
+        if line.starts_with("# ") {
+            return true;
+        }
+        if line.starts_with("= ") {
+            return false;
+        }
+    }
+
+    false
+}
+
+/// Split the contents of an RFD into sections at its headings.
+///
+/// The title and everything before the first heading are not a section. Headings in code and
+/// other delimited blocks are ignored. The anchors are the ones the rendered RFD has: explicit
+/// AsciiDoc anchors, or the ids Asciidoctor and GitHub generate from the title.
+pub fn parse_sections(content: &str, is_markdown: bool) -> Result<Vec<ParsedSection>> {
+    let heading = if is_markdown {
+        Regex::new(r"^(#{1,6})\s+(.+?)(?:\s+#+)?\s*$")?
+    } else {
+        Regex::new(r"^(={1,6})\s+(.+?)\s*$")?
+    };
+    let block_anchor = Regex::new(r"^\[\[([^\],\s]+)(?:,[^\]]*)?\]\]$")?;
+    let block_id = Regex::new(r"^\[#([^\].%,\s]+)[^\]]*\]$")?;
+    let attribute = Regex::new(r"^(?::[\w-]+!?:|\[.*\]$|\+$)")?;
+    let markup = markup_replacements(is_markdown)?;
+
+    let mut sections: Vec<ParsedSection> = Vec::new();
+    let mut current: Option<ParsedSection> = None;
+    let mut anchors: HashSet<String> = HashSet::new();
+    let mut pending_anchor: Option<String> = None;
+    let mut fence: Option<String> = None;
+
+    for line in content.lines() {
+        if let Some(open) = &fence {
+            if fence_marker(line, is_markdown).map_or(false, |m| closes_fence(open, &m, is_markdown)) {
+                fence = None;
+            } else if open != "////" {
+                // Keep code as it is, it is already plain text.
+                push_line(&mut current, line.trim_end());
+            }
+            continue;
+        }
+
+        if let Some(marker) = fence_marker(line, is_markdown) {
+            fence = Some(marker);
+            pending_anchor = None;
+            continue;
+        }
+
+        if !is_markdown {
+            if let Some(c) = block_anchor.captures(line).or_else(|| block_id.captures(line)) {
+                pending_anchor = Some(c[1].to_string());
+                continue;
+            }
+            if line.starts_with("//") || attribute.is_match(line) {
+                continue;
+            }
+        }
+
+        if let Some(c) = heading.captures(line) {
+            let level = c[1].len() as i32;
+            if level == 1 {
+                // This is the title of the RFD.
+                pending_anchor = None;
+                continue;
+            }
+
+            if let Some(section) = current.take() {
+                sections.push(finish(section));
+            }
+
+            // Skip the list markers, a title can start with a number.
+            let name = strip_markup(&c[2], &markup[1..]);
+            let anchor = match pending_anchor.take() {
+                Some(anchor) => anchor,
+                None if is_markdown => unique_anchor(&anchors, &github_id(&name), "-", 1),
+                None => unique_anchor(&anchors, &asciidoc_id(&name), "_", 2),
+            };
+            anchors.insert(anchor.to_string());
+
+            current = Some(ParsedSection {
+                anchor,
+                level,
+                name,
+                content: String::new(),
+            });
+            continue;
+        }
+
+        if !line.trim().is_empty() {
+            pending_anchor = None;
+        }
+        push_line(&mut current, &strip_markup(line, &markup));
+    }
+
+    if let Some(section) = current.take() {
+        sections.push(finish(section));
+    }
+
+    Ok(sections)
+}
+
+/// Returns the delimiter if the line opens or closes a block.
+fn fence_marker(line: &str, is_markdown: bool) -> Option<String> {
+    let trimmed = line.trim();
+    if trimmed.starts_with("```") || (is_markdown && trimmed.starts_with("~~~")) {
+        let c = trimmed.chars().next().unwrap();
+        return Some(trimmed.chars().take_while(|x| *x == c).collect());
+    }
+
+    if !is_markdown && trimmed.len() >= 4 {
+        let c = trimmed.chars().next().unwrap();
+        if "-.+/=*_".contains(c) && trimmed.chars().all(|x| x == c) {
+            return Some(trimmed.to_string());
+        }
+    }
+
+    None
+}
+
+/// Returns if a delimiter closes the block that was opened with `open`.
+fn closes_fence(open: &str, marker: &str, is_markdown: bool) -> bool {
+    if is_markdown {
+        marker.starts_with(&open[..1]) && marker.len() >= open.len()
+    } else {
+        marker == open
+    }
+}
+
+fn push_line(current: &mut Option<ParsedSection>, line: &str) {
+    if let Some(section) = current {
+        // Don't keep more than one blank line in a row.
+        if line.trim().is_empty() && (section.content.is_empty() || section.content.ends_with("\n\n")) {
+            return;
+        }
+        section.content.push_str(line);
+        section.content.push('\n');
+    }
+}
+
+fn finish(mut section: ParsedSection) -> ParsedSection {
+    section.content = section.content.trim().to_string();
+    section
+}
+
+/// Returns the patterns to strip inline markup with, in the order they apply.
+fn markup_replacements(is_markdown: bool) -> Result<Vec<(Regex, &'static str)>> {
+    let patterns: &[(&str, &'static str)] = if is_markdown {
+        &[
+            (r"^\s*(?:[*+>-]|\d+\.)\s+", ""),
+            (r"!\[([^\]]*)\]\([^)]*\)", "$1"),
+            (r"\[([^\]]+)\]\([^)]*\)", "$1"),
+            (r"<[^>]+>", ""),
+            (r"\*\*([^*]+)\*\*", "$1"),
+            (r"__([^_]+)__", "$1"),
+            (r"\*([^*\s][^*]*)\*", "$1"),
+            (r"(^|\W)_([^_\s][^_]*)_(\W|$)", "$1$2$3"),
+            (r"`([^`]+)`", "$1"),
+        ]
+    } else {
+        &[
+            (r"^\s*(?:[*.-]+|\d+\.)\s+", ""),
+            (r"<<[^,>]+,\s*([^>]+)>>", "$1"),
+            (r"<<([^>]+)>>", "$1"),
+            (r"((?:https?://|mailto:)[^\s\[]+)\[\]", "$1"),
+            (r"(?:https?://|mailto:|link:|xref:)[^\s\[]*\[([^\]]+)\]", "$1"),
+            (r"image::?[^\s\[]*\[([^\],]*)[^\]]*\]", "$1"),
+            (r"footnote:\[([^\]]*)\]", " ($1)"),
+            (r"\*\*([^*]+)\*\*", "$1"),
+            (r"\*([^*\s][^*]*)\*", "$1"),
+            (r"__([^_]+)__", "$1"),
+            (r"(^|\W)_([^_\s][^_]*)_(\W|$)", "$1$2$3"),
+            (r"`\+?([^`+]+)\+?`", "$1"),
+        ]
+    };
+
+    let mut replacements = Vec::new();
+    for (pattern, replacement) in patterns {
+        replacements.push((Regex::new(pattern)?, *replacement));
+    }
+
+    Ok(replacements)
+}
+
+fn strip_markup(line: &str, markup: &[(Regex, &'static str)]) -> String {
+    let mut text = line.trim_end().to_string();
+    for (re, replacement) in markup {
+        text = re.replace_all(&text, *replacement).to_string();
+    }
+
+    text
+}
+
+/// Returns the id Asciidoctor generates for a section title, with the default
+/// `idprefix` and `idseparator` of `_`.
+fn asciidoc_id(title: &str) -> String {
+    let mut id = "_".to_string();
+    for c in title.to_lowercase().chars() {
+        if " _.-".contains(c) {
+            if !id.ends_with('_') {
+                id.push('_');
+            }
+        } else if c.is_alphanumeric() {
+            id.push(c);
+        }
+    }
+    if id.len() > 1 && id.ends_with('_') {
+        id.pop();
+    }
+
+    id
+}
+
+/// Returns the id GitHub generates for a Markdown heading.
+fn github_id(title: &str) -> String {
+    title
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the anchor, with a counter at the end if another heading already has it.
+fn unique_anchor(anchors: &HashSet<String>, anchor: &str, separator: &str, start: usize) -> String {
+    if !anchors.contains(anchor) {
+        return anchor.to_string();
+    }
+
+    let mut n = start;
+    loop {
+        let candidate = format!("{}{}{}", anchor, separator, n);
+        if !anchors.contains(&candidate) {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rfd_sections::{is_markdown, parse_sections, ParsedSection};
+
+    #[test]
+    fn test_rfd_sections_asciidoc() {
+        let content = r#":showtitle:
+:toc: left
+:state: published
+
+= RFD 43 Identity and Access Management (IAM)
+{authors}
+
+This is the preamble.
+
+== Determinations
+
+We *decided* to use `OAuth`, see <<_background,the background>>.
+
+[[principles]]
+=== Principles & Goals
+
+* Least privilege
+* Read https://example.com[the docs].
+
+----
+== Not a heading
+----
+
+== Background
+
+// A comment.
+A link:thing.html[thing].
+
+== Background
+"#;
+        assert!(!is_markdown(content));
+
+        let sections = parse_sections(content, false).unwrap();
+        assert_eq!(
+            sections,
+            vec![
+                ParsedSection {
+                    anchor: "_determinations".to_string(),
+                    level: 2,
+                    name: "Determinations".to_string(),
+                    content: "We decided to use OAuth, see the background.".to_string(),
+                },
+                ParsedSection {
+                    anchor: "principles".to_string(),
+                    level: 3,
+                    name: "Principles & Goals".to_string(),
+                    content: "Least privilege\nRead the docs.\n\n== Not a heading".to_string(),
+                },
+                ParsedSection {
+                    anchor: "_background".to_string(),
+                    level: 2,
+                    name: "Background".to_string(),
+                    content: "A thing.".to_string(),
+                },
+                ParsedSection {
+                    anchor: "_background_2".to_string(),
+                    level: 2,
+                    name: "Background".to_string(),
+                    content: "".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rfd_sections_markdown() {
+        let content = r#"---
+authors: Jess Frazelle <jess@oxide.computer>
+state: discussion
+---
+
+# RFD 53 Control Plane
+
+## Why `Rust`?
+
+- It is **fast**, see [the benchmarks](https://example.com).
+
+```
+## Not a heading
+```
+
+## Why Rust?
+"#;
+        assert!(is_markdown(content));
+
+        let sections = parse_sections(content, true).unwrap();
+        assert_eq!(
+            sections,
+            vec![
+                ParsedSection {
+                    anchor: "why-rust".to_string(),
+                    level: 2,
+                    name: "Why Rust?".to_string(),
+                    content: "It is fast, see the benchmarks.\n\n## Not a heading".to_string(),
+                },
+                ParsedSection {
+                    anchor: "why-rust-1".to_string(),
+                    level: 2,
+                    name: "Why Rust?".to_string(),
+                    content: "".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{db::Database, rfds::RFD, schema::rfd_state_transitions};
+
+/// The states an RFD moves through.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum RFDState {
+    /// The RFD is being written, before it is ready to be discussed.
+    Prediscussion,
+    /// The idea is being worked out, the RFD is not ready to be discussed yet.
+    Ideation,
+    /// The RFD has a pull request open to discuss it.
+    Discussion,
+    /// The discussion is done and the RFD is merged.
+    Published,
+    /// What the RFD describes has been built.
+    Committed,
+    /// We are not going to do what the RFD describes.
+    Abandoned,
+}
+
+impl fmt::Display for RFDState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RFDState::Prediscussion => write!(f, "prediscussion"),
+            RFDState::Ideation => write!(f, "ideation"),
+            RFDState::Discussion => write!(f, "discussion"),
+            RFDState::Published => write!(f, "published"),
+            RFDState::Committed => write!(f, "committed"),
+            RFDState::Abandoned => write!(f, "abandoned"),
+        }
+    }
+}
+
+impl FromStr for RFDState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "prediscussion" => Ok(RFDState::Prediscussion),
+            "ideation" => Ok(RFDState::Ideation),
+            "discussion" => Ok(RFDState::Discussion),
+            "published" => Ok(RFDState::Published),
+            "committed" => Ok(RFDState::Committed),
+            "abandoned" => Ok(RFDState::Abandoned),
+            _ => bail!(
+                "`{}` is not an RFD state, it must be one of `prediscussion`, `ideation`, `discussion`, \
+                 `published`, `committed` or `abandoned`",
+                s
+            ),
+        }
+    }
+}
+
+impl RFDState {
+    /// Returns the states an RFD in this state can move to.
+    pub fn next_states(&self) -> &'static [RFDState] {
+        match self {
+            RFDState::Prediscussion => &[RFDState::Ideation, RFDState::Discussion, RFDState::Abandoned],
+            RFDState::Ideation => &[RFDState::Discussion, RFDState::Abandoned],
+            RFDState::Discussion => &[RFDState::Published, RFDState::Abandoned],
+            RFDState::Published => &[RFDState::Committed, RFDState::Abandoned],
+            RFDState::Committed => &[RFDState::Abandoned],
+            RFDState::Abandoned => &[],
+        }
+    }
+
+    /// Returns if an RFD in this state can move to the other state.
+    /// Staying in the same state is always allowed.
+    pub fn can_transition_to(&self, to: RFDState) -> bool {
+        *self == to || self.next_states().contains(&to)
+    }
+
+    /// Returns if an RFD in this state must have a `discussion:` link.
+    pub fn requires_discussion(&self) -> bool {
+        matches!(self, RFDState::Discussion | RFDState::Published | RFDState::Committed)
+    }
+
+    /// Check that an RFD can move from one state to another, and return the new state.
+    ///
+    /// If there was no state before, or it was not one we know, the RFD can move to any state.
+    pub fn transition(from: &str, to: &str) -> Result<RFDState> {
+        let to_state = RFDState::from_str(to)?;
+
+        if let Ok(from_state) = RFDState::from_str(from) {
+            if !from_state.can_transition_to(to_state) {
+                let next: Vec<String> = from_state.next_states().iter().map(|s| format!("`{}`", s)).collect();
+                bail!(
+                    "an RFD in `{}` can't move to `{}`, it can only move to: {}",
+                    from_state,
+                    to_state,
+                    if next.is_empty() {
+                        "nothing".to_string()
+                    } else {
+                        next.join(", ")
+                    }
+                );
+            }
+        }
+
+        Ok(to_state)
+    }
+
+    /// Check that an RFD can be published by merging it into the default branch.
+    ///
+    /// Merging is what publishes an RFD, so unlike `transition` this lets an RFD in
+    /// prediscussion or ideation skip ahead. Committed and abandoned RFDs stay as they are.
+    pub fn publish(from: &str) -> Result<RFDState> {
+        if let Ok(from_state) = RFDState::from_str(from) {
+            if matches!(from_state, RFDState::Committed | RFDState::Abandoned) {
+                bail!("an RFD in `{}` can't move to `{}`", from_state, RFDState::Published);
+            }
+        }
+
+        Ok(RFDState::Published)
+    }
+}
+
+/// A change of the state of an RFD.
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "rfd_state_transitions"]
+pub struct NewRFDStateTransition {
+    pub rfds_id: i32,
+    /// The state before, this is empty when the RFD was first seen.
+    pub from_state: String,
+    pub to_state: String,
+    pub created_at: DateTime<Utc>,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Queryable, Identifiable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "rfd_state_transitions"]
+pub struct RFDStateTransition {
+    pub id: i32,
+    pub rfds_id: i32,
+    pub from_state: String,
+    pub to_state: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+impl RFDStateTransition {
+    /// Record that an RFD moved from a state to the one it is in now.
+    /// Nothing is recorded if the state did not change.
+    pub fn record(db: &Database, rfd: &RFD, from_state: &str) -> Result<Option<RFDStateTransition>> {
+        if from_state == rfd.state {
+            return Ok(None);
+        }
+
+        let transition = diesel::insert_into(rfd_state_transitions::table)
+            .values(&NewRFDStateTransition {
+                rfds_id: rfd.id,
+                from_state: from_state.to_string(),
+                to_state: rfd.state.to_string(),
+                created_at: Utc::now(),
+                cio_company_id: rfd.cio_company_id,
+            })
+            .get_result::<RFDStateTransition>(&db.conn()?)?;
+
+        Ok(Some(transition))
+    }
+
+    /// Get the state changes of an RFD, the oldest first.
+    pub fn get_for_rfd(db: &Database, rfds_id: i32) -> Result<Vec<RFDStateTransition>> {
+        let transitions = rfd_state_transitions::dsl::rfd_state_transitions
+            .filter(rfd_state_transitions::dsl::rfds_id.eq(rfds_id))
+            .order_by(rfd_state_transitions::dsl::created_at.asc())
+            .load::<RFDStateTransition>(&db.conn()?)?;
+
+        Ok(transitions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::rfd_states::RFDState;
+
+    #[test]
+    fn test_rfd_states_transition() {
+        assert_eq!(RFDState::from_str(" Published").unwrap(), RFDState::Published);
+        assert!(RFDState::from_str("done").is_err());
+
+        assert_eq!(
+            RFDState::transition("prediscussion", "discussion").unwrap(),
+            RFDState::Discussion
+        );
+        assert_eq!(RFDState::transition("", "ideation").unwrap(), RFDState::Ideation);
+        assert_eq!(
+            RFDState::transition("published", "published").unwrap(),
+            RFDState::Published
+        );
+        assert_eq!(
+            RFDState::transition("committed", "abandoned").unwrap(),
+            RFDState::Abandoned
+        );
+
+        assert!(RFDState::transition("published", "discussion").is_err());
+        assert!(RFDState::transition("ideation", "published").is_err());
+        assert!(RFDState::transition("abandoned", "ideation").is_err());
+        assert!(RFDState::transition("discussion", "nope").is_err());
+
+        // Merging publishes an RFD from any state before it.
+        assert_eq!(RFDState::publish("ideation").unwrap(), RFDState::Published);
+        assert_eq!(RFDState::publish("prediscussion").unwrap(), RFDState::Published);
+        assert_eq!(RFDState::publish("").unwrap(), RFDState::Published);
+        assert!(RFDState::publish("abandoned").is_err());
+
+        assert!(RFDState::Discussion.requires_discussion());
+        assert!(!RFDState::Ideation.requires_discussion());
+    }
+}
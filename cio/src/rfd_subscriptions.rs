@@ -0,0 +1,494 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl, RunQueryDsl};
+use log::{info, warn};
+use schemars::JsonSchema;
+use sendgrid_api::{traits::MailOps, Client as SendGrid};
+use serde::{Deserialize, Serialize};
+use slack_chat_api::{FormattedMessage, MessageBlock, MessageBlockText, MessageBlockType, MessageType};
+
+use crate::{
+    companies::Company,
+    db::Database,
+    rfd_states::{RFDState, RFDStateTransition},
+    rfds::RFD,
+    schema::rfd_subscriptions,
+};
+
+/// How to tell someone that an RFD they follow changed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum NotifyBy {
+    /// A direct message in Slack.
+    Slack,
+    /// An email.
+    Email,
+}
+
+impl fmt::Display for NotifyBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotifyBy::Slack => write!(f, "slack"),
+            NotifyBy::Email => write!(f, "email"),
+        }
+    }
+}
+
+impl FromStr for NotifyBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "slack" => Ok(NotifyBy::Slack),
+            "email" => Ok(NotifyBy::Email),
+            _ => bail!("invalid notify by: `{}`", s),
+        }
+    }
+}
+
+/// What someone follows: a single RFD, or every RFD with a label.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum FollowTarget {
+    /// The number of an RFD.
+    RFD(i32),
+    /// A milestone or relevant component of the RFDs, lowercased.
+    Label(String),
+}
+
+impl fmt::Display for FollowTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FollowTarget::RFD(n) => write!(f, "RFD {}", n),
+            FollowTarget::Label(l) => write!(f, "label `{}`", l),
+        }
+    }
+}
+
+impl FromStr for FollowTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(label) = s.strip_prefix("label:") {
+            let label = label.trim().to_lowercase();
+            if label.is_empty() {
+                bail!("invalid label: `{}`", s);
+            }
+
+            return Ok(FollowTarget::Label(label));
+        }
+
+        match s.trim_start_matches('#').parse::<i32>() {
+            Ok(n) if n > 0 => Ok(FollowTarget::RFD(n)),
+            _ => bail!("invalid RFD number: `{}`", s),
+        }
+    }
+}
+
+impl FollowTarget {
+    /// Returns if the RFD is followed by following this.
+    pub fn matches(&self, rfd: &RFD) -> bool {
+        match self {
+            FollowTarget::RFD(n) => rfd.number == *n,
+            FollowTarget::Label(l) => rfd
+                .milestones
+                .iter()
+                .chain(rfd.relevant_components.iter())
+                .any(|m| m.trim().to_lowercase() == *l),
+        }
+    }
+}
+
+/// A command to manage RFD subscriptions, from the text of the Slack `/rfd` command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubscriptionCommand {
+    /// `/rfd follow 123` or `/rfd follow label:storage email`.
+    Follow { target: FollowTarget, notify_by: NotifyBy },
+    /// `/rfd unfollow 123` or `/rfd unfollow label:storage`.
+    Unfollow(FollowTarget),
+    /// `/rfd following`.
+    Following,
+}
+
+/// How to use the subscription commands, sent back when one is not right.
+pub const SUBSCRIPTION_USAGE: &str = "`/rfd follow <number|label:name> [slack|email]`, `/rfd unfollow \
+                                      <number|label:name>` or `/rfd following`";
+
+impl SubscriptionCommand {
+    /// Parse the text of an `/rfd` command.
+    ///
+    /// Returns `None` if the text is not a subscription command, then it is a search for an RFD.
+    pub fn parse(text: &str) -> Result<Option<Self>> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        if words.is_empty() {
+            return Ok(None);
+        }
+
+        let command = match (words[0].to_lowercase().as_str(), &words[1..]) {
+            ("follow", [target]) => SubscriptionCommand::Follow {
+                target: FollowTarget::from_str(target)?,
+                notify_by: NotifyBy::Slack,
+            },
+            ("follow", [target, notify_by]) => SubscriptionCommand::Follow {
+                target: FollowTarget::from_str(target)?,
+                notify_by: NotifyBy::from_str(&notify_by.to_lowercase())?,
+            },
+            ("unfollow", [target]) => SubscriptionCommand::Unfollow(FollowTarget::from_str(target)?),
+            ("following", []) => SubscriptionCommand::Following,
+            ("follow", _) | ("unfollow", _) | ("following", _) => bail!("usage: {}", SUBSCRIPTION_USAGE),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(command))
+    }
+}
+
+/// Someone following an RFD, or the RFDs with a label.
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "rfd_subscriptions"]
+pub struct NewRFDSubscription {
+    /// The Slack user who follows, subscriptions are managed from Slack.
+    pub slack_user_id: String,
+    /// The email to notify, only set if `notify_by` is `email`.
+    pub email: String,
+    /// One of `slack` or `email`.
+    pub notify_by: String,
+    /// The number of the RFD that is followed, this is `0` when a label is followed.
+    pub rfd_number: i32,
+    /// The label that is followed, this is empty when an RFD is followed.
+    pub label: String,
+    pub created_at: DateTime<Utc>,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Queryable, Identifiable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "rfd_subscriptions"]
+pub struct RFDSubscription {
+    pub id: i32,
+    pub slack_user_id: String,
+    pub email: String,
+    pub notify_by: String,
+    pub rfd_number: i32,
+    pub label: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+impl RFDSubscription {
+    /// Returns what is followed.
+    pub fn target(&self) -> FollowTarget {
+        if self.label.is_empty() {
+            FollowTarget::RFD(self.rfd_number)
+        } else {
+            FollowTarget::Label(self.label.to_string())
+        }
+    }
+
+    /// Follow an RFD or a label, following it again changes how the follower is notified.
+    pub fn follow(
+        db: &Database,
+        company: &Company,
+        slack_user_id: &str,
+        email: &str,
+        target: &FollowTarget,
+        notify_by: NotifyBy,
+    ) -> Result<RFDSubscription> {
+        let (rfd_number, label) = match target {
+            FollowTarget::RFD(n) => (*n, String::new()),
+            FollowTarget::Label(l) => (0, l.to_string()),
+        };
+
+        let subscription = diesel::insert_into(rfd_subscriptions::table)
+            .values(&NewRFDSubscription {
+                slack_user_id: slack_user_id.to_string(),
+                email: email.to_string(),
+                notify_by: notify_by.to_string(),
+                rfd_number,
+                label,
+                created_at: Utc::now(),
+                cio_company_id: company.id,
+            })
+            .on_conflict((
+                rfd_subscriptions::dsl::cio_company_id,
+                rfd_subscriptions::dsl::slack_user_id,
+                rfd_subscriptions::dsl::rfd_number,
+                rfd_subscriptions::dsl::label,
+            ))
+            .do_update()
+            .set((
+                rfd_subscriptions::dsl::email.eq(email.to_string()),
+                rfd_subscriptions::dsl::notify_by.eq(notify_by.to_string()),
+            ))
+            .get_result::<RFDSubscription>(&db.conn()?)?;
+
+        Ok(subscription)
+    }
+
+    /// Stop following an RFD or a label. Returns if it was followed.
+    pub fn unfollow(db: &Database, company: &Company, slack_user_id: &str, target: &FollowTarget) -> Result<bool> {
+        let (rfd_number, label) = match target {
+            FollowTarget::RFD(n) => (*n, String::new()),
+            FollowTarget::Label(l) => (0, l.to_string()),
+        };
+
+        let deleted = diesel::delete(
+            rfd_subscriptions::dsl::rfd_subscriptions.filter(
+                rfd_subscriptions::dsl::cio_company_id
+                    .eq(company.id)
+                    .and(rfd_subscriptions::dsl::slack_user_id.eq(slack_user_id.to_string()))
+                    .and(rfd_subscriptions::dsl::rfd_number.eq(rfd_number))
+                    .and(rfd_subscriptions::dsl::label.eq(label)),
+            ),
+        )
+        .execute(&db.conn()?)?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Get what a Slack user follows.
+    pub fn get_for_slack_user(db: &Database, company: &Company, slack_user_id: &str) -> Result<Vec<RFDSubscription>> {
+        let subscriptions = rfd_subscriptions::dsl::rfd_subscriptions
+            .filter(
+                rfd_subscriptions::dsl::cio_company_id
+                    .eq(company.id)
+                    .and(rfd_subscriptions::dsl::slack_user_id.eq(slack_user_id.to_string())),
+            )
+            .order_by((
+                rfd_subscriptions::dsl::rfd_number.asc(),
+                rfd_subscriptions::dsl::label.asc(),
+            ))
+            .load::<RFDSubscription>(&db.conn()?)?;
+
+        Ok(subscriptions)
+    }
+
+    /// Get all the subscriptions for a company.
+    pub fn get_for_company(db: &Database, company: &Company) -> Result<Vec<RFDSubscription>> {
+        let subscriptions = rfd_subscriptions::dsl::rfd_subscriptions
+            .filter(rfd_subscriptions::dsl::cio_company_id.eq(company.id))
+            .order_by(rfd_subscriptions::dsl::id.asc())
+            .load::<RFDSubscription>(&db.conn()?)?;
+
+        Ok(subscriptions)
+    }
+}
+
+/// Someone to notify, from one or more of their subscriptions.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+struct Follower {
+    slack_user_id: String,
+    email: String,
+    notify_by: NotifyBy,
+}
+
+impl Follower {
+    fn from_subscription(subscription: &RFDSubscription) -> Self {
+        Follower {
+            slack_user_id: subscription.slack_user_id.to_string(),
+            email: subscription.email.to_string(),
+            // Subscriptions are only saved with a valid way to notify.
+            notify_by: NotifyBy::from_str(&subscription.notify_by).unwrap_or(NotifyBy::Slack),
+        }
+    }
+
+    async fn notify(&self, db: &Database, company: &Company, subject: &str, text: &str) -> Result<()> {
+        match self.notify_by {
+            NotifyBy::Slack => {
+                company
+                    .post_to_slack_channel(
+                        db,
+                        &FormattedMessage {
+                            // Posting to a user id sends them a direct message.
+                            channel: self.slack_user_id.to_string(),
+                            blocks: vec![MessageBlock {
+                                block_type: MessageBlockType::Section,
+                                text: Some(MessageBlockText {
+                                    text_type: MessageType::Markdown,
+                                    text: text.to_string(),
+                                }),
+                                elements: Default::default(),
+                                accessory: Default::default(),
+                                block_id: Default::default(),
+                                fields: Default::default(),
+                            }],
+                            attachments: Default::default(),
+                        },
+                    )
+                    .await?;
+            }
+            NotifyBy::Email => {
+                SendGrid::new_from_env()
+                    .mail_send()
+                    .send_plain_text(
+                        subject,
+                        text,
+                        &[self.email.to_string()],
+                        &[],
+                        &[],
+                        &format!("rfds@{}", company.gsuite_domain),
+                    )
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Something that happened to an RFD that its followers are told about right away.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RFDEvent {
+    /// The RFD moved from one state to another.
+    StateChanged { from: String, to: String },
+    /// A pull request was opened to discuss the RFD, with its link.
+    DiscussionOpened(String),
+}
+
+impl From<RFDStateTransition> for RFDEvent {
+    fn from(transition: RFDStateTransition) -> Self {
+        RFDEvent::StateChanged {
+            from: transition.from_state,
+            to: transition.to_state,
+        }
+    }
+}
+
+impl RFDEvent {
+    /// Returns the subject and text of the notification for an RFD.
+    pub fn message(&self, rfd: &RFD) -> (String, String) {
+        match self {
+            RFDEvent::StateChanged { to, .. } if RFDState::from_str(to).ok() == Some(RFDState::Committed) => (
+                format!("{} was committed", rfd.name),
+                format!("{} was committed\n{}", rfd.name, rfd.short_link),
+            ),
+            RFDEvent::StateChanged { from, to } if from.is_empty() => (
+                format!("{} is in {}", rfd.name, to),
+                format!("{} is in `{}`\n{}", rfd.name, to, rfd.short_link),
+            ),
+            RFDEvent::StateChanged { from, to } => (
+                format!("{} moved to {}", rfd.name, to),
+                format!("{} moved from `{}` to `{}`\n{}", rfd.name, from, to, rfd.short_link),
+            ),
+            RFDEvent::DiscussionOpened(link) => (
+                format!("{} has a new discussion", rfd.name),
+                format!("{} has a new discussion: {}\n{}", rfd.name, link, rfd.short_link),
+            ),
+        }
+    }
+}
+
+/// Tell everyone who follows an RFD, or one of its labels, that something happened to it.
+///
+/// Failing to notify someone does not stop the others from being notified, it is only logged.
+pub async fn notify_followers(db: &Database, company: &Company, rfd: &RFD, event: &RFDEvent) -> Result<()> {
+    let mut followers: Vec<Follower> = RFDSubscription::get_for_company(db, company)?
+        .iter()
+        .filter(|s| s.target().matches(rfd))
+        .map(Follower::from_subscription)
+        .collect();
+    // Someone following the RFD and one of its labels only hears about it once.
+    followers.sort();
+    followers.dedup();
+
+    let (subject, text) = event.message(rfd);
+    for follower in followers {
+        if let Err(e) = follower.notify(db, company, &subject, &text).await {
+            warn!(
+                "notifying {} about RFD {} by {} failed: {}",
+                follower.slack_user_id, rfd.number_string, follower.notify_by, e
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Send everyone who follows RFDs a digest of the changes to only the RFDs they follow.
+///
+/// The changes are the RFDs that changed and their changelogs, the same ones that go in
+/// the changelog for everyone.
+pub async fn send_rfd_subscription_digests(
+    db: &Database,
+    company: &Company,
+    changes: &[(RFD, String)],
+    week_format: &str,
+) -> Result<()> {
+    let mut digests: BTreeMap<Follower, String> = Default::default();
+    for subscription in RFDSubscription::get_for_company(db, company)? {
+        let target = subscription.target();
+        let digest = digests.entry(Follower::from_subscription(&subscription)).or_default();
+        for (rfd, changelog) in changes {
+            let entry = format!("\n{} {}\n{}", rfd.name, rfd.short_link, changelog);
+            if target.matches(rfd) && !digest.contains(&entry) {
+                *digest += &entry;
+            }
+        }
+    }
+
+    for (follower, digest) in digests {
+        if digest.is_empty() {
+            continue;
+        }
+
+        let subject = format!("Changes to the RFDs you follow for the week {}", week_format);
+        let text = format!("{}:\n{}", subject, digest);
+        if let Err(e) = follower.notify(db, company, &subject, &text).await {
+            warn!(
+                "sending the RFD digest to {} by {} failed: {}",
+                follower.slack_user_id, follower.notify_by, e
+            );
+        } else {
+            info!(
+                "sent the RFD digest to {} by {}",
+                follower.slack_user_id, follower.notify_by
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rfd_subscriptions::{FollowTarget, NotifyBy, SubscriptionCommand};
+
+    #[test]
+    fn test_rfd_subscriptions_parse() {
+        assert_eq!(
+            SubscriptionCommand::parse("follow 123").unwrap(),
+            Some(SubscriptionCommand::Follow {
+                target: FollowTarget::RFD(123),
+                notify_by: NotifyBy::Slack,
+            })
+        );
+        assert_eq!(
+            SubscriptionCommand::parse("Follow label:Storage  email").unwrap(),
+            Some(SubscriptionCommand::Follow {
+                target: FollowTarget::Label("storage".to_string()),
+                notify_by: NotifyBy::Email,
+            })
+        );
+        assert_eq!(
+            SubscriptionCommand::parse("unfollow #43").unwrap(),
+            Some(SubscriptionCommand::Unfollow(FollowTarget::RFD(43)))
+        );
+        assert_eq!(
+            SubscriptionCommand::parse("following").unwrap(),
+            Some(SubscriptionCommand::Following)
+        );
+
+        // Anything else is a search for an RFD.
+        assert_eq!(SubscriptionCommand::parse("123").unwrap(), None);
+        assert_eq!(SubscriptionCommand::parse("followers of the sun").unwrap(), None);
+
+        assert!(SubscriptionCommand::parse("follow").is_err());
+        assert!(SubscriptionCommand::parse("follow abc").is_err());
+        assert!(SubscriptionCommand::parse("follow label:").is_err());
+        assert!(SubscriptionCommand::parse("follow 123 pager").is_err());
+        assert!(SubscriptionCommand::parse("following 123").is_err());
+    }
+}
@@ -26,9 +26,17 @@ use slack_chat_api::{FormattedMessage, MessageBlock, MessageBlockText, MessageBl
 use crate::{
     airtable::AIRTABLE_RFD_TABLE,
     companies::Company,
+    configs::{User, Users},
     core::{GitHubPullRequest, UpdateAirtableRecord},
     db::Database,
+    error::CioError,
     repos::FromUrl,
+    rfd_authors::{refresh_rfds_authored, RFDAuthor},
+    rfd_references::refresh_rfd_references,
+    rfd_renders::RFDRender,
+    rfd_sections::RFDSection,
+    rfd_states::{RFDState, RFDStateTransition},
+    rfd_subscriptions::{notify_followers, send_rfd_subscription_digests, RFDEvent},
     schema::rfds as r_f_ds,
     schema::rfds,
     utils::{
@@ -98,6 +106,15 @@ pub struct NewRFD {
     /// The section IDs of the RFD. This is set elsewhere.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub rfd_sections_id: Vec<i32>,
+    /// (generated) backlinks are the names of the RFDs that reference this one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backlinks: Vec<String>,
+    /// (generated) link_to_authors are the Airtable records of the authors in the Employees table.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub link_to_authors: Vec<String>,
+    /// (generated) unresolved_authors are the authors we could not find a user for.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unresolved_authors: Vec<String>,
     /// The CIO company ID.
     #[serde(default)]
     pub cio_company_id: i32,
@@ -163,6 +180,9 @@ impl NewRFD {
             pdf_link_github: Default::default(),
             pdf_link_google_drive: Default::default(),
             rfd_sections_id: Default::default(),
+            backlinks: Default::default(),
+            link_to_authors: Default::default(),
+            unresolved_authors: Default::default(),
             cio_company_id: company.id,
         })
     }
@@ -466,10 +486,20 @@ impl RFD {
         )
     }
 
-    /// Update an RFDs state.
+    /// Update an RFDs state, if the RFD can move to it from the state it is in.
     pub fn update_state(&mut self, state: &str, is_markdown: bool) -> Result<()> {
-        self.content = update_state(&self.content, state, is_markdown)?;
-        self.state = state.to_string();
+        let state = RFDState::transition(&self.state, state)?.to_string();
+        self.content = update_state(&self.content, &state, is_markdown)?;
+        self.state = state;
+
+        Ok(())
+    }
+
+    /// Publish an RFD, this is done when it is merged into the default branch.
+    pub fn publish(&mut self, is_markdown: bool) -> Result<()> {
+        let state = RFDState::publish(&self.state)?.to_string();
+        self.content = update_state(&self.content, &state, is_markdown)?;
+        self.state = state;
 
         Ok(())
     }
@@ -482,19 +512,46 @@ impl RFD {
         Ok(())
     }
 
-    /// Convert the RFD content to a PDF and upload the PDF to the /pdfs folder of the RFD
-    /// repository.
-    pub async fn convert_and_upload_pdf(
-        &mut self,
-        db: &Database,
-        github: &octorust::Client,
-        company: &Company,
-    ) -> Result<()> {
-        // Initialize the Google Drive client.
-        // We do this here so we know the token is not expired.
-        let drive_client = company.authenticate_google_drive(db).await?;
+    /// Split the RFD into its sections and save them, so searches can link to a section.
+    /// Call this after `expand`, it needs the contents.
+    pub fn update_sections(&mut self, db: &Database) -> Result<()> {
+        let sections = RFDSection::sync_for_rfd(db, self)?;
+        self.rfd_sections_id = sections.iter().map(|s| s.id).collect();
 
-        // Get the rfd repo client.
+        Ok(())
+    }
+
+    /// Find the users for the authors of the RFD, and flag the ones we could not find.
+    /// `users` are the users of the company, see `Users::get_from_db`.
+    pub fn update_authors(&mut self, db: &Database, users: &[User], company: &Company) -> Result<()> {
+        let authors = RFDAuthor::sync_for_rfd(db, self, users, &company.gsuite_domain)?;
+
+        self.link_to_authors = Default::default();
+        self.unresolved_authors = Default::default();
+        for author in authors {
+            match author.users_id.and_then(|id| users.iter().find(|u| u.id == id)) {
+                Some(user) => {
+                    if !user.airtable_record_id.is_empty() && !self.link_to_authors.contains(&user.airtable_record_id) {
+                        self.link_to_authors.push(user.airtable_record_id.to_string());
+                    }
+                }
+                None => self.unresolved_authors.push(author.to_string()),
+            }
+        }
+
+        if !self.unresolved_authors.is_empty() {
+            warn!(
+                "could not find users for the authors of RFD {}: {}",
+                self.number_string,
+                self.unresolved_authors.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Convert the RFD content to a PDF, with the images from its branch.
+    pub async fn render_pdf(&self, github: &octorust::Client, company: &Company) -> Result<Vec<u8>> {
         let owner = &company.github_org;
         let rfd_repo = "rfd";
         let repo = github.repos().get(owner, rfd_repo).await?;
@@ -508,9 +565,6 @@ impl RFD {
         let mut file = fs::File::create(path.clone())?;
         file.write_all(rfd_content.as_bytes())?;
 
-        let file_name = self.get_pdf_filename();
-        let rfd_path = format!("/pdfs/{}", file_name);
-
         let mut branch = self.number_string.to_string();
         if self.link.contains(&format!("/{}/", repo.default_branch)) {
             branch = repo.default_branch.to_string();
@@ -540,6 +594,11 @@ impl RFD {
             .args(&["-o", "-", "-a", "source-highlighter=rouge", path.to_str().unwrap()])
             .output()?;
 
+        // Delete our temporary file.
+        if path.exists() && !path.is_dir() {
+            fs::remove_file(path)?;
+        }
+
         if !cmd_output.status.success() {
             bail!(
                 "running asciidoctor failed: {} {}",
@@ -548,16 +607,47 @@ impl RFD {
             );
         }
 
+        Ok(cmd_output.stdout)
+    }
+
+    /// Convert the RFD content to a PDF and upload the PDF to the /pdfs folder of the RFD
+    /// repository.
+    ///
+    /// This is skipped if we already uploaded the PDF for this version of the file, unless
+    /// `force_render` is set.
+    pub async fn convert_and_upload_pdf(
+        &mut self,
+        db: &Database,
+        github: &octorust::Client,
+        company: &Company,
+        force_render: bool,
+    ) -> Result<()> {
+        if !force_render {
+            if let Some(render) = RFDRender::get_for_rfd(db, self.id)? {
+                if let Some(link) = render.pdf_link_for(&self.sha, &self.get_pdf_filename()) {
+                    self.pdf_link_google_drive = link.to_string();
+                    return Ok(());
+                }
+            }
+        }
+
+        // Initialize the Google Drive client.
+        // We do this here so we know the token is not expired.
+        let drive_client = company.authenticate_google_drive(db).await?;
+
+        // Get the rfd repo client.
+        let owner = &company.github_org;
+        let rfd_repo = "rfd";
+        let repo = github.repos().get(owner, rfd_repo).await?;
+
+        let pdf = self.render_pdf(github, company).await?;
+
+        let file_name = self.get_pdf_filename();
+        let rfd_path = format!("/pdfs/{}", file_name);
+
         // Create or update the file in the github repository.
-        create_or_update_file_in_github_repo(
-            github,
-            owner,
-            rfd_repo,
-            &repo.default_branch,
-            &rfd_path,
-            cmd_output.stdout.clone(),
-        )
-        .await?;
+        create_or_update_file_in_github_repo(github, owner, rfd_repo, &repo.default_branch, &rfd_path, pdf.clone())
+            .await?;
 
         // Figure out where our directory is.
         // It should be in the shared drive : "Automated Documents"/"rfds"
@@ -570,14 +660,11 @@ impl RFD {
         // Create or update the file in the google_drive.
         let drive_file = drive_client
             .files()
-            .create_or_update(&drive_id, &parent_id, &file_name, "application/pdf", &cmd_output.stdout)
+            .create_or_update(&drive_id, &parent_id, &file_name, "application/pdf", &pdf)
             .await?;
         self.pdf_link_google_drive = format!("https://drive.google.com/open?id={}", drive_file.id);
 
-        // Delete our temporary file.
-        if path.exists() && !path.is_dir() {
-            fs::remove_file(path)?;
-        }
+        RFDRender::save_pdf(db, self)?;
 
         Ok(())
     }
@@ -649,18 +736,19 @@ impl RFD {
         Ok(())
     }
 
-    /// Trigger updating the search index for the RFD.
-    pub async fn update_search_index(&self) -> Result<()> {
-        let client = reqwest::Client::new();
-        let req = client.put(&format!("https://rfd.shared.oxide.computer/api/search/{}", self.number));
-        req.send().await?;
-
-        Ok(())
-    }
-
     /// Expand the fields in the RFD.
     /// This will get the content, html, sha, commit_date as well as fill in all generated fields.
-    pub async fn expand(&mut self, github: &octorust::Client, company: &Company) -> Result<()> {
+    ///
+    /// The HTML is only rendered again if the file or the renderer changed, or if `force_render` is set.
+    /// `users` are the users of the company, they are matched against the authors.
+    pub async fn expand(
+        &mut self,
+        db: &Database,
+        github: &octorust::Client,
+        users: &[User],
+        company: &Company,
+        force_render: bool,
+    ) -> Result<()> {
         let owner = &company.github_org;
         let repo = "rfd";
         let r = github.repos().get(owner, repo).await?;
@@ -701,10 +789,23 @@ impl RFD {
             self.commit_date = commit.commit.committer.as_ref().unwrap().date.parse()?;
         }
 
-        // Parse the HTML.
-        self.html = self.get_html(github, owner, repo, &branch, is_markdown).await?;
+        // Parse the HTML, unless we already did for this version of the file.
+        let render = if force_render {
+            None
+        } else {
+            RFDRender::get_for_rfd(db, self.id)?
+        };
+        match render.as_ref().and_then(|r| r.html_for(&self.sha)) {
+            Some(html) => self.html = html.to_string(),
+            None => {
+                self.html = self.get_html(github, owner, repo, &branch, is_markdown).await?;
+                RFDRender::save_html(db, self)?;
+            }
+        }
 
         self.authors = NewRFD::get_authors(&self.content, is_markdown)?;
+        // Find the users for the authors.
+        self.update_authors(db, users, company)?;
 
         // Set the pdf link
         let file_name = self.get_pdf_filename();
@@ -720,7 +821,7 @@ impl RFD {
 /// Implement updating the Airtable record for an RFD.
 #[async_trait]
 impl UpdateAirtableRecord<RFD> for RFD {
-    async fn update_airtable_record(&mut self, record: RFD) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, record: RFD) -> Result<()> {
         // Set the Link to People from the original so it stays intact.
         self.milestones = record.milestones.clone();
         self.relevant_components = record.relevant_components;
@@ -982,13 +1083,14 @@ pub fn update_state(content: &str, state: &str, is_markdown: bool) -> Result<Str
 }
 
 // Sync the rfds with our database.
-pub async fn refresh_db_rfds(db: &Database, company: &Company) -> Result<()> {
+// RFDs are only rendered again if they changed, unless `force_render` is set.
+pub async fn refresh_db_rfds(db: &Database, company: &Company, force_render: bool) -> Result<()> {
     // Authenticate GitHub.
     let github = company.authenticate_github()?;
 
     // Check if the repo exists, if not exit early.
     if let Err(e) = github.repos().get(&company.github_org, "rfd").await {
-        if e.to_string().contains("404") {
+        if CioError::is_not_found(&e) {
             return Ok(());
         } else {
             bail!("checking for rfd repo failed: {}", e);
@@ -996,22 +1098,36 @@ pub async fn refresh_db_rfds(db: &Database, company: &Company) -> Result<()> {
     }
 
     let rfds = get_rfds_from_repo(&github, company).await?;
+    // Get the users once, we match the authors of every RFD against them.
+    let users: Vec<User> = Users::get_from_db(db, company.id)?.into();
 
     // Sync rfds.
     for (_, mut rfd) in rfds {
+        let mut old_state = String::new();
         // Check if we already have an existing RFD.
         if let Some(existing) = RFD::get_from_db(db, rfd.number) {
-            // Set the rfd_sections_id so we don't overwrite it.
+            old_state = existing.state;
+            // Set the generated fields so we don't overwrite them.
             rfd.rfd_sections_id = existing.rfd_sections_id;
+            rfd.backlinks = existing.backlinks;
+            rfd.link_to_authors = existing.link_to_authors;
+            rfd.unresolved_authors = existing.unresolved_authors;
         }
 
         let mut new_rfd = rfd.upsert(db).await?;
+        // Record if the state changed since the last sync, and tell the followers of the RFD.
+        if let Some(transition) = RFDStateTransition::record(db, &new_rfd, &old_state)? {
+            notify_followers(db, company, &new_rfd, &transition.into()).await?;
+        }
 
         // Expand the fields in the RFD.
-        new_rfd.expand(&github, company).await?;
+        new_rfd.expand(db, &github, &users, company, force_render).await?;
+
+        // Update the sections, so search can link to them.
+        new_rfd.update_sections(db)?;
 
         // Make and update the PDF versions.
-        if let Err(err) = new_rfd.convert_and_upload_pdf(db, &github, company).await {
+        if let Err(err) = new_rfd.convert_and_upload_pdf(db, &github, company, force_render).await {
             warn!(
                 "failed to convert and upload PDF for RFD {}: {}",
                 new_rfd.number_string, err
@@ -1021,11 +1137,14 @@ pub async fn refresh_db_rfds(db: &Database, company: &Company) -> Result<()> {
         // Update the RFD again.
         // We do this so the expand functions are only one place.
         new_rfd.update(db).await?;
-
-        // Now that the database is updated, update the search index.
-        new_rfd.update_search_index().await?;
     }
 
+    // Update the references between RFDs, now that we have all of them.
+    refresh_rfd_references(db, company)?;
+
+    // Update the RFDs each user is an author of.
+    refresh_rfds_authored(db, company)?;
+
     // Update rfds in airtable.
     RFDs::get_from_db(db, company.id)?.update_airtable(db).await?;
 
@@ -1039,7 +1158,7 @@ pub async fn cleanup_rfd_pdfs(db: &Database, company: &Company) -> Result<()> {
 
     // Check if the repo exists, if not exit early.
     if let Err(e) = github.repos().get(&company.github_org, "rfd").await {
-        if e.to_string().contains("404") {
+        if CioError::is_not_found(&e) {
             return Ok(());
         } else {
             bail!("checking for rfd repo failed: {}", e);
@@ -1168,12 +1287,14 @@ pub async fn send_rfd_changelog(db: &Database, company: &Company) -> Result<()>
     );
 
     let mut changelog = format!("Changes to RFDs for the week {}:\n", week_format);
+    let mut changed: Vec<(RFD, String)> = Default::default();
 
     // Iterate over the RFDs.
     for rfd in rfds {
         let changes = rfd.get_weekly_changelog(&github, seven_days_ago, company).await?;
         if !changes.is_empty() {
             changelog += &format!("\n{} {}\n{}", rfd.name, rfd.short_link, changes);
+            changed.push((rfd, changes));
         }
     }
 
@@ -1193,32 +1314,102 @@ pub async fn send_rfd_changelog(db: &Database, company: &Company) -> Result<()>
         )
         .await?;
 
+    // Send everyone who follows RFDs the changes to only what they follow.
+    send_rfd_subscription_digests(db, company, &changed, &week_format).await?;
+
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::{
-        companies::Company,
-        db::Database,
-        rfds::{clean_rfd_html_links, send_rfd_changelog, update_discussion_link, update_state, NewRFD},
-    };
+/// The most results a search returns.
+pub const MAX_SEARCH_RESULTS: i64 = 100;
 
-    #[ignore]
-    #[tokio::test(flavor = "multi_thread")]
-    async fn test_monday_cron_rfds_changelog() {
-        crate::utils::setup_logger();
+/// A match for a search of the RFDs.
+#[derive(Debug, Clone, PartialEq, QueryableByName, JsonSchema, Deserialize, Serialize)]
+pub struct RFDSearchResult {
+    #[sql_type = "diesel::sql_types::Integer"]
+    pub number: i32,
+    #[sql_type = "diesel::sql_types::Text"]
+    pub name: String,
+    #[sql_type = "diesel::sql_types::Text"]
+    pub state: String,
+    /// The title of the section that matched, empty if the match was not in a section.
+    #[sql_type = "diesel::sql_types::Text"]
+    pub section: String,
+    /// The link to the rendered RFD, or to the section that matched.
+    #[sql_type = "diesel::sql_types::Text"]
+    pub link: String,
+    /// Where the search terms show up, they are wrapped in `<b>` tags.
+    #[sql_type = "diesel::sql_types::Text"]
+    pub snippet: String,
+    /// How well the RFD matched, higher is better.
+    #[sql_type = "diesel::sql_types::Float4"]
+    pub rank: f32,
+}
 
-        // Initialize our database.
-        let db = Database::new();
+impl RFDSearchResult {
+    /// Returns the result as a Slack message, linking to the section that matched.
+    pub fn slack_text(&self) -> String {
+        let title = if self.section.is_empty() {
+            self.name.to_string()
+        } else {
+            format!("{} § {}", self.name, self.section)
+        };
 
-        // Get the company id for Oxide.
-        // TODO: split this out per company.
-        let oxide = Company::get_from_db(&db, "Oxide".to_string()).unwrap();
+        format!(
+            "<{}|{}> (_*{}*_)\n>{}",
+            self.link,
+            title,
+            self.state,
+            self.snippet.replace("<b>", "*").replace("</b>", "*").replace('\n', " ")
+        )
+    }
+}
 
-        send_rfd_changelog(&db, &oxide).await.unwrap();
+/// Search the titles, contents and sections of a company's RFDs, the best matches first.
+///
+/// This uses Postgres full text search, so every RFD shows up at most once, with the
+/// section that matched best.
+pub fn search_rfds(db: &Database, cio_company_id: i32, q: &str, limit: i64) -> Result<Vec<RFDSearchResult>> {
+    if q.trim().is_empty() {
+        return Ok(vec![]);
     }
 
+    // The `to_tsvector` expressions in the `WHERE` clauses have to match the indexes
+    // in the rfds_search migration, otherwise Postgres won't use them.
+    let results = diesel::sql_query(
+        r#"SELECT number, name, state, section, link, snippet, rank FROM (
+    SELECT DISTINCT ON (number) * FROM (
+        SELECT r.number, r.name, r.state, '' AS section, r.rendered_link AS link,
+            ts_headline('english', r.content, q, 'MaxFragments=1, MaxWords=30, MinWords=10') AS snippet,
+            ts_rank(setweight(to_tsvector('english', r.title), 'A') || setweight(to_tsvector('english', r.content), 'B'), q) AS rank
+        FROM rfds r, plainto_tsquery('english', $1) q
+        WHERE r.cio_company_id = $2 AND to_tsvector('english', r.title || ' ' || r.content) @@ q
+        UNION ALL
+        SELECT r.number, r.name, r.state, s.name AS section, r.rendered_link || '#' || s.anchor AS link,
+            ts_headline('english', s.content, q, 'MaxFragments=1, MaxWords=30, MinWords=10') AS snippet,
+            ts_rank(setweight(to_tsvector('english', s.name), 'A') || setweight(to_tsvector('english', s.content), 'B'), q) AS rank
+        FROM rfd_sections s JOIN rfds r ON r.id = s.rfds_id, plainto_tsquery('english', $1) q
+        WHERE r.cio_company_id = $2 AND to_tsvector('english', s.name || ' ' || s.content) @@ q
+    ) matches ORDER BY number, rank DESC
+) best ORDER BY rank DESC, number DESC LIMIT $3"#,
+    )
+    .bind::<diesel::sql_types::Text, _>(q)
+    .bind::<diesel::sql_types::Integer, _>(cio_company_id)
+    .bind::<diesel::sql_types::BigInt, _>(limit.min(MAX_SEARCH_RESULTS))
+    .load::<RFDSearchResult>(&db.conn()?)?;
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::ExpressionMethods;
+
+    use crate::{
+        rfds::{clean_rfd_html_links, update_discussion_link, update_state, NewRFD, RFDs, RFDsQuery},
+        schema::rfds,
+    };
+
     #[test]
     fn test_clean_rfd_html_links() {
         crate::utils::setup_logger();
@@ -1494,4 +1685,49 @@ sdf
         title = NewRFD::get_title(content).unwrap();
         assert_eq!(expected, title);
     }
+
+    #[test]
+    fn test_rfds_query() {
+        let sql = |query: &RFDsQuery| {
+            (
+                diesel::debug_query::<diesel::pg::Pg, _>(&query.query).to_string(),
+                diesel::debug_query::<diesel::pg::Pg, _>(&query.count_query).to_string(),
+            )
+        };
+
+        // Every query is for a company.
+        let query = RFDs::query(1);
+        let (select, count) = sql(&query);
+        assert!(select.ends_with(r#"FROM "rfds" WHERE "rfds"."cio_company_id" = $1 -- binds: [1]"#));
+        assert_eq!(
+            count,
+            r#"SELECT COUNT(*) FROM "rfds" WHERE "rfds"."cio_company_id" = $1 -- binds: [1]"#
+        );
+        assert!(!query.ordered);
+
+        // The filters are combined with AND and the orderings are kept in the order they were
+        // given. The count only has the filters.
+        let query = RFDs::query(1)
+            .filter(rfds::dsl::state.eq("published"))
+            .filter(rfds::dsl::number.gt(100))
+            .filter(rfds::dsl::id.eq_any(vec![3, 4]))
+            .order_by(rfds::dsl::number.asc())
+            .order_by(rfds::dsl::id.desc())
+            .limit(10)
+            .offset(20);
+        let (select, count) = sql(&query);
+        assert!(select.ends_with(
+            r#"FROM "rfds" WHERE "rfds"."cio_company_id" = $1 AND "rfds"."state" = $2 AND "rfds"."number" > $3 AND "rfds"."id" IN ($4, $5) ORDER BY "rfds"."number" ASC, "rfds"."id" DESC LIMIT $6 OFFSET $7 -- binds: [1, "published", 100, 3, 4, 10, 20]"#
+        ));
+        assert_eq!(
+            count,
+            r#"SELECT COUNT(*) FROM "rfds" WHERE "rfds"."cio_company_id" = $1 AND "rfds"."state" = $2 AND "rfds"."number" > $3 AND "rfds"."id" IN ($4, $5) -- binds: [1, "published", 100, 3, 4]"#
+        );
+        assert!(query.ordered);
+
+        // The fields that can be asked for are the columns, by their name in JSON.
+        assert!(RFDsQuery::COLUMNS.contains(&("id", "id")));
+        assert!(RFDsQuery::COLUMNS.contains(&("number", "number")));
+        assert!(RFDsQuery::COLUMNS.contains(&("cio_company_id", "cio_company_id")));
+    }
 }
@@ -0,0 +1,283 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use anyhow::{bail, Result};
+use chrono::Utc;
+use lazy_static::lazy_static;
+use log::{info, warn};
+
+use crate::{
+    applicants::SendOffer,
+    configs::OnboardUser,
+    db::Database,
+    functions::{Function, FunctionStep, FunctionStepStatus},
+    shipments::CreateOutboundShipment,
+};
+
+/// A saga with more than one step.
+pub trait StepSaga: steno::SagaType<ExecContextType = Arc<SagaContext>> {
+    /// The name of the saga, this is also the name of its functions.
+    const NAME: &'static str;
+    /// The names of the steps, in the order they are in the template.
+    const STEPS: &'static [&'static str];
+
+    /// The template for the saga, with what undoes each step.
+    fn template() -> steno::SagaTemplate<Self>;
+}
+
+/// Runs a saga again from the parameters it was saved with.
+type Retry = fn(Database, uuid::Uuid, serde_json::Value) -> Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+
+lazy_static! {
+    static ref SEC: Mutex<Option<Arc<steno::SecClient>>> = Mutex::new(None);
+}
+
+/// Every saga, add new ones here so they can be told apart from jobs and retried.
+static SAGAS: &[(&str, Retry)] = &[
+    (CreateOutboundShipment::NAME, retry::<CreateOutboundShipment>),
+    (OnboardUser::NAME, retry::<OnboardUser>),
+    (SendOffer::NAME, retry::<SendOffer>),
+];
+
+/// The `Retry` for a saga, it reads the parameters back into the ones the saga takes.
+fn retry<S: StepSaga>(
+    db: Database,
+    id: uuid::Uuid,
+    params: serde_json::Value,
+) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
+    Box::pin(async move { run_saga::<S>(&db, &id, serde_json::from_value(params)?).await })
+}
+
+/// The context the steps of a saga run with.
+#[derive(Debug)]
+pub struct SagaContext {
+    pub db: Database,
+    /// The id of the saga, this is also the `saga_id` of its function.
+    pub saga_id: uuid::Uuid,
+}
+
+/// Returns the saga execution coordinator for the process, it is created the first time it is
+/// needed. Every saga in the process, jobs included, runs on this one, so `log` is only used
+/// the first time.
+pub fn sec(db: &Database, log: slog::Logger) -> Arc<steno::SecClient> {
+    SEC.lock()
+        .unwrap()
+        .get_or_insert_with(|| Arc::new(steno::sec(log, Arc::new(db.clone()))))
+        .clone()
+}
+
+/// Run a saga with more than one step and wait for it to finish.
+///
+/// The saga shows up in the functions like the jobs do, with the status of each of its steps.
+/// If a step fails or the saga is cancelled, the steps before it are undone and the saga fails.
+pub async fn run_saga<S: StepSaga>(db: &Database, id: &uuid::Uuid, params: S::SagaParamsType) -> Result<()> {
+    let name = S::NAME;
+    let saga_id = steno::SagaId(*id);
+
+    FunctionStep::queue(db, id, S::STEPS, 1)?;
+
+    let context = Arc::new(SagaContext {
+        db: db.clone(),
+        saga_id: *id,
+    });
+
+    let sec = sec(db, slog_scope::logger());
+
+    let saga_future = sec
+        .saga_create(
+            saga_id,
+            Arc::new(context),
+            Arc::new(S::template()),
+            name.to_string(),
+            params,
+        )
+        .await?;
+    sec.saga_start(saga_id).await?;
+
+    info!("started saga `{}` with id `{}`", name, id);
+    let start = Instant::now();
+    let result = saga_future.await;
+
+    let mut f = match Function::get_from_db(db, id.to_string()) {
+        Some(f) => f,
+        None => bail!("saga `{}` with id `{}` has no function", name, id),
+    };
+    f.completed_at = Some(Utc::now());
+
+    match result.kind {
+        Ok(_) => {
+            f.conclusion = octorust::types::Conclusion::Success.to_string();
+            f.update(db).await?;
+            crate::metrics::record_job_run(name, &f.conclusion, start.elapsed());
+
+            Ok(())
+        }
+        Err(e) => {
+            f.logs = format!("{}\n\n{:?}", f.logs, e).trim().to_string();
+            if !f.is_cancelled() {
+                f.conclusion = octorust::types::Conclusion::Failure.to_string();
+            }
+            f.update(db).await?;
+            crate::metrics::record_job_run(name, &f.conclusion, start.elapsed());
+
+            bail!("saga `{}` with id `{}` failed: {:?}", name, id, e);
+        }
+    }
+}
+
+/// Run a step of a saga, keeping its status up to date.
+pub async fn run_step<T, F>(context: &SagaContext, name: &str, step: F) -> Result<T, steno::ActionError>
+where
+    F: Future<Output = Result<T>>,
+{
+    start_step(context, name)?;
+
+    match step.await {
+        Ok(t) => {
+            set_step_status(context, name, FunctionStepStatus::Succeeded, "");
+            Ok(t)
+        }
+        Err(e) => Err(fail_step(context, name, e)),
+    }
+}
+
+/// Run a step of a saga that may have nothing to do, like adding someone to a service their
+/// company does not use. If the step returns `None` it is marked as skipped.
+pub async fn run_optional_step<T, F>(context: &SagaContext, name: &str, step: F) -> Result<T, steno::ActionError>
+where
+    T: Default,
+    F: Future<Output = Result<Option<T>>>,
+{
+    start_step(context, name)?;
+
+    match step.await {
+        Ok(Some(t)) => {
+            set_step_status(context, name, FunctionStepStatus::Succeeded, "");
+            Ok(t)
+        }
+        Ok(None) => {
+            set_step_status(context, name, FunctionStepStatus::Skipped, "");
+            Ok(T::default())
+        }
+        Err(e) => Err(fail_step(context, name, e)),
+    }
+}
+
+fn start_step(context: &SagaContext, name: &str) -> Result<(), steno::ActionError> {
+    // Check if the saga was cancelled before we start the step, failing it undoes the ones
+    // before it.
+    if let Some(f) = Function::get_from_db(&context.db, context.saga_id.to_string()) {
+        if f.is_cancelled() {
+            set_step_status(context, name, FunctionStepStatus::Failed, "the saga was cancelled");
+            return Err(steno::ActionError::action_failed("the saga was cancelled".to_string()));
+        }
+    }
+
+    set_step_status(context, name, FunctionStepStatus::InProgress, "");
+    Ok(())
+}
+
+fn fail_step(context: &SagaContext, name: &str, e: anyhow::Error) -> steno::ActionError {
+    let error = format!("{:?}", e);
+    set_step_status(context, name, FunctionStepStatus::Failed, &error);
+    steno::ActionError::action_failed(format!("ERROR:\n\n{}", error))
+}
+
+/// Run a step of a saga that should not fail it, like letting someone know what the saga did.
+///
+/// These come after the steps that need undoing, so if one fails it is marked as failed and
+/// the saga carries on. They also run if the saga was cancelled, there is nothing left to
+/// stop by then.
+pub async fn run_best_effort_step<F>(context: &SagaContext, name: &str, step: F) -> Result<(), steno::ActionError>
+where
+    F: Future<Output = Result<()>>,
+{
+    set_step_status(context, name, FunctionStepStatus::InProgress, "");
+
+    match step.await {
+        Ok(()) => set_step_status(context, name, FunctionStepStatus::Succeeded, ""),
+        Err(e) => {
+            warn!(
+                "step `{}` of saga `{}` failed, carrying on: {:?}",
+                name, context.saga_id, e
+            );
+            set_step_status(context, name, FunctionStepStatus::Failed, &format!("{:?}", e));
+        }
+    }
+
+    Ok(())
+}
+
+/// Undo a step of a saga, because a step after it failed.
+pub async fn undo_step<F>(context: &SagaContext, name: &str, undo: F) -> Result<()>
+where
+    F: Future<Output = Result<()>>,
+{
+    match undo.await {
+        Ok(()) => {
+            set_step_status(context, name, FunctionStepStatus::Undone, "");
+            Ok(())
+        }
+        Err(e) => {
+            // The step still did what it did, say so next to it.
+            set_step_status(
+                context,
+                name,
+                FunctionStepStatus::Succeeded,
+                &format!("undoing the step failed: {:?}", e),
+            );
+            Err(e)
+        }
+    }
+}
+
+/// Returns if the function with the name is one of the sagas here, rather than a job.
+pub fn is_saga(name: &str) -> bool {
+    SAGAS.iter().any(|(n, _)| *n == name)
+}
+
+/// Run a saga again, with the parameters it ran with before.
+pub async fn retry_saga(db: &Database, id: &uuid::Uuid, name: &str, params: &serde_json::Value) -> Result<()> {
+    let retry = match SAGAS.iter().find(|(n, _)| *n == name) {
+        Some((_, retry)) => retry,
+        None => bail!("`{}` is not a saga that can be retried", name),
+    };
+
+    info!("retrying saga `{}` with id `{}`", name, id);
+    retry(db.clone(), *id, params.clone()).await
+}
+
+/// Undo a step that there is nothing to undo for.
+pub async fn undo_nothing<S: steno::SagaType>(_action_context: steno::ActionContext<S>) -> Result<()> {
+    Ok(())
+}
+
+fn set_step_status(context: &SagaContext, name: &str, status: FunctionStepStatus, error: &str) {
+    // The status is only there to look at, it should not fail the step.
+    if let Err(e) = FunctionStep::set_status(&context.db, &context.saga_id, name, status, error) {
+        warn!(
+            "setting the status of step `{}` of saga `{}` to `{}` failed: {}",
+            name, context.saga_id, status, e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_saga, StepSaga, SAGAS};
+    use crate::{applicants::SendOffer, configs::OnboardUser, shipments::CreateOutboundShipment};
+
+    #[test]
+    fn test_sagas_registry() {
+        for name in &[CreateOutboundShipment::NAME, OnboardUser::NAME, SendOffer::NAME] {
+            assert!(is_saga(name));
+            assert_eq!(SAGAS.iter().filter(|(n, _)| n == name).count(), 1);
+        }
+
+        assert!(!is_saga("sync-repos"));
+    }
+}
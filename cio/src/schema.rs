@@ -17,6 +17,66 @@ table! {
     }
 }
 
+table! {
+    airtable_outbox_entries (id) {
+        id -> Int4,
+        record_type -> Varchar,
+        record_id -> Int4,
+        airtable_record_id -> Varchar,
+        operation -> Varchar,
+        status -> Varchar,
+        attempts -> Int4,
+        last_error -> Text,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        next_attempt_at -> Timestamptz,
+        cio_company_id -> Int4,
+    }
+}
+
+table! {
+    airtable_sync_conflicts (id) {
+        id -> Int4,
+        record_type -> Varchar,
+        record_id -> Int4,
+        field -> Varchar,
+        base_value -> Jsonb,
+        db_value -> Jsonb,
+        airtable_value -> Jsonb,
+        winner -> Varchar,
+        db_updated_at -> Nullable<Timestamptz>,
+        airtable_modified_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        cio_company_id -> Int4,
+    }
+}
+
+table! {
+    airtable_sync_states (id) {
+        id -> Int4,
+        record_type -> Varchar,
+        record_id -> Int4,
+        fields -> Jsonb,
+        db_updated_at -> Nullable<Timestamptz>,
+        synced_at -> Nullable<Timestamptz>,
+        cio_company_id -> Int4,
+    }
+}
+
+table! {
+    api_keys (id) {
+        id -> Int4,
+        name -> Varchar,
+        key_prefix -> Varchar,
+        key_hash -> Varchar,
+        scopes -> Array<Text>,
+        last_used_at -> Nullable<Timestamptz>,
+        revoked_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        cio_company_id -> Int4,
+    }
+}
+
 table! {
     api_tokens (id) {
         id -> Int4,
@@ -172,6 +232,20 @@ table! {
     }
 }
 
+table! {
+    applicants_history (id) {
+        id -> Int4,
+        record_id -> Int4,
+        action -> Varchar,
+        before -> Jsonb,
+        after -> Jsonb,
+        diff -> Jsonb,
+        component -> Varchar,
+        created_at -> Timestamptz,
+        cio_company_id -> Int4,
+    }
+}
+
 table! {
     asset_items (id) {
         id -> Int4,
@@ -341,6 +415,20 @@ table! {
     }
 }
 
+table! {
+    certificates_history (id) {
+        id -> Int4,
+        record_id -> Int4,
+        action -> Varchar,
+        before -> Jsonb,
+        after -> Jsonb,
+        diff -> Jsonb,
+        component -> Varchar,
+        created_at -> Timestamptz,
+        cio_company_id -> Int4,
+    }
+}
+
 table! {
     companys (id) {
         id -> Int4,
@@ -450,6 +538,29 @@ table! {
     }
 }
 
+table! {
+    function_logs (id) {
+        id -> Int4,
+        saga_id -> Varchar,
+        logs -> Text,
+        created_at -> Timestamptz,
+        cio_company_id -> Int4,
+    }
+}
+
+table! {
+    function_steps (id) {
+        id -> Int4,
+        saga_id -> Varchar,
+        name -> Varchar,
+        status -> Varchar,
+        error -> Text,
+        started_at -> Nullable<Timestamptz>,
+        completed_at -> Nullable<Timestamptz>,
+        cio_company_id -> Int4,
+    }
+}
+
 table! {
     functions (id) {
         id -> Int4,
@@ -460,6 +571,7 @@ table! {
         completed_at -> Nullable<Timestamptz>,
         logs -> Text,
         saga_id -> Varchar,
+        params -> Jsonb,
         cio_company_id -> Int4,
         airtable_record_id -> Varchar,
     }
@@ -583,6 +695,18 @@ table! {
     }
 }
 
+table! {
+    job_schedules (id) {
+        id -> Int4,
+        name -> Varchar,
+        cron -> Varchar,
+        timezone -> Varchar,
+        enabled -> Bool,
+        updated_at -> Timestamptz,
+        cio_company_id -> Int4,
+    }
+}
+
 table! {
     journal_club_meetings (id) {
         id -> Int4,
@@ -774,6 +898,43 @@ table! {
     }
 }
 
+table! {
+    rfd_authors (id) {
+        id -> Int4,
+        rfds_id -> Int4,
+        users_id -> Nullable<Int4>,
+        name -> Varchar,
+        email -> Varchar,
+        github -> Varchar,
+        cio_company_id -> Int4,
+    }
+}
+
+table! {
+    rfd_references (id) {
+        id -> Int4,
+        rfds_id -> Int4,
+        kind -> Varchar,
+        target -> Varchar,
+        link -> Varchar,
+        cio_company_id -> Int4,
+    }
+}
+
+table! {
+    rfd_renders (id) {
+        id -> Int4,
+        rfds_id -> Int4,
+        html_cache_key -> Varchar,
+        html -> Text,
+        pdf_cache_key -> Varchar,
+        pdf_file_name -> Varchar,
+        pdf_link_google_drive -> Varchar,
+        updated_at -> Timestamptz,
+        cio_company_id -> Int4,
+    }
+}
+
 table! {
     rfd_sections (id) {
         id -> Int4,
@@ -781,6 +942,32 @@ table! {
         content -> Text,
         name -> Text,
         rfds_id -> Int4,
+        level -> Int4,
+        position -> Int4,
+    }
+}
+
+table! {
+    rfd_state_transitions (id) {
+        id -> Int4,
+        rfds_id -> Int4,
+        from_state -> Varchar,
+        to_state -> Varchar,
+        created_at -> Timestamptz,
+        cio_company_id -> Int4,
+    }
+}
+
+table! {
+    rfd_subscriptions (id) {
+        id -> Int4,
+        slack_user_id -> Varchar,
+        email -> Varchar,
+        notify_by -> Varchar,
+        rfd_number -> Int4,
+        label -> Varchar,
+        created_at -> Timestamptz,
+        cio_company_id -> Int4,
     }
 }
 
@@ -806,6 +993,9 @@ table! {
         pdf_link_github -> Varchar,
         pdf_link_google_drive -> Varchar,
         rfd_sections_id -> Array<Int4>,
+        backlinks -> Array<Text>,
+        link_to_authors -> Array<Text>,
+        unresolved_authors -> Array<Text>,
         cio_company_id -> Int4,
         airtable_record_id -> Varchar,
     }
@@ -925,20 +1115,70 @@ table! {
         zoom_id -> Varchar,
         geocode_cache -> Varchar,
         working_on -> Array<Text>,
+        rfds_authored -> Array<Text>,
         cio_company_id -> Int4,
         airtable_record_id -> Varchar,
     }
 }
 
+table! {
+    users_history (id) {
+        id -> Int4,
+        record_id -> Int4,
+        action -> Varchar,
+        before -> Jsonb,
+        after -> Jsonb,
+        diff -> Jsonb,
+        component -> Varchar,
+        created_at -> Timestamptz,
+        cio_company_id -> Int4,
+    }
+}
+
+table! {
+    webhook_events (id) {
+        id -> Int4,
+        provider -> Varchar,
+        endpoint -> Varchar,
+        headers -> Jsonb,
+        body -> Bytea,
+        status -> Varchar,
+        error -> Text,
+        attempts -> Int4,
+        created_at -> Timestamptz,
+        processed_at -> Nullable<Timestamptz>,
+        cio_company_id -> Int4,
+    }
+}
+
+table! {
+    webhook_secrets (id) {
+        id -> Int4,
+        provider -> Varchar,
+        encrypted_secret -> Varchar,
+        created_at -> Timestamptz,
+        cio_company_id -> Int4,
+    }
+}
+
+joinable!(rfd_authors -> rfds (rfds_id));
+joinable!(rfd_references -> rfds (rfds_id));
+joinable!(rfd_renders -> rfds (rfds_id));
 joinable!(rfd_sections -> rfds (rfds_id));
+joinable!(rfd_state_transitions -> rfds (rfds_id));
 
 allow_tables_to_appear_in_same_query!(
     accounts_payables,
+    airtable_outbox_entries,
+    airtable_sync_conflicts,
+    airtable_sync_states,
+    api_keys,
     api_tokens,
     applicant_interviews,
     applicant_reviewers,
     applicant_reviews,
     applicants,
+    applicants_history,
     asset_items,
     auth_user_logins,
     auth_users,
@@ -946,14 +1186,18 @@ allow_tables_to_appear_in_same_query!(
     bookings,
     buildings,
     certificates,
+    certificates_history,
     companys,
     conference_rooms,
     credit_card_transactions,
     expensed_items,
+    function_logs,
+    function_steps,
     functions,
     github_repos,
     groups,
     inbound_shipments,
+    job_schedules,
     journal_club_meetings,
     journal_club_papers,
     links,
@@ -963,10 +1207,18 @@ allow_tables_to_appear_in_same_query!(
     page_views,
     rack_line_subscribers,
     recorded_meetings,
+    rfd_authors,
+    rfd_references,
+    rfd_renders,
     rfd_sections,
+    rfd_state_transitions,
+    rfd_subscriptions,
     rfds,
     software_vendors,
     swag_inventory_items,
     swag_items,
     users,
+    users_history,
+    webhook_events,
+    webhook_secrets,
 );
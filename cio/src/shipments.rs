@@ -1,5 +1,5 @@
 #![allow(clippy::from_over_into)]
-use std::convert::From;
+use std::{convert::From, sync::Arc};
 
 use anyhow::{bail, Result};
 use async_trait::async_trait;
@@ -12,7 +12,7 @@ use reqwest::StatusCode;
 use schemars::JsonSchema;
 use sendgrid_api::{traits::MailOps, Client as SendGrid};
 use serde::{Deserialize, Serialize};
-use shippo::{Address, CustomsDeclaration, CustomsItem, NewShipment, NewTransaction, Parcel, Shippo};
+use shippo::{Address, CustomsDeclaration, CustomsItem, NewRefund, NewShipment, NewTransaction, Parcel, Shippo};
 use slack_chat_api::{
     FormattedMessage, MessageAttachment, MessageBlock, MessageBlockText, MessageBlockType, MessageType,
 };
@@ -23,6 +23,8 @@ use crate::{
     configs::User,
     core::UpdateAirtableRecord,
     db::Database,
+    metrics::ApiCallMetrics,
+    sagas::{run_best_effort_step, run_saga, run_step, undo_nothing, undo_step, SagaContext, StepSaga},
     schema::{inbound_shipments, outbound_shipments, package_pickups},
 };
 
@@ -31,6 +33,7 @@ use crate::{
     new_struct_name = "InboundShipment",
     airtable_base = "shipments",
     airtable_table = "AIRTABLE_INBOUND_TABLE",
+    reconcile = true,
     match_on = {
         "carrier" = "String",
         "tracking_number" = "String",
@@ -74,7 +77,7 @@ pub struct NewInboundShipment {
 /// Implement updating the Airtable record for an InboundShipment.
 #[async_trait]
 impl UpdateAirtableRecord<InboundShipment> for InboundShipment {
-    async fn update_airtable_record(&mut self, record: InboundShipment) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, record: InboundShipment) -> Result<()> {
         if self.carrier.is_empty() {
             self.carrier = record.carrier;
         }
@@ -147,7 +150,7 @@ impl NewInboundShipment {
     /// Get the details about the shipment from the tracking API.
     pub async fn expand(&mut self, db: &Database, company: &Company) -> Result<()> {
         // Create the shippo client.
-        let shippo = Shippo::new_from_env();
+        let shippo = Shippo::new_from_env().with_middleware(ApiCallMetrics::new("shippo"));
 
         let mut carrier = self.carrier.to_lowercase().to_string();
         if carrier == "dhl" {
@@ -382,6 +385,7 @@ impl InboundShipment {
     new_struct_name = "OutboundShipment",
     airtable_base = "shipments",
     airtable_table = "AIRTABLE_OUTBOUND_TABLE",
+    reconcile = true,
     match_on = {
         "carrier" = "String",
         "tracking_number" = "String",
@@ -778,7 +782,7 @@ pub struct NewPackagePickup {
 /// Implement updating the Airtable record for an PackagePickup.
 #[async_trait]
 impl UpdateAirtableRecord<PackagePickup> for PackagePickup {
-    async fn update_airtable_record(&mut self, _record: PackagePickup) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, _record: PackagePickup) -> Result<()> {
         Ok(())
     }
 }
@@ -797,7 +801,7 @@ impl OutboundShipments {
                     .and(outbound_shipments::dsl::provider.eq("Shippo".to_string()))
                     .and(outbound_shipments::dsl::pickup_date.is_null()),
             )
-            .load::<OutboundShipment>(&db.conn())?;
+            .load::<OutboundShipment>(&db.conn()?)?;
 
         if shipments.is_empty() {
             // We can return early.
@@ -820,7 +824,7 @@ impl OutboundShipments {
 
         // Get the carrier ID for USPS.
         // Create the shippo client.
-        let shippo_client = Shippo::new_from_env();
+        let shippo_client = Shippo::new_from_env().with_middleware(ApiCallMetrics::new("shippo"));
         let carrier_accounts = shippo_client.list_carrier_accounts().await?;
         let mut carrier_account_id = "".to_string();
         for ca in carrier_accounts {
@@ -927,7 +931,7 @@ pub fn get_next_business_day() -> (DateTime<Utc>, DateTime<Utc>) {
 /// Implement updating the Airtable record for an OutboundShipment.
 #[async_trait]
 impl UpdateAirtableRecord<OutboundShipment> for OutboundShipment {
-    async fn update_airtable_record(&mut self, record: OutboundShipment) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, record: OutboundShipment) -> Result<()> {
         self.link_to_package_pickup = record.link_to_package_pickup;
 
         self.geocode_cache = record.geocode_cache;
@@ -1280,7 +1284,7 @@ The Shipping Bot",
 
         // Update the tracking status.
         // Create the shippo client.
-        let shippo = Shippo::new_from_env();
+        let shippo = Shippo::new_from_env().with_middleware(ApiCallMetrics::new("shippo"));
 
         let mut carrier = self.carrier.to_lowercase().to_string();
         if carrier == "dhl" {
@@ -1361,7 +1365,7 @@ The Shipping Bot",
         self.set_lat_lng(db).await?;
 
         // Create the shippo client.
-        let shippo_client = Shippo::new_from_env();
+        let shippo_client = Shippo::new_from_env().with_middleware(ApiCallMetrics::new("shippo"));
 
         // If we did local_pickup, we can return early here.
         if self.local_pickup {
@@ -1451,6 +1455,24 @@ The Shipping Bot",
         }
 
         // We need to create the label since we don't have one already.
+        // Save what we have so far, creating the shipment works from the database.
+        self.update(db).await?;
+
+        create_outbound_shipment(db, self.id).await?;
+
+        // Get the label and the rest of what creating the shipment did.
+        *self = OutboundShipment::get_by_id(db, self.id)?;
+
+        Ok(())
+    }
+
+    /// Create the label for the shipment in Shippo, from the best value or cheapest rate.
+    async fn create_shippo_label(&mut self, db: &Database) -> Result<()> {
+        let company = self.company(db)?;
+
+        // Create the shippo client.
+        let shippo_client = Shippo::new_from_env().with_middleware(ApiCallMetrics::new("shippo"));
+
         let address_from = company.hq_shipping_address(db)?;
 
         // If this is an international shipment, we need to define our customs
@@ -1580,24 +1602,9 @@ The Shipping Bot",
                         .await?;
                 }
 
-                // Save it in Airtable here, in case one of the below steps fails.
+                // Save it here, in case one of the later steps fails.
                 self.update(db).await?;
 
-                // Register a tracking webhook for this shipment.
-                shippo_client
-                    .register_tracking_webhook(&self.carrier, &self.tracking_number)
-                    .await?;
-
-                // Print the label.
-                self.print_label(db).await?;
-                // Print the receipt.
-                self.print_receipt(db).await?;
-                self.set_status(db, crate::shipment_status::Status::LabelPrinted, &company)
-                    .await?;
-
-                // Send an email to us that we need to package the shipment.
-                self.send_email_internally(db).await?;
-
                 break;
             }
         }
@@ -1608,6 +1615,190 @@ The Shipping Bot",
     }
 }
 
+#[derive(Debug)]
+pub struct CreateOutboundShipment;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateOutboundShipmentParams {
+    shipment_id: i32,
+}
+
+impl steno::SagaType for CreateOutboundShipment {
+    type SagaParamsType = CreateOutboundShipmentParams;
+    type ExecContextType = Arc<SagaContext>;
+}
+
+/// Create the label for an outbound shipment, print it and let us know we need to package it.
+/// If the label can't be printed, it is refunded. Once it is printed it might already be on a
+/// box, so registering the tracking webhook and the email can't fail the saga.
+pub async fn create_outbound_shipment(db: &Database, shipment_id: i32) -> Result<()> {
+    run_saga::<CreateOutboundShipment>(db, &uuid::Uuid::new_v4(), CreateOutboundShipmentParams { shipment_id }).await
+}
+
+impl StepSaga for CreateOutboundShipment {
+    const NAME: &'static str = "create-outbound-shipment";
+    const STEPS: &'static [&'static str] = &["label", "print", "tracking", "email"];
+
+    /// The saga for creating an outbound shipment, only the label needs undoing and only the print
+    /// step after it can fail.
+    fn template() -> steno::SagaTemplate<CreateOutboundShipment> {
+        let mut builder = steno::SagaTemplateBuilder::new();
+        builder.append(
+            "label",
+            "label",
+            steno::ActionFunc::new_action(action_shipment_label, undo_shipment_label),
+        );
+        builder.append(
+            "print",
+            "print",
+            steno::ActionFunc::new_action(action_shipment_print, undo_nothing::<CreateOutboundShipment>),
+        );
+        builder.append(
+            "tracking",
+            "tracking",
+            steno::ActionFunc::new_action(action_shipment_tracking, undo_nothing::<CreateOutboundShipment>),
+        );
+        builder.append(
+            "email",
+            "email",
+            steno::ActionFunc::new_action(action_shipment_email, undo_nothing::<CreateOutboundShipment>),
+        );
+
+        builder.build()
+    }
+}
+
+async fn action_shipment_label(
+    action_context: steno::ActionContext<CreateOutboundShipment>,
+) -> Result<String, steno::ActionError> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+
+    run_step(context, "label", async {
+        let mut shipment = OutboundShipment::get_by_id(&context.db, params.shipment_id)?;
+        shipment.create_shippo_label(&context.db).await?;
+
+        Ok(shipment.provider_id)
+    })
+    .await
+}
+
+async fn undo_shipment_label(action_context: steno::ActionContext<CreateOutboundShipment>) -> Result<()> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+
+    undo_step(context, "label", async {
+        let db = &context.db;
+        let mut shipment = OutboundShipment::get_by_id(db, params.shipment_id)?;
+        if shipment.provider_id.is_empty() {
+            // We never got a label, there is nothing to refund.
+            return Ok(());
+        }
+
+        // Refund the label so we don't pay for it.
+        let shippo_client = Shippo::new_from_env().with_middleware(ApiCallMetrics::new("shippo"));
+        shippo_client
+            .create_refund(NewRefund {
+                transaction: shipment.provider_id.to_string(),
+                r#async: false,
+            })
+            .await?;
+
+        // Clear out the label so the next time we try, we create a new one.
+        shipment.carrier = Default::default();
+        shipment.cost = Default::default();
+        shipment.tracking_number = Default::default();
+        shipment.tracking_link = Default::default();
+        shipment.tracking_status = Default::default();
+        shipment.label_link = Default::default();
+        shipment.eta = None;
+        shipment.provider_id = Default::default();
+        shipment.oxide_tracking_link = Default::default();
+        shipment.status = crate::shipment_status::Status::Queued.to_string();
+        shipment.update(db).await?;
+
+        Ok(())
+    })
+    .await
+}
+
+async fn action_shipment_tracking(
+    action_context: steno::ActionContext<CreateOutboundShipment>,
+) -> Result<(), steno::ActionError> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+
+    run_best_effort_step(context, "tracking", async {
+        let shipment = OutboundShipment::get_by_id(&context.db, params.shipment_id)?;
+        if shipment.provider_id.is_empty() {
+            // We didn't find a rate to create a label from.
+            return Ok(());
+        }
+
+        // Register a tracking webhook for this shipment.
+        let shippo_client = Shippo::new_from_env().with_middleware(ApiCallMetrics::new("shippo"));
+        shippo_client
+            .register_tracking_webhook(&shipment.carrier, &shipment.tracking_number)
+            .await?;
+
+        Ok(())
+    })
+    .await
+}
+
+async fn action_shipment_print(
+    action_context: steno::ActionContext<CreateOutboundShipment>,
+) -> Result<(), steno::ActionError> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+
+    run_step(context, "print", async {
+        let db = &context.db;
+        let mut shipment = OutboundShipment::get_by_id(db, params.shipment_id)?;
+        if shipment.provider_id.is_empty() {
+            return Ok(());
+        }
+
+        let company = shipment.company(db)?;
+
+        // Print the receipt.
+        shipment.print_receipt(db).await?;
+        // Print the label, after this we can't refund it.
+        shipment.print_label(db).await?;
+
+        if let Err(e) = shipment
+            .set_status(db, crate::shipment_status::Status::LabelPrinted, &company)
+            .await
+        {
+            warn!("setting the status of shipment `{}` failed: {}", shipment.id, e);
+        }
+        if let Err(e) = shipment.update(db).await {
+            warn!("updating shipment `{}` failed: {}", shipment.id, e);
+        }
+
+        Ok(())
+    })
+    .await
+}
+
+async fn action_shipment_email(
+    action_context: steno::ActionContext<CreateOutboundShipment>,
+) -> Result<(), steno::ActionError> {
+    let context = action_context.user_data();
+    let params = action_context.saga_params();
+
+    run_best_effort_step(context, "email", async {
+        let shipment = OutboundShipment::get_by_id(&context.db, params.shipment_id)?;
+        if shipment.provider_id.is_empty() {
+            return Ok(());
+        }
+
+        // Send an email to us that we need to package the shipment.
+        shipment.send_email_internally(&context.db).await
+    })
+    .await
+}
+
 // Sync the outbound shipments.
 pub async fn refresh_outbound_shipments(db: &Database, company: &Company) -> Result<()> {
     if company.airtable_base_id_shipments.is_empty() {
@@ -1696,7 +1887,7 @@ async fn update_manual_shippo_shipments(db: &Database, company: &Company) -> Res
     }
 
     // Create the shippo client.
-    let shippo = Shippo::new_from_env();
+    let shippo = Shippo::new_from_env().with_middleware(ApiCallMetrics::new("shippo"));
 
     // Get each of the shippo orders and create or update it in our set.
     // These are typically one off labels made from the UI.
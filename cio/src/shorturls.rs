@@ -3,12 +3,7 @@ use cloudflare::endpoints::dns;
 use serde::Serialize;
 
 use crate::{
-    companies::{Company, Companys},
-    configs::Links,
-    db::Database,
-    dns_providers::DNSProviderOps,
-    repos::GithubRepos,
-    rfds::RFDs,
+    companies::Company, configs::Links, db::Database, dns_providers::DNSProviderOps, repos::GithubRepos, rfds::RFDs,
     templates::generate_nginx_files_for_shorturls,
 };
 
@@ -222,23 +217,16 @@ pub async fn generate_dns_for_tailscale_devices(company: &Company) -> Result<()>
     Ok(())
 }
 
-/// Update all the short URLs and DNS.
-pub async fn refresh_shorturls() -> Result<()> {
-    let db = Database::new();
+/// Update the short URLs and DNS for a company.
+pub async fn refresh_shorturls(db: &Database, company: &Company) -> Result<()> {
+    let github = company.authenticate_github()?;
+    generate_shorturls_for_repos(db, &github, company, "configs").await?;
+    generate_shorturls_for_rfds(db, &github, company, "configs").await?;
+    generate_shorturls_for_configs_links(db, &github, company, "configs").await?;
 
-    let companies = Companys::get_from_db(&db, 1)?;
-
-    // Iterate over the companies and update.
-    for company in companies {
-        let github = company.authenticate_github()?;
-        generate_shorturls_for_repos(&db, &github, &company, "configs").await?;
-        generate_shorturls_for_rfds(&db, &github, &company, "configs").await?;
-        generate_shorturls_for_configs_links(&db, &github, &company, "configs").await?;
-
-        // Only do this if we can auth with Tailscale.
-        if !company.tailscale_api_key.is_empty() {
-            generate_dns_for_tailscale_devices(&company).await?;
-        }
+    // Only do this if we can auth with Tailscale.
+    if !company.tailscale_api_key.is_empty() {
+        generate_dns_for_tailscale_devices(company).await?;
     }
 
     // TODO: cleanup any DNS records that no longer need to exist.
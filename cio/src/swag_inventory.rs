@@ -76,7 +76,7 @@ pub struct NewSwagItem {
 /// Implement updating the Airtable record for a SwagItem.
 #[async_trait]
 impl UpdateAirtableRecord<SwagItem> for SwagItem {
-    async fn update_airtable_record(&mut self, record: SwagItem) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, record: SwagItem) -> Result<()> {
         if !record.link_to_inventory.is_empty() {
             self.link_to_inventory = record.link_to_inventory;
         }
@@ -118,6 +118,7 @@ pub async fn refresh_swag_items(db: &Database, company: &Company) -> Result<()>
     new_struct_name = "SwagInventoryItem",
     airtable_base = "swag",
     airtable_table = "AIRTABLE_SWAG_INVENTORY_ITEMS_TABLE",
+    reconcile = true,
     match_on = {
         "item" = "String",
         "size" = "String",
@@ -177,7 +178,7 @@ pub struct NewSwagInventoryItem {
 /// Implement updating the Airtable record for a SwagInventoryItem.
 #[async_trait]
 impl UpdateAirtableRecord<SwagInventoryItem> for SwagInventoryItem {
-    async fn update_airtable_record(&mut self, record: SwagInventoryItem) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, record: SwagInventoryItem) -> Result<()> {
         if !record.link_to_item.is_empty() {
             self.link_to_item = record.link_to_item;
         }
@@ -677,7 +678,7 @@ pub struct NewBarcodeScan {
 /// Implement updating the Airtable record for a BarcodeScan.
 #[async_trait]
 impl UpdateAirtableRecord<BarcodeScan> for BarcodeScan {
-    async fn update_airtable_record(&mut self, _record: BarcodeScan) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, _record: BarcodeScan) -> Result<()> {
         Ok(())
     }
 }
@@ -685,19 +686,21 @@ impl UpdateAirtableRecord<BarcodeScan> for BarcodeScan {
 impl BarcodeScan {
     // Takes a scanned barcode and updates the inventory count for the item
     // as well as adds the scan to the barcodes_scan table for tracking.
-    pub async fn scan(b: String) -> Result<()> {
+    pub async fn scan(db: &Database, b: String) -> Result<()> {
         let time = Utc::now();
 
         // Make sure the barcode is formatted correctly.
         let barcode = b.trim().to_uppercase().to_string();
 
-        // Initialize the database connection.
-        let db = Database::new();
-
         // Firstly, let's make sure we have the barcode in the database.
-        match swag_inventory_items::dsl::swag_inventory_items
-            .filter(swag_inventory_items::dsl::barcode.eq(barcode.to_string()))
-            .first::<SwagInventoryItem>(&db.conn())
+        let b = barcode.to_string();
+        match db
+            .run(move |db| {
+                Ok(swag_inventory_items::dsl::swag_inventory_items
+                    .filter(swag_inventory_items::dsl::barcode.eq(b))
+                    .first::<SwagInventoryItem>(&db.conn()?)?)
+            })
+            .await
         {
             Ok(mut swag_inventory_item) => {
                 // We found the matching inventory item!
@@ -705,7 +708,7 @@ impl BarcodeScan {
                 // in the database.
                 swag_inventory_item.current_stock -= 1;
                 // Update the database.
-                swag_inventory_item.update(&db).await?;
+                swag_inventory_item.update(db).await?;
                 info!(
                     "subtracted one from {} stock, we now have {}",
                     swag_inventory_item.name, swag_inventory_item.current_stock
@@ -723,7 +726,7 @@ impl BarcodeScan {
                 };
 
                 // Add our barcode scan to the database.
-                new_barcode_scan.upsert(&db).await?;
+                new_barcode_scan.upsert(db).await?;
             }
             Err(e) => bail!("could not find inventory item with barcode {}: {}", barcode, e),
         }
@@ -6,7 +6,8 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    airtable::AIRTABLE_BOOKINGS_TABLE, companies::Company, core::UpdateAirtableRecord, db::Database, schema::bookings,
+    airtable::AIRTABLE_BOOKINGS_TABLE, companies::Company, core::UpdateAirtableRecord, db::Database, error::CioError,
+    schema::bookings,
 };
 
 #[db {
@@ -87,7 +88,7 @@ pub struct NewBooking {
 /// Implement updating the Airtable record for a Booking.
 #[async_trait]
 impl UpdateAirtableRecord<Booking> for Booking {
-    async fn update_airtable_record(&mut self, _record: Booking) -> Result<()> {
+    async fn update_airtable_record(&mut self, _db: &Database, _record: Booking) -> Result<()> {
         Ok(())
     }
 }
@@ -96,7 +97,7 @@ pub async fn refresh_trip_actions(db: &Database, company: &Company) -> Result<()
     // Authenticate with TripActions.
     let tripactions_auth = company.authenticate_tripactions(db).await;
     if let Err(e) = tripactions_auth {
-        if e.to_string().contains("no token") {
+        if CioError::is_not_connected(&e) {
             // Return early, this company does not use TripActions.
             return Ok(());
         }
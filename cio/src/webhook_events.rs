@@ -0,0 +1,250 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, COOKIE};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{db::Database, error::CioError, schema::webhook_events, webhook_secrets::WebhookProvider};
+
+/// The status of a webhook we received.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum WebhookEventStatus {
+    /// The webhook was saved and is being handled.
+    Received,
+
+    /// The webhook was handled.
+    Succeeded,
+
+    /// Handling the webhook failed, it can be replayed.
+    Failed,
+}
+
+impl fmt::Display for WebhookEventStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebhookEventStatus::Received => write!(f, "received"),
+            WebhookEventStatus::Succeeded => write!(f, "succeeded"),
+            WebhookEventStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+impl FromStr for WebhookEventStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "received" => Ok(WebhookEventStatus::Received),
+            "succeeded" => Ok(WebhookEventStatus::Succeeded),
+            "failed" => Ok(WebhookEventStatus::Failed),
+            _ => bail!("invalid webhook event status: `{}`", s),
+        }
+    }
+}
+
+/// A webhook we received, saved before we handle it so it can be replayed if handling it
+/// fails.
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "webhook_events"]
+pub struct NewWebhookEvent {
+    pub provider: String,
+    /// The path of the endpoint that received the webhook, ie. `/github`.
+    pub endpoint: String,
+    /// The headers of the request, without the ones that carry credentials.
+    pub headers: serde_json::Value,
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub body: Vec<u8>,
+    pub status: String,
+    /// The error from the last time we handled the webhook.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub error: String,
+    /// How many times we handled the webhook.
+    #[serde(default)]
+    pub attempts: i32,
+    pub created_at: DateTime<Utc>,
+    pub processed_at: Option<DateTime<Utc>>,
+    /// The CIO company ID, from the secret the webhook was verified with.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Queryable, Identifiable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "webhook_events"]
+pub struct WebhookEvent {
+    pub id: i32,
+    pub provider: String,
+    pub endpoint: String,
+    pub headers: serde_json::Value,
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub body: Vec<u8>,
+    pub status: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub error: String,
+    #[serde(default)]
+    pub attempts: i32,
+    pub created_at: DateTime<Utc>,
+    pub processed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+impl WebhookEvent {
+    /// Save a webhook we received, before we handle it.
+    pub fn record(
+        db: &Database,
+        provider: WebhookProvider,
+        endpoint: &str,
+        headers: &HeaderMap,
+        body: &[u8],
+        cio_company_id: i32,
+    ) -> Result<WebhookEvent> {
+        let event = diesel::insert_into(webhook_events::table)
+            .values(&NewWebhookEvent {
+                provider: provider.to_string(),
+                endpoint: endpoint.to_string(),
+                headers: headers_to_json(headers),
+                body: body.to_vec(),
+                status: WebhookEventStatus::Received.to_string(),
+                error: Default::default(),
+                attempts: 0,
+                created_at: Utc::now(),
+                processed_at: None,
+                cio_company_id,
+            })
+            .get_result::<WebhookEvent>(&db.conn()?)?;
+
+        Ok(event)
+    }
+
+    pub fn get_by_id(db: &Database, id: i32) -> Result<WebhookEvent> {
+        let event = webhook_events::dsl::webhook_events
+            .find(id)
+            .first::<WebhookEvent>(&db.conn()?)
+            .map_err(|e| CioError::from_db(e, "webhook event"))?;
+
+        Ok(event)
+    }
+
+    /// Get the events with a status, the newest first.
+    pub fn get_by_status(
+        db: &Database,
+        status: WebhookEventStatus,
+        provider: Option<WebhookProvider>,
+    ) -> Result<Vec<WebhookEvent>> {
+        let mut query = webhook_events::dsl::webhook_events
+            .filter(webhook_events::dsl::status.eq(status.to_string()))
+            .order_by(webhook_events::dsl::id.desc())
+            .into_boxed();
+        if let Some(provider) = provider {
+            query = query.filter(webhook_events::dsl::provider.eq(provider.to_string()));
+        }
+
+        let events = query.load::<WebhookEvent>(&db.conn()?)?;
+
+        Ok(events)
+    }
+
+    /// Returns the headers of the request the webhook came in.
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let serde_json::Value::Object(map) = &self.headers {
+            for (name, value) in map {
+                if let (Ok(name), Some(Ok(value))) = (
+                    HeaderName::from_bytes(name.as_bytes()),
+                    value.as_str().map(HeaderValue::from_str),
+                ) {
+                    headers.insert(name, value);
+                }
+            }
+        }
+
+        headers
+    }
+
+    /// Save the result of handling the webhook.
+    pub fn finish<T>(&mut self, db: &Database, result: &Result<T>) -> Result<()> {
+        self.attempts += 1;
+        self.processed_at = Some(Utc::now());
+        match result {
+            Ok(_) => {
+                self.status = WebhookEventStatus::Succeeded.to_string();
+                self.error = Default::default();
+            }
+            Err(e) => {
+                self.status = WebhookEventStatus::Failed.to_string();
+                self.error = format!("{:?}", e);
+            }
+        }
+
+        crate::metrics::record_webhook_event(&self.provider, &self.status);
+
+        *self = diesel::update(&*self).set(self.clone()).get_result(&db.conn()?)?;
+
+        Ok(())
+    }
+}
+
+/// Returns the headers as a JSON object. We leave out the ones that carry credentials, the
+/// webhook was already verified so we don't need them to replay it.
+fn headers_to_json(headers: &HeaderMap) -> serde_json::Value {
+    let mut map: BTreeMap<String, String> = Default::default();
+    for name in headers.keys() {
+        if name == AUTHORIZATION || name == COOKIE {
+            continue;
+        }
+
+        let values: Vec<&str> = headers.get_all(name).iter().filter_map(|v| v.to_str().ok()).collect();
+        if !values.is_empty() {
+            map.insert(name.to_string(), values.join(", "));
+        }
+    }
+
+    json!(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+
+    use crate::webhook_events::{headers_to_json, WebhookEvent, WebhookEventStatus};
+
+    #[test]
+    fn test_webhook_events_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-github-event", HeaderValue::from_static("push"));
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_static("Basic Y2lvOnNoYXJlZC1zZWNyZXQ="),
+        );
+
+        let event = WebhookEvent {
+            id: 1,
+            provider: "github".to_string(),
+            endpoint: "/github".to_string(),
+            headers: headers_to_json(&headers),
+            body: br#"{"action":"opened"}"#.to_vec(),
+            status: WebhookEventStatus::Failed.to_string(),
+            error: Default::default(),
+            attempts: 1,
+            created_at: Utc::now(),
+            processed_at: None,
+            cio_company_id: 1,
+        };
+        assert_eq!(
+            event.headers,
+            json!({"content-type": "application/json", "x-github-event": "push"})
+        );
+
+        let replayed = event.headers();
+        assert_eq!(replayed.get("x-github-event").unwrap(), "push");
+        assert_eq!(replayed.get("content-type").unwrap(), "application/json");
+        assert!(replayed.get(AUTHORIZATION).is_none());
+    }
+}
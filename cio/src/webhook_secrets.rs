@@ -0,0 +1,480 @@
+use std::{env, fmt, str::FromStr};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use log::warn;
+use reqwest::header::{HeaderMap, AUTHORIZATION};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sodiumoxide::crypto::{auth::hmacsha256, secretbox};
+
+use crate::{db::Database, error::CioError, schema::webhook_secrets};
+
+/// How old a signed Slack request can be before we treat it as a replay.
+const SLACK_REPLAY_WINDOW_SECONDS: i64 = 5 * 60;
+
+/// The query parameter we look for a shared secret in, for providers that can't sign requests.
+static SHARED_SECRET_PARAM: &str = "token";
+
+/// The environment variable with the key the secrets are encrypted with, base64 encoded.
+static SECRETS_KEY_VAR: &str = "WEBHOOK_SECRETS_KEY";
+
+/// The environment variable with the providers, separated by commas, whose webhooks we take
+/// without checking them when no company has a secret for them.
+static UNVERIFIED_PROVIDERS_VAR: &str = "WEBHOOK_UNVERIFIED_PROVIDERS";
+
+/// A provider that sends us webhooks, and how it proves they came from it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum WebhookProvider {
+    /// Checkr signs the body with the API key, in `X-Checkr-Signature`.
+    Checkr,
+    /// DocuSign Connect signs the body with its HMAC keys, in `X-DocuSign-Signature-<n>`.
+    DocuSign,
+    /// GitHub signs the body, in `X-Hub-Signature-256`.
+    GitHub,
+    Mailchimp,
+    SendGrid,
+    Shipbob,
+    Shippo,
+    /// Slack signs the timestamp and the body with the signing secret, in `X-Slack-Signature`.
+    Slack,
+}
+
+impl fmt::Display for WebhookProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebhookProvider::Checkr => write!(f, "checkr"),
+            WebhookProvider::DocuSign => write!(f, "docusign"),
+            WebhookProvider::GitHub => write!(f, "github"),
+            WebhookProvider::Mailchimp => write!(f, "mailchimp"),
+            WebhookProvider::SendGrid => write!(f, "sendgrid"),
+            WebhookProvider::Shipbob => write!(f, "shipbob"),
+            WebhookProvider::Shippo => write!(f, "shippo"),
+            WebhookProvider::Slack => write!(f, "slack"),
+        }
+    }
+}
+
+impl FromStr for WebhookProvider {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "checkr" => Ok(WebhookProvider::Checkr),
+            "docusign" => Ok(WebhookProvider::DocuSign),
+            "github" => Ok(WebhookProvider::GitHub),
+            "mailchimp" => Ok(WebhookProvider::Mailchimp),
+            "sendgrid" => Ok(WebhookProvider::SendGrid),
+            "shipbob" => Ok(WebhookProvider::Shipbob),
+            "shippo" => Ok(WebhookProvider::Shippo),
+            "slack" => Ok(WebhookProvider::Slack),
+            _ => bail!("invalid webhook provider: `{}`", s),
+        }
+    }
+}
+
+impl WebhookProvider {
+    /// Returns if we take the provider's webhooks without checking them when no company has
+    /// a secret for it. This has to be turned on for each provider in `WEBHOOK_UNVERIFIED_PROVIDERS`.
+    pub fn accepts_unverified(&self) -> bool {
+        accepts_unverified(&env::var(UNVERIFIED_PROVIDERS_VAR).unwrap_or_default(), *self)
+    }
+}
+
+fn accepts_unverified(providers: &str, provider: WebhookProvider) -> bool {
+    providers
+        .split(',')
+        .any(|p| p.trim().eq_ignore_ascii_case(&provider.to_string()))
+}
+
+/// What we found when we checked a webhook against the secrets for its provider.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WebhookAuthentication {
+    /// It was signed with the secret, which says which company it is for.
+    Verified(WebhookSecret),
+    /// No company has a secret for the provider yet, so there was nothing to check it with.
+    /// These are rejected unless the provider `accepts_unverified`.
+    NoSecrets,
+    /// It was not signed with any of our secrets, the reason is logged.
+    Rejected,
+}
+
+/// Check a webhook is for the company it was verified for, by the company its payload names.
+///
+/// `verified_company_id` is 0 for webhooks we took without a secret to check them with, there
+/// is nothing to compare against then.
+pub fn check_webhook_company(verified_company_id: i32, cio_company_id: i32) -> Result<()> {
+    if verified_company_id != 0 && verified_company_id != cio_company_id {
+        return Err(CioError::Forbidden(format!(
+            "the webhook was signed for company {} but is for company {}",
+            verified_company_id, cio_company_id
+        ))
+        .into());
+    }
+
+    Ok(())
+}
+
+/// The secret a company set up with a provider, to check its webhooks with.
+///
+/// Providers without a signing scheme are given a URL with the secret in the `token` query
+/// parameter, or as the password for basic auth.
+///
+/// The secret is encrypted with the key in `WEBHOOK_SECRETS_KEY`, we never store it as is.
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "webhook_secrets"]
+pub struct NewWebhookSecret {
+    pub provider: String,
+    /// The nonce and the encrypted secret, base64 encoded.
+    pub encrypted_secret: String,
+    pub created_at: DateTime<Utc>,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Queryable, Identifiable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "webhook_secrets"]
+pub struct WebhookSecret {
+    pub id: i32,
+    pub provider: String,
+    pub encrypted_secret: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+impl WebhookSecret {
+    /// Set the secret for a provider for a company, replacing the one it had.
+    pub fn set(db: &Database, cio_company_id: i32, provider: WebhookProvider, secret: &str) -> Result<WebhookSecret> {
+        let encrypted_secret = seal(&secrets_key()?, secret);
+        let secret = diesel::insert_into(webhook_secrets::table)
+            .values(&NewWebhookSecret {
+                provider: provider.to_string(),
+                encrypted_secret: encrypted_secret.to_string(),
+                created_at: Utc::now(),
+                cio_company_id,
+            })
+            .on_conflict((webhook_secrets::dsl::cio_company_id, webhook_secrets::dsl::provider))
+            .do_update()
+            .set((
+                webhook_secrets::dsl::encrypted_secret.eq(encrypted_secret),
+                webhook_secrets::dsl::created_at.eq(Utc::now()),
+            ))
+            .get_result::<WebhookSecret>(&db.conn()?)?;
+
+        Ok(secret)
+    }
+
+    /// Get the secrets every company set up for a provider.
+    pub fn get_for_provider(db: &Database, provider: WebhookProvider) -> Result<Vec<WebhookSecret>> {
+        let secrets = webhook_secrets::dsl::webhook_secrets
+            .filter(webhook_secrets::dsl::provider.eq(provider.to_string()))
+            .order_by(webhook_secrets::dsl::cio_company_id.asc())
+            .load::<WebhookSecret>(&db.conn()?)?;
+
+        Ok(secrets)
+    }
+
+    /// Check a webhook from the provider was signed with one of our secrets. The secret it was
+    /// signed with says which company the webhook is for.
+    ///
+    /// Webhooks we reject are logged with the reason.
+    pub fn authenticate(
+        db: &Database,
+        provider: WebhookProvider,
+        headers: &HeaderMap,
+        query: &str,
+        body: &[u8],
+    ) -> Result<WebhookAuthentication> {
+        let secrets = WebhookSecret::get_for_provider(db, provider)?;
+        if secrets.is_empty() {
+            return Ok(WebhookAuthentication::NoSecrets);
+        }
+
+        let key = secrets_key()?;
+        let now = Utc::now();
+        let mut reasons: Vec<String> = Default::default();
+        for secret in secrets {
+            match secret.verify(&key, headers, query, body, now) {
+                Ok(()) => return Ok(WebhookAuthentication::Verified(secret)),
+                Err(e) => {
+                    let reason = e.to_string();
+                    if !reasons.contains(&reason) {
+                        reasons.push(reason);
+                    }
+                }
+            }
+        }
+
+        warn!("rejected {} webhook: {}", provider, reasons.join(", "));
+        Ok(WebhookAuthentication::Rejected)
+    }
+
+    /// Check a webhook was signed with the secret, by the scheme of the provider.
+    /// `key` is the key the secret was encrypted with.
+    pub fn verify(
+        &self,
+        key: &secretbox::Key,
+        headers: &HeaderMap,
+        query: &str,
+        body: &[u8],
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let provider = WebhookProvider::from_str(&self.provider)?;
+        let secret = open(key, &self.encrypted_secret)?;
+        match provider {
+            WebhookProvider::Checkr => {
+                let signature = header(headers, "x-checkr-signature")?;
+                check(&signature.to_lowercase(), &hmac_hex(&secret, &[body]))
+            }
+            WebhookProvider::DocuSign => {
+                // DocuSign sends a signature for each of the keys it has, any of them will do.
+                let expected = base64::encode(hmac(&secret, &[body]));
+                let mut found = false;
+                for (name, value) in headers {
+                    if name.as_str().starts_with("x-docusign-signature-") {
+                        found = true;
+                        if check(value.to_str().unwrap_or_default(), &expected).is_ok() {
+                            return Ok(());
+                        }
+                    }
+                }
+
+                if !found {
+                    bail!("missing `x-docusign-signature-1` header");
+                }
+                bail!("signature does not match");
+            }
+            WebhookProvider::GitHub => {
+                let signature = header(headers, "x-hub-signature-256")?;
+                check(&signature, &format!("sha256={}", hmac_hex(&secret, &[body])))
+            }
+            WebhookProvider::Slack => {
+                let timestamp = header(headers, "x-slack-request-timestamp")?;
+                let sent = match timestamp.parse::<i64>() {
+                    Ok(sent) => sent,
+                    Err(_) => bail!("invalid `x-slack-request-timestamp` header: `{}`", timestamp),
+                };
+                if (now.timestamp() - sent).abs() > SLACK_REPLAY_WINDOW_SECONDS {
+                    bail!("request timestamp {} is outside the replay window", sent);
+                }
+
+                let signature = header(headers, "x-slack-signature")?;
+                let base = format!("v0:{}:", timestamp);
+                check(
+                    &signature,
+                    &format!("v0={}", hmac_hex(&secret, &[base.as_bytes(), body])),
+                )
+            }
+            WebhookProvider::Mailchimp
+            | WebhookProvider::SendGrid
+            | WebhookProvider::Shipbob
+            | WebhookProvider::Shippo => match shared_secret(headers, query) {
+                Some(given) => check(&given, &secret),
+                None => bail!("missing `{}` query parameter or basic auth", SHARED_SECRET_PARAM),
+            },
+        }
+    }
+}
+
+/// Returns the key the secrets are encrypted with, from `WEBHOOK_SECRETS_KEY`.
+fn secrets_key() -> Result<secretbox::Key> {
+    let encoded = env::var(SECRETS_KEY_VAR).map_err(|_| anyhow!("`{}` is not set", SECRETS_KEY_VAR))?;
+    base64::decode(encoded.trim())
+        .ok()
+        .and_then(|k| secretbox::Key::from_slice(&k))
+        .ok_or_else(|| {
+            anyhow!(
+                "`{}` must be {} bytes, base64 encoded",
+                SECRETS_KEY_VAR,
+                secretbox::KEYBYTES
+            )
+        })
+}
+
+/// Encrypt a secret, returns the nonce and the encrypted secret, base64 encoded.
+fn seal(key: &secretbox::Key, secret: &str) -> String {
+    let nonce = secretbox::gen_nonce();
+    let mut sealed = nonce.0.to_vec();
+    sealed.extend(secretbox::seal(secret.as_bytes(), &nonce, key));
+    base64::encode(sealed)
+}
+
+/// Decrypt a secret that was encrypted with `seal`.
+fn open(key: &secretbox::Key, sealed: &str) -> Result<String> {
+    let sealed = base64::decode(sealed)?;
+    if sealed.len() < secretbox::NONCEBYTES {
+        bail!("the secret is not encrypted");
+    }
+    let (nonce, ciphertext) = sealed.split_at(secretbox::NONCEBYTES);
+    // The length was checked above.
+    let nonce = secretbox::Nonce::from_slice(nonce).unwrap();
+    match secretbox::open(ciphertext, &nonce, key) {
+        Ok(secret) => Ok(String::from_utf8(secret)?),
+        Err(_) => bail!("the secret could not be decrypted, was it encrypted with another key?"),
+    }
+}
+
+/// Returns the value of a header, or an error saying it is missing.
+fn header(headers: &HeaderMap, name: &str) -> Result<String> {
+    match headers.get(name).and_then(|v| v.to_str().ok()) {
+        Some(value) if !value.trim().is_empty() => Ok(value.trim().to_string()),
+        _ => bail!("missing `{}` header", name),
+    }
+}
+
+/// Compare a signature to what we expected, in constant time.
+fn check(given: &str, expected: &str) -> Result<()> {
+    if !sodiumoxide::utils::memcmp(given.as_bytes(), expected.as_bytes()) {
+        bail!("signature does not match");
+    }
+
+    Ok(())
+}
+
+/// Returns the HMAC-SHA256 of the parts, one after another, with the secret as the key.
+fn hmac(secret: &str, parts: &[&[u8]]) -> Vec<u8> {
+    let mut state = hmacsha256::State::init(secret.as_bytes());
+    for part in parts {
+        state.update(part);
+    }
+    state.finalize().0.to_vec()
+}
+
+/// Returns the hex encoded HMAC-SHA256 of the parts.
+fn hmac_hex(secret: &str, parts: &[&[u8]]) -> String {
+    hmac(secret, parts).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Returns the shared secret from the `token` query parameter, or the password of an
+/// `Authorization: Basic` header.
+fn shared_secret(headers: &HeaderMap, query: &str) -> Option<String> {
+    if let Some((_, token)) = url::form_urlencoded::parse(query.as_bytes()).find(|(k, _)| k == SHARED_SECRET_PARAM) {
+        return Some(token.to_string());
+    }
+
+    let value = headers.get(AUTHORIZATION).and_then(|v| v.to_str().ok())?;
+    let mut parts = value.splitn(2, ' ');
+    match (parts.next(), parts.next()) {
+        (Some(scheme), Some(credentials)) if scheme.eq_ignore_ascii_case("basic") => {
+            let decoded = String::from_utf8(base64::decode(credentials.trim()).ok()?).ok()?;
+            decoded.splitn(2, ':').nth(1).map(|password| password.to_string())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+
+    use sodiumoxide::crypto::secretbox;
+
+    use crate::webhook_secrets::{
+        accepts_unverified, check_webhook_company, hmac_hex, open, seal, WebhookProvider, WebhookSecret,
+    };
+
+    fn secret(key: &secretbox::Key, provider: WebhookProvider, secret: &str) -> WebhookSecret {
+        WebhookSecret {
+            id: 1,
+            provider: provider.to_string(),
+            encrypted_secret: seal(key, secret),
+            created_at: Utc::now(),
+            cio_company_id: 1,
+        }
+    }
+
+    #[test]
+    fn test_webhook_secrets_verify() {
+        // This is the example from the GitHub docs.
+        assert_eq!(
+            hmac_hex("It's a Secret to Everybody", &[b"Hello, World!"]),
+            "757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17"
+        );
+
+        let key = secretbox::gen_key();
+        let now = Utc.timestamp(1642410000, 0);
+        let body = br#"{"action":"opened"}"#;
+
+        let github = secret(&key, WebhookProvider::GitHub, "gh-secret");
+        let mut headers = HeaderMap::new();
+        assert!(github.verify(&key, &headers, "", body, now).is_err());
+        headers.insert(
+            "x-hub-signature-256",
+            HeaderValue::from_static("sha256=e6f0cd1cc45318f67b23a36be38951181ced496b01c1ed36023ae5fab179e975"),
+        );
+        assert!(github.verify(&key, &headers, "", body, now).is_ok());
+        assert!(github
+            .verify(&key, &headers, "", br#"{"action":"closed"}"#, now)
+            .is_err());
+
+        let slack = secret(&key, WebhookProvider::Slack, "slack-secret");
+        let body = b"token=x&team_id=T1";
+        let mut headers = HeaderMap::new();
+        headers.insert("x-slack-request-timestamp", HeaderValue::from_static("1642410000"));
+        headers.insert(
+            "x-slack-signature",
+            HeaderValue::from_static("v0=f186497c72af13030a9b9912190455b48a0122eaccb5d6b7f20b77eafab9c6b7"),
+        );
+        assert!(slack.verify(&key, &headers, "", body, now).is_ok());
+        // A request from an hour ago is a replay, even if it was signed.
+        let err = slack
+            .verify(&key, &headers, "", body, Utc.timestamp(1642413600, 0))
+            .unwrap_err();
+        assert!(err.to_string().contains("replay window"));
+
+        let docusign = secret(&key, WebhookProvider::DocuSign, "docusign-secret");
+        let body = br#"{"action":"opened"}"#;
+        let mut headers = HeaderMap::new();
+        headers.insert("x-docusign-signature-1", HeaderValue::from_static("bm90IHRoaXMgb25l"));
+        assert!(docusign.verify(&key, &headers, "", body, now).is_err());
+        headers.insert(
+            "x-docusign-signature-2",
+            HeaderValue::from_static("wwCYkpsNQw2YTjDqVlFklWR883xLDF4fGU2FCQeelM4="),
+        );
+        assert!(docusign.verify(&key, &headers, "", body, now).is_ok());
+
+        let shippo = secret(&key, WebhookProvider::Shippo, "shared-secret");
+        let mut headers = HeaderMap::new();
+        assert!(shippo.verify(&key, &headers, "", body, now).is_err());
+        assert!(shippo.verify(&key, &headers, "token=shared-secret", body, now).is_ok());
+        assert!(shippo.verify(&key, &headers, "token=wrong", body, now).is_err());
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_static("Basic Y2lvOnNoYXJlZC1zZWNyZXQ="),
+        );
+        assert!(shippo.verify(&key, &headers, "", body, now).is_ok());
+    }
+
+    #[test]
+    fn test_webhook_secrets_encryption() {
+        let key = secretbox::gen_key();
+        let sealed = seal(&key, "gh-secret");
+        assert!(!sealed.contains("gh-secret"));
+        // A new nonce is used every time.
+        assert_ne!(sealed, seal(&key, "gh-secret"));
+        assert_eq!(open(&key, &sealed).unwrap(), "gh-secret");
+
+        assert!(open(&secretbox::gen_key(), &sealed).is_err());
+        // Secrets we stored before we encrypted them don't decrypt.
+        assert!(open(&key, "gh-secret").is_err());
+    }
+
+    #[test]
+    fn test_webhook_secrets_accepts_unverified() {
+        assert!(!accepts_unverified("", WebhookProvider::Shippo));
+        assert!(accepts_unverified("mailchimp, Shippo", WebhookProvider::Shippo));
+        assert!(!accepts_unverified("mailchimp,shippo", WebhookProvider::GitHub));
+    }
+
+    #[test]
+    fn test_webhook_secrets_check_company() {
+        assert!(check_webhook_company(1, 1).is_ok());
+        assert!(check_webhook_company(1, 2).is_err());
+        // We had no secret to verify it with, so it isn't for any company in particular.
+        assert!(check_webhook_company(0, 2).is_ok());
+    }
+}
@@ -26,6 +26,7 @@ use std::{env, error, fmt, sync::Arc};
 use bytes::Bytes;
 use chrono::{offset::Utc, DateTime};
 use reqwest::{header, Client, Method, Request, StatusCode, Url};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -42,7 +43,7 @@ pub struct DocuSign {
     account_id: String,
     endpoint: String,
 
-    client: Arc<Client>,
+    client: Arc<ClientWithMiddleware>,
 }
 
 impl DocuSign {
@@ -79,7 +80,7 @@ impl DocuSign {
                     account_id: account_id.to_string(),
                     endpoint: endpoint.to_string(),
 
-                    client: Arc::new(c),
+                    client: Arc::new(ClientWithMiddleware::from(c)),
                 };
 
                 if g.token.is_empty() || g.refresh_token.is_empty() {
@@ -122,6 +123,15 @@ impl DocuSign {
         )
     }
 
+    /// Send the requests through `middleware`, for example to count or retry them.
+    pub fn with_middleware<M>(mut self, middleware: M) -> Self
+    where
+        M: Middleware,
+    {
+        self.client = Arc::new(ClientBuilder::new(Client::new()).with(middleware).build());
+        self
+    }
+
     /// user_consent_url creates a url allowing a user to consent to impersonation
     /// https://developers.docusign.com/esign-rest-api/guides/authentication/obtaining-consent#individual-consent
     pub fn user_consent_url(&self) -> String {
@@ -182,7 +192,7 @@ impl DocuSign {
         let bearer = header::HeaderValue::from_str(&bt).unwrap();
         headers.append(header::AUTHORIZATION, bearer);
 
-        let client = reqwest::Client::new();
+        let client = &self.client;
         let resp = client
             .get("https://account.docusign.com/oauth/userinfo")
             .headers(headers)
@@ -199,7 +209,7 @@ impl DocuSign {
         headers.append(header::ACCEPT, header::HeaderValue::from_static("application/json"));
 
         let params = [("grant_type", "refresh_token"), ("refresh_token", &self.refresh_token)];
-        let client = reqwest::Client::new();
+        let client = &self.client;
         let resp = client
             .post("https://account.docusign.com/oauth/token")
             .headers(headers)
@@ -223,7 +233,7 @@ impl DocuSign {
         headers.append(header::ACCEPT, header::HeaderValue::from_static("application/json"));
 
         let params = [("grant_type", "authorization_code"), ("code", code)];
-        let client = reqwest::Client::new();
+        let client = &self.client;
         let resp = client
             .post("https://account.docusign.com/oauth/token")
             .headers(headers)
@@ -399,6 +409,33 @@ impl DocuSign {
         Ok(resp.json().await.unwrap())
     }
 
+    /// Send an envelope that was created as a draft, with the status `created`.
+    pub async fn send_envelope(&self, envelope_id: &str) -> Result<(), APIError> {
+        // Build the request.
+        let request = self.request(
+            Method::PUT,
+            &format!("accounts/{}/envelopes/{}", self.account_id, envelope_id),
+            Envelope {
+                status: "sent".to_string(),
+                ..Default::default()
+            },
+            None,
+        );
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
+        };
+
+        Ok(())
+    }
+
     /// Get envelope form fields.
     pub async fn get_envelope_form_data(&self, envelope_id: &str) -> Result<Vec<FormDatum>, APIError> {
         // Build the request.
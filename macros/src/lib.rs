@@ -7,7 +7,7 @@ use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 use serde::Deserialize;
 use serde_tokenstream::from_tokenstream;
-use syn::{Field, ItemStruct, Type};
+use syn::{Field, ItemFn, ItemStruct, Type};
 
 /// The parameters passed to our macro.
 #[derive(Deserialize, Debug)]
@@ -16,34 +16,81 @@ struct Params {
     ///   - id: i32
     ///   - airtable_record_id: String
     new_struct_name: String,
+    /// The backend we mirror the records to on every database operation, one of
+    /// `airtable`, `google_sheets` or `none`. Defaults to `airtable`.
+    #[serde(default = "default_mirror")]
+    mirror: String,
     /// The name of the table in Airtable where this information should be sync on every
-    /// database operation.
+    /// database operation. Required if the mirror is `airtable`.
+    #[serde(default)]
     airtable_table: String,
     /// The Airtable base where this information should be sync on every
-    /// database operation.
+    /// database operation. Required if the mirror is `airtable`.
+    #[serde(default)]
     airtable_base: String,
+    /// The constant with the id of the Google Sheets spreadsheet where this information
+    /// should be sync on every database operation. The records are written to a tab named
+    /// after the plural of the new struct name. Required if the mirror is `google_sheets`.
+    #[serde(default)]
+    google_sheets_spreadsheet: String,
     /// A boolean representing if the new struct has a custom PartialEq implementation.
     /// If so, we will not add the derive method PartialEq to the new struct.
     #[serde(default)]
     custom_partial_eq: bool,
+    /// A boolean representing if we should record the history of changes to the records.
+    /// If so, the changes are stored in a table named after the database schema with a
+    /// `_history` suffix, ie. `applicants_history`.
+    #[serde(default)]
+    history: bool,
+    /// A boolean representing if we should pull the edits made in Airtable back into the
+    /// database. Only valid with the `airtable` mirror, the Airtable table needs a
+    /// "Last Modified" field.
+    #[serde(default)]
+    reconcile: bool,
     /// The struct item and type that we will filter on to find unique database entries.
     match_on: BTreeMap<String, String>,
 }
 
+fn default_mirror() -> String {
+    "airtable".to_string()
+}
+
+/// Returns the name a field has in JSON if it is renamed with `#[serde(rename = "...")]`.
+fn serde_rename(field: &Field) -> Option<String> {
+    for attr in field.attrs.iter().filter(|a| a.path.is_ident("serde")) {
+        if let Ok(syn::Meta::List(list)) = attr.parse_meta() {
+            for nested in list.nested {
+                if let syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) = nested {
+                    if let (true, syn::Lit::Str(s)) = (nv.path.is_ident("rename"), &nv.lit) {
+                        return Some(s.value());
+                    }
+                }
+            }
+        }
+    }
+
+    None
+}
+
 #[proc_macro_attribute]
 pub fn db(attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    do_db(attr.into(), item.into()).into()
+    match do_db(attr.into(), item.into()) {
+        Ok(t) => t.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
 }
 
-fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
+fn do_db(attr: TokenStream, item: TokenStream) -> Result<TokenStream, syn::Error> {
     // Get the data from the parameters.
-    let params = from_tokenstream::<Params>(&attr).unwrap();
+    let params = from_tokenstream::<Params>(&attr)?;
 
     // Get the names of the new structs.
     let new_struct_name = format_ident!("{}", params.new_struct_name);
     // We also get the name of the new struct in it's plural form so we can generate
     // a type that represents a vector of all the records.
     let new_struct_name_plural = format_ident!("{}s", params.new_struct_name);
+    // The name of the query builder for the plural type.
+    let query_struct_name = format_ident!("{}sQuery", params.new_struct_name);
 
     // The `new_struct_name_plural` but in snake_case is the same as our database schema.
     let mut db_schema = format_ident!("{}s", params.new_struct_name.to_snake_case());
@@ -54,86 +101,791 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
         db_schema = format_ident!("{}s", params.new_struct_name.to_lowercase());
     }
 
+    // Get the code for recording the history of the records, if enabled.
+    let history_schema = format_ident!("{}_history", db_schema);
+    let (history, record_create_history, record_upsert_history, record_update_history, record_delete_history) =
+        if params.history {
+            (
+                quote! {
+                    impl #new_struct_name {
+                        /// Record a change to the record in its history table. This takes the
+                        /// connection the change was made with, so the two are written in the
+                        /// same transaction.
+                        fn record_history(
+                            db: &crate::db::Database,
+                            conn: &diesel::PgConnection,
+                            action: crate::history::HistoryAction,
+                            record_id: i32,
+                            cio_company_id: i32,
+                            before: Option<&Self>,
+                            after: Option<&Self>,
+                        ) -> anyhow::Result<()> {
+                            let entry = match crate::history::NewHistoryEntry::new(db, action, record_id, before, after, cio_company_id)? {
+                                Some(e) => e,
+                                // Nothing changed.
+                                None => return Ok(()),
+                            };
+
+                            diesel::insert_into(crate::schema::#history_schema::table)
+                                .values((
+                                    crate::schema::#history_schema::dsl::record_id.eq(entry.record_id),
+                                    crate::schema::#history_schema::dsl::action.eq(entry.action),
+                                    crate::schema::#history_schema::dsl::before.eq(entry.before),
+                                    crate::schema::#history_schema::dsl::after.eq(entry.after),
+                                    crate::schema::#history_schema::dsl::diff.eq(entry.diff),
+                                    crate::schema::#history_schema::dsl::component.eq(entry.component),
+                                    crate::schema::#history_schema::dsl::created_at.eq(entry.created_at),
+                                    crate::schema::#history_schema::dsl::cio_company_id.eq(entry.cio_company_id),
+                                ))
+                                .execute(conn)?;
+
+                            Ok(())
+                        }
+
+                        /// Get the history of changes to the record, the oldest first.
+                        pub fn history(&self, db: &crate::db::Database) -> anyhow::Result<Vec<crate::history::HistoryEntry>> {
+                            let entries = crate::schema::#history_schema::dsl::#history_schema
+                                .filter(crate::schema::#history_schema::dsl::record_id.eq(self.id))
+                                .order_by(crate::schema::#history_schema::dsl::id.asc())
+                                .load::<crate::history::HistoryEntry>(&db.conn()?)?;
+
+                            Ok(entries)
+                        }
+                    }
+                },
+                quote! {
+                    #new_struct_name::record_history(db, &conn, crate::history::HistoryAction::Create, r.id, r.cio_company_id, None, Some(&r))?;
+                },
+                quote! {
+                    #new_struct_name::record_history(db, &conn, crate::history::HistoryAction::Update, r.id, r.cio_company_id, Some(&r), Some(&record))?;
+                },
+                quote! {
+                    #new_struct_name::record_history(db, &conn, crate::history::HistoryAction::Update, record.id, record.cio_company_id, before.as_ref(), Some(&record))?;
+                },
+                quote! {
+                    #new_struct_name::record_history(db, &conn, crate::history::HistoryAction::Delete, self.id, self.cio_company_id, Some(self), None)?;
+                },
+            )
+        } else {
+            (quote!(), quote!(), quote!(), quote!(), quote!())
+        };
+
+    // Get the record as it was before an update, if we need it.
+    let get_update_before = if params.history || params.reconcile {
+        quote! {
+            let before = #db_schema::dsl::#db_schema.find(self.id)
+                .first::<#new_struct_name>(&conn)
+                .optional()?;
+        }
+    } else {
+        quote!()
+    };
+
     // Let's create the database filter.
     let mut filter = quote!();
     let mut args = quote!();
+    let mut call_args = quote!();
     let mut function_args = quote!();
     for (field, type_) in params.match_on {
         let f = format_ident!("{}", field);
-        let t: Type = syn::parse_str(&type_).unwrap();
+        let t: Type = syn::parse_str(&type_)?;
         filter = quote!(#filter.filter(#db_schema::dsl::#f.eq(#f.clone())));
         args = quote!(#args,#f: #t);
+        call_args = quote!(#call_args #f,);
         function_args = quote!(#function_args self.#f.clone(),);
     }
 
     // Get the original struct information.
-    let og_struct: ItemStruct = syn::parse2(item.clone()).unwrap();
+    let og_struct: ItemStruct = syn::parse2(item.clone())?;
     let mut fields: Vec<&Field> = Default::default();
     let mut struct_inners = quote!();
+    // The name of each field in JSON and its column.
+    let mut columns = quote!();
     for field in og_struct.fields.iter() {
         fields.push(field);
         let ident = field.ident.clone();
         struct_inners = quote!(#struct_inners#ident: item.#ident.clone(),);
+
+        let column = ident.as_ref().map(|i| i.to_string()).unwrap_or_default();
+        let name = serde_rename(field).unwrap_or_else(|| column.to_string());
+        columns = quote!(#columns (#name, #column),);
     }
     let og_struct_name = og_struct.ident;
 
-    // Get the Airtable information.
-    let airtable_base = format_ident!("airtable_base_id_{}", params.airtable_base);
-    let airtable_table = format_ident!("{}", params.airtable_table);
+    // The name we use to find this type again when running entries from the outbox.
+    let record_type = params.new_struct_name.to_string();
+
+    // Get the code for pulling the edits made in Airtable back into the database, if enabled.
+    let (
+        reconcile_fns,
+        reconcile_plural_fns,
+        reconcile_before_upsert,
+        reconcile_before_update_airtable,
+        touch_create,
+        touch_upsert,
+        touch_update,
+        forget_delete,
+    ) = if params.reconcile {
+        if params.mirror != "airtable" {
+            return Err(syn::Error::new_spanned(
+                &attr,
+                "`reconcile` is only supported for the `airtable` mirror",
+            ));
+        }
+
+        (
+            quote! {
+                /// Reconcile the record with its row in Airtable, taking the fields that were
+                /// edited in Airtable into the database.
+                /// Returns if the database has changes that still need to be pushed to Airtable.
+                async fn reconcile_with_airtable_record(
+                    &mut self,
+                    db: &crate::db::Database,
+                    airtable_record: &airtable_api::Record<serde_json::Value>,
+                    report: &mut crate::reconcile::ReconcileReport,
+                ) -> anyhow::Result<bool> {
+                    // Go through our type so that the fields Airtable leaves out when they are
+                    // empty compare equal to ours.
+                    let airtable_fields: #new_struct_name = serde_json::from_value(airtable_record.fields.clone())?;
+                    let airtable_value = serde_json::to_value(&airtable_fields)?;
+
+                    let (id, cio_company_id) = (self.id, self.cio_company_id);
+                    let database_value = serde_json::to_value(&*self)?;
+                    let value = airtable_value.clone();
+                    let modified_at = crate::reconcile::airtable_modified_at(airtable_record);
+                    let reconciled = db.run(move |db| crate::reconcile::reconcile(
+                        db,
+                        #record_type,
+                        id,
+                        cio_company_id,
+                        &database_value,
+                        &value,
+                        modified_at,
+                    )).await?;
+                    report.add(&reconciled.merge);
+
+                    if !reconciled.merge.pull.is_empty() {
+                        log::info!("[reconcile] {} id={} pulling fields from Airtable: {:?}", #record_type, self.id, reconciled.merge.pull);
+
+                        let mut merged: #new_struct_name = serde_json::from_value(reconciled.fields)?;
+                        merged.id = self.id;
+                        merged.airtable_record_id = airtable_record.id.to_string();
+                        merged.cio_company_id = self.cio_company_id;
+                        *self = db.run(move |db| merged.update_in_db(db)).await?;
+                    }
+
+                    if reconciled.merge.push.is_empty() {
+                        // Both sides agree now.
+                        db.run(move |db| crate::reconcile::AirtableSyncState::save_snapshot(db, #record_type, id, cio_company_id, &airtable_value)).await?;
+                        return Ok(false);
+                    }
+
+                    Ok(true)
+                }
+
+                /// Get the row in Airtable for the record, with the fields as Airtable has them.
+                async fn get_existing_airtable_value(&self, db: &crate::db::Database) -> Option<airtable_api::Record<serde_json::Value>> {
+                    if self.airtable_record_id.is_empty() {
+                        return None;
+                    }
+
+                    let record = self.clone();
+                    let airtable = db.run(move |db| record.airtable(db)).await.ok()?;
+                    match airtable.get_record(&#new_struct_name::airtable_table(), &self.airtable_record_id).await {
+                        Ok(r) => Some(r),
+                        Err(e) => {
+                            log::info!("[reconcile] getting airtable record {} failed: {}", self.airtable_record_id, e);
+                            None
+                        }
+                    }
+                }
+
+                /// Push the record to Airtable and remember what both sides agree on.
+                async fn push_to_airtable(&mut self, db: &crate::db::Database) -> anyhow::Result<airtable_api::Record<#new_struct_name>> {
+                    let record = self.upsert_in_airtable(db).await?;
+
+                    let (id, cio_company_id) = (self.id, self.cio_company_id);
+                    let fields = serde_json::to_value(&record.fields)?;
+                    db.run(move |db| crate::reconcile::AirtableSyncState::save_snapshot(db, #record_type, id, cio_company_id, &fields)).await?;
+
+                    Ok(record)
+                }
+            },
+            quote! {
+                /// Pull the edits made in Airtable back into the database.
+                ///
+                /// Every field is compared to what both sides last agreed on. If only one side
+                /// changed it, that side wins. If both changed it, the side that was modified last
+                /// wins and the conflict is logged to `airtable_sync_conflicts`. If they never
+                /// agreed before, the database wins.
+                ///
+                /// A record that fails is skipped so it doesn't hold up the others, the failures
+                /// are in the `errors` of the report.
+                pub async fn reconcile_airtable(db: &crate::db::Database, cio_company_id: i32) -> anyhow::Result<crate::reconcile::ReconcileReport> {
+                    let airtable = db.run(move |db| #new_struct_name::airtable_from_company_id(db, cio_company_id)).await?;
+                    let result: Vec<airtable_api::Record<serde_json::Value>> = airtable
+                        .list_records(&#new_struct_name::airtable_table(), "Grid view", vec![])
+                        .await?;
+
+                    let mut records: std::collections::BTreeMap<i32, airtable_api::Record<serde_json::Value>> = Default::default();
+                    for record in result {
+                        if let Some(id) = record.fields.get("id").and_then(|id| id.as_i64()) {
+                            records.insert(id as i32, record);
+                        }
+                    }
+
+                    let mut report = crate::reconcile::ReconcileReport::default();
+                    let db_records = db.run(move |db| #new_struct_name_plural::get_from_db(db, cio_company_id)).await?;
+                    for mut record in db_records.0 {
+                        let airtable_record = match records.get(&record.id) {
+                            Some(r) => r,
+                            // It is not in Airtable yet, we have nothing to pull.
+                            None => continue,
+                        };
+
+                        let push = match record.reconcile_with_airtable_record(db, airtable_record, &mut report).await {
+                            Ok(push) => push,
+                            Err(e) => {
+                                report.fail(record.id, &e);
+                                continue;
+                            }
+                        };
+
+                        if push {
+                            if let Err(e) = record.push_to_airtable(db).await {
+                                report.fail(record.id, &e);
+                            }
+                        }
+                    }
+
+                    for error in &report.errors {
+                        log::warn!("[reconcile] {} {}", #record_type, error);
+                    }
+
+                    log::info!("[reconcile] {}: {:?}", #record_type, report);
+
+                    Ok(report)
+                }
+            },
+            quote! {
+                // Take any edits made in Airtable first, so we don't clobber them.
+                if let Some(airtable_record) = self.get_existing_airtable_value(db).await {
+                    let mut report = crate::reconcile::ReconcileReport::default();
+                    if let Err(e) = self.reconcile_with_airtable_record(db, &airtable_record, &mut report).await {
+                        log::warn!("[reconcile] {} id={} failed: {}", #record_type, self.id, e);
+                    }
+                }
+
+                let record = self
+                    .push_to_airtable(db)
+                    .await
+                    .map_err(|e| crate::error::CioError::from_mirror(crate::mirror::MirrorBackend::Airtable, e))?;
+            },
+            quote! {
+                // Take any edits made in Airtable first, so we don't clobber them.
+                #new_struct_name_plural::reconcile_airtable(db, self.0.get(0).unwrap().cio_company_id).await?;
+            },
+            quote! {
+                crate::reconcile::AirtableSyncState::touch(db, #record_type, r.id, r.cio_company_id)?;
+            },
+            quote! {
+                if r != record {
+                    crate::reconcile::AirtableSyncState::touch(db, #record_type, record.id, record.cio_company_id)?;
+                }
+            },
+            quote! {
+                if before.as_ref() != Some(&record) {
+                    crate::reconcile::AirtableSyncState::touch(db, #record_type, record.id, record.cio_company_id)?;
+                }
+            },
+            quote! {
+                crate::reconcile::AirtableSyncState::delete(db, #record_type, self.id)?;
+            },
+        )
+    } else {
+        (
+            quote!(),
+            quote!(),
+            quote! {
+                let record = self
+                    .upsert_in_airtable(db)
+                    .await
+                    .map_err(|e| crate::error::CioError::from_mirror(crate::mirror::MirrorBackend::Airtable, e))?;
+            },
+            quote!(),
+            quote!(),
+            quote!(),
+            quote!(),
+            quote!(),
+        )
+    };
+
+    // Get the code for the backend we mirror the records to.
+    let (mirror, airtable_fns, airtable_plural_fns, update_mirror) = match params.mirror.as_str() {
+        "airtable" => {
+            if params.airtable_base.is_empty() || params.airtable_table.is_empty() {
+                return Err(syn::Error::new_spanned(
+                    &attr,
+                    "`airtable_base` and `airtable_table` are required for the `airtable` mirror",
+                ));
+            }
+
+            // Get the Airtable information.
+            let airtable_base = format_ident!("airtable_base_id_{}", params.airtable_base);
+            let airtable_table = format_ident!("{}", params.airtable_table);
+
+            (
+                quote! {
+                    #[async_trait::async_trait]
+                    impl crate::mirror::Mirror for #new_struct_name {
+                        fn mirror_backend() -> crate::mirror::MirrorBackend {
+                            crate::mirror::MirrorBackend::Airtable
+                        }
+
+                        async fn upsert_in_mirror(&mut self, db: &crate::db::Database) -> anyhow::Result<String> {
+                            #reconcile_before_upsert
+
+                            Ok(record.id)
+                        }
+
+                        async fn delete_from_mirror(db: &crate::db::Database, cio_company_id: i32, mirror_record_id: &str) -> anyhow::Result<()> {
+                            if let Err(e) = #new_struct_name::airtable_from_company_id(db, cio_company_id)?
+                                .delete_record(&#new_struct_name::airtable_table(), mirror_record_id)
+                                .await
+                            {
+                                // Ignore if we got a not found error since then the record does not exist.
+                                let e = crate::error::CioError::from_mirror(crate::mirror::MirrorBackend::Airtable, e);
+                                if !crate::error::CioError::is_not_found(&e) {
+                                    return Err(e);
+                                }
+                            }
+
+                            Ok(())
+                        }
+                    }
+                },
+                quote! {
+                /// Get the row in our airtable workspace.
+                pub async fn get_from_airtable(id: &str, db: &crate::db::Database, cio_company_id: i32) -> anyhow::Result<Self> {
+                    let record = #new_struct_name::airtable_from_company_id(db, cio_company_id)?
+                        .get_record(&#new_struct_name::airtable_table(), id)
+                        .await?;
+
+                    Ok(record.fields)
+                }
+
+                /// Create the Airtable client.
+                /// We do this in it's own function so our other functions are more DRY.
+                fn airtable(&self, db: &crate::db::Database) -> anyhow::Result<airtable_api::Airtable> {
+                    // Get the company for the company_id.
+                    let company = self.company(db)?;
+                    Ok(company.authenticate_airtable(&company.#airtable_base))
+                }
+
+                /// Create the Airtable client.
+                /// We do this in it's own function so our other functions are more DRY.
+                fn airtable_from_company_id(db: &crate::db::Database, cio_company_id: i32) -> anyhow::Result<airtable_api::Airtable> {
+                    // Get the company for the company_id.
+                    let company = crate::companies::Company::get_by_id(db, cio_company_id)?;
+                    Ok(company.authenticate_airtable(&company.#airtable_base))
+                }
+
+                /// Return the Airtable table name.
+                /// We do this in it's own function so our other functions are more DRY.
+                fn airtable_table() -> String {
+                    #airtable_table.to_string()
+                }
+
+                /// Create the row in the Airtable base.
+                pub async fn create_in_airtable(&mut self, db: &crate::db::Database) -> anyhow::Result<airtable_api::Record<#new_struct_name>> {
+                    let airtable = self.airtable(db)?;
+                    self.create_in_airtable_with(db, &airtable).await
+                }
+
+                /// Create the row in the Airtable base, with the given client.
+                pub async fn create_in_airtable_with(&mut self, db: &crate::db::Database, airtable: &airtable_api::Airtable) -> anyhow::Result<airtable_api::Record<#new_struct_name>> {
+                    let mut mut_self = self.clone();
+                    // Run the custom trait to update the new record from the old record.
+                    // We do this because where we join Airtable tables, things tend to get a little
+                    // weird if we aren't nit picky about this.
+                    mut_self.update_airtable_record(db, self.clone()).await?;
+
+                    // Create the record.
+                    let record = airtable_api::Record {
+                        id: "".to_string(),
+                        created_time: None,
+                        fields: mut_self,
+                    };
+
+                    // Send the new record to the Airtable client.
+                    let records : Vec<airtable_api::Record<#new_struct_name>> = airtable
+                        .create_records(&#new_struct_name::airtable_table(), vec![record])
+                        .await
+                        ?;
+
+                    log::info!("[airtable] created new row: {:?}", self);
+
+                    // Return the first record back.
+                    Ok(records.get(0).unwrap().clone())
+                }
+
+                /// Update the record in Airtable.
+                pub async fn update_in_airtable(&self, db: &crate::db::Database, existing_record: &mut airtable_api::Record<#new_struct_name>) -> anyhow::Result<airtable_api::Record<#new_struct_name>> {
+                    let airtable = self.airtable(db)?;
+                    self.update_in_airtable_with(db, &airtable, existing_record).await
+                }
+
+                /// Update the record in Airtable, with the given client.
+                pub async fn update_in_airtable_with(&self, db: &crate::db::Database, airtable: &airtable_api::Airtable, existing_record: &mut airtable_api::Record<#new_struct_name>) -> anyhow::Result<airtable_api::Record<#new_struct_name>> {
+                    let mut mut_self = self.clone();
+                    // Run the custom trait to update the new record from the old record.
+                    // We do this because where we join Airtable tables, things tend to get a little
+                    // weird if we aren't nit picky about this.
+                    mut_self.update_airtable_record(db, existing_record.fields.clone()).await?;
+
+                    // If the Airtable record and the record that was passed in are the same, then we can return early since
+                    // we do not need to update it in Airtable.
+                    // We do this after we update the record so that any fields that are links to other
+                    // tables match as well and this can return true even if we have linked records.
+                    if mut_self == existing_record.fields {
+                        log::info!("[airtable] id={} in given object equals Airtable record, skipping update", self.id);
+                        return Ok(existing_record.clone());
+                    }
+
+                    existing_record.fields = mut_self;
+
+                    // Send the updated record to Airtable.
+                    let records : Vec<airtable_api::Record<#new_struct_name>> = airtable.update_records(
+                        &#new_struct_name::airtable_table(),
+                        vec![existing_record.clone()],
+                    ).await?;
+
+                    log::info!("[airtable] id={} updated", self.id);
+
+                    if records.is_empty() {
+                        return Ok(existing_record.clone());
+                    }
+
+                    Ok(records.get(0).unwrap().clone())
+                }
+
+                /// Get the existing record in Airtable that matches this id.
+                pub async fn get_existing_airtable_record(&self, db: &crate::db::Database) -> Option<airtable_api::Record<#new_struct_name>> {
+                    if self.airtable_record_id.is_empty() {
+                        return None;
+                    }
+                        // Let's get the existing record from airtable.
+                        if let Ok(a) = self.airtable(db) {
+                                match a.get_record(&#new_struct_name::airtable_table(), &self.airtable_record_id)
+                                .await {
+                                    Ok(v) => return Some(v),
+                                    Err(e) => {
+                                        log::info!("getting airtable record failed: {}", self.airtable_record_id);
+                                        return None;
+                                    }
+                                }
+                        }
+
+                    None
+                }
+
+
+                /// Create or update a row in the Airtable base.
+                pub async fn upsert_in_airtable(&mut self, db: &crate::db::Database) -> anyhow::Result<airtable_api::Record<#new_struct_name>> {
+                    // First check if we have an `airtable_record_id` for this record.
+                    // If we do we can move ahead faster.
+                    if !self.airtable_record_id.is_empty() {
+                        let mut er: Option<airtable_api::Record<#new_struct_name>> = self.get_existing_airtable_record(db).await;
+
+                        if let Some(mut existing_record) = er {
+                            // Return the result from the update.
+                            return self.update_in_airtable(db, &mut existing_record).await;
+                        }
+                        // Otherwise we need to continue through the other loop.
+                    }
+
+                    // Since we don't know the airtable record id, we need to find it by looking
+                    // through all the existing records in Airtable and matching on our database id.
+                    // This is slow so we should always try to make sure we have the airtable_record_id
+                    // set. This function is mostly here until we migrate away from the old way of doing
+                    // things.
+                    let records = #new_struct_name_plural::get_from_airtable(db,self.cio_company_id).await?;
+                    for (id, record) in records {
+                        if self.id == id {
+                            return self.update_in_airtable(db, &mut record.clone()).await;
+                        }
+                    }
+
+                    // We've tried everything to find the record in our existing Airtable but it is not
+                    // there. We need to create it.
+                    let record = self.create_in_airtable(db).await?;
+
+                    Ok(record)
+                }
+
+                /// Delete a record from Airtable.
+                pub async fn delete_from_airtable(&self, db: &crate::db::Database) -> anyhow::Result<()> {
+                    if !self.airtable_record_id.is_empty() {
+                        // Delete the record from airtable.
+                        if let Err(e) = self.airtable(db)?.delete_record(&#new_struct_name::airtable_table(), &self.airtable_record_id).await {
+                            // Ignore if we got a not found error since then the record does not exist.
+                            let e = crate::error::CioError::from_mirror(crate::mirror::MirrorBackend::Airtable, e);
+                            if crate::error::CioError::is_not_found(&e) {
+                                return Ok(());
+                            }
+
+                            // Otherwise return the actual error.
+                            return Err(e.context(format!("deleting record: `{:?}` from Airtable failed", self)));
+                        }
+                    }
+
+                    Ok(())
+                }
+
+                #reconcile_fns
+                        },
+                quote! {
+                /// Get the current records for this type from Airtable.
+                pub async fn get_from_airtable(db: &crate::db::Database, cio_company_id: i32) -> anyhow::Result<std::collections::BTreeMap<i32, airtable_api::Record<#new_struct_name>>> {
+                    let result: Vec<airtable_api::Record<#new_struct_name>> = #new_struct_name::airtable_from_company_id(db, cio_company_id)?
+                        .list_records(&#new_struct_name::airtable_table(), "Grid view", vec![])
+                        .await?;
+
+                    let mut records: std::collections::BTreeMap<i32, airtable_api::Record<#new_struct_name>> =
+                        Default::default();
+                    for record in result {
+                        records.insert(record.fields.id, record);
+                    }
+
+                    Ok(records)
+                }
+
+                #reconcile_plural_fns
+
+                /// Update Airtable records in a table from a vector.
+                pub async fn update_airtable(&self, db: &crate::db::Database) -> anyhow::Result<()> {
+                    if self.0.is_empty() {
+                        // Return early.
+                        return Ok(());
+                    }
+
+                    #reconcile_before_update_airtable
+
+                    let mut records = #new_struct_name_plural::get_from_airtable(db, self.0.get(0).unwrap().cio_company_id).await?;
+
+                    for mut vec_record in self.0.clone() {
+                        // Get the latest of this record from the database.
+                        // This make this less racy if we have a bunch and things got
+                        // out of sync.
+                        if let Ok(mut vec_record) = #new_struct_name::get_by_id(db, vec_record.id) {
+                            // See if we have it in our Airtable records.
+                            match records.get(&vec_record.id) {
+                                Some(r) => {
+                                    let mut record = r.clone();
+
+                                    // Update the record in Airtable.
+                                    vec_record.update_in_airtable(db, &mut record).await?;
+
+                                    // Remove it from the map.
+                                    records.remove(&vec_record.id);
+                                }
+                                None => {
+                                    // We do not have the record in Airtable, Let's create it.
+                                    // Create the record in Airtable.
+                                    vec_record.create_in_airtable(db).await?;
+
+                                    // Remove it from the map.
+                                    records.remove(&vec_record.id);
+                                }
+                            }
+                        }
+                    }
+
+                    // Iterate over the records remaining and remove them from airtable
+                    // since they don't exist in our vector.
+                    for (_, record) in records {
+                        // TODO: Ensure it didn't _just_ get added to the database.
+                        // Delete the record from airtable.
+                        record.fields.airtable(db)?.delete_record(&#new_struct_name::airtable_table(), &record.id).await?;
+                    }
+
+                    Ok(())
+                }
+                        },
+                quote! {
+                    self.update_airtable(db).await
+                },
+            )
+        }
+        "google_sheets" => {
+            if params.google_sheets_spreadsheet.is_empty() {
+                return Err(syn::Error::new_spanned(
+                    &attr,
+                    "`google_sheets_spreadsheet` is required for the `google_sheets` mirror",
+                ));
+            }
+
+            let google_sheets_spreadsheet = format_ident!("{}", params.google_sheets_spreadsheet);
+            let google_sheets_tab = format!("{}s", params.new_struct_name);
+
+            (
+                quote! {
+                    #[async_trait::async_trait]
+                    impl crate::mirror::Mirror for #new_struct_name {
+                        fn mirror_backend() -> crate::mirror::MirrorBackend {
+                            crate::mirror::MirrorBackend::GoogleSheets
+                        }
+
+                        async fn upsert_in_mirror(&mut self, db: &crate::db::Database) -> anyhow::Result<String> {
+                            crate::mirror::GoogleSheetsMirror::new(db, self.cio_company_id, #google_sheets_spreadsheet, #google_sheets_tab)
+                                .await?
+                                .upsert(&self.airtable_record_id, self)
+                                .await
+                                .map_err(|e| crate::error::CioError::from_mirror(crate::mirror::MirrorBackend::GoogleSheets, e))
+                        }
+
+                        async fn delete_from_mirror(db: &crate::db::Database, cio_company_id: i32, mirror_record_id: &str) -> anyhow::Result<()> {
+                            crate::mirror::GoogleSheetsMirror::new(db, cio_company_id, #google_sheets_spreadsheet, #google_sheets_tab)
+                                .await?
+                                .delete(mirror_record_id)
+                                .await
+                                .map_err(|e| crate::error::CioError::from_mirror(crate::mirror::MirrorBackend::GoogleSheets, e))
+                        }
+                    }
+                },
+                quote!(),
+                quote!(),
+                quote! {
+                    if self.0.is_empty() {
+                        // Return early.
+                        return Ok(());
+                    }
+
+                    let sheet = crate::mirror::GoogleSheetsMirror::new(
+                        db,
+                        self.0.get(0).unwrap().cio_company_id,
+                        #google_sheets_spreadsheet,
+                        #google_sheets_tab,
+                    )
+                    .await?;
+                    for record in &self.0 {
+                        let mirror_record_id = sheet.upsert(&record.airtable_record_id, record).await?;
+
+                        // Remember the row of a new record, so we update it in place next time.
+                        if record.airtable_record_id != mirror_record_id {
+                            let mut record = record.clone();
+                            record.airtable_record_id = mirror_record_id;
+                            db.run(move |db| record.update_in_db(db)).await?;
+                        }
+                    }
+
+                    Ok(())
+                },
+            )
+        }
+        "none" => (
+            quote! {
+                #[async_trait::async_trait]
+                impl crate::mirror::Mirror for #new_struct_name {
+                    fn mirror_backend() -> crate::mirror::MirrorBackend {
+                        crate::mirror::MirrorBackend::None
+                    }
+
+                    async fn upsert_in_mirror(&mut self, _db: &crate::db::Database) -> anyhow::Result<String> {
+                        Ok(self.airtable_record_id.to_string())
+                    }
+
+                    async fn delete_from_mirror(_db: &crate::db::Database, _cio_company_id: i32, _mirror_record_id: &str) -> anyhow::Result<()> {
+                        Ok(())
+                    }
+                }
+            },
+            quote!(),
+            quote!(),
+            quote! {
+                // We have nothing to mirror to.
+                let _ = db;
+                Ok(())
+            },
+        ),
+        m => {
+            return Err(syn::Error::new_spanned(
+                &attr,
+                format!(
+                    "unknown mirror `{}`, expected one of `airtable`, `google_sheets` or `none`",
+                    m
+                ),
+            ))
+        }
+    };
 
     let airtable = quote! {
     // Import what we need from diesel so the database queries work.
     use diesel::prelude::*;
 
     impl #og_struct_name {
-        /// Create a new record in the database and Airtable.
+        /// Create a new record in the database and its mirror.
+        ///
+        /// The mirror write goes through the outbox, so this only fails if the
+        /// database write fails.
         pub async fn create(&self, db: &crate::db::Database) -> anyhow::Result<#new_struct_name> {
-            let mut new_record = self.create_in_db(db)?;
-
-            // Let's also create this record in Airtable.
-            let new_airtable_record = new_record.create_in_airtable(db).await?;
+            let item = self.clone();
+            let new_record = db.run(move |db| item.create_in_db(db)).await?;
 
-            // Now we have the id we need to update the database.
-            new_record.airtable_record_id = new_airtable_record.id.to_string();
-            let r = new_record.update_in_db(db)?;
-            Ok(r)
+            // Let's also create this record in the mirror.
+            new_record.mirror_upsert(db).await
         }
 
         /// Create a new record in the database.
         pub fn create_in_db(&self, db: &crate::db::Database) -> anyhow::Result<#new_struct_name> {
-            // // TODO: special error here.
-            let r = diesel::insert_into(crate::schema::#db_schema::table)
-                .values(self)
-                .get_result(&db.conn())?;
+            // The record and its history are written together, or not at all.
+            let conn = db.conn()?;
+            let r = conn.transaction::<_, anyhow::Error, _>(|| {
+                let r: #new_struct_name = diesel::insert_into(crate::schema::#db_schema::table)
+                    .values(self)
+                    .get_result(&conn)
+                    .map_err(|e| crate::error::CioError::from_db(e, #record_type))?;
+
+                #record_create_history
+
+                Ok(r)
+            })?;
+
+            #touch_create
 
             Ok(r)
         }
 
-        /// Create or update the record in the database and Airtable.
+        /// Create or update the record in the database and its mirror.
+        ///
+        /// The mirror write goes through the outbox, so this only fails if the
+        /// database write fails.
         pub async fn upsert(&self, db: &crate::db::Database) -> anyhow::Result<#new_struct_name> {
-            let mut record = self.upsert_in_db(db)?;
-
-            // Let's also update this record in Airtable.
-            let new_airtable_record = record.upsert_in_airtable(db).await?;
-
-            if record.airtable_record_id.is_empty(){
-                // Now we have the id we need to update the database.
-                record.airtable_record_id = new_airtable_record.id.to_string();
-                return record.update_in_db(db);
-            }
+            let item = self.clone();
+            let record = db.run(move |db| item.upsert_in_db(db)).await?;
 
-            Ok(record)
+            // Let's also update this record in the mirror.
+            record.mirror_upsert(db).await
         }
 
         /// Create or update the record in the database.
         pub fn upsert_in_db(&self, db: &crate::db::Database) -> anyhow::Result<#new_struct_name> {
             // See if we already have the record in the database.
-            if let Some(r) = #new_struct_name::get_from_db(db, #function_args) {
-                // Update the record.
-                // // TODO: special error here.
-                let record = diesel::update(&r)
-                    .set(self)
-                    .get_result::<#new_struct_name>(&db.conn())?;
+            if let Some(r) = #new_struct_name::find_in_db(db, #function_args)? {
+                // Update the record, with its history in the same transaction.
+                let conn = db.conn()?;
+                let record = conn.transaction::<_, anyhow::Error, _>(|| {
+                    let record = diesel::update(&r)
+                        .set(self)
+                        .get_result::<#new_struct_name>(&conn)
+                        .map_err(|e| crate::error::CioError::from_db(e, #record_type))?;
+
+                    #record_upsert_history
+
+                    Ok(record)
+                })?;
+
+                #touch_upsert
 
                 return Ok(record);
             }
@@ -169,46 +921,147 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
     }
 
     impl #new_struct_name {
-        /// Update the record in the database and Airtable.
+        /// Update the record in the database and its mirror.
+        ///
+        /// The mirror write goes through the outbox, so this only fails if the
+        /// database write fails.
         pub async fn update(&self, db: &crate::db::Database) -> anyhow::Result<Self> {
             // Update the record.
-            let mut record = self.update_in_db(db)?;
+            let item = self.clone();
+            let record = db.run(move |db| item.update_in_db(db)).await?;
+
+            // Let's also update this record in the mirror.
+            record.mirror_upsert(db).await
+        }
+
+        /// Enqueue an upsert of the record in the mirror and try to run it right away.
+        /// If the mirror is unavailable the entry stays in the outbox and the background
+        /// worker retries it with backoff.
+        async fn mirror_upsert(mut self, db: &crate::db::Database) -> anyhow::Result<Self> {
+            let backend = <#new_struct_name as crate::mirror::Mirror>::mirror_backend();
+            if backend == crate::mirror::MirrorBackend::None {
+                // Return early, we have nothing to mirror to.
+                return Ok(self);
+            }
+
+            let (id, airtable_record_id, cio_company_id) = (self.id, self.airtable_record_id.to_string(), self.cio_company_id);
+            let entry = db.run(move |db| crate::outbox::AirtableOutboxEntry::enqueue(
+                db,
+                crate::outbox::OutboxOperation::Upsert,
+                #record_type,
+                id,
+                &airtable_record_id,
+                cio_company_id,
+            )).await?;
+
+            match crate::mirror::Mirror::upsert_in_mirror(&mut self, db).await {
+                Ok(mirror_record_id) => {
+                    db.run(move |db| entry.complete(db)).await?;
+
+                    if self.airtable_record_id != mirror_record_id {
+                        // Now we have the id we need to update the database.
+                        self.airtable_record_id = mirror_record_id;
+                        return db.run(move |db| self.update_in_db(db)).await;
+                    }
+
+                    Ok(self)
+                }
+                Err(e) => {
+                    log::warn!("[{}] upserting {} id={} failed, leaving it in the outbox: {}", backend, #record_type, self.id, e);
+                    db.run(move |db| entry.fail(db, &e)).await?;
+
+                    Ok(self)
+                }
+            }
+        }
 
-            // Let's also update this record in Airtable.
-            let new_airtable_record = record.upsert_in_airtable(db).await?;
+        /// Run an entry from the outbox for this type.
+        pub async fn process_airtable_outbox_entry(db: &crate::db::Database, entry: &crate::outbox::AirtableOutboxEntry) -> anyhow::Result<()> {
+            match entry.operation()? {
+                crate::outbox::OutboxOperation::Upsert => {
+                    // Get the latest version of the record, if it was deleted since the
+                    // entry was enqueued there is nothing left to mirror.
+                    let record_id = entry.record_id;
+                    let record = db.run(move |db| {
+                        Ok(#db_schema::dsl::#db_schema.find(record_id)
+                            .first::<#new_struct_name>(&db.conn()?)
+                            .optional()?)
+                    }).await?;
+                    if let Some(mut record) = record {
+                        let mirror_record_id = crate::mirror::Mirror::upsert_in_mirror(&mut record, db).await?;
+
+                        if record.airtable_record_id != mirror_record_id {
+                            record.airtable_record_id = mirror_record_id;
+                            db.run(move |db| record.update_in_db(db)).await?;
+                        }
+                    }
+                }
+                crate::outbox::OutboxOperation::Delete => {
+                    if entry.airtable_record_id.is_empty() {
+                        // It never made it to the mirror, we have nothing to delete.
+                        return Ok(());
+                    }
+
+                    <#new_struct_name as crate::mirror::Mirror>::delete_from_mirror(db, entry.cio_company_id, &entry.airtable_record_id).await?;
+                }
+            }
+
+            Ok(())
+        }
 
-            // Now we have the id we need to update the database.
-            record.airtable_record_id = new_airtable_record.id.to_string();
-            record.update_in_db(db)
+        /// Run an entry from the outbox for this type, boxed so the outbox can find it by the
+        /// name of the type along with the others.
+        fn process_airtable_outbox_entry_boxed<'a>(db: &'a crate::db::Database, entry: &'a crate::outbox::AirtableOutboxEntry) -> futures_util::future::BoxFuture<'a, anyhow::Result<()>> {
+            Box::pin(#new_struct_name::process_airtable_outbox_entry(db, entry))
         }
 
         /// Update the record in the database.
         pub fn update_in_db(&self, db: &crate::db::Database) -> anyhow::Result<Self> {
-            // Update the record.
-            let record = diesel::update(self)
-                .set(self.clone())
-                .get_result::<#new_struct_name>(&db.conn())?;
+            let conn = db.conn()?;
+            #get_update_before
+
+            // Update the record, with its history in the same transaction.
+            let record = conn.transaction::<_, anyhow::Error, _>(|| {
+                let record = diesel::update(self)
+                    .set(self.clone())
+                    .get_result::<#new_struct_name>(&conn)
+                    .map_err(|e| crate::error::CioError::from_db(e, #record_type))?;
+
+                #record_update_history
+
+                Ok(record)
+            })?;
+
+            #touch_update
 
             Ok(record)
         }
 
         /// Get a record from the database.
         pub fn get_from_db(db: &crate::db::Database#args) -> Option<Self> {
-            match #db_schema::dsl::#db_schema#filter.first::<#new_struct_name>(&db.conn()) {
-                Ok(r) => {
-                    return Some(r);
-                }
+            match #new_struct_name::find_in_db(db, #call_args) {
+                Ok(r) => r,
                 Err(e) => {
-                    log::info!("[db] we don't have the record in the database: {}", e);
-                    return None;
+                    log::warn!("[db] getting the record from the database failed: {}", e);
+                    None
                 }
             }
         }
 
+        /// Find a record in the database, this only returns an error if the query failed.
+        fn find_in_db(db: &crate::db::Database#args) -> anyhow::Result<Option<Self>> {
+            let r = #db_schema::dsl::#db_schema#filter
+                .first::<#new_struct_name>(&db.conn()?)
+                .optional()?;
+
+            Ok(r)
+        }
+
         /// Get a record by its id.
         pub fn get_by_id(db: &crate::db::Database, id: i32) -> anyhow::Result<Self> {
             let record = #db_schema::dsl::#db_schema.find(id)
-                .first::<#new_struct_name>(&db.conn())?;
+                .first::<#new_struct_name>(&db.conn()?)
+                .map_err(|e| crate::error::CioError::from_db(e, #record_type))?;
 
             Ok(record)
         }
@@ -221,191 +1074,66 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
             }
         }
 
-        /// Get the row in our airtable workspace.
-        pub async fn get_from_airtable(id: &str, db: &crate::db::Database, cio_company_id: i32) -> anyhow::Result<Self> {
-            let record = #new_struct_name::airtable_from_company_id(db, cio_company_id)?
-                .get_record(&#new_struct_name::airtable_table(), id)
-                .await?;
-
-            Ok(record.fields)
-        }
-
-        /// Delete a record from the database and Airtable.
+        /// Delete a record from the database and its mirror.
+        ///
+        /// The mirror delete goes through the outbox, so this only fails if the
+        /// database delete fails.
         pub async fn delete(&self, db: &crate::db::Database) -> anyhow::Result<()> {
-            self.delete_from_db(db)?;
+            let item = self.clone();
+            db.run(move |db| item.delete_from_db(db)).await?;
 
-            // Let's also delete the record from Airtable.
-            self.delete_from_airtable(db).await?;
-
-            Ok(())
-        }
-
-        /// Delete a record from the database.
-        pub fn delete_from_db(&self, db: &crate::db::Database) -> anyhow::Result<()> {
-            diesel::delete(
-                crate::schema::#db_schema::dsl::#db_schema.filter(
-                    crate::schema::#db_schema::dsl::id.eq(self.id)))
-                    .execute(&db.conn())?;
-
-            Ok(())
-        }
-
-        /// Create the Airtable client.
-        /// We do this in it's own function so our other functions are more DRY.
-        fn airtable(&self, db: &crate::db::Database) -> anyhow::Result<airtable_api::Airtable> {
-            // Get the company for the company_id.
-            let company = self.company(db)?;
-            Ok(company.authenticate_airtable(&company.#airtable_base))
-        }
-
-        /// Create the Airtable client.
-        /// We do this in it's own function so our other functions are more DRY.
-        fn airtable_from_company_id(db: &crate::db::Database, cio_company_id: i32) -> anyhow::Result<airtable_api::Airtable> {
-            // Get the company for the company_id.
-            let company = crate::companies::Company::get_by_id(db, cio_company_id)?;
-            Ok(company.authenticate_airtable(&company.#airtable_base))
-        }
-
-        /// Return the Airtable table name.
-        /// We do this in it's own function so our other functions are more DRY.
-        fn airtable_table() -> String {
-            #airtable_table.to_string()
-        }
-
-        /// Create the row in the Airtable base.
-        pub async fn create_in_airtable(&mut self, db: &crate::db::Database) -> anyhow::Result<airtable_api::Record<#new_struct_name>> {
-            let mut mut_self = self.clone();
-            // Run the custom trait to update the new record from the old record.
-            // We do this because where we join Airtable tables, things tend to get a little
-            // weird if we aren't nit picky about this.
-            mut_self.update_airtable_record(self.clone()).await?;
-
-            // Create the record.
-            let record = airtable_api::Record {
-                id: "".to_string(),
-                created_time: None,
-                fields: mut_self,
-            };
-
-            // Send the new record to the Airtable client.
-            let records : Vec<airtable_api::Record<#new_struct_name>> = self.airtable(db)?
-                .create_records(&#new_struct_name::airtable_table(), vec![record])
-                .await
-                ?;
-
-            log::info!("[airtable] created new row: {:?}", self);
-
-            // Return the first record back.
-            Ok(records.get(0).unwrap().clone())
-        }
-
-        /// Update the record in Airtable.
-        pub async fn update_in_airtable(&self, db: &crate::db::Database, existing_record: &mut airtable_api::Record<#new_struct_name>) -> anyhow::Result<airtable_api::Record<#new_struct_name>> {
-            let mut mut_self = self.clone();
-            // Run the custom trait to update the new record from the old record.
-            // We do this because where we join Airtable tables, things tend to get a little
-            // weird if we aren't nit picky about this.
-            mut_self.update_airtable_record(existing_record.fields.clone()).await?;
-
-            // If the Airtable record and the record that was passed in are the same, then we can return early since
-            // we do not need to update it in Airtable.
-            // We do this after we update the record so that any fields that are links to other
-            // tables match as well and this can return true even if we have linked records.
-            if mut_self == existing_record.fields {
-                log::info!("[airtable] id={} in given object equals Airtable record, skipping update", self.id);
-                return Ok(existing_record.clone());
+            let backend = <#new_struct_name as crate::mirror::Mirror>::mirror_backend();
+            if backend == crate::mirror::MirrorBackend::None || self.airtable_record_id.is_empty() {
+                // Return early, it was never in the mirror.
+                return Ok(());
             }
 
-            existing_record.fields = mut_self;
-
-            // Send the updated record to Airtable.
-            let records : Vec<airtable_api::Record<#new_struct_name>> = self.airtable(db)?.update_records(
-                &#new_struct_name::airtable_table(),
-                vec![existing_record.clone()],
-            ).await?;
-
-            log::info!("[airtable] id={} updated", self.id);
-
-            if records.is_empty() {
-                return Ok(existing_record.clone());
+            // Let's also delete the record from the mirror.
+            let (id, airtable_record_id, cio_company_id) = (self.id, self.airtable_record_id.to_string(), self.cio_company_id);
+            let entry = db.run(move |db| crate::outbox::AirtableOutboxEntry::enqueue(
+                db,
+                crate::outbox::OutboxOperation::Delete,
+                #record_type,
+                id,
+                &airtable_record_id,
+                cio_company_id,
+            )).await?;
+
+            if let Err(e) = <#new_struct_name as crate::mirror::Mirror>::delete_from_mirror(db, self.cio_company_id, &self.airtable_record_id).await {
+                log::warn!("[{}] deleting {} id={} failed, leaving it in the outbox: {}", backend, #record_type, self.id, e);
+                db.run(move |db| entry.fail(db, &e)).await?;
+            } else {
+                db.run(move |db| entry.complete(db)).await?;
             }
 
-            Ok(records.get(0).unwrap().clone())
-        }
-
-        /// Get the existing record in Airtable that matches this id.
-        pub async fn get_existing_airtable_record(&self, db: &crate::db::Database) -> Option<airtable_api::Record<#new_struct_name>> {
-            if self.airtable_record_id.is_empty() {
-                return None;
-            }
-                // Let's get the existing record from airtable.
-                if let Ok(a) = self.airtable(db) {
-                        match a.get_record(&#new_struct_name::airtable_table(), &self.airtable_record_id)
-                        .await {
-                            Ok(v) => return Some(v),
-                            Err(e) => {
-                                log::info!("getting airtable record failed: {}", self.airtable_record_id);
-                                return None;
-                            }
-                        }
-                }
-
-            None
+            Ok(())
         }
 
+        /// Delete a record from the database.
+        pub fn delete_from_db(&self, db: &crate::db::Database) -> anyhow::Result<()> {
+            // The delete and its history are written together, or not at all.
+            let conn = db.conn()?;
+            conn.transaction::<_, anyhow::Error, _>(|| {
+                diesel::delete(
+                    crate::schema::#db_schema::dsl::#db_schema.filter(
+                        crate::schema::#db_schema::dsl::id.eq(self.id)))
+                        .execute(&conn)?;
 
-        /// Create or update a row in the Airtable base.
-        pub async fn upsert_in_airtable(&mut self, db: &crate::db::Database) -> anyhow::Result<airtable_api::Record<#new_struct_name>> {
-            // First check if we have an `airtable_record_id` for this record.
-            // If we do we can move ahead faster.
-            if !self.airtable_record_id.is_empty() {
-                let mut er: Option<airtable_api::Record<#new_struct_name>> = self.get_existing_airtable_record(db).await;
-
-                if let Some(mut existing_record) = er {
-                    // Return the result from the update.
-                    return self.update_in_airtable(db, &mut existing_record).await;
-                }
-                // Otherwise we need to continue through the other loop.
-            }
-
-            // Since we don't know the airtable record id, we need to find it by looking
-            // through all the existing records in Airtable and matching on our database id.
-            // This is slow so we should always try to make sure we have the airtable_record_id
-            // set. This function is mostly here until we migrate away from the old way of doing
-            // things.
-            let records = #new_struct_name_plural::get_from_airtable(db,self.cio_company_id).await?;
-            for (id, record) in records {
-                if self.id == id {
-                    return self.update_in_airtable(db, &mut record.clone()).await;
-                }
-            }
-
-            // We've tried everything to find the record in our existing Airtable but it is not
-            // there. We need to create it.
-            let record = self.create_in_airtable(db).await?;
+                #record_delete_history
 
-            Ok(record)
-        }
+                Ok(())
+            })?;
 
-        /// Delete a record from Airtable.
-        pub async fn delete_from_airtable(&self, db: &crate::db::Database) -> anyhow::Result<()> {
-            if !self.airtable_record_id.is_empty() {
-                // Delete the record from airtable.
-                if let Err(e) = self.airtable(db)?.delete_record(&#new_struct_name::airtable_table(), &self.airtable_record_id).await {
-                    // Ignore if we got a NOT_FOUND error since then the record does not exist.
-                    if e.to_string().contains("NOT_FOUND") {
-                        return Ok(());
-                    }
-
-                    // Otherwise return the actual error.
-                    anyhow::bail!("deleting record: `{:?}` from Airtable failed: {}", self, e);
-                }
-            }
+            #forget_delete
 
             Ok(())
         }
+
+        #airtable_fns
     }
 
+    #mirror
+
     #[derive(Debug, Clone, Deserialize, Serialize)]
     pub struct #new_struct_name_plural(pub Vec<#new_struct_name>);
 
@@ -431,74 +1159,184 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
                 crate::schema::#db_schema::dsl::#db_schema
                     .filter(crate::schema::#db_schema::dsl::cio_company_id.eq(cio_company_id))
                     .order_by(crate::schema::#db_schema::dsl::id.desc())
-                    .load::<#new_struct_name>(&db.conn())
+                    .load::<#new_struct_name>(&db.conn()?)
             {
                 Ok(r) => Ok(#new_struct_name_plural(r)),
                 Err(e) => Err(anyhow::anyhow!("getting `{:?}` from the database for cio_company_id `{}` failed: {}", #new_struct_name_plural(vec![]), cio_company_id, e)),
             }
         }
 
-        /// Get the current records for this type from Airtable.
-        pub async fn get_from_airtable(db: &crate::db::Database, cio_company_id: i32) -> anyhow::Result<std::collections::BTreeMap<i32, airtable_api::Record<#new_struct_name>>> {
-            let result: Vec<airtable_api::Record<#new_struct_name>> = #new_struct_name::airtable_from_company_id(db, cio_company_id)?
-                .list_records(&#new_struct_name::airtable_table(), "Grid view", vec![])
-                .await?;
+        /// Start a query for the records of a company.
+        pub fn query(cio_company_id: i32) -> #query_struct_name {
+            #query_struct_name {
+                query: crate::schema::#db_schema::table.into_boxed(),
+                count_query: crate::schema::#db_schema::table.count().into_boxed(),
+                ordered: false,
+            }
+            .filter(crate::schema::#db_schema::dsl::cio_company_id.eq(cio_company_id))
+        }
+
+        /// Update the records in the mirror from a vector.
+        pub async fn update_mirror(&self, db: &crate::db::Database) -> anyhow::Result<()> {
+            #update_mirror
+        }
+
+        #airtable_plural_fns
+    }
+
+    /// A query for records in the database.
+    ///
+    /// The filters and ordering take diesel expressions on the table's columns, so they are
+    /// checked against the schema at compile time.
+    pub struct #query_struct_name {
+        query: crate::schema::#db_schema::BoxedQuery<'static, diesel::pg::Pg>,
+        count_query: crate::schema::#db_schema::BoxedQuery<'static, diesel::pg::Pg, diesel::sql_types::BigInt>,
+        ordered: bool,
+    }
 
-            let mut records: std::collections::BTreeMap<i32, airtable_api::Record<#new_struct_name>> =
-                Default::default();
-            for record in result {
-                records.insert(record.fields.id, record);
+    impl #query_struct_name {
+        /// The columns of the table, by the name of their field in JSON.
+        const COLUMNS: &'static [(&'static str, &'static str)] = &[
+            ("id", "id"),
+            #columns
+            ("airtable_record_id", "airtable_record_id"),
+        ];
+
+        /// Only return the records that match the predicate.
+        /// This can be called more than once, the predicates are combined with `AND`.
+        pub fn filter<P>(mut self, predicate: P) -> Self
+        where
+            P: diesel::expression::BoxableExpression<crate::schema::#db_schema::table, diesel::pg::Pg, SqlType = diesel::sql_types::Bool> + Clone + 'static,
+        {
+            self.query = self.query.filter(predicate.clone());
+            self.count_query = self.count_query.filter(predicate);
+            self
+        }
+
+        /// Order the records by an expression, ie. `created_at.desc()`.
+        /// This can be called more than once, later orderings break ties in earlier ones.
+        /// If this is never called the records are ordered by their id, the newest first.
+        pub fn order_by<O>(mut self, order: O) -> Self
+        where
+            O: diesel::expression::Expression
+                + diesel::expression::AppearsOnTable<crate::schema::#db_schema::table>
+                + diesel::query_builder::QueryFragment<diesel::pg::Pg>
+                + 'static,
+        {
+            self.query = self.query.then_order_by(order);
+            self.ordered = true;
+            self
+        }
+
+        /// Return at most this many records.
+        pub fn limit(mut self, limit: i64) -> Self {
+            self.query = self.query.limit(limit);
+            self
+        }
+
+        /// Skip this many records.
+        pub fn offset(mut self, offset: i64) -> Self {
+            self.query = self.query.offset(offset);
+            self
+        }
+
+        /// Load the records from the database.
+        pub fn load(self, db: &crate::db::Database) -> anyhow::Result<#new_struct_name_plural> {
+            let mut query = self.query;
+            if !self.ordered {
+                query = query.then_order_by(crate::schema::#db_schema::dsl::id.desc());
             }
 
-            Ok(records)
+            let records = query.load::<#new_struct_name>(&db.conn()?)?;
+
+            Ok(#new_struct_name_plural(records))
         }
 
-        /// Update Airtable records in a table from a vector.
-        pub async fn update_airtable(&self, db: &crate::db::Database) -> anyhow::Result<()> {
-            if self.0.is_empty() {
-                // Return early.
-                return Ok(());
+        /// Load a page of records, ordered by id with the newest first.
+        /// Pass `None` to get the first page, or the id this returned for the page before.
+        /// Returns the records and the id to pass for the next page, which is `None` on the last page.
+        pub fn page(mut self, db: &crate::db::Database, after_id: Option<i32>, limit: i64) -> anyhow::Result<(#new_struct_name_plural, Option<i32>)> {
+            if self.ordered {
+                anyhow::bail!("pages are always ordered by id, they can't be combined with `order_by`");
             }
 
-            let mut records = #new_struct_name_plural::get_from_airtable(db, self.0.get(0).unwrap().cio_company_id).await?;
+            if let Some(after_id) = after_id {
+                self = self.filter(crate::schema::#db_schema::dsl::id.lt(after_id));
+            }
 
-            for mut vec_record in self.0.clone() {
-                // Get the latest of this record from the database.
-                // This make this less racy if we have a bunch and things got
-                // out of sync.
-                if let Ok(mut vec_record) = #new_struct_name::get_by_id(db, vec_record.id) {
-                    // See if we have it in our Airtable records.
-                    match records.get(&vec_record.id) {
-                        Some(r) => {
-                            let mut record = r.clone();
+            // Load one more than we need, so we know if there is another page.
+            let mut records = self.limit(limit + 1).load(db)?;
+            let mut next = None;
+            if records.0.len() as i64 > limit {
+                records.0.truncate(limit as usize);
+                next = records.0.last().map(|r| r.id);
+            }
 
-                            // Update the record in Airtable.
-                            vec_record.update_in_airtable(db, &mut record).await?;
+            Ok((records, next))
+        }
 
-                            // Remove it from the map.
-                            records.remove(&vec_record.id);
-                        }
-                        None => {
-                            // We do not have the record in Airtable, Let's create it.
-                            // Create the record in Airtable.
-                            vec_record.create_in_airtable(db).await?;
+        /// Load a page of records like `page`, but only the id and the columns for the fields
+        /// that are given, by their name in JSON. The records are the JSON objects of those fields.
+        pub fn page_fields(mut self, db: &crate::db::Database, after_id: Option<i32>, limit: i64, fields: &[String]) -> anyhow::Result<(Vec<serde_json::Value>, Option<i32>)> {
+            if self.ordered {
+                anyhow::bail!("pages are always ordered by id, they can't be combined with `order_by`");
+            }
 
-                            // Remove it from the map.
-                            records.remove(&vec_record.id);
-                        }
-                    }
+            // The names come from our list of columns, not from the caller, so they are
+            // safe to put in the SQL.
+            let mut object = vec!["'id', id".to_string()];
+            for field in fields.iter().filter(|f| *f != "id") {
+                match Self::COLUMNS.iter().find(|(name, _)| name == field) {
+                    Some((name, column)) => object.push(format!("'{}', \"{}\"", name, column)),
+                    None => anyhow::bail!("unknown field: `{}`", field),
                 }
             }
 
-            // Iterate over the records remaining and remove them from airtable
-            // since they don't exist in our vector.
-            for (_, record) in records {
-                // TODO: Ensure it didn't _just_ get added to the database.
-                // Delete the record from airtable.
-                record.fields.airtable(db)?.delete_record(&#new_struct_name::airtable_table(), &record.id).await?;
+            if let Some(after_id) = after_id {
+                self = self.filter(crate::schema::#db_schema::dsl::id.lt(after_id));
             }
 
-            Ok(())
+            // Load one more than we need, so we know if there is another page.
+            let mut records = self.query
+                .select(diesel::dsl::sql::<diesel::sql_types::Json>(&format!("json_build_object({})", object.join(", "))))
+                .then_order_by(crate::schema::#db_schema::dsl::id.desc())
+                .limit(limit + 1)
+                .load::<serde_json::Value>(&db.conn()?)?;
+            let mut next = None;
+            if records.len() as i64 > limit {
+                records.truncate(limit as usize);
+                next = records.last().and_then(|r| r["id"].as_i64()).map(|id| id as i32);
+            }
+
+            Ok((records, next))
+        }
+
+        /// Load the page of records the parameters of a list endpoint ask for. If they ask for
+        /// some fields, only those columns are loaded.
+        pub fn list(self, db: &crate::db::Database, params: &crate::pagination::ListParams) -> anyhow::Result<crate::pagination::Page<#new_struct_name>> {
+            if params.fields.is_empty() {
+                let (records, next_id) = self.page(db, params.after_id, params.limit)?;
+                return crate::pagination::Page::new(&records.0, next_id);
+            }
+
+            let (items, next_id) = self.page_fields(db, params.after_id, params.limit, &params.fields)?;
+
+            Ok(crate::pagination::Page::from_items(items, next_id))
+        }
+
+        /// Load the first record from the database, if there is one.
+        pub fn first(self, db: &crate::db::Database) -> anyhow::Result<Option<#new_struct_name>> {
+            let records = self.limit(1).load(db)?;
+
+            Ok(records.0.into_iter().next())
+        }
+
+        /// Count the records that match the filters, this ignores the ordering, limit
+        /// and offset.
+        pub fn count(self, db: &crate::db::Database) -> anyhow::Result<i64> {
+            let count = self.count_query.get_result::<i64>(&db.conn()?)?;
+
+            Ok(count)
         }
     }
     };
@@ -535,6 +1373,262 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
         }
 
         #airtable
+
+        #history
+
+        // Register the type with the outbox, so the worker can run its entries.
+        inventory::submit! {
+            crate::outbox::OutboxProcessor {
+                record_type: #record_type,
+                process: #new_struct_name::process_airtable_outbox_entry_boxed,
+            }
+        }
     );
-    new_struct
+    Ok(new_struct)
+}
+
+/// Wraps a dropshot endpoint so the requests it handles are counted in our metrics, by the
+/// method and path of the endpoint, with the status code it returned and how long it took.
+/// It has to come before the `#[endpoint]` attribute, that is where we get the route from.
+#[proc_macro_attribute]
+pub fn request_metrics(attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    match do_request_metrics(attr.into(), item.into()) {
+        Ok(t) => t.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn do_request_metrics(attr: TokenStream, item: TokenStream) -> Result<TokenStream, syn::Error> {
+    if !attr.is_empty() {
+        return Err(syn::Error::new_spanned(attr, "`request_metrics` takes no parameters"));
+    }
+
+    let mut item: ItemFn = syn::parse2(item)?;
+    let endpoint = item.attrs.iter().find(|a| a.path.is_ident("endpoint")).ok_or_else(|| {
+        syn::Error::new_spanned(
+            &item.sig.ident,
+            "`request_metrics` has to come before the `#[endpoint]` attribute",
+        )
+    })?;
+    let (method, path) = endpoint_route(endpoint)?;
+
+    let output = match &item.sig.output {
+        syn::ReturnType::Type(_, ty) => ty.clone(),
+        syn::ReturnType::Default => {
+            return Err(syn::Error::new_spanned(
+                &item.sig,
+                "an endpoint has to return a `Result`",
+            ));
+        }
+    };
+    // The status code of a successful request is set by the type of response, unless the
+    // endpoint builds the response itself.
+    let ok = match response_type(&output).as_deref() {
+        Some("Response") => quote!(Ok(response) => response.status().as_u16()),
+        Some("HttpResponseCreated") => quote!(Ok(_) => 201),
+        Some("HttpResponseAccepted") => quote!(Ok(_) => 202),
+        Some("HttpResponseDeleted") | Some("HttpResponseUpdatedNoContent") => quote!(Ok(_) => 204),
+        _ => quote!(Ok(_) => 200),
+    };
+
+    let block = &item.block;
+    item.block = syn::parse2(quote! {{
+        let start = std::time::Instant::now();
+        let result: #output = async move #block.await;
+        let status = match &result {
+            #ok,
+            Err(e) => e.status_code.as_u16(),
+        };
+        cio_api::metrics::record_request(#method, #path, status, start.elapsed());
+        result
+    }})?;
+
+    Ok(quote!(#item))
+}
+
+/// Returns the method and path from the `#[endpoint { method = GET, path = "/", .. }]`
+/// attribute of an endpoint.
+fn endpoint_route(endpoint: &syn::Attribute) -> Result<(String, String), syn::Error> {
+    let mut method = None;
+    let mut path = None;
+
+    let tokens: Vec<proc_macro2::TokenTree> = match endpoint.tokens.clone().into_iter().next() {
+        Some(proc_macro2::TokenTree::Group(g)) => g.stream().into_iter().collect(),
+        _ => Vec::new(),
+    };
+    for window in tokens.windows(3) {
+        if let (proc_macro2::TokenTree::Ident(key), proc_macro2::TokenTree::Punct(eq)) = (&window[0], &window[1]) {
+            if eq.as_char() != '=' {
+                continue;
+            }
+            if key == "method" {
+                method = Some(window[2].to_string());
+            } else if key == "path" {
+                path = Some(syn::parse2::<syn::LitStr>(window[2].clone().into())?.value());
+            }
+        }
+    }
+
+    match (method, path) {
+        (Some(method), Some(path)) => Ok((method, path)),
+        _ => Err(syn::Error::new_spanned(
+            endpoint,
+            "the `#[endpoint]` attribute needs a `method` and a `path`",
+        )),
+    }
+}
+
+/// Returns the name of the type of the successful response in `Result<T, HttpError>`.
+fn response_type(output: &Type) -> Option<String> {
+    let result = match output {
+        Type::Path(p) => p.path.segments.last()?,
+        _ => return None,
+    };
+    let args = match &result.arguments {
+        syn::PathArguments::AngleBracketed(a) => a,
+        _ => return None,
+    };
+    match args.args.first()? {
+        syn::GenericArgument::Type(Type::Path(p)) => Some(p.path.segments.last()?.ident.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quote::quote;
+
+    use crate::{do_db, do_request_metrics};
+
+    #[test]
+    fn test_db_invalid_params() {
+        let item = quote! {
+            pub struct NewThing {
+                pub name: String,
+                pub cio_company_id: i32,
+            }
+        };
+
+        assert!(do_db(
+            quote!(
+                new_struct_name = "Thing",
+                mirror = "none",
+                match_on = { "name" = "String" }
+            ),
+            item.clone()
+        )
+        .is_ok());
+
+        // Bad parameters are a compile error on the attribute, not a panic.
+        let err = do_db(
+            quote!(
+                new_struct_name = "Thing",
+                mirror = "dropbox",
+                match_on = { "name" = "String" }
+            ),
+            item.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown mirror `dropbox`, expected one of `airtable`, `google_sheets` or `none`"
+        );
+
+        let err = do_db(
+            quote!(new_struct_name = "Thing", match_on = { "name" = "String" }),
+            item.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`airtable_base` and `airtable_table` are required for the `airtable` mirror"
+        );
+
+        let err = do_db(
+            quote!(
+                new_struct_name = "Thing",
+                mirror = "none",
+                reconcile = true,
+                match_on = { "name" = "String" }
+            ),
+            item.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`reconcile` is only supported for the `airtable` mirror"
+        );
+
+        let err = do_db(
+            quote!(
+                new_struct_name = "Thing",
+                mirror = "google_sheets",
+                match_on = { "name" = "String" }
+            ),
+            item.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`google_sheets_spreadsheet` is required for the `google_sheets` mirror"
+        );
+
+        assert!(do_db(quote!(new_struct_name = "Thing"), item.clone()).is_err());
+        assert!(do_db(
+            quote!(
+                new_struct_name = "Thing",
+                mirror = "none",
+                match_on = { "name" = "Not A Type" }
+            ),
+            item
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_request_metrics() {
+        let item = quote! {
+            /** Get the functions. */
+            #[endpoint {
+                method = GET,
+                path = "/functions/{uuid}",
+            }]
+            async fn api_get_function(rqctx: Arc<RequestContext<Context>>) -> Result<HttpResponseAccepted<String>, HttpError> {
+                Ok(HttpResponseAccepted("ok".to_string()))
+            }
+        };
+
+        let wrapped = do_request_metrics(quote!(), item.clone()).unwrap().to_string();
+        assert!(wrapped.contains(r#"record_request ("GET" , "/functions/{uuid}" , status"#));
+        assert!(wrapped.contains("Ok (_) => 202"));
+        // The endpoint attribute stays for dropshot.
+        assert!(wrapped.contains("# [endpoint"));
+
+        let err = do_request_metrics(
+            quote!(),
+            quote!(
+                async fn api_get_function() {}
+            ),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`request_metrics` has to come before the `#[endpoint]` attribute"
+        );
+
+        let err = do_request_metrics(
+            quote!(),
+            quote! {
+                #[endpoint { method = GET }]
+                async fn api_get_function() -> Result<HttpResponseOk<String>, HttpError> {}
+            },
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the `#[endpoint]` attribute needs a `method` and a `path`"
+        );
+
+        assert!(do_request_metrics(quote!(name = "functions"), item).is_err());
+    }
 }
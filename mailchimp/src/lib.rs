@@ -23,7 +23,8 @@
 use std::{collections::HashMap, env, error, fmt, fmt::Debug, sync::Arc};
 
 use chrono::{DateTime, Utc};
-use reqwest::{header, Client, Method, RequestBuilder, StatusCode, Url};
+use reqwest::{header, Client, Method, StatusCode, Url};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware, RequestBuilder};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -38,7 +39,7 @@ pub struct MailChimp {
     redirect_uri: String,
     endpoint: String,
 
-    client: Arc<Client>,
+    client: Arc<ClientWithMiddleware>,
 }
 
 impl MailChimp {
@@ -72,7 +73,7 @@ impl MailChimp {
                     refresh_token: refresh_token.to_string(),
                     endpoint: endpoint.to_string(),
 
-                    client: Arc::new(c),
+                    client: Arc::new(ClientWithMiddleware::from(c)),
                 };
 
                 if g.token.is_empty() {
@@ -108,6 +109,15 @@ impl MailChimp {
         MailChimp::new(client_id, client_secret, redirect_uri, token, refresh_token, endpoint)
     }
 
+    /// Send the requests through `middleware`, for example to count or retry them.
+    pub fn with_middleware<M>(mut self, middleware: M) -> Self
+    where
+        M: Middleware,
+    {
+        self.client = Arc::new(ClientBuilder::new(Client::new()).with(middleware).build());
+        self
+    }
+
     fn request<P>(&self, method: Method, path: P) -> RequestBuilder
     where
         P: ToString,
@@ -158,7 +168,7 @@ impl MailChimp {
             self.refresh_token
         );
 
-        let client = reqwest::Client::new();
+        let client = &self.client;
         let req = client
             .post("https://login.mailchimp.com/oauth2/token")
             .headers(headers)
@@ -189,7 +199,7 @@ impl MailChimp {
             code
         );
 
-        let client = reqwest::Client::new();
+        let client = &self.client;
         let req = client
             .post("https://login.mailchimp.com/oauth2/token")
             .headers(headers)
@@ -215,7 +225,7 @@ impl MailChimp {
         );
 
         // Build the request.
-        let client = reqwest::Client::new();
+        let client = &self.client;
         let resp = client
             .get("https://login.mailchimp.com/oauth2/metadata")
             .headers(headers)
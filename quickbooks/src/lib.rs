@@ -24,6 +24,7 @@ use std::{env, error, fmt, sync::Arc};
 
 use chrono::{DateTime, NaiveDate, Utc};
 use reqwest::{header, Client, Method, Request, StatusCode, Url};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -44,7 +45,7 @@ pub struct QuickBooks {
     redirect_uri: String,
     company_id: String,
 
-    client: Arc<Client>,
+    client: Arc<ClientWithMiddleware>,
 }
 
 impl QuickBooks {
@@ -78,7 +79,7 @@ impl QuickBooks {
                     token: token.to_string(),
                     refresh_token: refresh_token.to_string(),
 
-                    client: Arc::new(c),
+                    client: Arc::new(ClientWithMiddleware::from(c)),
                 };
 
                 if qb.token.is_empty() || qb.refresh_token.is_empty() {
@@ -114,6 +115,15 @@ impl QuickBooks {
         QuickBooks::new(client_id, client_secret, company_id, redirect_uri, token, refresh_token)
     }
 
+    /// Send the requests through `middleware`, for example to count or retry them.
+    pub fn with_middleware<M>(mut self, middleware: M) -> Self
+    where
+        M: Middleware,
+    {
+        self.client = Arc::new(ClientBuilder::new(Client::new()).with(middleware).build());
+        self
+    }
+
     fn request<B>(&self, method: Method, path: &str, body: B, query: Option<&[(&str, &str)]>) -> Request
     where
         B: Serialize,
@@ -161,7 +171,7 @@ impl QuickBooks {
         headers.append(header::ACCEPT, header::HeaderValue::from_static("application/json"));
 
         let params = [("grant_type", "refresh_token"), ("refresh_token", &self.refresh_token)];
-        let client = reqwest::Client::new();
+        let client = &self.client;
         let resp = client
             .post("https://oauth.platform.intuit.com/oauth2/v1/tokens/bearer")
             .headers(headers)
@@ -189,7 +199,7 @@ impl QuickBooks {
             ("code", code),
             ("redirect_uri", &self.redirect_uri),
         ];
-        let client = reqwest::Client::new();
+        let client = &self.client;
         let resp = client
             .post("https://oauth.platform.intuit.com/oauth2/v1/tokens/bearer")
             .headers(headers)
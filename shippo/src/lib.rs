@@ -28,6 +28,7 @@ use std::{borrow::Cow, collections::HashMap, env, error, fmt, fmt::Debug, str::F
 
 use chrono::{offset::Utc, DateTime};
 use reqwest::{header, Client, Method, Request, StatusCode, Url};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware};
 use schemars::JsonSchema;
 use serde::{
     de::{self, Visitor},
@@ -41,7 +42,7 @@ const ENDPOINT: &str = "https://api.goshippo.com/";
 pub struct Shippo {
     token: String,
 
-    client: Arc<Client>,
+    client: Arc<ClientWithMiddleware>,
 }
 
 impl Shippo {
@@ -57,7 +58,7 @@ impl Shippo {
             Ok(c) => Self {
                 token: token.to_string(),
 
-                client: Arc::new(c),
+                client: Arc::new(ClientWithMiddleware::from(c)),
             },
             Err(e) => panic!("creating client failed: {:?}", e),
         }
@@ -73,6 +74,15 @@ impl Shippo {
         Shippo::new(token)
     }
 
+    /// Send the requests through `middleware`, for example to count or retry them.
+    pub fn with_middleware<M>(mut self, middleware: M) -> Self
+    where
+        M: Middleware,
+    {
+        self.client = Arc::new(ClientBuilder::new(Client::new()).with(middleware).build());
+        self
+    }
+
     fn request<B>(&self, method: Method, path: &str, body: B, query: Option<Vec<(String, String)>>) -> Request
     where
         B: Serialize,
@@ -406,6 +416,26 @@ impl Shippo {
         }
     }
 
+    /// Refund a shipping label that was not used.
+    /// FROM: https://goshippo.com/docs/reference#refunds-create
+    pub async fn create_refund(&self, nr: NewRefund) -> Result<Refund, APIError> {
+        // Build the request.
+        let request = self.request(Method::POST, "refunds/", nr, None);
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::CREATED => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
+        };
+
+        Ok(resp.json().await.unwrap())
+    }
+
     /// List shiping labels.
     /// FROM: https://goshippo.com/docs/reference#transactions-list
     pub async fn list_shipping_labels(&self) -> Result<Vec<Transaction>, APIError> {
@@ -1161,6 +1191,36 @@ pub struct NewTransaction {
     pub r#async: bool,
 }
 
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct NewRefund {
+    /// The object id of the transaction of the label to refund.
+    pub transaction: String,
+    #[serde(default)]
+    pub r#async: bool,
+}
+
+/// The data type for a refund.
+/// FROM: https://goshippo.com/docs/reference#refunds
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Refund {
+    /// Unique identifier of the given Refund object.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub object_id: String,
+    /// Date and time of Refund creation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object_created: Option<DateTime<Utc>>,
+    /// Indicates the status of the Refund.
+    /// "QUEUED" "PENDING" "SUCCESS" "ERROR"
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub status: String,
+    /// Object ID of the Transaction to be refunded.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub transaction: String,
+    /// Indicates whether the object has been created in test mode.
+    #[serde(default)]
+    pub test: bool,
+}
+
 #[derive(Clone, Debug, Default, JsonSchema, Serialize, Deserialize)]
 pub struct Message {
     #[serde(default, skip_serializing_if = "String::is_empty")]
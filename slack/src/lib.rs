@@ -30,6 +30,7 @@ use std::{collections::HashMap, env, sync::Arc};
 use anyhow::{bail, Result};
 use async_recursion::async_recursion;
 use reqwest::{header, Body, Client, Method, Request, StatusCode, Url};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -48,7 +49,7 @@ pub struct Slack {
     redirect_uri: String,
     workspace_id: String,
 
-    client: Arc<Client>,
+    client: Arc<ClientWithMiddleware>,
 }
 
 impl Slack {
@@ -82,7 +83,7 @@ impl Slack {
                     token: token.to_string(),
                     user_token: user_token.to_string(),
 
-                    client: Arc::new(c),
+                    client: Arc::new(ClientWithMiddleware::from(c)),
                 };
 
                 if s.token.is_empty() || s.user_token.is_empty() {
@@ -116,6 +117,15 @@ impl Slack {
         Slack::new(client_id, client_secret, workspace_id, redirect_uri, token, user_token)
     }
 
+    /// Send the requests through `middleware`, for example to count or retry them.
+    pub fn with_middleware<M>(mut self, middleware: M) -> Self
+    where
+        M: Middleware,
+    {
+        self.client = Arc::new(ClientBuilder::new(Client::new()).with(middleware).build());
+        self
+    }
+
     fn request<B>(
         &self,
         token: &str,
@@ -181,7 +191,7 @@ impl Slack {
             ("code", code.to_string()),
             ("redirect_uri", self.redirect_uri.to_string()),
         ];
-        let client = reqwest::Client::new();
+        let client = &self.client;
         let resp = client
             .post("https://slack.com/api/oauth.v2.access")
             .basic_auth(&self.client_id, Some(&self.client_secret))
@@ -26,6 +26,7 @@ use std::{env, error, fmt, sync::Arc};
 
 use chrono::{offset::Utc, DateTime};
 use reqwest::{header, Client, Method, Request, StatusCode, Url};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware};
 use serde::{Deserialize, Serialize};
 
 /// Endpoint for the Tailscale API.
@@ -36,7 +37,7 @@ pub struct Tailscale {
     key: String,
     domain: String,
 
-    client: Arc<Client>,
+    client: Arc<ClientWithMiddleware>,
 }
 
 impl Tailscale {
@@ -54,7 +55,7 @@ impl Tailscale {
                 key: key.to_string(),
                 domain: domain.to_string(),
 
-                client: Arc::new(c),
+                client: Arc::new(ClientWithMiddleware::from(c)),
             },
             Err(e) => panic!("creating client failed: {:?}", e),
         }
@@ -71,6 +72,15 @@ impl Tailscale {
         Tailscale::new(key, domain)
     }
 
+    /// Send the requests through `middleware`, for example to count or retry them.
+    pub fn with_middleware<M>(mut self, middleware: M) -> Self
+    where
+        M: Middleware,
+    {
+        self.client = Arc::new(ClientBuilder::new(Client::new()).with(middleware).build());
+        self
+    }
+
     fn request<B>(&self, method: Method, path: &str, body: B, query: Option<Vec<(&str, String)>>) -> Request
     where
         B: Serialize,
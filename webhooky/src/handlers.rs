@@ -1,28 +1,36 @@
 use std::{collections::HashMap, ffi::OsStr, str::FromStr, sync::Arc};
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 use chrono::{TimeZone, Utc};
 use chrono_humanize::HumanTime;
 use cio_api::{
     analytics::NewPageView,
-    applicants::{get_docusign_template_id, Applicant},
+    applicants::{get_docusign_template_id, Applicant, Applicants},
     asset_inventory::AssetItem,
     certs::Certificate,
     companies::Company,
-    configs::User,
+    configs::{User, Users},
+    db::Database,
     journal_clubs::JournalClubMeeting,
     mailing_list::MailingListSubscriber,
+    metrics,
     rack_line::RackLineSubscriber,
-    rfds::RFD,
+    rfd_authors::RFDAuthor,
+    rfd_subscriptions::{NotifyBy, RFDSubscription, SubscriptionCommand, SUBSCRIPTION_USAGE},
+    rfds::{search_rfds, RFD},
     schema::{applicants, inbound_shipments, journal_club_meetings, outbound_shipments, rfds},
     shipments::{InboundShipment, NewInboundShipment, OutboundShipment, OutboundShipments},
     swag_inventory::SwagInventoryItem,
     swag_store::Order,
     utils::{decode_base64, merge_json},
+    webhook_events::WebhookEvent,
+    webhook_secrets::check_webhook_company,
 };
 use diesel::{BoolExpressionMethods, ExpressionMethods, PgTextExpressionMethods, QueryDsl, RunQueryDsl};
-use dropshot::{Path, RequestContext, TypedBody, UntypedBody};
+use dropshot::{Path, RequestContext, TypedBody};
 use google_drive::traits::{DriveOps, FileOps};
+use http::{header::CONTENT_TYPE, Response};
+use hyper014::Body;
 use log::{info, warn};
 use mailchimp_api::Webhook as MailChimpWebhook;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
@@ -50,17 +58,11 @@ pub async fn handle_products_sold_count(rqctx: Arc<RequestContext<Context>>) ->
     // TODO: change this one day to be the number of racks sold.
     // For now, use it as number of applications that need to be triaged.
     // Get the applicants that need to be triaged.
-    let applicants = applicants::dsl::applicants
-        .filter(
-            applicants::dsl::cio_company_id
-                .eq(company.id)
-                .and(applicants::dsl::status.eq(cio_api::applicant_status::Status::NeedsToBeTriaged.to_string())),
-        )
-        .load::<Applicant>(&api_context.db.conn())?;
+    let count = Applicants::query(company.id)
+        .filter(applicants::dsl::status.eq(cio_api::applicant_status::Status::NeedsToBeTriaged.to_string()))
+        .count(&api_context.db)?;
 
-    Ok(CounterResponse {
-        count: applicants.len() as i32,
-    })
+    Ok(CounterResponse { count: count as i32 })
 }
 
 pub async fn handle_rfd_update_by_number(
@@ -78,6 +80,7 @@ pub async fn handle_rfd_update_by_number(
     let oxide = Company::get_from_db(db, "Oxide".to_string()).unwrap();
 
     let github = oxide.authenticate_github()?;
+    let users: Vec<User> = Users::get_from_db(db, oxide.id)?.into();
 
     let result = RFD::get_from_db(db, num);
     if result.is_none() {
@@ -87,19 +90,21 @@ pub async fn handle_rfd_update_by_number(
     let mut rfd = result.unwrap();
 
     // Update the RFD.
-    if let Err(e) = rfd.expand(&github, &oxide).await {
+    if let Err(e) = rfd.expand(db, &github, &users, &oxide, true).await {
         if (e.to_string()).contains("No commit found for the ref") {
             // Likely it was merged into master, let's try that.
             // And likely something messed up, so let's try again.
             // And no worries if it's not merged into master it will just fail again.
             // And won't save it back to the database.
             rfd.state = "published".to_string();
-            rfd.expand(&github, &oxide).await?;
+            rfd.expand(db, &github, &users, &oxide, true).await?;
         }
     }
     info!("updated  RFD {}", rfd.number_string);
 
-    rfd.convert_and_upload_pdf(db, &github, &oxide).await?;
+    rfd.update_sections(db)?;
+
+    rfd.convert_and_upload_pdf(db, &github, &oxide, true).await?;
     info!("updated pdf `{}` for RFD {}", rfd.get_pdf_filename(), rfd.number_string);
 
     // Save the rfd back to our database.
@@ -131,19 +136,26 @@ pub async fn handle_github_rate_limit(rqctx: Arc<RequestContext<Context>>) -> Re
     })
 }
 
-pub async fn handle_slack_commands(
-    rqctx: Arc<RequestContext<Context>>,
-    body_param: UntypedBody,
-) -> Result<serde_json::Value> {
+pub async fn handle_metrics(rqctx: Arc<RequestContext<Context>>) -> Result<Response<Body>> {
     let api_context = rqctx.context();
+
+    let body = metrics::render(&api_context.db)?;
+
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, metrics::TEXT_FORMAT)
+        .body(body.into())?)
+}
+
+pub async fn handle_slack_commands(api_context: &Context, company_id: i32, body: &[u8]) -> Result<serde_json::Value> {
     let db = &api_context.db;
 
     // We should have a string, which we will then parse into our args.
     // Parse the request body as a Slack BotCommand.
-    let bot_command: BotCommand = serde_urlencoded::from_bytes(body_param.as_bytes())?;
+    let bot_command: BotCommand = serde_urlencoded::from_bytes(body)?;
 
     // Get the company from the Slack team id.
     let company = Company::get_from_slack_team_id(db, &bot_command.team_id)?;
+    check_webhook_company(company_id, company.id)?;
 
     // Get the command type.
     let command = SlackCommand::from_str(&bot_command.command).unwrap();
@@ -180,10 +192,14 @@ pub async fn handle_slack_commands(
     let response = match command {
         SlackCommand::RFD => {
             let num = text.parse::<i32>().unwrap_or(0);
-            if num > 0 {
+            if let Some(response) = handle_rfd_subscription_command(db, &company, &bot_command)? {
+                response
+            } else if let Some(response) = handle_rfd_authored_command(db, &company, &bot_command)? {
+                response
+            } else if num > 0 {
                 if let Ok(rfd) = rfds::dsl::rfds
                     .filter(rfds::dsl::cio_company_id.eq(company.id).and(rfds::dsl::number.eq(num)))
-                    .first::<RFD>(&db.conn())
+                    .first::<RFD>(&db.conn()?)
                 {
                     let r: FormattedMessage = rfd.into();
                     json!(r)
@@ -193,7 +209,7 @@ pub async fn handle_slack_commands(
                             .eq(company.id)
                             .and(rfds::dsl::name.ilike(format!("%{}%", text))),
                     )
-                    .first::<RFD>(&db.conn())
+                    .first::<RFD>(&db.conn()?)
                 {
                     let r: FormattedMessage = rfd.into();
                     json!(r)
@@ -212,10 +228,16 @@ pub async fn handle_slack_commands(
                         .eq(company.id)
                         .and(rfds::dsl::name.ilike(format!("%{}%", text))),
                 )
-                .first::<RFD>(&db.conn())
+                .first::<RFD>(&db.conn()?)
             {
                 let r: FormattedMessage = rfd.into();
                 json!(r)
+            } else if let Some(result) = search_rfds(db, company.id, &text, 1)?.into_iter().next() {
+                // Link to the section that matched best.
+                json!(MessageResponse {
+                    response_type: MessageResponseType::InChannel,
+                    text: result.slack_text(),
+                })
             } else {
                 json!(MessageResponse {
                     response_type: MessageResponseType::InChannel,
@@ -261,21 +283,23 @@ pub async fn handle_slack_commands(
                 status = cio_api::applicant_status::Status::Declined;
             }
 
-            // Get the applicants that need to be triaged.
-            let applicants = applicants::dsl::applicants
-                .filter(
-                    applicants::dsl::cio_company_id
-                        .eq(company.id)
-                        .and(applicants::dsl::status.eq(status.to_string())),
-                )
-                .load::<Applicant>(&db.conn())?;
-
-            if applicants.len() > 10 {
+            // Get the applicants with the status, the newest first.
+            let count = Applicants::query(company.id)
+                .filter(applicants::dsl::status.eq(status.to_string()))
+                .count(db)?;
+            let applicants: Vec<Applicant> = Applicants::query(company.id)
+                .filter(applicants::dsl::status.eq(status.to_string()))
+                .order_by(applicants::dsl::submitted_time.desc())
+                .limit(10)
+                .load(db)?
+                .into();
+
+            if count > 10 {
                 json!(MessageResponse {
                     response_type: MessageResponseType::InChannel,
                     text: format!(
                         "Found `{}` applicants with status `{}`. Sorry, that's too many to return at once.",
-                        applicants.len(),
+                        count,
                         status.to_string()
                     ),
                 })
@@ -316,7 +340,7 @@ pub async fn handle_slack_commands(
                         .eq(company.id)
                         .and(applicants::dsl::name.ilike(format!("%{}%", text))),
                 )
-                .first::<Applicant>(&db.conn())
+                .first::<Applicant>(&db.conn()?)
             {
                 let r: FormattedMessage = applicant.into();
                 json!(r)
@@ -351,7 +375,7 @@ pub async fn handle_slack_commands(
                                         .ne(cio_api::shipment_status::Status::PickedUp.to_string()),
                                 ),
                         )
-                        .load::<OutboundShipment>(&db.conn())?
+                        .load::<OutboundShipment>(&db.conn()?)?
                 } else {
                     Default::default()
                 };
@@ -364,7 +388,7 @@ pub async fn handle_slack_commands(
                                 .and(inbound_shipments::dsl::tracking_status.ne("DELIVERED".to_string()))
                                 .and(inbound_shipments::dsl::delivered_time.is_null()),
                         )
-                        .load::<InboundShipment>(&db.conn())?
+                        .load::<InboundShipment>(&db.conn()?)?
                 } else {
                     Default::default()
                 };
@@ -449,7 +473,7 @@ pub async fn handle_slack_commands(
                         .eq(company.id)
                         .and(journal_club_meetings::dsl::state.eq(state.to_string())),
                 )
-                .load::<JournalClubMeeting>(&db.conn())?;
+                .load::<JournalClubMeeting>(&db.conn()?)?;
 
             let mut msg: serde_json::Value = Default::default();
             for (i, m) in meetings.into_iter().enumerate() {
@@ -477,7 +501,7 @@ pub async fn handle_slack_commands(
                         .eq(company.id)
                         .and(journal_club_meetings::dsl::title.ilike(format!("%{}%", text))),
                 )
-                .first::<JournalClubMeeting>(&db.conn())
+                .first::<JournalClubMeeting>(&db.conn()?)
             {
                 let r: FormattedMessage = meeting.into();
                 json!(r)
@@ -497,11 +521,129 @@ pub async fn handle_slack_commands(
     Ok(response)
 }
 
+/// Handle `/rfd follow`, `/rfd unfollow` and `/rfd following`.
+/// Returns `None` if the command is not one of them.
+fn handle_rfd_subscription_command(
+    db: &Database,
+    company: &Company,
+    bot_command: &BotCommand,
+) -> Result<Option<serde_json::Value>> {
+    let command = match SubscriptionCommand::parse(&bot_command.text) {
+        Ok(Some(command)) => command,
+        Ok(None) => return Ok(None),
+        Err(e) => {
+            return Ok(Some(json!(MessageResponse {
+                response_type: MessageResponseType::Ephemeral,
+                text: format!("Sorry <@{}> :scream: {}", bot_command.user_id, e),
+            })))
+        }
+    };
+
+    let text = match command {
+        SubscriptionCommand::Follow { target, notify_by } => {
+            // Find the email of the Slack user, from the user with the same username.
+            let mut email = String::new();
+            if notify_by == NotifyBy::Email {
+                match User::get_from_db(db, company.id, bot_command.user_name.to_string()) {
+                    Some(user) if !user.email.is_empty() => email = user.email,
+                    _ => {
+                        return Ok(Some(json!(MessageResponse {
+                            response_type: MessageResponseType::Ephemeral,
+                            text: format!(
+                                "Sorry <@{}> :scream: I could not find your email, use `/rfd follow {} slack` instead",
+                                bot_command.user_id,
+                                bot_command.text.split_whitespace().nth(1).unwrap_or_default()
+                            ),
+                        })));
+                    }
+                }
+            }
+
+            RFDSubscription::follow(db, company, &bot_command.user_id, &email, &target, notify_by)?;
+            format!(
+                "<@{}> you are following {}, I'll let you know by {} when it changes",
+                bot_command.user_id, target, notify_by
+            )
+        }
+        SubscriptionCommand::Unfollow(target) => {
+            if RFDSubscription::unfollow(db, company, &bot_command.user_id, &target)? {
+                format!("<@{}> you are not following {} anymore", bot_command.user_id, target)
+            } else {
+                format!("<@{}> you were not following {}", bot_command.user_id, target)
+            }
+        }
+        SubscriptionCommand::Following => {
+            let subscriptions = RFDSubscription::get_for_slack_user(db, company, &bot_command.user_id)?;
+            if subscriptions.is_empty() {
+                format!(
+                    "<@{}> you are not following any RFDs, try {}",
+                    bot_command.user_id, SUBSCRIPTION_USAGE
+                )
+            } else {
+                let mut text = format!("<@{}> you are following:", bot_command.user_id);
+                for subscription in subscriptions {
+                    text += &format!("\n• {} by {}", subscription.target(), subscription.notify_by);
+                }
+                text
+            }
+        }
+    };
+
+    Ok(Some(json!(MessageResponse {
+        response_type: MessageResponseType::Ephemeral,
+        text,
+    })))
+}
+
+/// Handle `/rfd authored [username]`, the RFDs a user is an author of.
+/// Without a username, it is the RFDs of the user who ran the command.
+/// Returns `None` if the command is not `authored`.
+fn handle_rfd_authored_command(
+    db: &Database,
+    company: &Company,
+    bot_command: &BotCommand,
+) -> Result<Option<serde_json::Value>> {
+    let mut words = bot_command.text.split_whitespace();
+    if words.next().map(|w| w.to_lowercase()) != Some("authored".to_string()) {
+        return Ok(None);
+    }
+    let username = words
+        .next()
+        .unwrap_or(&bot_command.user_name)
+        .trim_start_matches('@')
+        .to_string();
+
+    let text = match User::get_from_db(db, company.id, username.to_string()) {
+        Some(user) => {
+            let rfds = RFDAuthor::get_rfds_for_user(db, user.id)?;
+            if rfds.is_empty() {
+                format!("{} is not an author of any RFDs", user.full_name())
+            } else {
+                let mut text = format!("{} is an author of:", user.full_name());
+                for rfd in rfds {
+                    text += &format!("\n• <{}|{}> (_*{}*_)", rfd.short_link, rfd.name, rfd.state);
+                }
+                text
+            }
+        }
+        None => format!(
+            "Sorry <@{}> :scream: I could not find a user `{}`",
+            bot_command.user_id, username
+        ),
+    };
+
+    Ok(Some(json!(MessageResponse {
+        response_type: MessageResponseType::InChannel,
+        text,
+    })))
+}
+
 pub async fn handle_slack_interactive(
-    rqctx: Arc<RequestContext<Context>>,
-    body_param: UntypedBody,
+    api_context: &Context,
+    company_id: i32,
+    body: &[u8],
 ) -> Result<InteractiveResponse> {
-    let s = String::from_utf8(body_param.as_bytes().to_vec())?;
+    let s = String::from_utf8(body.to_vec())?;
 
     // Decode the URL encoded struct.
     let decoded = urlencoding::decode(s.trim_start_matches("payload="))?;
@@ -515,13 +657,13 @@ pub async fn handle_slack_interactive(
         }
     };
 
-    let ctx = rqctx.context();
-    let db = &ctx.db;
+    let db = &api_context.db;
 
     let mut interactive_response: InteractiveResponse = Default::default();
 
     // Get the company from the Slack team id.
     let company = Company::get_from_slack_team_id(db, &payload.team.id)?;
+    check_webhook_company(company_id, company.id)?;
 
     let slack = company.authenticate_slack(db)?;
 
@@ -646,8 +788,8 @@ pub async fn handle_slack_interactive(
     for action in payload.actions {
         // Trigger the action if it's a function.
         if action.action_id == "function" {
-            // Run the command in the background so we don't have to wait for it.
-            if let Err(e) = crate::handlers_cron::handle_reexec_cmd(ctx, &action.value, true).await {
+            // Run it again in the background so we don't have to wait for it.
+            if let Err(e) = crate::handlers_cron::retry_function(ctx, &action.value).await {
                 sentry_anyhow::capture_anyhow(&anyhow::anyhow!("{:?}", e));
             }
         }
@@ -980,14 +1122,15 @@ pub async fn handle_airtable_shipments_outbound_schedule_pickup(
 }
 
 pub async fn handle_emails_incoming_sendgrid_parse(
-    rqctx: Arc<RequestContext<Context>>,
-    body_param: UntypedBody,
+    api_context: &Context,
+    company_id: i32,
+    headers: &http::HeaderMap,
+    body: &[u8],
 ) -> Result<()> {
     // Parse the body as bytes.
-    let mut b = body_param.as_bytes();
+    let mut b = body;
 
-    // Get the headers and parse the form data.
-    let headers = rqctx.request.lock().await.headers().clone();
+    // Parse the form data with the headers.
 
     let content_type = headers.get("content-type").unwrap();
     let content_length = headers.get("content-length").unwrap();
@@ -1058,7 +1201,7 @@ pub async fn handle_emails_incoming_sendgrid_parse(
     }
 
     i.notes = format!("Parsed email from {}:\n{}", from, i.notes);
-    i.cio_company_id = 1;
+    i.cio_company_id = webhook_company(&api_context.db, company_id)?.id;
 
     if i.carrier.is_empty() {
         bail!(
@@ -1070,7 +1213,6 @@ pub async fn handle_emails_incoming_sendgrid_parse(
     }
 
     // Add the shipment to our database.
-    let api_context = rqctx.context();
     i.upsert(&api_context.db).await?;
 
     Ok(())
@@ -1301,13 +1443,8 @@ pub async fn handle_easypost_tracking_update(
     Ok(())
 }
 
-pub async fn handle_shippo_tracking_update(
-    rqctx: Arc<RequestContext<Context>>,
-    body_param: TypedBody<serde_json::Value>,
-) -> Result<()> {
-    let api_context = rqctx.context();
-
-    let event = body_param.into_inner();
+pub async fn handle_shippo_tracking_update(api_context: &Context, company_id: i32, body: &[u8]) -> Result<()> {
+    let event: serde_json::Value = serde_json::from_slice(body)?;
     let body: ShippoTrackingUpdateEvent = match serde_json::from_str(&event.to_string()) {
         Ok(b) => b,
         Err(e) => bail!("decoding event body for shippo `{}` failed: {}", event.to_string(), e),
@@ -1326,6 +1463,7 @@ pub async fn handle_shippo_tracking_update(
         InboundShipment::get_from_db(&api_context.db, ts.carrier.to_string(), ts.tracking_number.to_string())
     {
         let company = shipment.company(&api_context.db)?;
+        check_webhook_company(company_id, company.id)?;
 
         shipment.expand(&api_context.db, &company).await?;
     }
@@ -1334,6 +1472,8 @@ pub async fn handle_shippo_tracking_update(
     if let Some(mut shipment) =
         OutboundShipment::get_from_db(&api_context.db, ts.carrier.to_string(), ts.tracking_number.to_string())
     {
+        check_webhook_company(company_id, shipment.cio_company_id)?;
+
         // Update the shipment in shippo.
         // TODO: we likely don't need the extra request here, but it makes the code more DRY.
         // Clean this up eventually.
@@ -1345,12 +1485,8 @@ pub async fn handle_shippo_tracking_update(
     Ok(())
 }
 
-pub async fn handle_checkr_background_update(
-    rqctx: Arc<RequestContext<Context>>,
-    body_param: TypedBody<checkr::WebhookEvent>,
-) -> Result<()> {
-    let api_context = rqctx.context();
-    let event = body_param.into_inner();
+pub async fn handle_checkr_background_update(api_context: &Context, company_id: i32, body: &[u8]) -> Result<()> {
+    let event: checkr::WebhookEvent = serde_json::from_slice(body)?;
 
     // Run the update of the background checks.
     // If we have a candidate ID let's get them from checkr.
@@ -1363,13 +1499,12 @@ pub async fn handle_checkr_background_update(
         return Ok(());
     }
 
-    // TODO: change this to the real company name.
-    let oxide = Company::get_from_db(&api_context.db, "Oxide".to_string()).unwrap();
+    let company = webhook_company(&api_context.db, company_id)?;
 
-    let checkr_auth = oxide.authenticate_checkr();
+    let checkr_auth = company.authenticate_checkr();
     if checkr_auth.is_none() {
         // Return early.
-        bail!("this company {:?} does not have a checkr api key: {:?}", oxide, event);
+        bail!("this company {:?} does not have a checkr api key: {:?}", company, event);
     }
 
     let checkr = checkr_auth.unwrap();
@@ -1382,14 +1517,13 @@ pub async fn handle_checkr_background_update(
                 .or(applicants::dsl::name.eq(format!("{} {}", candidate.first_name, candidate.last_name))),
         )
         .filter(applicants::dsl::status.eq(cio_api::applicant_status::Status::Onboarding.to_string()))
-        .first::<Applicant>(&api_context.db.conn());
+        .filter(applicants::dsl::cio_company_id.eq(company.id))
+        .first::<Applicant>(&api_context.db.conn()?);
     if result.is_ok() {
         let mut applicant = result?;
         // Keep the fields from Airtable we need just in case they changed.
         applicant.keep_fields_from_airtable(&api_context.db).await;
 
-        let company = applicant.company(&api_context.db)?;
-
         let mut send_notification = false;
 
         // Set the status for the report.
@@ -1415,23 +1549,20 @@ pub async fn handle_checkr_background_update(
     Ok(())
 }
 
-pub async fn handle_docusign_envelope_update(
-    rqctx: Arc<RequestContext<Context>>,
-    body_param: TypedBody<docusign::Envelope>,
-) -> Result<()> {
-    let api_context = rqctx.context();
+pub async fn handle_docusign_envelope_update(api_context: &Context, company_id: i32, body: &[u8]) -> Result<()> {
     let db = &api_context.db;
 
-    let event = body_param.into_inner();
+    let event: docusign::Envelope = serde_json::from_slice(body)?;
 
     // We need to get the applicant for the envelope.
     // Check their offer first.
     let result = applicants::dsl::applicants
         .filter(applicants::dsl::docusign_envelope_id.eq(event.envelope_id.to_string()))
-        .first::<Applicant>(&db.conn());
+        .first::<Applicant>(&db.conn()?);
     match result {
         Ok(mut applicant) => {
             let company = applicant.company(db)?;
+            check_webhook_company(company_id, company.id)?;
 
             // Create our docusign client.
             let dsa = company.authenticate_docusign(db).await;
@@ -1457,10 +1588,11 @@ pub async fn handle_docusign_envelope_update(
     // Now try to match on PIIA.
     let result = applicants::dsl::applicants
         .filter(applicants::dsl::docusign_piia_envelope_id.eq(event.envelope_id.to_string()))
-        .first::<Applicant>(&db.conn());
+        .first::<Applicant>(&db.conn()?);
     match result {
         Ok(mut applicant) => {
             let company = applicant.company(db)?;
+            check_webhook_company(company_id, company.id)?;
 
             // Create our docusign client.
             let dsa = company.authenticate_docusign(db).await;
@@ -1504,12 +1636,11 @@ pub async fn handle_analytics_page_view(
     Ok(())
 }
 
-pub async fn handle_mailchimp_mailing_list(rqctx: Arc<RequestContext<Context>>, body_param: UntypedBody) -> Result<()> {
-    let api_context = rqctx.context();
+pub async fn handle_mailchimp_mailing_list(api_context: &Context, company_id: i32, body: &[u8]) -> Result<()> {
     let db = &api_context.db;
 
     // We should have a string, which we will then parse into our args.
-    let event_string = body_param.as_str().unwrap().to_string();
+    let event_string = String::from_utf8(body.to_vec())?;
     let qs_non_strict = QSConfig::new(10, false);
 
     let event: MailChimpWebhook = qs_non_strict.deserialize_str(&event_string)?;
@@ -1521,6 +1652,7 @@ pub async fn handle_mailchimp_mailing_list(rqctx: Arc<RequestContext<Context>>,
 
     // Parse the webhook as a new mailing list subscriber.
     let new_subscriber = cio_api::mailing_list::as_mailing_list_subscriber(event, db)?;
+    check_webhook_company(company_id, new_subscriber.cio_company_id)?;
 
     let existing = MailingListSubscriber::get_from_db(db, new_subscriber.email.to_string());
     if existing.is_none() {
@@ -1541,12 +1673,11 @@ pub async fn handle_mailchimp_mailing_list(rqctx: Arc<RequestContext<Context>>,
     Ok(())
 }
 
-pub async fn handle_mailchimp_rack_line(rqctx: Arc<RequestContext<Context>>, body_param: UntypedBody) -> Result<()> {
-    let api_context = rqctx.context();
+pub async fn handle_mailchimp_rack_line(api_context: &Context, company_id: i32, body: &[u8]) -> Result<()> {
     let db = &api_context.db;
 
     // We should have a string, which we will then parse into our args.
-    let event_string = body_param.as_str().unwrap().to_string();
+    let event_string = String::from_utf8(body.to_vec())?;
     let qs_non_strict = QSConfig::new(10, false);
 
     let event: MailChimpWebhook = qs_non_strict.deserialize_str(&event_string)?;
@@ -1558,6 +1689,7 @@ pub async fn handle_mailchimp_rack_line(rqctx: Arc<RequestContext<Context>>, bod
 
     // Parse the webhook as a new rack line subscriber.
     let new_subscriber = cio_api::rack_line::as_rack_line_subscriber(event, db);
+    check_webhook_company(company_id, new_subscriber.cio_company_id)?;
 
     let existing = RackLineSubscriber::get_from_db(db, new_subscriber.email.to_string());
     if existing.is_none() {
@@ -1578,17 +1710,24 @@ pub async fn handle_mailchimp_rack_line(rqctx: Arc<RequestContext<Context>>, bod
     Ok(())
 }
 
-pub async fn handle_shipbob(
-    rqctx: Arc<RequestContext<Context>>,
-    body_param: TypedBody<serde_json::Value>,
-) -> Result<()> {
+/// Returns the company a webhook is for, the one whose secret it was signed with. Checkr and
+/// SendGrid don't say which company they are for, we used Oxide for them before we checked
+/// webhooks so we still do for the ones we took without a secret.
+fn webhook_company(db: &Database, company_id: i32) -> Result<Company> {
+    if company_id == 0 {
+        return Company::get_from_db(db, "Oxide".to_string()).ok_or_else(|| anyhow!("company `Oxide` not found"));
+    }
+
+    Company::get_by_id(db, company_id)
+}
+
+pub async fn handle_shipbob(headers: &http::HeaderMap, body: &[u8]) -> Result<()> {
     // We need to get the webhook type from the header.
-    let headers = rqctx.request.lock().await.headers().clone();
 
     let shipbob_topic = headers.get("shipbob-topic").unwrap().to_str()?;
     let shipbob_subscription_id = headers.get("shipbob-subscription-id").unwrap().to_str()?;
 
-    let event = body_param.into_inner();
+    let event: serde_json::Value = serde_json::from_slice(body)?;
 
     sentry::capture_message(
         &format!(
@@ -1762,3 +1901,68 @@ fn from_json_value_to_string(t: &serde_json::Map<String, serde_json::Value>) ->
         Err(_) => String::new(),
     }
 }
+
+/// Handle a webhook we saved, by the endpoint it came in on. This is how webhooks are handled
+/// both when they come in and when they are replayed. The result is saved on the event.
+pub async fn handle_webhook_event(api_context: &Context, event: &mut WebhookEvent) -> Result<serde_json::Value> {
+    let headers = event.headers();
+    let body = &event.body;
+
+    // The company of the secret the webhook was signed with.
+    let company_id = event.cio_company_id;
+
+    let result = match event.endpoint.as_str() {
+        "/github" => crate::handlers_github::handle_github(api_context, company_id, &headers, body)
+            .await
+            .map(|_| serde_json::Value::Null),
+        "/slack/commands" => handle_slack_commands(api_context, company_id, body).await,
+        "/slack/interactive" => handle_slack_interactive(api_context, company_id, body)
+            .await
+            .map(|_| serde_json::Value::Null),
+        "/docusign/envelope/update" => handle_docusign_envelope_update(api_context, company_id, body)
+            .await
+            .map(|_| serde_json::Value::Null),
+        "/checkr/background/update" => handle_checkr_background_update(api_context, company_id, body)
+            .await
+            .map(|_| serde_json::Value::Null),
+        "/shippo/tracking/update" => handle_shippo_tracking_update(api_context, company_id, body)
+            .await
+            .map(|_| serde_json::Value::Null),
+        "/shipbob" => handle_shipbob(&headers, body).await.map(|_| serde_json::Value::Null),
+        "/mailchimp/mailing_list" => handle_mailchimp_mailing_list(api_context, company_id, body)
+            .await
+            .map(|_| serde_json::Value::Null),
+        "/mailchimp/rack_line" => handle_mailchimp_rack_line(api_context, company_id, body)
+            .await
+            .map(|_| serde_json::Value::Null),
+        "/emails/incoming/sendgrid/parse" => {
+            handle_emails_incoming_sendgrid_parse(api_context, company_id, &headers, body)
+                .await
+                .map(|_| serde_json::Value::Null)
+        }
+        _ => Err(anyhow!("no handler for webhooks to `{}`", event.endpoint)),
+    };
+
+    event.finish(&api_context.db, &result)?;
+
+    result
+}
+
+/// Handle a webhook we saved again, through the same handler as when it came in.
+pub async fn replay_webhook_event(api_context: &Context, id: i32) -> Result<WebhookEvent> {
+    let mut event = WebhookEvent::get_by_id(&api_context.db, id)?;
+    info!(
+        "replaying {} webhook event `{}` to `{}`, attempt {}",
+        event.provider,
+        event.id,
+        event.endpoint,
+        event.attempts + 1
+    );
+
+    // The error is saved on the event, so we return it either way.
+    if let Err(e) = handle_webhook_event(api_context, &mut event).await {
+        warn!("replaying webhook event `{}` failed: {}", event.id, e);
+    }
+
+    Ok(event)
+}
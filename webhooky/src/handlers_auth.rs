@@ -5,6 +5,7 @@ use chrono::Utc;
 use cio_api::{
     api_tokens::{APIToken, NewAPIToken},
     companies::{Company, Companys},
+    metrics::ApiCallMetrics,
     schema::api_tokens,
 };
 use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl, RunQueryDsl};
@@ -166,7 +167,7 @@ pub async fn handle_auth_mailchimp_callback(
     let event = query_args.into_inner();
 
     // Initialize the MailChimp client.
-    let mut g = MailChimp::new_from_env("", "", "");
+    let mut g = MailChimp::new_from_env("", "", "").with_middleware(ApiCallMetrics::new("mailchimp"));
 
     // Let's get the token from the code.
     let t = g.get_access_token(&event.code).await?;
@@ -401,7 +402,7 @@ pub async fn handle_auth_slack_callback(
     let event = query_args.into_inner();
 
     // Initialize the Slack client.
-    let mut s = Slack::new_from_env("", "", "");
+    let mut s = Slack::new_from_env("", "", "").with_middleware(ApiCallMetrics::new("slack"));
 
     // Let's get the token from the code.
     let t = s.get_access_token(&event.code).await?;
@@ -455,11 +456,11 @@ pub async fn handle_auth_slack_callback(
                 .and(api_tokens::dsl::auth_company_id.eq(company.id))
                 .and(api_tokens::dsl::token_type.eq(token.token_type.to_string())),
         )
-        .first::<APIToken>(&api_context.db.conn())
+        .first::<APIToken>(&api_context.db.conn()?)
     {
         diesel::update(&existing)
             .set(token)
-            .get_result::<APIToken>(&api_context.db.conn())?
+            .get_result::<APIToken>(&api_context.db.conn()?)?
     } else {
         token.create_in_db(&api_context.db)?
     };
@@ -497,11 +498,11 @@ pub async fn handle_auth_slack_callback(
                     .and(api_tokens::dsl::auth_company_id.eq(company.id))
                     .and(api_tokens::dsl::token_type.eq(user_token.token_type.to_string())),
             )
-            .first::<APIToken>(&api_context.db.conn())
+            .first::<APIToken>(&api_context.db.conn()?)
         {
             diesel::update(&existing)
                 .set(user_token)
-                .get_result::<APIToken>(&api_context.db.conn())?
+                .get_result::<APIToken>(&api_context.db.conn()?)?
         } else {
             user_token.create_in_db(&api_context.db)?
         };
@@ -519,7 +520,7 @@ pub async fn handle_auth_quickbooks_callback(
     let event = query_args.into_inner();
 
     // Initialize the QuickBooks client.
-    let mut qb = QuickBooks::new_from_env("", "", "");
+    let mut qb = QuickBooks::new_from_env("", "", "").with_middleware(ApiCallMetrics::new("quickbooks"));
 
     // Let's get the token from the code.
     let t = qb.get_access_token(&event.code).await?;
@@ -573,7 +574,7 @@ pub async fn handle_auth_docusign_callback(
     let event = query_args.into_inner();
 
     // Initialize the DocuSign client.
-    let mut d = DocuSign::new_from_env("", "", "", "");
+    let mut d = DocuSign::new_from_env("", "", "", "").with_middleware(ApiCallMetrics::new("docusign"));
     // Let's get the token from the code.
     let t = d.get_access_token(&event.code).await?;
 
@@ -1,14 +1,48 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
-use anyhow::{bail, Result};
+use anyhow::Result;
 use chrono::Utc;
 use chrono_humanize::HumanTime;
-use cio_api::{functions::Function, schema::functions};
+use cio_api::{
+    db::Database,
+    error::CioError,
+    functions::{Function, FunctionLog, FunctionStep},
+    job_schedules::{get_job_schedule_statuses, JobScheduleStatus},
+    outbox::AirtableOutboxEntry,
+    sagas::{is_saga, retry_saga},
+    schema::functions,
+    webhook_events::{WebhookEvent, WebhookEventStatus},
+    webhook_secrets::WebhookProvider,
+};
 use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
-use dropshot::{Path, RequestContext};
-use log::info;
+use dropshot::{Path, Query, RequestContext};
+use http::{
+    header::{CACHE_CONTROL, CONTENT_TYPE},
+    Response, StatusCode,
+};
+use hyper014::{body::Sender, Body};
+use log::{info, warn};
 
-use crate::server::{Context, FunctionPathParams};
+use crate::server::{
+    Context, FunctionPathParams, FunctionsQuery, OutboxEntryPathParams, WebhookEventPathParams, WebhookEventsQuery,
+};
+
+pub async fn handle_get_functions(
+    rqctx: Arc<RequestContext<Context>>,
+    query_args: Query<FunctionsQuery>,
+) -> Result<Vec<Function>> {
+    let query = query_args.into_inner();
+    let api_context = rqctx.context();
+
+    let mut functions =
+        Function::get_by_name_and_status(&api_context.db, query.name.as_deref(), query.status.as_deref())?;
+    // The logs can be big, they are at `/functions/{uuid}/logs`.
+    for f in functions.iter_mut() {
+        f.logs = Default::default();
+    }
+
+    Ok(functions)
+}
 
 pub async fn handle_get_function_by_uuid(
     rqctx: Arc<RequestContext<Context>>,
@@ -20,33 +54,327 @@ pub async fn handle_get_function_by_uuid(
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    let result = Function::get_from_db(db, uuid.to_string());
-    if result.is_none() {
-        // Return early, we couldn't find a function.
-        bail!("no function was found with uuid `{}`", uuid);
-    }
-
-    Ok(result.unwrap())
+    Ok(
+        Function::get_from_db(db, uuid.to_string())
+            .ok_or_else(|| CioError::NotFound(format!("function `{}`", uuid)))?,
+    )
 }
 
 pub async fn handle_get_function_logs_by_uuid(
     rqctx: Arc<RequestContext<Context>>,
     path_params: Path<FunctionPathParams>,
 ) -> Result<String> {
-    let f = handle_get_function_by_uuid(rqctx, path_params).await?;
+    let f = handle_get_function_by_uuid(rqctx.clone(), path_params).await?;
+    if f.status == octorust::types::JobStatus::InProgress.to_string() {
+        // The logs are only saved on the function once it is done, get what it wrote so far.
+        return FunctionLog::get_for_saga(&rqctx.context().db, &f.saga_id);
+    }
 
     Ok(f.logs)
 }
 
+/// The most function logs we stream at once.
+pub const MAX_LOG_STREAMS: usize = 32;
+
+pub async fn handle_stream_function_logs_by_uuid(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<FunctionPathParams>,
+) -> Result<Response<Body>> {
+    let f = handle_get_function_by_uuid(rqctx.clone(), path_params).await?;
+
+    // The permit is held until the stream ends.
+    let permit = match rqctx.context().log_streams.clone().try_acquire_owned() {
+        Ok(permit) => permit,
+        Err(_) => return Err(CioError::RateLimited("log streams".to_string()).into()),
+    };
+
+    // Clients that reconnect tell us the last chunk they got.
+    let last_event_id = {
+        let req = rqctx.request.lock().await;
+        req.headers()
+            .get("last-event-id")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_default()
+    };
+
+    let (mut sender, body) = Body::channel();
+    let db = rqctx.context().db.clone();
+    tokio::spawn(async move {
+        if let Err(e) = stream_function_logs(&db, &f, last_event_id, &mut sender).await {
+            info!(
+                "streaming the logs of function `{}` `{}` stopped: {}",
+                f.name, f.saga_id, e
+            );
+        }
+        drop(permit);
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "text/event-stream")
+        .header(CACHE_CONTROL, "no-cache")
+        .body(body)?)
+}
+
+/// Send the logs of a function as server-sent events as they are saved, until it is done.
+///
+/// We check for new logs every second, backing off to every 10 seconds while the function
+/// is quiet.
+async fn stream_function_logs(db: &Database, f: &Function, mut last_id: i32, sender: &mut Sender) -> Result<()> {
+    let min_wait = Duration::from_secs(1);
+    let max_wait = Duration::from_secs(10);
+
+    let mut wait = min_wait;
+    let mut idle = Duration::from_secs(0);
+    loop {
+        // Check if it is done before we get the logs, so we don't miss the last of them.
+        let saga_id = f.saga_id.to_string();
+        let (status, logs) = db
+            .run(move |db| {
+                let status = Function::get_status(db, &saga_id)?;
+                Ok((status, FunctionLog::get_after(db, &saga_id, last_id)?))
+            })
+            .await?;
+        let done = match status {
+            Some(status) => status == octorust::types::JobStatus::Completed.to_string(),
+            None => true,
+        };
+
+        if logs.is_empty() {
+            wait = (wait * 2).min(max_wait);
+        } else {
+            wait = min_wait;
+            idle = Duration::from_secs(0);
+        }
+        for l in logs {
+            sender.send_data(sse_event(Some(l.id), "", &l.logs).into()).await?;
+            last_id = l.id;
+        }
+
+        if done {
+            if last_id == 0 {
+                // We never saved the logs as it ran, send the ones on the function.
+                let saga_id = f.saga_id.to_string();
+                let logs = db
+                    .run(move |db| Ok(Function::get_from_db(db, saga_id).map(|f| f.logs)))
+                    .await?
+                    .unwrap_or_else(|| f.logs.to_string());
+                sender.send_data(sse_event(None, "", &logs).into()).await?;
+            }
+
+            sender.send_data(sse_event(None, "done", "").into()).await?;
+            return Ok(());
+        }
+
+        // Let proxies know we are still here when the function isn't writing anything.
+        idle += wait;
+        if idle >= Duration::from_secs(15) {
+            sender.send_data(": keep-alive\n\n".into()).await?;
+            idle = Duration::from_secs(0);
+        }
+
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Returns a server-sent event, every line of the data needs its own field.
+fn sse_event(id: Option<i32>, event: &str, data: &str) -> String {
+    let mut e = String::new();
+    if let Some(id) = id {
+        e.push_str(&format!("id: {}\n", id));
+    }
+    if !event.is_empty() {
+        e.push_str(&format!("event: {}\n", event));
+    }
+    for line in data.trim_end_matches('\n').split('\n') {
+        e.push_str(&format!("data: {}\n", line));
+    }
+    e.push('\n');
+
+    e
+}
+
+pub async fn handle_get_function_steps_by_uuid(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<FunctionPathParams>,
+) -> Result<Vec<FunctionStep>> {
+    let f = handle_get_function_by_uuid(rqctx.clone(), path_params).await?;
+
+    // Jobs that are not sagas with more than one step have none.
+    FunctionStep::get_for_saga(&rqctx.context().db, &f.saga_id)
+}
+
+pub async fn handle_cancel_function_by_uuid(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<FunctionPathParams>,
+) -> Result<Function> {
+    let api_context = rqctx.context();
+    let db = &api_context.db;
+
+    let mut f = handle_get_function_by_uuid(rqctx.clone(), path_params).await?;
+    info!("cancelling function `{}` `{}`", f.name, f.saga_id);
+
+    // Sagas check this before each step.
+    f.cancel(db).await?;
+
+    let id = uuid::Uuid::parse_str(&f.saga_id)?;
+    if !crate::sagas::kill_cmd(&api_context.running, &id)? && !is_saga(&f.name) {
+        // Nothing is running the job, it was left over from before the server restarted.
+        f.status = octorust::types::JobStatus::Completed.to_string();
+        f.completed_at = Some(Utc::now());
+        f = f.update(db).await?;
+    }
+
+    Ok(f)
+}
+
+pub async fn handle_retry_function_by_uuid(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<FunctionPathParams>,
+) -> Result<uuid::Uuid> {
+    let uuid = path_params.into_inner().uuid;
+
+    retry_function(rqctx.context(), &uuid).await
+}
+
+/// Run a function again, in the background. Returns the uuid of the new run.
+pub async fn retry_function(api_context: &Context, saga_id: &str) -> Result<uuid::Uuid> {
+    let f = Function::get_from_db(&api_context.db, saga_id.to_string())
+        .ok_or_else(|| CioError::NotFound(format!("function `{}`", saga_id)))?;
+    if f.status != octorust::types::JobStatus::Completed.to_string() {
+        return Err(CioError::Conflict(format!(
+            "function `{}` `{}` is still running, cancel it first",
+            f.name, f.saga_id
+        ))
+        .into());
+    }
+
+    if !is_saga(&f.name) {
+        // Jobs only have the arguments they were run with.
+        let args: Vec<String> = f
+            .params
+            .get("args")
+            .and_then(|a| serde_json::from_value(a.clone()).ok())
+            .unwrap_or_default();
+        return handle_reexec_cmd_with_args(api_context, &f.name, &args, true).await;
+    }
+
+    let id = uuid::Uuid::new_v4();
+    let db = api_context.db.clone();
+    tokio::spawn(async move {
+        if let Err(e) = retry_saga(&db, &id, &f.name, &f.params).await {
+            warn!("retrying saga `{}` `{}` failed: {:?}", f.name, f.saga_id, e);
+        }
+    });
+
+    Ok(id)
+}
+
+pub async fn handle_get_airtable_outbox(rqctx: Arc<RequestContext<Context>>) -> Result<Vec<AirtableOutboxEntry>> {
+    let api_context = rqctx.context();
+
+    AirtableOutboxEntry::get_unfinished(&api_context.db)
+}
+
+pub async fn handle_replay_airtable_outbox_entry(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<OutboxEntryPathParams>,
+) -> Result<AirtableOutboxEntry> {
+    let id = path_params.into_inner().id;
+    info!("replaying Airtable outbox entry `{}`", id);
+
+    let api_context = rqctx.context();
+
+    AirtableOutboxEntry::replay(&api_context.db, id)
+}
+
+pub async fn handle_get_webhook_events(
+    rqctx: Arc<RequestContext<Context>>,
+    query_args: Query<WebhookEventsQuery>,
+) -> Result<Vec<WebhookEvent>> {
+    let query = query_args.into_inner();
+    let (status, provider) = query.parse()?;
+
+    let api_context = rqctx.context();
+
+    WebhookEvent::get_by_status(&api_context.db, status, provider)
+}
+
+pub async fn handle_replay_webhook_event(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<WebhookEventPathParams>,
+) -> Result<WebhookEvent> {
+    let id = path_params.into_inner().id;
+
+    let api_context = rqctx.context();
+
+    crate::handlers::replay_webhook_event(api_context, id).await
+}
+
+pub async fn handle_replay_webhook_events(
+    rqctx: Arc<RequestContext<Context>>,
+    query_args: Query<WebhookEventsQuery>,
+) -> Result<uuid::Uuid> {
+    let query = query_args.into_inner();
+    let (status, provider) = query.parse()?;
+
+    // There can be a lot of them, so we replay them in a job rather than in the request.
+    let mut args = vec!["--status".to_string(), status.to_string()];
+    if let Some(provider) = provider {
+        args.push("--provider".to_string());
+        args.push(provider.to_string());
+    }
+
+    handle_reexec_cmd_with_args(rqctx.context(), "replay-webhook-events", &args, true).await
+}
+
+/// Replay the webhook events with a status, the oldest first so they are handled in the order
+/// they came in.
+pub async fn replay_webhook_events(
+    api_context: &Context,
+    status: WebhookEventStatus,
+    provider: Option<WebhookProvider>,
+) -> Result<Vec<WebhookEvent>> {
+    let mut events = WebhookEvent::get_by_status(&api_context.db, status, provider)?;
+    events.reverse();
+
+    let mut replayed = Vec::new();
+    for event in events {
+        replayed.push(crate::handlers::replay_webhook_event(api_context, event.id).await?);
+    }
+
+    Ok(replayed)
+}
+
+pub async fn handle_get_schedules(rqctx: Arc<RequestContext<Context>>) -> Result<Vec<JobScheduleStatus>> {
+    let api_context = rqctx.context();
+
+    get_job_schedule_statuses(&api_context.db)
+}
+
 pub async fn handle_reexec_cmd(api_context: &Context, cmd_name: &str, background: bool) -> Result<uuid::Uuid> {
+    handle_reexec_cmd_with_args(api_context, cmd_name, &[], background).await
+}
+
+/// Run a job with arguments for its subcommand.
+pub async fn handle_reexec_cmd_with_args(
+    api_context: &Context,
+    cmd_name: &str,
+    args: &[String],
+    background: bool,
+) -> Result<uuid::Uuid> {
     let db = &api_context.db;
 
-    // Check if we already have an in-progress run for this job.
-    if let Ok(f) = functions::dsl::functions
+    // Check if we already have an in-progress run for this job with the same arguments, a run
+    // for one company doesn't stand in for a run for the others.
+    if let Some(f) = functions::dsl::functions
         .filter(functions::dsl::name.eq(cmd_name.to_string()))
         .filter(functions::dsl::status.eq(octorust::types::JobStatus::InProgress.to_string()))
         .order_by(functions::dsl::created_at.desc()) // Get the most recent one first.
-        .first::<Function>(&db.conn())
+        .load::<Function>(&db.conn()?)?
+        .into_iter()
+        .find(|f| function_args(f) == args)
     {
         let u = uuid::Uuid::parse_str(&f.saga_id)?;
 
@@ -70,7 +398,35 @@ pub async fn handle_reexec_cmd(api_context: &Context, cmd_name: &str, background
     let id = uuid::Uuid::new_v4();
 
     // Run the saga.
-    crate::sagas::run_cmd(db, &api_context.sec, &id, cmd_name, background).await?;
+    crate::sagas::run_cmd(
+        db,
+        &api_context.sec,
+        &api_context.running,
+        &id,
+        cmd_name,
+        args,
+        background,
+    )
+    .await?;
 
     Ok(id)
 }
+
+/// Returns the arguments a function's job was started with, from its parameters.
+fn function_args(f: &Function) -> Vec<String> {
+    serde_json::from_value(f.params["args"].clone()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers_cron::sse_event;
+
+    #[test]
+    fn test_handlers_cron_sse_event() {
+        assert_eq!(
+            sse_event(Some(7), "", "syncing repos\nsyncing rfds\n"),
+            "id: 7\ndata: syncing repos\ndata: syncing rfds\n\n"
+        );
+        assert_eq!(sse_event(None, "done", ""), "event: done\ndata: \n\n");
+    }
+}
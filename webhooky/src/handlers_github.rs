@@ -1,4 +1,4 @@
-use std::{str::FromStr, sync::Arc};
+use std::str::FromStr;
 
 use anyhow::Result;
 use chrono::offset::Utc;
@@ -6,30 +6,34 @@ use cio_api::{
     companies::Company,
     configs::{
         get_configs_from_repo, sync_buildings, sync_certificates, sync_conference_rooms,
-        sync_github_outside_collaborators, sync_groups, sync_links, sync_users,
+        sync_github_outside_collaborators, sync_groups, sync_links, sync_users, User, Users,
     },
+    error::CioError,
     repos::{FromUrl, NewRepo},
+    rfd_authors::refresh_rfds_authored,
+    rfd_states::{RFDState, RFDStateTransition},
+    rfd_subscriptions::{notify_followers, RFDEvent},
     rfds::{is_image, NewRFD, RFD},
     shorturls::{generate_shorturls_for_configs_links, generate_shorturls_for_repos, generate_shorturls_for_rfds},
     utils::{create_or_update_file_in_github_repo, decode_base64_to_string, get_file_content_from_repo},
+    webhook_secrets::check_webhook_company,
 };
-use dropshot::{RequestContext, TypedBody};
 use google_drive::traits::{DriveOps, FileOps};
 use log::{info, warn};
 
 use crate::{event_types::EventType, github_types::GitHubWebhook, repos::Repo, server::Context};
 
 /// Handle a request to the /github endpoint.
-pub async fn handle_github(rqctx: Arc<RequestContext<Context>>, body_param: TypedBody<GitHubWebhook>) -> Result<()> {
-    let api_context = rqctx.context();
-
-    let event = body_param.into_inner();
+pub async fn handle_github(
+    api_context: &Context,
+    company_id: i32,
+    headers: &http::HeaderMap,
+    body: &[u8],
+) -> Result<()> {
+    let event: GitHubWebhook = serde_json::from_slice(body)?;
 
     // Parse the `X-GitHub-Event` header.
-    // TODO: make this nicer when supported as a first class method in dropshot.
-    let req = rqctx.request.lock().await;
-    let req_headers = req.headers();
-    let event_type_string = req_headers
+    let event_type_string = headers
         .get("X-GitHub-Event")
         .unwrap_or(&http::header::HeaderValue::from_str("")?)
         .to_str()
@@ -78,6 +82,7 @@ pub async fn handle_github(rqctx: Arc<RequestContext<Context>>, body_param: Type
         }
         EventType::Repository => {
             let company = Company::get_from_github_org(&api_context.db, &event.repository.owner.login)?;
+            check_webhook_company(company_id, company.id)?;
             let github = company.authenticate_github()?;
 
             sentry::configure_scope(|scope| {
@@ -102,6 +107,7 @@ pub async fn handle_github(rqctx: Arc<RequestContext<Context>>, body_param: Type
         let repo_name = Repo::from_str(&repo.name).unwrap();
 
         let company = Company::get_from_github_org(&api_context.db, &repo.owner.login)?;
+        check_webhook_company(company_id, company.id)?;
         let github = company.authenticate_github()?;
 
         match repo_name {
@@ -262,8 +268,8 @@ pub async fn handle_rfd_pull_request(
         }
     }
 
-    // We only care if the pull request was `opened`.
-    if event.action != "opened" {
+    // We only care if the pull request was `opened`, or its branch changed.
+    if event.action != "opened" && event.action != "synchronize" && event.action != "reopened" {
         // We can throw this out, log it and return early.
         a(&format!(
             "[SUCCESS]: completed automations for `{}` action",
@@ -272,14 +278,6 @@ pub async fn handle_rfd_pull_request(
         return Ok((octorust::types::ChecksCreateRequestConclusion::Success, message));
     }
 
-    // Okay, now we finally have the RFD.
-    // We need to do two things.
-    //  1. Update the discussion link.
-    //  2. Update the state of the RFD to be in discussion if it is not
-    //      in an acceptable current state. More on this below.
-    // To do both these tasks we need to first get the path of the file on GitHub,
-    // so we can update it later, and also find out if it is markdown or not for parsing.
-
     // Get the file path from GitHub.
     // We need to figure out whether this file is a README.adoc or README.md
     // before we update it.
@@ -292,7 +290,7 @@ pub async fn handle_rfd_pull_request(
             rfd.content = decode_base64_to_string(&contents.content);
             rfd.sha = contents.sha;
         }
-        Err(e) => {
+        Err(e) if CioError::is_not_found(&e) => {
             info!(
                 "[rfd] getting file contents for {} on branch {} failed: {}, trying markdown instead...",
                 path, branch, e
@@ -305,36 +303,84 @@ pub async fn handle_rfd_pull_request(
             rfd.content = decode_base64_to_string(&contents.content);
             rfd.sha = contents.sha;
         }
+        Err(e) => return Err(e),
     }
+    let is_markdown = path.ends_with(".md");
+
+    // We only update the RFD when the pull request is `opened`.
+    if event.action == "opened" {
+        // Okay, now we finally have the RFD.
+        // We need to do two things.
+        //  1. Update the discussion link.
+        //  2. Update the state of the RFD to be in discussion if it is still
+        //      in prediscussion.
+        let discussion_link = event.pull_request.html_url.to_string();
+        rfd.update_discussion(&discussion_link, is_markdown)?;
+        a(&format!(
+            "[SUCCESS]: ensured RFD discussion link is `{}`",
+            discussion_link
+        ));
 
-    // Update the discussion link.
-    let discussion_link = event.pull_request.html_url;
-    rfd.update_discussion(&discussion_link, path.ends_with(".md"))?;
-    a(&format!(
-        "[SUCCESS]: ensured RFD discussion link is `{}`",
-        discussion_link
-    ));
+        // A pull request can be open for an RFD in any state after prediscussion,
+        // for example a published RFD can be updated in a pull request.
+        // An RFD in prediscussion (or without a state) is ready for discussion now.
+        let old_state = rfd.state.to_string();
+        if matches!(RFDState::from_str(&old_state), Ok(RFDState::Prediscussion) | Err(_)) {
+            //  Update the state of the RFD in GitHub to show it as `discussion`.
+            rfd.update_state("discussion", is_markdown)?;
+            a("[SUCCESS]: updated RFD state to `discussion`");
+        }
+
+        // Update the RFD to show the new state and link in the database.
+        rfd.update(db).await?;
+        let transition = RFDStateTransition::record(db, &rfd, &old_state)?;
+        a("[SUCCESS]: updated RFD in the database");
+        a("[SUCCESS]: updated RFD in Airtable");
+
+        // Tell the followers of the RFD about the discussion, and the state it moved to.
+        notify_followers(db, company, &rfd, &RFDEvent::DiscussionOpened(discussion_link)).await?;
+        if let Some(transition) = transition {
+            notify_followers(db, company, &rfd, &transition.into()).await?;
+        }
+        a("[SUCCESS]: notified the followers of the RFD");
 
-    // A pull request can be open for an RFD if it is in the following states:
-    //  - published: a already published RFD is being updated in a pull request.
-    //  - discussion: it is in discussion
-    //  - ideation: it is in ideation
-    // We can update the state if it is not currently in an acceptable state.
-    if rfd.state != "discussion" && rfd.state != "published" && rfd.state != "ideation" {
-        //  Update the state of the RFD in GitHub to show it as `discussion`.
-        rfd.update_state("discussion", path.ends_with(".md"))?;
-        a("[SUCCESS]: updated RFD state to `discussion`");
+        // Update the file in GitHub.
+        // Keep in mind: this push will kick off another webhook.
+        create_or_update_file_in_github_repo(github, owner, repo, &branch, &path, rfd.content.as_bytes().to_vec())
+            .await?;
+        a("[SUCCESS]: updated RFD file in GitHub with any changes");
     }
 
-    // Update the RFD to show the new state and link in the database.
-    rfd.update(db).await?;
-    a("[SUCCESS]: updated RFD in the database");
-    a("[SUCCESS]: updated RFD in Airtable");
+    // Check the state in the branch against the state on the default branch, since
+    // that is the change merging the pull request would make.
+    let state = NewRFD::get_state(&rfd.content)?;
+    let default_branch_state = match github
+        .repos()
+        .get_content_file(owner, repo, &path, &event.repository.default_branch)
+        .await
+    {
+        Ok(contents) => NewRFD::get_state(&decode_base64_to_string(&contents.content))?,
+        // The RFD has not been merged yet.
+        Err(e) if CioError::is_not_found(&e) => String::new(),
+        Err(e) => return Err(e),
+    };
+    match RFDState::transition(&default_branch_state, &state) {
+        Ok(new_state) => {
+            a(&format!("[SUCCESS]: RFD state `{}` is valid", new_state));
 
-    // Update the file in GitHub.
-    // Keep in mind: this push will kick off another webhook.
-    create_or_update_file_in_github_repo(github, owner, repo, &branch, &path, rfd.content.as_bytes().to_vec()).await?;
-    a("[SUCCESS]: updated RFD file in GitHub with any changes");
+            if new_state.requires_discussion() && NewRFD::get_discussion(&rfd.content)?.is_empty() {
+                a(&format!(
+                    "[ERROR]: an RFD in `{}` needs a `discussion:` link to its pull request",
+                    new_state
+                ));
+                has_errors = true;
+            }
+        }
+        Err(e) => {
+            a(&format!("[ERROR]: {}", e));
+            has_errors = true;
+        }
+    }
 
     a(&format!(
         "[SUCCESS]: completed automations for `{}` action",
@@ -461,6 +507,8 @@ pub async fn handle_rfd_push(
     // modified in our database.
     let mut changed_files = commit.added.clone();
     changed_files.append(&mut commit.modified.clone());
+    // Get the users once, we match the authors of every RFD against them.
+    let users: Vec<User> = Users::get_from_db(db, company.id)?.into();
     for file in changed_files {
         // Make sure the file has a prefix of "rfd/".
         if !file.starts_with("rfd/") {
@@ -546,14 +594,18 @@ pub async fn handle_rfd_push(
             if let Some(o) = old_rfd {
                 old_rfd_state = o.state.to_string();
                 old_rfd_pdf = o.get_pdf_filename();
-                // Set the rfd_sections_id so we don't overwrite it.
+                // Set the generated fields so we don't overwrite them.
                 new_rfd.rfd_sections_id = o.rfd_sections_id;
+                new_rfd.backlinks = o.backlinks;
+                new_rfd.link_to_authors = o.link_to_authors;
+                new_rfd.unresolved_authors = o.unresolved_authors;
             }
 
             // Update the RFD in the database.
             let mut rfd = new_rfd.upsert(db).await?;
             // Update all the fields for the RFD.
-            rfd.expand(github, company).await?;
+            rfd.expand(db, github, &users, company, false).await?;
+            rfd.update_sections(db)?;
             rfd.update(db).await?;
             a(&format!(
                 "[SUCCESS]: updated RFD {} in the database",
@@ -564,9 +616,23 @@ pub async fn handle_rfd_push(
                 new_rfd.number_string
             ));
 
-            // Now that the database is updated, update the search index.
-            rfd.update_search_index().await?;
-            a("[SUCCESS]: triggered update of the search index");
+            // Update the RFDs each user is an author of, the authors may have changed.
+            refresh_rfds_authored(db, company)?;
+            a("[SUCCESS]: updated the RFDs authored by each user");
+
+            // Record the state change, and call out the ones an RFD is not allowed to make.
+            if old_rfd_state != rfd.state {
+                if let Err(e) = RFDState::transition(&old_rfd_state, &rfd.state) {
+                    a(&format!("[ERROR]: RFD {}: {}", new_rfd.number_string, e));
+                }
+                if let Some(transition) = RFDStateTransition::record(db, &rfd, &old_rfd_state)? {
+                    notify_followers(db, company, &rfd, &transition.into()).await?;
+                }
+                a(&format!(
+                    "[SUCCESS]: recorded state change for RFD {} from `{}` to `{}`, and notified its followers",
+                    new_rfd.number_string, old_rfd_state, rfd.state
+                ));
+            }
 
             // Create all the shorturls for the RFD if we need to,
             // this would be on added files, only.
@@ -574,7 +640,7 @@ pub async fn handle_rfd_push(
             a("[SUCCESS]: updated shorturls for the rfds");
 
             // Update the PDFs for the RFD.
-            rfd.convert_and_upload_pdf(db, github, company).await?;
+            rfd.convert_and_upload_pdf(db, github, company, false).await?;
             rfd.update(db).await?;
             a(&format!(
                 "[SUCCESS]: updated pdf `{}` for RFD {}",
@@ -684,29 +750,47 @@ pub async fn handle_rfd_push(
 
             // If the RFD was merged into the default branch, but the RFD state is not `published`,
             // update the state of the RFD in GitHub to show it as `published`.
-            if branch == event.repository.default_branch && rfd.state != "published" {
+            // Committed and abandoned RFDs can be updated on the default branch, they stay as they are.
+            let merged_state = RFDState::from_str(&rfd.state).ok();
+            if branch == event.repository.default_branch
+                && !matches!(
+                    merged_state,
+                    Some(RFDState::Published) | Some(RFDState::Committed) | Some(RFDState::Abandoned)
+                )
+            {
                 //  Update the state of the RFD in GitHub to show it as `published`.
                 let mut rfd_mut = rfd.clone();
-                rfd_mut.update_state("published", file.ends_with(".md"))?;
-
-                // Update the RFD to show the new state in the database.
-                rfd_mut.update(db).await?;
-
-                // Update the file in GitHub.
-                // Keep in mind: this push will kick off another webhook.
-                create_or_update_file_in_github_repo(
-                    github,
-                    owner,
-                    &repo,
-                    branch,
-                    &file,
-                    rfd_mut.content.as_bytes().to_vec(),
-                )
-                .await?;
-                a(&format!(
-                    "[SUCCESS]: updated state to `published` for RFD {}, since it was merged into branch {}",
-                    new_rfd.number_string, event.repository.default_branch
-                ));
+                match rfd_mut.publish(file.ends_with(".md")) {
+                    Ok(_) => {
+                        // Update the RFD to show the new state in the database.
+                        rfd_mut.update(db).await?;
+                        if let Some(transition) = RFDStateTransition::record(db, &rfd_mut, &rfd.state)? {
+                            notify_followers(db, company, &rfd_mut, &transition.into()).await?;
+                        }
+
+                        // Update the file in GitHub.
+                        // Keep in mind: this push will kick off another webhook.
+                        create_or_update_file_in_github_repo(
+                            github,
+                            owner,
+                            &repo,
+                            branch,
+                            &file,
+                            rfd_mut.content.as_bytes().to_vec(),
+                        )
+                        .await?;
+                        a(&format!(
+                            "[SUCCESS]: updated state to `published` for RFD {}, since it was merged into branch {}",
+                            new_rfd.number_string, event.repository.default_branch
+                        ));
+                    }
+                    Err(e) => {
+                        a(&format!(
+                            "[ERROR]: RFD {} was merged into branch {}, but it can't be published: {}",
+                            new_rfd.number_string, event.repository.default_branch, e
+                        ));
+                    }
+                }
             }
 
             // If the title of the RFD changed, delete the old PDF file so it
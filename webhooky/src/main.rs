@@ -15,7 +15,14 @@ extern crate serde_json;
 use std::env;
 
 use anyhow::{bail, Result};
-use cio_api::{companies::Companys, db::Database};
+use cio_api::{
+    api_keys::{ApiKey, ApiScope},
+    companies::Company,
+    db::Database,
+    job_schedules::{companies_for_job, JobSchedule},
+    webhook_events::{WebhookEvent, WebhookEventStatus},
+    webhook_secrets::{WebhookProvider, WebhookSecret},
+};
 use clap::Parser;
 use sentry::IntoDsn;
 use slog::Drain;
@@ -29,6 +36,10 @@ struct Opts {
     #[clap(short, long)]
     debug: bool,
 
+    /// Only run the job for this company, for the companies that run it on their own schedule
+    #[clap(long, global = true)]
+    only_company_id: Option<i32>,
+
     #[clap(subcommand)]
     subcmd: SubCommand,
 }
@@ -37,6 +48,17 @@ struct Opts {
 enum SubCommand {
     Server(Server),
 
+    #[clap(name = "create-api-key")]
+    CreateAPIKey(CreateAPIKey),
+    #[clap(name = "export-rfds")]
+    ExportRFDs(ExportRFDs),
+    ListWebhookEvents(ListWebhookEvents),
+    ReplayWebhookEvents(ReplayWebhookEvents),
+    SetJobSchedule(SetJobSchedule),
+    SetWebhookSecret(SetWebhookSecret),
+
+    #[clap(name = "send-rfd-changelog")]
+    SendRFDChangelog(SendRFDChangelog),
     SyncAnalytics(SyncAnalytics),
     #[clap(name = "sync-api-tokens")]
     SyncAPITokens(SyncAPITokens),
@@ -77,6 +99,116 @@ pub struct Server {
     do_cron: bool,
 }
 
+/// A subcommand for creating a key for the cio-api server.
+#[derive(Parser, Clone, Debug)]
+pub struct CreateAPIKey {
+    /// The id of the company whose records the key can read
+    #[clap(long)]
+    company_id: i32,
+
+    /// What the key is for
+    #[clap(long)]
+    name: String,
+
+    /// The scopes the key has, ie. read:applicants, at least one is needed. Use `*` for a key that
+    /// can read everything
+    #[clap(long, required = true)]
+    scope: Vec<String>,
+}
+
+/// A subcommand for exporting the published RFDs to a static site, an EPUB and a PDF.
+#[derive(Parser, Clone, Debug)]
+pub struct ExportRFDs {
+    /// The id of the company whose RFDs to export
+    #[clap(long, default_value = "1")]
+    company_id: i32,
+
+    /// The directory to export the RFDs to, the tarball is made next to it
+    #[clap(short, long, parse(from_os_str), default_value = ".", value_hint = clap::ValueHint::DirPath)]
+    output: std::path::PathBuf,
+
+    /// Upload the tarball to the RFDs folder in Google Drive
+    #[clap(long)]
+    upload: bool,
+}
+
+/// A subcommand for listing the webhooks we received.
+#[derive(Parser, Clone, Debug)]
+pub struct ListWebhookEvents {
+    /// The status of the events, ie. received, succeeded or failed
+    #[clap(long, default_value = "failed")]
+    status: String,
+
+    /// Only the events from this provider, ie. github
+    #[clap(long)]
+    provider: Option<String>,
+}
+
+/// A subcommand for handling webhooks we received again, through the same handler as when
+/// they came in.
+#[derive(Parser, Clone, Debug)]
+pub struct ReplayWebhookEvents {
+    /// The ids of the events to replay. If none are given, every event with the status is replayed
+    #[clap(long)]
+    id: Vec<i32>,
+
+    /// The status of the events to replay, ie. received, succeeded or failed
+    #[clap(long, default_value = "failed")]
+    status: String,
+
+    /// Only replay the events from this provider, ie. github
+    #[clap(long)]
+    provider: Option<String>,
+}
+
+/// A subcommand for changing when a cron job runs, or when it runs for a company or turning it
+/// off for one.
+#[derive(Parser, Clone, Debug)]
+pub struct SetJobSchedule {
+    /// The name of the job, ie. sync-rfds
+    #[clap(long)]
+    name: String,
+
+    /// When the job runs, as a cron expression with five fields, ie. "0 */6 * * *". For a
+    /// company, "" runs it with everyone else again
+    #[clap(long)]
+    cron: Option<String>,
+
+    /// The timezone the cron expression is in, ie. America/Los_Angeles. For a company, "" uses
+    /// the job's again
+    #[clap(long)]
+    timezone: Option<String>,
+
+    /// Turn the job on or off
+    #[clap(long)]
+    enabled: Option<bool>,
+
+    /// Only change when the job runs for this company, or turn it on or off for it
+    #[clap(long)]
+    company_id: Option<i32>,
+}
+
+/// A subcommand for setting the secret we check a provider's webhooks for a company with.
+/// The secret is encrypted with the key in `WEBHOOK_SECRETS_KEY` before it is stored.
+#[derive(Parser, Clone, Debug)]
+pub struct SetWebhookSecret {
+    /// The id of the company the webhooks are for
+    #[clap(long)]
+    company_id: i32,
+
+    /// The provider, ie. github, slack, docusign, checkr, mailchimp, sendgrid, shipbob or shippo
+    #[clap(long)]
+    provider: String,
+
+    /// The secret, for providers that can't sign webhooks this goes in the `token` query parameter
+    #[clap(long)]
+    secret: String,
+}
+
+/// A subcommand for running the background job of sending the weekly RFD changelog.
+#[derive(Parser, Debug, Clone)]
+pub struct SendRFDChangelog {}
+
 /// A subcommand for running the background job of syncing analytics.
 #[derive(Parser, Debug, Clone)]
 pub struct SyncAnalytics {}
@@ -139,7 +271,11 @@ pub struct SyncRepos {}
 
 /// A subcommand for running the background job of syncing RFDs.
 #[derive(Parser, Debug, Clone)]
-pub struct SyncRFDs {}
+pub struct SyncRFDs {
+    /// Render the HTML and PDF of every RFD again, even if they did not change
+    #[clap(long)]
+    force_render: bool,
+}
 
 /// A subcommand for running the background job of syncing shipments.
 #[derive(Parser, Debug, Clone)]
@@ -204,13 +340,105 @@ async fn run_cmd(opts: Opts, logger: slog::Logger) -> Result<()> {
         scope.set_tag("command", &format!("{:?}", opts.subcmd));
     });
 
+    let only_company_id = opts.only_company_id;
     match opts.subcmd {
         SubCommand::Server(s) => {
             crate::server::server(s, logger).await?;
         }
+        SubCommand::CreateAPIKey(c) => {
+            let db = Database::new()?;
+            let mut scopes = Vec::new();
+            for scope in &c.scope {
+                scopes.push(scope.parse::<ApiScope>()?);
+            }
+
+            let (api_key, key) = ApiKey::create(&db, c.company_id, &c.name, &scopes)?;
+            // This is the only time we have the key, so print it.
+            println!("created api key {} ({}): {}", api_key.id, api_key.name, key);
+        }
+        SubCommand::ExportRFDs(e) => {
+            let db = Database::new()?;
+            let company = Company::get_by_id(&db, e.company_id)?;
+
+            let archive = cio_api::rfd_archive::export_rfd_archive(&db, &company, &e.output, e.upload).await?;
+            println!("exported {} RFDs to {}", archive.rfds, archive.tarball.display());
+            if !archive.link_google_drive.is_empty() {
+                println!("uploaded to {}", archive.link_google_drive);
+            }
+        }
+        SubCommand::ListWebhookEvents(l) => {
+            let db = Database::new()?;
+            let status = l.status.parse::<WebhookEventStatus>()?;
+            let provider = l.provider.map(|p| p.parse::<WebhookProvider>()).transpose()?;
+
+            for event in WebhookEvent::get_by_status(&db, status, provider)? {
+                println!(
+                    "{}\t{}\t{}\t{}\tattempts: {}\t{}",
+                    event.id,
+                    event.created_at,
+                    event.provider,
+                    event.endpoint,
+                    event.attempts,
+                    event.error.lines().next().unwrap_or_default()
+                );
+            }
+        }
+        SubCommand::ReplayWebhookEvents(r) => {
+            let api_context = crate::server::Context::new(serde_json::Value::Null, logger).await?;
+
+            let events = if r.id.is_empty() {
+                let status = r.status.parse::<WebhookEventStatus>()?;
+                let provider = r.provider.map(|p| p.parse::<WebhookProvider>()).transpose()?;
+                crate::handlers_cron::replay_webhook_events(&api_context, status, provider).await?
+            } else {
+                let mut events = Vec::new();
+                for id in r.id {
+                    events.push(crate::handlers::replay_webhook_event(&api_context, id).await?);
+                }
+                events
+            };
+
+            for event in events {
+                println!("{}\t{}\t{}", event.id, event.endpoint, event.status);
+            }
+        }
+        SubCommand::SetJobSchedule(j) => {
+            let db = Database::new()?;
+            let schedule = JobSchedule::set(
+                &db,
+                &j.name,
+                j.company_id.unwrap_or_default(),
+                j.cron.as_deref(),
+                j.timezone.as_deref(),
+                j.enabled,
+            )?;
+            println!(
+                "{}\t{}\t{}\tenabled: {}\tcompany: {}",
+                schedule.name, schedule.cron, schedule.timezone, schedule.enabled, schedule.cio_company_id
+            );
+        }
+        SubCommand::SetWebhookSecret(w) => {
+            let db = Database::new()?;
+            let provider = w.provider.parse::<WebhookProvider>()?;
+
+            let secret = WebhookSecret::set(&db, w.company_id, provider, &w.secret)?;
+            println!(
+                "set the {} webhook secret for company {}",
+                secret.provider, secret.cio_company_id
+            );
+        }
+        SubCommand::SendRFDChangelog(_) => {
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "send-rfd-changelog", only_company_id)?;
+
+            // Iterate over the companies and send the changelog.
+            for company in companies {
+                cio_api::rfds::send_rfd_changelog(&db, &company).await?;
+            }
+        }
         SubCommand::SyncAnalytics(_) => {
-            let db = Database::new();
-            let companies = Companys::get_from_db(&db, 1)?;
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "sync-analytics", only_company_id)?;
 
             // Iterate over the companies and update.
             for company in companies {
@@ -218,8 +446,8 @@ async fn run_cmd(opts: Opts, logger: slog::Logger) -> Result<()> {
             }
         }
         SubCommand::SyncAPITokens(_) => {
-            let db = Database::new();
-            let companies = Companys::get_from_db(&db, 1)?;
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "sync-api-tokens", only_company_id)?;
 
             // Iterate over the companies and update.
             for company in companies {
@@ -227,8 +455,8 @@ async fn run_cmd(opts: Opts, logger: slog::Logger) -> Result<()> {
             }
         }
         SubCommand::SyncApplications(_) => {
-            let db = Database::new();
-            let companies = Companys::get_from_db(&db, 1)?;
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "sync-applications", only_company_id)?;
 
             // Iterate over the companies and update.
             for company in companies {
@@ -241,8 +469,8 @@ async fn run_cmd(opts: Opts, logger: slog::Logger) -> Result<()> {
             }
         }
         SubCommand::SyncAssetInventory(_) => {
-            let db = Database::new();
-            let companies = Companys::get_from_db(&db, 1)?;
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "sync-asset-inventory", only_company_id)?;
 
             // Iterate over the companies and update.
             for company in companies {
@@ -250,11 +478,12 @@ async fn run_cmd(opts: Opts, logger: slog::Logger) -> Result<()> {
             }
         }
         SubCommand::SyncCompanies(_) => {
-            cio_api::companies::refresh_companies().await?;
+            let db = Database::new()?;
+            cio_api::companies::refresh_companies(&db).await?;
         }
         SubCommand::SyncConfigs(_) => {
-            let db = Database::new();
-            let companies = Companys::get_from_db(&db, 1)?;
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "sync-configs", only_company_id)?;
 
             // Iterate over the companies and update.
             for company in companies {
@@ -262,8 +491,8 @@ async fn run_cmd(opts: Opts, logger: slog::Logger) -> Result<()> {
             }
         }
         SubCommand::SyncFinance(_) => {
-            let db = Database::new();
-            let companies = Companys::get_from_db(&db, 1)?;
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "sync-finance", only_company_id)?;
 
             // Iterate over the companies and update.
             for company in companies {
@@ -271,11 +500,12 @@ async fn run_cmd(opts: Opts, logger: slog::Logger) -> Result<()> {
             }
         }
         SubCommand::SyncFunctions(_) => {
-            cio_api::functions::refresh_functions().await?;
+            let db = Database::new()?;
+            cio_api::functions::refresh_functions(&db).await?;
         }
         SubCommand::SyncHuddles(_) => {
-            let db = Database::new();
-            let companies = Companys::get_from_db(&db, 1)?;
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "sync-huddles", only_company_id)?;
 
             // Iterate over the companies and update.
             for company in companies {
@@ -289,8 +519,8 @@ async fn run_cmd(opts: Opts, logger: slog::Logger) -> Result<()> {
             }
         }
         SubCommand::SyncInterviews(_) => {
-            let db = Database::new();
-            let companies = Companys::get_from_db(&db, 1)?;
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "sync-interviews", only_company_id)?;
 
             // Iterate over the companies and update.
             for company in companies {
@@ -299,8 +529,8 @@ async fn run_cmd(opts: Opts, logger: slog::Logger) -> Result<()> {
             }
         }
         SubCommand::SyncJournalClubs(_) => {
-            let db = Database::new();
-            let companies = Companys::get_from_db(&db, 1)?;
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "sync-journal-clubs", only_company_id)?;
 
             // Iterate over the companies and update.
             for company in companies {
@@ -308,8 +538,8 @@ async fn run_cmd(opts: Opts, logger: slog::Logger) -> Result<()> {
             }
         }
         SubCommand::SyncMailingLists(_) => {
-            let db = Database::new();
-            let companies = Companys::get_from_db(&db, 1)?;
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "sync-mailing-lists", only_company_id)?;
 
             // Iterate over the companies and update.
             for company in companies {
@@ -320,8 +550,8 @@ async fn run_cmd(opts: Opts, logger: slog::Logger) -> Result<()> {
             }
         }
         SubCommand::SyncRecordedMeetings(_) => {
-            let db = Database::new();
-            let companies = Companys::get_from_db(&db, 1)?;
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "sync-recorded-meetings", only_company_id)?;
 
             // Iterate over the companies and update.
             for company in companies {
@@ -330,8 +560,8 @@ async fn run_cmd(opts: Opts, logger: slog::Logger) -> Result<()> {
             }
         }
         SubCommand::SyncRepos(_) => {
-            let db = Database::new();
-            let companies = Companys::get_from_db(&db, 1)?;
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "sync-repos", only_company_id)?;
 
             // Iterate over the companies and update.
             for company in companies {
@@ -340,19 +570,19 @@ async fn run_cmd(opts: Opts, logger: slog::Logger) -> Result<()> {
                 cio_api::repos::refresh_db_github_repos(&db, &github, &company).await?;
             }
         }
-        SubCommand::SyncRFDs(_) => {
-            let db = Database::new();
-            let companies = Companys::get_from_db(&db, 1)?;
+        SubCommand::SyncRFDs(s) => {
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "sync-rfds", only_company_id)?;
 
             // Iterate over the companies and update.
             for company in companies {
-                cio_api::rfds::refresh_db_rfds(&db, &company).await?;
+                cio_api::rfds::refresh_db_rfds(&db, &company, s.force_render).await?;
                 cio_api::rfds::cleanup_rfd_pdfs(&db, &company).await?;
             }
         }
         SubCommand::SyncOther(_) => {
-            let db = Database::new();
-            let companies = Companys::get_from_db(&db, 1)?;
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "sync-other", only_company_id)?;
 
             // Iterate over the companies and update.
             for company in companies {
@@ -363,8 +593,8 @@ async fn run_cmd(opts: Opts, logger: slog::Logger) -> Result<()> {
             }
         }
         SubCommand::SyncShipments(_) => {
-            let db = Database::new();
-            let companies = Companys::get_from_db(&db, 1)?;
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "sync-shipments", only_company_id)?;
 
             // Iterate over the companies and update.
             for company in companies {
@@ -376,11 +606,17 @@ async fn run_cmd(opts: Opts, logger: slog::Logger) -> Result<()> {
             }
         }
         SubCommand::SyncShorturls(_) => {
-            cio_api::shorturls::refresh_shorturls().await?;
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "sync-shorturls", only_company_id)?;
+
+            // Iterate over the companies and update.
+            for company in companies {
+                cio_api::shorturls::refresh_shorturls(&db, &company).await?;
+            }
         }
         SubCommand::SyncSwagInventory(_) => {
-            let db = Database::new();
-            let companies = Companys::get_from_db(&db, 1)?;
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "sync-swag-inventory", only_company_id)?;
 
             // Iterate over the companies and update.
             for company in companies {
@@ -390,8 +626,8 @@ async fn run_cmd(opts: Opts, logger: slog::Logger) -> Result<()> {
             }
         }
         SubCommand::SyncTravel(_) => {
-            let db = Database::new();
-            let companies = Companys::get_from_db(&db, 1)?;
+            let db = Database::new()?;
+            let companies = companies_for_job(&db, "sync-travel", only_company_id)?;
 
             // Iterate over the companies and update.
             for company in companies {
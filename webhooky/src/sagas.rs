@@ -1,13 +1,21 @@
 use std::{
+    collections::HashMap,
     env, fmt,
     io::{BufRead, BufReader},
-    sync::Arc,
+    sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
 
 use anyhow::{bail, Result};
 use chrono::Utc;
-use cio_api::{db::Database, functions::Function};
+use cio_api::{
+    db::Database,
+    error::CioError,
+    functions::{Function, FunctionLog, FunctionStep},
+    job_schedules::{companies_for_job, DEFAULT_SCHEDULES, JOBS_NOT_PER_COMPANY},
+    sagas::{run_step, SagaContext},
+};
+use log::warn;
 use serde::{Deserialize, Serialize};
 
 /// Define our saga for syncing repos.
@@ -17,12 +25,31 @@ pub struct Saga;
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Params {
     cmd_name: String,
+    /// The arguments for the subcommand, most jobs don't have any.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    args: Vec<String>,
     saga_id: uuid::Uuid,
 }
 
 #[derive(Debug)]
 pub struct Context {
     db: Database,
+    running: RunningCommands,
+}
+
+/// The commands we are running for sagas, by the id of the saga, so they can be cancelled.
+pub type RunningCommands = Arc<Mutex<HashMap<uuid::Uuid, Arc<duct::ReaderHandle>>>>;
+
+/// Stop the command running for a saga. Returns false if we are not running one for it.
+pub fn kill_cmd(running: &RunningCommands, id: &uuid::Uuid) -> Result<bool> {
+    let child = running.lock().unwrap().get(id).cloned();
+    match child {
+        Some(child) => {
+            child.kill()?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
 }
 
 impl steno::SagaType for Saga {
@@ -47,8 +74,9 @@ impl fmt::Display for FnOutput {
     }
 }
 
+// The jobs sync us up with other services, whatever a step did is picked up again by the next
+// run, so there is nothing to undo.
 async fn undo_action(_action_context: steno::ActionContext<Saga>) -> Result<()> {
-    // This is a noop, we don't have to undo anything.
     Ok(())
 }
 
@@ -56,20 +84,20 @@ async fn undo_action(_action_context: steno::ActionContext<Saga>) -> Result<()>
 pub async fn do_saga(
     db: &Database,
     sec: &steno::SecClient,
-    id: &uuid::Uuid,
+    running: &RunningCommands,
     template: steno::SagaTemplate<Saga>,
-    cmd_name: &str,
+    params: Params,
     background: bool,
 ) -> Result<()> {
-    let context = Arc::new(Context { db: db.clone() });
-    let params = Params {
-        cmd_name: cmd_name.to_string(),
-        saga_id: *id,
-    };
+    let context = Arc::new(Context {
+        db: db.clone(),
+        running: running.clone(),
+    });
+    let cmd_name = params.cmd_name.to_string();
 
     let saga_template = Arc::new(template);
 
-    let saga_id = steno::SagaId(*id);
+    let saga_id = steno::SagaId(params.saga_id);
 
     // Create the saga.
     let saga_future = sec
@@ -79,134 +107,300 @@ pub async fn do_saga(
     // Set it running.
     sec.saga_start(saga_id).await?;
 
-    if !background {
-        //
-        // Wait for the saga to finish running.  This could take a while, depending
-        // on what the saga does!  This traverses the DAG of actions, executing each
-        // one.  If one fails, then it's all unwound: any actions that previously
-        // completed will be undone.
-        //
-        // Note that the SEC will run all this regardless of whether you wait for it
-        // here.  This is just a handle for you to know when the saga has finished.
-
+    //
+    // Wait for the saga to finish running.  This could take a while, depending
+    // on what the saga does!  This traverses the DAG of actions, executing each
+    // one.  If one fails, then it's all unwound: any actions that previously
+    // completed will be undone.
+    //
+    // Note that the SEC will run all this regardless of whether you wait for it
+    // here.  This is just a handle for you to know when the saga has finished.
+    let db = db.clone();
+    let start = Instant::now();
+    let finish = async move {
         let result = saga_future.await;
 
-        // Get the function.
-        let mut f = Function::get_from_db(db, saga_id.to_string()).unwrap();
+        // Get the function, the steps already saved their logs to it.
+        let mut f = Function::get_from_db(&db, saga_id.to_string())
+            .ok_or_else(|| CioError::NotFound(format!("function `{}`", saga_id)))?;
+        f.completed_at = Some(Utc::now());
 
-        // Print the results.
         match result.kind {
-            Ok(s) => {
-                // Save the success output to the logs.
-                // For each function.
-                let log = s.lookup_output::<FnOutput>(cmd_name)?;
-
-                f.logs = log.0.trim().to_string();
+            Ok(_) => {
                 f.conclusion = octorust::types::Conclusion::Success.to_string();
-                f.completed_at = Some(Utc::now());
+                f.update(&db).await?;
+                cio_api::metrics::record_job_run(&cmd_name, &f.conclusion, start.elapsed());
+
+                Ok(())
             }
             Err(e) => {
                 // Save the error to the logs.
                 f.logs = format!("{}\n\n{:?}", f.logs, e).trim().to_string();
-                f.conclusion = octorust::types::Conclusion::Failure.to_string();
-                f.completed_at = Some(Utc::now());
+                if !f.is_cancelled() {
+                    f.conclusion = octorust::types::Conclusion::Failure.to_string();
+                }
+                f.update(&db).await?;
+                cio_api::metrics::record_job_run(&cmd_name, &f.conclusion, start.elapsed());
 
                 bail!("action failed: {:#?}", e);
             }
         }
+    };
+
+    if background {
+        tokio::spawn(async move {
+            if let Err(e) = finish.await {
+                warn!("saga `{}` failed: {:?}", saga_id, e);
+            }
+        });
 
-        f.update(db).await?;
+        return Ok(());
     }
 
-    Ok(())
+    finish.await
 }
 
+/// Run a job as a saga.
+///
+/// A job that runs for each company gets a step for each of them, which runs the job for just
+/// that company, so the function shows how far the job got. Jobs that run once for everyone,
+/// or that were started for one company, are a single step.
 pub async fn run_cmd(
     db: &Database,
     sec: &steno::SecClient,
+    running: &RunningCommands,
     id: &uuid::Uuid,
     cmd_name: &str,
+    args: &[String],
     background: bool,
 ) -> Result<()> {
     let mut builder = steno::SagaTemplateBuilder::new();
-    builder.append(
-        // name of this action's output (can be used in subsequent actions)
-        cmd_name,
-        // human-readable label for the action
-        cmd_name,
-        steno::ActionFunc::new_action(
-            // action function
-            action_run_cmd,
-            // undo function
-            undo_action,
-        ),
-    );
-
-    do_saga(db, sec, id, builder.build(), cmd_name, background).await
+
+    let companies = if is_per_company(cmd_name, args) {
+        companies_for_job(db, cmd_name, None)?
+    } else {
+        Vec::new()
+    };
+    if companies.is_empty() {
+        builder.append(
+            // name of this action's output (can be used in subsequent actions)
+            cmd_name,
+            // human-readable label for the action
+            cmd_name,
+            steno::ActionFunc::new_action(
+                // action function
+                |action_context| action_run_cmd(action_context, None, true),
+                // undo function
+                undo_action,
+            ),
+        );
+    } else {
+        let steps: Vec<&str> = companies.iter().map(|c| c.name.as_str()).collect();
+        FunctionStep::queue(db, id, &steps, 1)?;
+
+        for (i, company) in companies.iter().enumerate() {
+            let step = (company.id, company.name.to_string());
+            let last = i == companies.len() - 1;
+            builder.append(
+                &company.name,
+                &company.name,
+                steno::ActionFunc::new_action(
+                    move |action_context| action_run_cmd(action_context, Some(step.clone()), last),
+                    undo_action,
+                ),
+            );
+        }
+    }
+
+    let params = Params {
+        cmd_name: cmd_name.to_string(),
+        args: args.to_vec(),
+        saga_id: *id,
+    };
+
+    do_saga(db, sec, running, builder.build(), params, background).await
 }
-async fn action_run_cmd(action_context: steno::ActionContext<Saga>) -> Result<FnOutput, steno::ActionError> {
+
+/// Returns if a job runs for each company, rather than once for everyone or for the one
+/// company it was started for.
+fn is_per_company(cmd_name: &str, args: &[String]) -> bool {
+    DEFAULT_SCHEDULES.iter().any(|(name, _)| *name == cmd_name)
+        && !JOBS_NOT_PER_COMPANY.contains(&cmd_name)
+        && !args.iter().any(|a| a == "--only-company-id")
+}
+
+/// Run a job, or the step of it for a company given its id and name. `last` is if this is the
+/// last step of the job.
+async fn action_run_cmd(
+    action_context: steno::ActionContext<Saga>,
+    company: Option<(i32, String)>,
+    last: bool,
+) -> Result<FnOutput, steno::ActionError> {
     let db = &action_context.user_data().db;
+    let running = &action_context.user_data().running;
     let cmd_name = &action_context.saga_params().cmd_name;
     let saga_id = &action_context.saga_params().saga_id;
 
-    // Execute the function within the scope of the logger.
-    // Print the error and return an ActionError.
-    match reexec(db, cmd_name, saga_id).await {
-        Ok(s) => Ok(FnOutput(s)),
-        Err(err) => {
-            // Return an action error but include the logs.
-            // Format the anyhow error with a stack trace.
-            Err(steno::ActionError::action_failed(format!("ERROR:\n\n{:?}", err)))
+    let (id, name) = match company {
+        Some(c) => c,
+        None => {
+            // Execute the function within the scope of the logger.
+            // Print the error and return an ActionError.
+            let args = &action_context.saga_params().args;
+            return match reexec(db, running, cmd_name, args, saga_id, last).await {
+                Ok(s) => Ok(FnOutput(s)),
+                // Return an action error but include the logs.
+                // Format the anyhow error with a stack trace.
+                Err(err) => Err(steno::ActionError::action_failed(format!("ERROR:\n\n{:?}", err))),
+            };
         }
-    }
+    };
+
+    let mut args = action_context.saga_params().args.clone();
+    args.push("--only-company-id".to_string());
+    args.push(id.to_string());
+
+    let context = SagaContext {
+        db: db.clone(),
+        saga_id: *saga_id,
+    };
+    run_step(&context, &name, async {
+        Ok(FnOutput(reexec(db, running, cmd_name, &args, saga_id, last).await?))
+    })
+    .await
 }
 
 // We re-exec our current binary so we can get the best log output.
 // The only downside is we are creating more connections to the database.
-async fn reexec(db: &Database, cmd: &str, saga_id: &uuid::Uuid) -> Result<String> {
+async fn reexec(
+    db: &Database,
+    running: &RunningCommands,
+    cmd: &str,
+    args: &[String],
+    saga_id: &uuid::Uuid,
+    last: bool,
+) -> Result<String> {
     let exe = env::current_exe()?;
 
-    let child = duct::cmd!(exe, cmd);
-    let reader = child.stderr_to_stdout().reader()?;
+    let mut argv = vec![cmd.to_string()];
+    argv.extend(args.iter().cloned());
+    let child = duct::cmd(exe, argv);
+    let reader = Arc::new(child.stderr_to_stdout().reader()?);
 
-    let mut output = String::new();
+    // Keep track of the command so it can be cancelled.
+    running.lock().unwrap().insert(*saga_id, reader.clone());
+    let result = read_cmd_output(db, &reader, cmd, saga_id, last).await;
+    running.lock().unwrap().remove(saga_id);
 
-    let out = BufReader::new(reader);
+    result
+}
+
+/// Read the output of a command, saving it as the logs of its function. The function is
+/// concluded once the `last` of its steps succeeds, or any of them fails.
+async fn read_cmd_output(
+    db: &Database,
+    reader: &Arc<duct::ReaderHandle>,
+    cmd: &str,
+    saga_id: &uuid::Uuid,
+    last: bool,
+) -> Result<String> {
+    let mut output = String::new();
+    // The logs we haven't saved yet.
+    let mut unsaved = String::new();
+
+    // Read the lines on their own thread, so we can save the logs on a timer even when
+    // the command is quiet.
+    let (tx, mut lines) = tokio::sync::mpsc::unbounded_channel();
+    let r = reader.clone();
+    std::thread::spawn(move || {
+        for line in BufReader::new(&*r).lines() {
+            let failed = line.is_err();
+            if tx.send(line).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    // The chunks of the logs are saved for the company of the function.
+    let id = saga_id.to_string();
+    let cio_company_id = db
+        .run(move |db| {
+            Function::get_from_db(db, id.to_string())
+                .map(|f| f.cio_company_id)
+                .ok_or_else(|| CioError::NotFound(format!("function `{}`", id)).into())
+        })
+        .await?;
 
     let mut start = Instant::now();
 
     // Scope a new logger for this command.
     let logger = slog_scope::logger().new(slog::slog_o!("cmd" => cmd.to_string(), "saga_id" => saga_id.to_string()));
 
-    for line in out.lines() {
-        match line {
-            Ok(l) => {
+    loop {
+        match tokio::time::timeout(Duration::from_secs(1), lines.recv()).await {
+            Ok(Some(Ok(l))) => {
                 output.push_str(&l);
                 output.push('\n');
+                unsaved.push_str(&l);
+                unsaved.push('\n');
 
                 slog::info!(logger, "{}", l);
+            }
+            Ok(Some(Err(e))) => {
+                FunctionLog::append(db, saga_id, cio_company_id, &unsaved).await?;
 
-                // Only save the logs when we have time, just do it async and don't
-                // wait on it, else we will be waiting forever.
-                // Update our start time after saving.
-                if start.elapsed() > Duration::from_secs(15) {
-                    // Save the logs.
-                    Function::add_logs(db, saga_id, &output).await?;
+                // If the command was killed because the saga was cancelled, say so.
+                let conclusion = match Function::get_from_db(db, saga_id.to_string()) {
+                    Some(f) if f.is_cancelled() => octorust::types::Conclusion::Cancelled,
+                    _ => octorust::types::Conclusion::Failure,
+                };
 
-                    // Reset our start time to now.
-                    start = Instant::now();
-                }
-            }
-            Err(e) => {
                 // Save the logs.
-                Function::add_logs_with_conclusion(db, saga_id, &output, &octorust::types::Conclusion::Failure).await?;
+                Function::add_logs_with_conclusion(db, saga_id, &output, &conclusion).await?;
 
                 bail!(e);
             }
+            // The command finished.
+            Ok(None) => break,
+            // The command didn't write anything, but we still save what we have.
+            Err(_) => (),
+        }
+
+        // Save what we have every second so the logs can be followed while the command
+        // runs, saving every line would be too many writes.
+        if !unsaved.is_empty() && start.elapsed() > Duration::from_secs(1) {
+            FunctionLog::append(db, saga_id, cio_company_id, &unsaved).await?;
+            unsaved.clear();
+
+            // Reset our start time to now.
+            start = Instant::now();
         }
     }
 
-    // We do this here because sometimes the saga fails to update.
-    Function::add_logs_with_conclusion(db, saga_id, &output, &octorust::types::Conclusion::Success).await?;
+    FunctionLog::append(db, saga_id, cio_company_id, &unsaved).await?;
+
+    if last {
+        // We do this here because sometimes the saga fails to update.
+        Function::add_logs_with_conclusion(db, saga_id, &output, &octorust::types::Conclusion::Success).await?;
+    } else {
+        Function::add_logs(db, saga_id, &output).await?;
+    }
     Ok(output)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::sagas::is_per_company;
+
+    #[test]
+    fn test_sagas_is_per_company() {
+        assert!(is_per_company("sync-repos", &[]));
+        // A run for one company is a single step.
+        assert!(!is_per_company(
+            "sync-repos",
+            &["--only-company-id".to_string(), "2".to_string()]
+        ));
+        assert!(!is_per_company("sync-companies", &[]));
+        assert!(!is_per_company("replay-webhook-events", &[]));
+    }
+}
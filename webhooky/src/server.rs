@@ -2,8 +2,17 @@ use std::{collections::HashMap, env, fs::File, sync::Arc};
 
 use anyhow::{bail, Result};
 use chrono::{DateTime, Utc};
-use cio_api::{analytics::NewPageView, db::Database, functions::Function, swag_store::Order};
-use clokwerk::{AsyncScheduler, TimeUnits};
+use cio_api::{
+    analytics::NewPageView,
+    db::Database,
+    error::CioError,
+    functions::{Function, FunctionStep},
+    job_schedules::{JobSchedule, JobScheduleStatus},
+    outbox::AirtableOutboxEntry,
+    swag_store::Order,
+    webhook_events::{WebhookEvent, WebhookEventStatus},
+    webhook_secrets::{WebhookAuthentication, WebhookProvider, WebhookSecret},
+};
 use docusign::DocuSign;
 use dropshot::{
     endpoint, ApiDescription, ConfigDropshot, ConfigLogging, ConfigLoggingLevel, HttpError, HttpResponseAccepted,
@@ -11,7 +20,10 @@ use dropshot::{
 };
 use google_drive::Client as GoogleDrive;
 use gusto_api::Client as Gusto;
+use http::Response;
+use hyper014::Body;
 use log::{info, warn};
+use macros::request_metrics;
 use mailchimp_api::MailChimp;
 use quickbooks::QuickBooks;
 use ramp_api::Client as Ramp;
@@ -25,8 +37,6 @@ use signal_hook::{
 use slack_chat_api::Slack;
 use zoom_api::Client as Zoom;
 
-use crate::github_types::GitHubWebhook;
-
 pub async fn server(s: crate::Server, logger: slog::Logger) -> Result<()> {
     /*
      * We must specify a configuration with a bind address.  We'll use 127.0.0.1
@@ -121,6 +131,7 @@ pub async fn server(s: crate::Server, logger: slog::Logger) -> Result<()> {
     api.register(trigger_rfd_update_by_number).unwrap();
     api.register(trigger_cleanup_create).unwrap();
 
+    api.register(trigger_send_rfd_changelog_create).unwrap();
     api.register(trigger_sync_analytics_create).unwrap();
     api.register(trigger_sync_api_tokens_create).unwrap();
     api.register(trigger_sync_applications_create).unwrap();
@@ -142,8 +153,20 @@ pub async fn server(s: crate::Server, logger: slog::Logger) -> Result<()> {
     api.register(trigger_sync_swag_inventory_create).unwrap();
     api.register(trigger_sync_travel_create).unwrap();
 
+    api.register(listen_get_functions).unwrap();
     api.register(listen_get_function_by_uuid).unwrap();
     api.register(listen_get_function_logs_by_uuid).unwrap();
+    api.register(listen_get_function_steps_by_uuid).unwrap();
+    api.register(listen_stream_function_logs_by_uuid).unwrap();
+    api.register(listen_cancel_function_by_uuid).unwrap();
+    api.register(listen_retry_function_by_uuid).unwrap();
+    api.register(listen_get_airtable_outbox).unwrap();
+    api.register(listen_replay_airtable_outbox_entry).unwrap();
+    api.register(listen_get_webhook_events).unwrap();
+    api.register(listen_replay_webhook_event).unwrap();
+    api.register(listen_replay_webhook_events).unwrap();
+    api.register(listen_get_schedules).unwrap();
+    api.register(listen_metrics).unwrap();
     api.register(api_get_schema).unwrap();
 
     // Create the API schema.
@@ -163,85 +186,69 @@ pub async fn server(s: crate::Server, logger: slog::Logger) -> Result<()> {
     /*
      * The functions that implement our API endpoints will share this context.
      */
-    let api_context = Context::new(schema, logger).await;
+    let api_context = Context::new(schema, logger).await?;
 
     // Copy the Server struct so we can move it into our loop.
     if s.do_cron {
         /*
-         * Setup our cron jobs, with our timezone.
+         * Setup our cron jobs, the schedules are in the database so they can be changed
+         * without a deploy.
          */
-        let mut scheduler = AsyncScheduler::with_tz(chrono_tz::US::Pacific);
-        scheduler.every(1.day()).run(|| async {
-            do_job("localhost:8080", "sync-analytics").await;
-        });
-        scheduler.every(23.hours()).run(|| async {
-            do_job("localhost:8080", "sync-api-tokens").await;
-        });
-        scheduler.every(6.hours()).run(|| async {
-            do_job("localhost:8080", "sync-applications").await;
-        });
-        scheduler.every(2.hours()).run(|| async {
-            do_job("localhost:8080", "sync-asset-inventory").await;
-        });
-        scheduler.every(12.hours()).run(|| async {
-            do_job("localhost:8080", "sync-companies").await;
-        });
-        scheduler.every(4.hours()).run(|| async {
-            do_job("localhost:8080", "sync-configs").await;
-        });
-        scheduler.every(6.hours()).run(|| async {
-            do_job("localhost:8080", "sync-finance").await;
-        });
-        scheduler.every(2.hours()).run(|| async {
-            do_job("localhost:8080", "sync-functions").await;
-        });
-        scheduler.every(1.hours()).run(|| async {
-            do_job("localhost:8080", "sync-huddles").await;
-        });
-        scheduler.every(4.hours()).run(|| async {
-            do_job("localhost:8080", "sync-interviews").await;
-        });
-        scheduler.every(12.hours()).run(|| async {
-            do_job("localhost:8080", "sync-journal-clubs").await;
-        });
-        scheduler.every(20.hours()).run(|| async {
-            do_job("localhost:8080", "sync-mailing-lists").await;
-        });
-        scheduler.every(18.hours()).run(|| async {
-            do_job("localhost:8080", "sync-other").await;
-        });
-        scheduler.every(2.hours()).run(|| async {
-            do_job("localhost:8080", "sync-recorded-meetings").await;
-        });
-        scheduler.every(16.hours()).run(|| async {
-            do_job("localhost:8080", "sync-repos").await;
-        });
-        scheduler.every(14.hours()).run(|| async {
-            do_job("localhost:8080", "sync-rfds").await;
-        });
-        scheduler.every(2.hours()).run(|| async {
-            do_job("localhost:8080", "sync-shipments").await;
-        });
-        scheduler.every(3.hours()).run(|| async {
-            do_job("localhost:8080", "sync-shorturls").await;
-        });
-        scheduler.every(9.hours()).run(|| async {
-            do_job("localhost:8080", "sync-swag-inventory").await;
-        });
-        scheduler.every(5.hours()).run(|| async {
-            do_job("localhost:8080", "sync-travel").await;
+        let scheduler_db = api_context.db.clone();
+        JobSchedule::seed_defaults(&scheduler_db)?;
+        // The companies that run a job on their own schedule are started from here, not
+        // through the endpoints.
+        let scheduler_context = Arc::new(api_context.clone());
+
+        // Run the Airtable outbox, this retries any writes to Airtable that failed.
+        let db = api_context.db.clone();
+        tokio::spawn(async move {
+            info!("starting Airtable outbox worker...");
+
+            loop {
+                if let Err(e) = cio_api::outbox::process_airtable_outbox(&db).await {
+                    warn!("processing Airtable outbox failed: {}", e);
+                    sentry_anyhow::capture_anyhow(&e);
+                }
+                tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+            }
         });
-        // TODO: Run the RFD changelog.
-        /*scheduler.every(Monday).at("8:00 am").run(|| async {
-            do_job("localhost:8080", "send-rfd-changelog").await;
-        });*/
 
         tokio::spawn(async move {
             info!("starting cron job scheduler...");
 
+            let mut last = Utc::now();
             loop {
-                scheduler.run_pending().await;
-                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+
+                let now = Utc::now();
+                match JobSchedule::get_due(&scheduler_db, last, now) {
+                    Ok(schedules) => {
+                        for schedule in schedules {
+                            if schedule.cio_company_id == 0 {
+                                info!("starting scheduled job `{}`", schedule.name);
+                                tokio::spawn(async move {
+                                    do_job("localhost:8080", &schedule.name).await;
+                                });
+                            } else {
+                                info!(
+                                    "starting scheduled job `{}` for company `{}`",
+                                    schedule.name, schedule.cio_company_id
+                                );
+                                let context = scheduler_context.clone();
+                                tokio::spawn(async move {
+                                    do_company_job(&context, &schedule.name, schedule.cio_company_id).await;
+                                });
+                            }
+                        }
+                        last = now;
+                    }
+                    // Keep `last` where it is, so the jobs that were due run the next time.
+                    Err(e) => {
+                        warn!("getting the scheduled jobs failed: {}", e);
+                        sentry_anyhow::capture_anyhow(&e);
+                    }
+                }
             }
         });
     }
@@ -280,11 +287,19 @@ pub async fn server(s: crate::Server, logger: slog::Logger) -> Result<()> {
 /**
  * Application-specific context (state shared by handler functions)
  */
+#[derive(Clone)]
 pub struct Context {
     pub db: Database,
 
     pub sec: Arc<steno::SecClient>,
 
+    /// The commands running for sagas, so they can be cancelled.
+    pub running: crate::sagas::RunningCommands,
+
+    /// The permits for streaming the logs of functions, every stream polls the database so
+    /// we only keep so many open at once.
+    pub log_streams: Arc<tokio::sync::Semaphore>,
+
     pub schema: serde_json::Value,
 }
 
@@ -292,17 +307,20 @@ impl Context {
     /**
      * Return a new Context.
      */
-    pub async fn new(schema: serde_json::Value, logger: slog::Logger) -> Context {
-        let db = Database::new();
+    pub async fn new(schema: serde_json::Value, logger: slog::Logger) -> Result<Context> {
+        let db = Database::new()?;
 
-        let sec = steno::sec(logger, Arc::new(db.clone()));
+        // The sagas the server starts itself, like onboarding, run on the same executor.
+        let sec = cio_api::sagas::sec(&db, logger);
 
         // Create the context.
-        Context {
+        Ok(Context {
             db,
-            sec: Arc::new(sec),
+            sec,
+            running: Default::default(),
+            log_streams: Arc::new(tokio::sync::Semaphore::new(crate::handlers_cron::MAX_LOG_STREAMS)),
             schema,
-        }
+        })
     }
 }
 
@@ -313,6 +331,7 @@ impl Context {
 /**
  * Return the OpenAPI schema in JSON format.
  */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/",
@@ -323,7 +342,33 @@ async fn api_get_schema(rqctx: Arc<RequestContext<Context>>) -> Result<HttpRespo
     Ok(HttpResponseOk(api_context.schema.clone()))
 }
 
+/** Get the metrics for the server, in the Prometheus text format. This needs the `METRICS_TOKEN`. */
+#[request_metrics]
+#[endpoint {
+    method = GET,
+    path = "/metrics",
+    unpublished = true,
+}]
+async fn listen_metrics(rqctx: Arc<RequestContext<Context>>) -> Result<Response<Body>, HttpError> {
+    require_token(&rqctx).await?;
+
+    sentry::start_session();
+
+    match crate::handlers::handle_metrics(rqctx).await {
+        Ok(r) => {
+            sentry::end_session();
+            Ok(r)
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            sentry::end_session();
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
 /** Return pong. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/ping",
@@ -339,6 +384,7 @@ pub struct CounterResponse {
 }
 
 /** Return the count of products sold. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/products/sold/count",
@@ -362,17 +408,19 @@ async fn listen_products_sold_count_requests(
 }
 
 /** Listen for GitHub webhooks. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/github",
 }]
 async fn listen_github_webhooks(
     rqctx: Arc<RequestContext<Context>>,
-    body_param: TypedBody<GitHubWebhook>,
+    body_param: UntypedBody,
 ) -> Result<HttpResponseAccepted<String>, HttpError> {
     sentry::start_session();
+    let mut event = receive_webhook(&rqctx, WebhookProvider::GitHub, body_param.as_bytes()).await?;
 
-    if let Err(e) = crate::handlers_github::handle_github(rqctx, body_param).await {
+    if let Err(e) = crate::handlers::handle_webhook_event(rqctx.context(), &mut event).await {
         // Send the error to sentry.
         return Err(handle_anyhow_err_as_http_err(e));
     }
@@ -387,6 +435,7 @@ pub struct RFDPathParams {
 }
 
 /** Trigger an update for an RFD. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/rfd/{num}",
@@ -407,6 +456,7 @@ async fn trigger_rfd_update_by_number(
 }
 
 /** Get our current GitHub rate limit. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/github/ratelimit",
@@ -441,6 +491,7 @@ pub struct GitHubRateLimit {
 /**
  * Listen for a button pressed to print a home address label for employees.
  */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/airtable/employees/print_home_address_label",
@@ -463,6 +514,7 @@ async fn listen_airtable_employees_print_home_address_label_webhooks(
 /**
  * Listen for a button pressed to renew a certificate.
  */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/airtable/certificates/renew",
@@ -485,6 +537,7 @@ async fn listen_airtable_certificates_renew_webhooks(
 /**
  * Listen for a button pressed to print a barcode label for an asset item.
  */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/airtable/assets/items/print_barcode_label",
@@ -507,6 +560,7 @@ async fn listen_airtable_assets_items_print_barcode_label_webhooks(
 /**
  * Listen for a button pressed to print barcode labels for a swag inventory item.
  */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/airtable/swag/inventory/items/print_barcode_labels",
@@ -530,6 +584,7 @@ async fn listen_airtable_swag_inventory_items_print_barcode_labels_webhooks(
 /**
  * Listen for a button pressed to request a background check for an applicant.
  */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/airtable/applicants/request_background_check",
@@ -553,6 +608,7 @@ async fn listen_airtable_applicants_request_background_check_webhooks(
  * Listen for rows updated in our Airtable workspace.
  * These are set up with an Airtable script on the workspaces themselves.
  */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/airtable/applicants/update",
@@ -576,6 +632,7 @@ async fn listen_airtable_applicants_update_webhooks(
  * Listen for rows created in our Airtable workspace.
  * These are set up with an Airtable script on the workspaces themselves.
  */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/airtable/shipments/outbound/create",
@@ -607,6 +664,7 @@ pub struct AirtableRowEvent {
 /**
  * Listen for a button pressed to reprint a label for an outbound shipment.
  */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/airtable/shipments/outbound/reprint_label",
@@ -629,6 +687,7 @@ async fn listen_airtable_shipments_outbound_reprint_label_webhooks(
 /**
  * Listen for a button pressed to reprint a receipt for an outbound shipment.
  */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/airtable/shipments/outbound/reprint_receipt",
@@ -651,6 +710,7 @@ async fn listen_airtable_shipments_outbound_reprint_receipt_webhooks(
 /**
  * Listen for a button pressed to resend a shipment status email to the recipient for an outbound shipment.
  */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/airtable/shipments/outbound/resend_shipment_status_email_to_recipient",
@@ -676,6 +736,7 @@ async fn listen_airtable_shipments_outbound_resend_shipment_status_email_to_reci
 /**
  * Listen for a button pressed to schedule a pickup for an outbound shipment.
  */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/airtable/shipments/outbound/schedule_pickup",
@@ -740,6 +801,7 @@ pub struct IncomingEmail {
  * Listen for emails coming inbound from SendGrid's parse API.
  * We use this for scanning for packages in emails.
  */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/emails/incoming/sendgrid/parse",
@@ -749,8 +811,9 @@ async fn listen_emails_incoming_sendgrid_parse_webhooks(
     body_param: UntypedBody,
 ) -> Result<HttpResponseAccepted<String>, HttpError> {
     sentry::start_session();
+    let mut event = receive_webhook(&rqctx, WebhookProvider::SendGrid, body_param.as_bytes()).await?;
 
-    if let Err(e) = crate::handlers::handle_emails_incoming_sendgrid_parse(rqctx, body_param).await {
+    if let Err(e) = crate::handlers::handle_webhook_event(rqctx.context(), &mut event).await {
         // Send the error to sentry.
         return Err(handle_anyhow_err_as_http_err(e));
     }
@@ -761,6 +824,7 @@ async fn listen_emails_incoming_sendgrid_parse_webhooks(
 
 /**
  * Listen for applicant reviews being submitted for job applicants */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/applicant/review/submit",
@@ -782,6 +846,7 @@ async fn listen_applicant_review_requests(
 
 /**
  * Listen for applications being submitted for incoming job applications */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/application/submit",
@@ -828,6 +893,7 @@ pub struct ApplicationFileUploadData {
 
 /**
  * Listen for files being uploaded for incoming job applications */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/application/files/upload",
@@ -855,6 +921,7 @@ async fn listen_application_files_upload_requests(
  * Listen for rows created in our Airtable workspace.
  * These are set up with an Airtable script on the workspaces themselves.
  */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/airtable/shipments/inbound/create",
@@ -877,6 +944,7 @@ async fn listen_airtable_shipments_inbound_create_webhooks(
 /**
  * Listen for orders being created by the Oxide store.
  */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/store/order",
@@ -899,6 +967,7 @@ async fn listen_store_order_create(
 /**
  * Listen for shipment tracking updated from EasyPost.
  */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/easypost/tracking/update",
@@ -964,17 +1033,19 @@ pub struct EasyPostTrackingUpdateEvent {
 /**
  * Listen for shipment tracking updated from Shippo.
  */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/shippo/tracking/update",
 }]
 async fn listen_shippo_tracking_update_webhooks(
     rqctx: Arc<RequestContext<Context>>,
-    body_param: TypedBody<serde_json::Value>,
+    body_param: UntypedBody,
 ) -> Result<HttpResponseAccepted<String>, HttpError> {
     sentry::start_session();
+    let mut event = receive_webhook(&rqctx, WebhookProvider::Shippo, body_param.as_bytes()).await?;
 
-    if let Err(e) = crate::handlers::handle_shippo_tracking_update(rqctx, body_param).await {
+    if let Err(e) = crate::handlers::handle_webhook_event(rqctx.context(), &mut event).await {
         // Send the error to sentry.
         return Err(handle_anyhow_err_as_http_err(e));
     }
@@ -995,17 +1066,19 @@ pub struct ShippoTrackingUpdateEvent {
 }
 
 /** Listen for updates to our checkr background checks. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/checkr/background/update",
 }]
 async fn listen_checkr_background_update_webhooks(
     rqctx: Arc<RequestContext<Context>>,
-    body_param: TypedBody<checkr::WebhookEvent>,
+    body_param: UntypedBody,
 ) -> Result<HttpResponseAccepted<String>, HttpError> {
     sentry::start_session();
+    let mut event = receive_webhook(&rqctx, WebhookProvider::Checkr, body_param.as_bytes()).await?;
 
-    if let Err(e) = crate::handlers::handle_checkr_background_update(rqctx, body_param).await {
+    if let Err(e) = crate::handlers::handle_webhook_event(rqctx.context(), &mut event).await {
         // Send the error to sentry.
         return Err(handle_anyhow_err_as_http_err(e));
     }
@@ -1032,6 +1105,7 @@ pub struct AuthCallback {
 }
 
 /** Get the consent URL for Google auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/google/consent",
@@ -1053,6 +1127,7 @@ async fn listen_auth_google_consent(
 }
 
 /** Listen for callbacks to Google auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/google/callback",
@@ -1073,6 +1148,7 @@ async fn listen_auth_google_callback(
 }
 
 /** Get the consent URL for shipbob auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/shipbob/consent",
@@ -1095,6 +1171,7 @@ async fn listen_auth_shipbob_consent(
 }
 
 /** Listen for callbacks to shipbob auth. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/auth/shipbob/callback",
@@ -1115,6 +1192,7 @@ async fn listen_auth_shipbob_callback(
 }
 
 /** Get the consent URL for GitHub auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/github/consent",
@@ -1131,6 +1209,7 @@ async fn listen_auth_github_consent(
 }
 
 /** Listen for callbacks to GitHub auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/github/callback",
@@ -1149,6 +1228,7 @@ async fn listen_auth_github_callback(
 }
 
 /** Get the consent URL for MailChimp auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/mailchimp/consent",
@@ -1168,6 +1248,7 @@ async fn listen_auth_mailchimp_consent(
 }
 
 /** Listen for callbacks to MailChimp auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/mailchimp/callback",
@@ -1188,6 +1269,7 @@ async fn listen_auth_mailchimp_callback(
 }
 
 /** Get the consent URL for Gusto auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/gusto/consent",
@@ -1208,6 +1290,7 @@ async fn listen_auth_gusto_consent(
 }
 
 /** Listen for callbacks to Gusto auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/gusto/callback",
@@ -1228,6 +1311,7 @@ async fn listen_auth_gusto_callback(
 }
 
 /** Listen to deauthorization requests for our Zoom app. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/zoom/deauthorization",
@@ -1247,6 +1331,7 @@ async fn listen_auth_zoom_deauthorization(
 }
 
 /** Get the consent URL for Zoom auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/zoom/consent",
@@ -1266,6 +1351,7 @@ async fn listen_auth_zoom_consent(
 }
 
 /** Listen for callbacks to Zoom auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/zoom/callback",
@@ -1286,6 +1372,7 @@ async fn listen_auth_zoom_callback(
 }
 
 /** Get the consent URL for Ramp auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/ramp/consent",
@@ -1313,6 +1400,7 @@ async fn listen_auth_ramp_consent(
 }
 
 /** Listen for callbacks to Ramp auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/ramp/callback",
@@ -1333,6 +1421,7 @@ async fn listen_auth_ramp_callback(
 }
 
 /** Get the consent URL for Slack auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/slack/consent",
@@ -1352,6 +1441,7 @@ async fn listen_auth_slack_consent(
 }
 
 /** Listen for callbacks to Slack auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/slack/callback",
@@ -1372,6 +1462,7 @@ async fn listen_auth_slack_callback(
 }
 
 /** Get the consent URL for QuickBooks auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/quickbooks/consent",
@@ -1391,6 +1482,7 @@ async fn listen_auth_quickbooks_consent(
 }
 
 /** Listen for callbacks to QuickBooks auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/quickbooks/callback",
@@ -1411,6 +1503,7 @@ async fn listen_auth_quickbooks_callback(
 }
 
 /** Listen for webhooks from Plaid. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/plaid",
@@ -1429,6 +1522,7 @@ async fn listen_auth_plaid_callback(
 }
 
 /** Get the consent URL for DocuSign auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/docusign/consent",
@@ -1448,6 +1542,7 @@ async fn listen_auth_docusign_consent(
 }
 
 /** Listen for callbacks to DocuSign auth. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/auth/docusign/callback",
@@ -1468,17 +1563,19 @@ async fn listen_auth_docusign_callback(
 }
 
 /** Listen for updates to our docusign envelopes. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/docusign/envelope/update",
 }]
 async fn listen_docusign_envelope_update_webhooks(
     rqctx: Arc<RequestContext<Context>>,
-    body_param: TypedBody<docusign::Envelope>,
+    body_param: UntypedBody,
 ) -> Result<HttpResponseAccepted<String>, HttpError> {
     sentry::start_session();
+    let mut event = receive_webhook(&rqctx, WebhookProvider::DocuSign, body_param.as_bytes()).await?;
 
-    if let Err(e) = crate::handlers::handle_docusign_envelope_update(rqctx, body_param).await {
+    if let Err(e) = crate::handlers::handle_webhook_event(rqctx.context(), &mut event).await {
         // Send the error to sentry.
         return Err(handle_anyhow_err_as_http_err(e));
     }
@@ -1488,6 +1585,7 @@ async fn listen_docusign_envelope_update_webhooks(
 }
 
 /** Listen for analytics page view events. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/analytics/page_view",
@@ -1508,6 +1606,7 @@ async fn listen_analytics_page_view_webhooks(
 }
 
 /** Ping endpoint for MailChimp mailing list webhooks. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/mailchimp/mailing_list",
@@ -1519,6 +1618,7 @@ async fn ping_mailchimp_mailing_list_webhooks(
 }
 
 /** Listen for MailChimp mailing list webhooks. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/mailchimp/mailing_list",
@@ -1528,8 +1628,9 @@ async fn listen_mailchimp_mailing_list_webhooks(
     body_param: UntypedBody,
 ) -> Result<HttpResponseAccepted<String>, HttpError> {
     sentry::start_session();
+    let mut event = receive_webhook(&rqctx, WebhookProvider::Mailchimp, body_param.as_bytes()).await?;
 
-    if let Err(e) = crate::handlers::handle_mailchimp_mailing_list(rqctx, body_param).await {
+    if let Err(e) = crate::handlers::handle_webhook_event(rqctx.context(), &mut event).await {
         // Send the error to sentry.
         return Err(handle_anyhow_err_as_http_err(e));
     }
@@ -1539,6 +1640,7 @@ async fn listen_mailchimp_mailing_list_webhooks(
 }
 
 /** Ping endpoint for MailChimp rack line webhooks. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/mailchimp/rack_line",
@@ -1550,6 +1652,7 @@ async fn ping_mailchimp_rack_line_webhooks(
 }
 
 /** Listen for MailChimp rack line webhooks. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/mailchimp/rack_line",
@@ -1559,8 +1662,9 @@ async fn listen_mailchimp_rack_line_webhooks(
     body_param: UntypedBody,
 ) -> Result<HttpResponseAccepted<String>, HttpError> {
     sentry::start_session();
+    let mut event = receive_webhook(&rqctx, WebhookProvider::Mailchimp, body_param.as_bytes()).await?;
 
-    if let Err(e) = crate::handlers::handle_mailchimp_rack_line(rqctx, body_param).await {
+    if let Err(e) = crate::handlers::handle_webhook_event(rqctx.context(), &mut event).await {
         // Send the error to sentry.
         return Err(handle_anyhow_err_as_http_err(e));
     }
@@ -1570,6 +1674,7 @@ async fn listen_mailchimp_rack_line_webhooks(
 }
 
 /** Listen for Slack commands webhooks. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/slack/commands",
@@ -1579,8 +1684,9 @@ async fn listen_slack_commands_webhooks(
     body_param: UntypedBody,
 ) -> Result<HttpResponseOk<serde_json::Value>, HttpError> {
     sentry::start_session();
+    let mut event = receive_webhook(&rqctx, WebhookProvider::Slack, body_param.as_bytes()).await?;
 
-    match crate::handlers::handle_slack_commands(rqctx, body_param).await {
+    match crate::handlers::handle_webhook_event(rqctx.context(), &mut event).await {
         Ok(r) => {
             sentry::end_session();
             Ok(HttpResponseOk(r))
@@ -1594,6 +1700,7 @@ async fn listen_slack_commands_webhooks(
 }
 
 /** Listen for Slack interactive webhooks. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/slack/interactive",
@@ -1603,8 +1710,9 @@ async fn listen_slack_interactive_webhooks(
     body_param: UntypedBody,
 ) -> Result<HttpResponseOk<String>, HttpError> {
     sentry::start_session();
+    let mut event = receive_webhook(&rqctx, WebhookProvider::Slack, body_param.as_bytes()).await?;
 
-    if let Err(e) = crate::handlers::handle_slack_interactive(rqctx, body_param).await {
+    if let Err(e) = crate::handlers::handle_webhook_event(rqctx.context(), &mut event).await {
         // Send the error to sentry.
         return Err(handle_anyhow_err_as_http_err(e));
     }
@@ -1614,17 +1722,19 @@ async fn listen_slack_interactive_webhooks(
 }
 
 /** Listen for shipbob webhooks. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/shipbob",
 }]
 async fn listen_shipbob_webhooks(
     rqctx: Arc<RequestContext<Context>>,
-    body_param: TypedBody<serde_json::Value>,
+    body_param: UntypedBody,
 ) -> Result<HttpResponseOk<String>, HttpError> {
     sentry::start_session();
+    let mut event = receive_webhook(&rqctx, WebhookProvider::Shipbob, body_param.as_bytes()).await?;
 
-    if let Err(e) = crate::handlers::handle_shipbob(rqctx, body_param).await {
+    if let Err(e) = crate::handlers::handle_webhook_event(rqctx.context(), &mut event).await {
         // Send the error to sentry.
         return Err(handle_anyhow_err_as_http_err(e));
     }
@@ -1633,12 +1743,47 @@ async fn listen_shipbob_webhooks(
     Ok(HttpResponseOk("ok".to_string()))
 }
 
+#[derive(Deserialize, Debug, JsonSchema)]
+pub struct FunctionsQuery {
+    /// Only the functions with this name, ie. `sync-repos`.
+    pub name: Option<String>,
+    /// Only the functions with this status, ie. `in_progress` or `completed`.
+    pub status: Option<String>,
+}
+
+/** List the most recent functions, optionally by name and status. This needs the `METRICS_TOKEN`. */
+#[request_metrics]
+#[endpoint {
+    method = GET,
+    path = "/functions",
+}]
+async fn listen_get_functions(
+    rqctx: Arc<RequestContext<Context>>,
+    query_args: Query<FunctionsQuery>,
+) -> Result<HttpResponseOk<Vec<Function>>, HttpError> {
+    require_token(&rqctx).await?;
+    sentry::start_session();
+
+    match crate::handlers_cron::handle_get_functions(rqctx, query_args).await {
+        Ok(r) => {
+            sentry::end_session();
+            Ok(HttpResponseOk(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            sentry::end_session();
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
 #[derive(Deserialize, Debug, JsonSchema)]
 pub struct FunctionPathParams {
     pub uuid: String,
 }
 
-/** Get information about a function by its uuid. */
+/** Get information about a function by its uuid. This needs the `METRICS_TOKEN`. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/functions/{uuid}",
@@ -1647,6 +1792,7 @@ async fn listen_get_function_by_uuid(
     rqctx: Arc<RequestContext<Context>>,
     path_params: Path<FunctionPathParams>,
 ) -> Result<HttpResponseOk<Function>, HttpError> {
+    require_token(&rqctx).await?;
     sentry::start_session();
 
     match crate::handlers_cron::handle_get_function_by_uuid(rqctx, path_params).await {
@@ -1662,7 +1808,8 @@ async fn listen_get_function_by_uuid(
     }
 }
 
-/** Get a functions logs by its uuid. */
+/** Get a functions logs by its uuid. This needs the `METRICS_TOKEN`. */
+#[request_metrics]
 #[endpoint {
     method = GET,
     path = "/functions/{uuid}/logs",
@@ -1671,6 +1818,7 @@ async fn listen_get_function_logs_by_uuid(
     rqctx: Arc<RequestContext<Context>>,
     path_params: Path<FunctionPathParams>,
 ) -> Result<HttpResponseOk<String>, HttpError> {
+    require_token(&rqctx).await?;
     sentry::start_session();
 
     match crate::handlers_cron::handle_get_function_logs_by_uuid(rqctx, path_params).await {
@@ -1686,7 +1834,306 @@ async fn listen_get_function_logs_by_uuid(
     }
 }
 
+/** Follow a functions logs by its uuid, as server-sent events until it is done. This needs the
+ * `METRICS_TOKEN`. */
+#[request_metrics]
+#[endpoint {
+    method = GET,
+    path = "/functions/{uuid}/logs/stream",
+    unpublished = true,
+}]
+async fn listen_stream_function_logs_by_uuid(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<FunctionPathParams>,
+) -> Result<Response<Body>, HttpError> {
+    require_token(&rqctx).await?;
+    sentry::start_session();
+
+    match crate::handlers_cron::handle_stream_function_logs_by_uuid(rqctx, path_params).await {
+        Ok(r) => {
+            sentry::end_session();
+            Ok(r)
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            sentry::end_session();
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** Get the status of each step of a function by its uuid. This needs the `METRICS_TOKEN`. */
+#[request_metrics]
+#[endpoint {
+    method = GET,
+    path = "/functions/{uuid}/steps",
+}]
+async fn listen_get_function_steps_by_uuid(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<FunctionPathParams>,
+) -> Result<HttpResponseOk<Vec<FunctionStep>>, HttpError> {
+    require_token(&rqctx).await?;
+    sentry::start_session();
+
+    match crate::handlers_cron::handle_get_function_steps_by_uuid(rqctx, path_params).await {
+        Ok(r) => {
+            sentry::end_session();
+            Ok(HttpResponseOk(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            sentry::end_session();
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** Cancel a running function by its uuid. Jobs are stopped right away, sagas before their next
+ * step, undoing the steps before it. This needs the `METRICS_TOKEN`. */
+#[request_metrics]
+#[endpoint {
+    method = POST,
+    path = "/functions/{uuid}/cancel",
+}]
+async fn listen_cancel_function_by_uuid(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<FunctionPathParams>,
+) -> Result<HttpResponseAccepted<Function>, HttpError> {
+    require_token(&rqctx).await?;
+    sentry::start_session();
+
+    match crate::handlers_cron::handle_cancel_function_by_uuid(rqctx, path_params).await {
+        Ok(r) => {
+            sentry::end_session();
+            Ok(HttpResponseAccepted(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            sentry::end_session();
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** Run a function again by its uuid, with the same parameters. Returns the uuid of the new run.
+ * This needs the `METRICS_TOKEN`. */
+#[request_metrics]
+#[endpoint {
+    method = POST,
+    path = "/functions/{uuid}/retry",
+}]
+async fn listen_retry_function_by_uuid(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<FunctionPathParams>,
+) -> Result<HttpResponseAccepted<uuid::Uuid>, HttpError> {
+    require_token(&rqctx).await?;
+    sentry::start_session();
+
+    match crate::handlers_cron::handle_retry_function_by_uuid(rqctx, path_params).await {
+        Ok(r) => {
+            sentry::end_session();
+            Ok(HttpResponseAccepted(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            sentry::end_session();
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** List the entries in the Airtable outbox that have not been completed. This needs the `METRICS_TOKEN`. */
+#[request_metrics]
+#[endpoint {
+    method = GET,
+    path = "/airtable/outbox",
+}]
+async fn listen_get_airtable_outbox(
+    rqctx: Arc<RequestContext<Context>>,
+) -> Result<HttpResponseOk<Vec<AirtableOutboxEntry>>, HttpError> {
+    require_token(&rqctx).await?;
+    sentry::start_session();
+
+    match crate::handlers_cron::handle_get_airtable_outbox(rqctx).await {
+        Ok(r) => {
+            sentry::end_session();
+            Ok(HttpResponseOk(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            sentry::end_session();
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+pub struct OutboxEntryPathParams {
+    pub id: i32,
+}
+
+/** Replay an entry in the Airtable outbox. This needs the `METRICS_TOKEN`. */
+#[request_metrics]
+#[endpoint {
+    method = POST,
+    path = "/airtable/outbox/{id}/replay",
+}]
+async fn listen_replay_airtable_outbox_entry(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<OutboxEntryPathParams>,
+) -> Result<HttpResponseAccepted<AirtableOutboxEntry>, HttpError> {
+    require_token(&rqctx).await?;
+    sentry::start_session();
+
+    match crate::handlers_cron::handle_replay_airtable_outbox_entry(rqctx, path_params).await {
+        Ok(r) => {
+            sentry::end_session();
+            Ok(HttpResponseAccepted(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            sentry::end_session();
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+pub struct WebhookEventsQuery {
+    /// The status of the events, ie. `received`, `succeeded` or `failed`. The default is `failed`.
+    pub status: Option<String>,
+    /// Only the events from this provider, ie. `github`.
+    pub provider: Option<String>,
+}
+
+impl WebhookEventsQuery {
+    pub fn parse(&self) -> Result<(WebhookEventStatus, Option<WebhookProvider>)> {
+        let status = match &self.status {
+            Some(s) => s.parse()?,
+            None => WebhookEventStatus::Failed,
+        };
+        let provider = match &self.provider {
+            Some(p) => Some(p.parse()?),
+            None => None,
+        };
+
+        Ok((status, provider))
+    }
+}
+
+/** List the webhooks we received, the ones that failed by default. This needs the `METRICS_TOKEN`. */
+#[request_metrics]
+#[endpoint {
+    method = GET,
+    path = "/webhooks/events",
+}]
+async fn listen_get_webhook_events(
+    rqctx: Arc<RequestContext<Context>>,
+    query_args: Query<WebhookEventsQuery>,
+) -> Result<HttpResponseOk<Vec<WebhookEvent>>, HttpError> {
+    require_token(&rqctx).await?;
+
+    sentry::start_session();
+
+    match crate::handlers_cron::handle_get_webhook_events(rqctx, query_args).await {
+        Ok(r) => {
+            sentry::end_session();
+            Ok(HttpResponseOk(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            sentry::end_session();
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+pub struct WebhookEventPathParams {
+    pub id: i32,
+}
+
+/** Replay a webhook we received, through the same handler as when it came in. This needs the `METRICS_TOKEN`. */
+#[request_metrics]
+#[endpoint {
+    method = POST,
+    path = "/webhooks/events/{id}/replay",
+}]
+async fn listen_replay_webhook_event(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<WebhookEventPathParams>,
+) -> Result<HttpResponseAccepted<WebhookEvent>, HttpError> {
+    require_token(&rqctx).await?;
+
+    sentry::start_session();
+
+    match crate::handlers_cron::handle_replay_webhook_event(rqctx, path_params).await {
+        Ok(r) => {
+            sentry::end_session();
+            Ok(HttpResponseAccepted(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            sentry::end_session();
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** Replay the webhooks we received with a status, the ones that failed by default. They are
+ * replayed by the `replay-webhook-events` job, this returns its uuid. This needs the `METRICS_TOKEN`. */
+#[request_metrics]
+#[endpoint {
+    method = POST,
+    path = "/webhooks/replay",
+}]
+async fn listen_replay_webhook_events(
+    rqctx: Arc<RequestContext<Context>>,
+    query_args: Query<WebhookEventsQuery>,
+) -> Result<HttpResponseAccepted<uuid::Uuid>, HttpError> {
+    require_token(&rqctx).await?;
+
+    sentry::start_session();
+
+    match crate::handlers_cron::handle_replay_webhook_events(rqctx, query_args).await {
+        Ok(r) => {
+            sentry::end_session();
+            Ok(HttpResponseAccepted(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            sentry::end_session();
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** List the schedules of the cron jobs, with when they run next and how they last ran. */
+#[request_metrics]
+#[endpoint {
+    method = GET,
+    path = "/schedules",
+}]
+async fn listen_get_schedules(
+    rqctx: Arc<RequestContext<Context>>,
+) -> Result<HttpResponseOk<Vec<JobScheduleStatus>>, HttpError> {
+    sentry::start_session();
+
+    match crate::handlers_cron::handle_get_schedules(rqctx).await {
+        Ok(r) => {
+            sentry::end_session();
+            Ok(HttpResponseOk(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            sentry::end_session();
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
 /** Listen for triggering a function run of sync repos. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-repos",
@@ -1710,6 +2157,7 @@ async fn trigger_sync_repos_create(
 }
 
 /** Listen for triggering a function run of sync RFDs. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-rfds",
@@ -1733,6 +2181,7 @@ async fn trigger_sync_rfds_create(
 }
 
 /** Listen for triggering a function run of sync travel. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-travel",
@@ -1756,6 +2205,7 @@ async fn trigger_sync_travel_create(
 }
 
 /** Listen for triggering a function run of sync functions. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-functions",
@@ -1779,6 +2229,7 @@ async fn trigger_sync_functions_create(
 }
 
 /** Listen for triggering a function run of sync finance. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-finance",
@@ -1802,6 +2253,7 @@ async fn trigger_sync_finance_create(
 }
 
 /** Listen for triggering a function run of sync shipments. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-shipments",
@@ -1825,6 +2277,7 @@ async fn trigger_sync_shipments_create(
 }
 
 /** Listen for triggering a function run of sync shorturls. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-shorturls",
@@ -1848,6 +2301,7 @@ async fn trigger_sync_shorturls_create(
 }
 
 /** Listen for triggering a function run of sync configs. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-configs",
@@ -1871,6 +2325,7 @@ async fn trigger_sync_configs_create(
 }
 
 /** Listen for triggering a function run of sync recorded meetings. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-recorded-meetings",
@@ -1894,6 +2349,7 @@ async fn trigger_sync_recorded_meetings_create(
 }
 
 /** Listen for triggering a function run of sync asset inventory. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-asset-inventory",
@@ -1917,6 +2373,7 @@ async fn trigger_sync_asset_inventory_create(
 }
 
 /** Listen for triggering a function run of sync swag inventory. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-swag-inventory",
@@ -1940,6 +2397,7 @@ async fn trigger_sync_swag_inventory_create(
 }
 
 /** Listen for triggering a function run of sync interviews. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-interviews",
@@ -1963,6 +2421,7 @@ async fn trigger_sync_interviews_create(
 }
 
 /** Listen for triggering a function run of sync applications. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-applications",
@@ -1985,7 +2444,32 @@ async fn trigger_sync_applications_create(
     }
 }
 
+/** Listen for triggering a function run of send rfd changelog. */
+#[request_metrics]
+#[endpoint {
+    method = POST,
+    path = "/run/send-rfd-changelog",
+}]
+async fn trigger_send_rfd_changelog_create(
+    rqctx: Arc<RequestContext<Context>>,
+) -> Result<HttpResponseAccepted<uuid::Uuid>, HttpError> {
+    sentry::start_session();
+
+    match crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "send-rfd-changelog", false).await {
+        Ok(r) => {
+            sentry::end_session();
+            Ok(HttpResponseAccepted(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            sentry::end_session();
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
 /** Listen for triggering a function run of sync analytics. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-analytics",
@@ -2009,6 +2493,7 @@ async fn trigger_sync_analytics_create(
 }
 
 /** Listen for triggering a function run of sync companies. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-companies",
@@ -2032,6 +2517,7 @@ async fn trigger_sync_companies_create(
 }
 
 /** Listen for triggering a function run of sync other. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-other",
@@ -2055,6 +2541,7 @@ async fn trigger_sync_other_create(
 }
 
 /** Listen for triggering a function run of sync huddles. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-huddles",
@@ -2078,6 +2565,7 @@ async fn trigger_sync_huddles_create(
 }
 
 /** Listen for triggering a function run of sync mailing lists. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-mailing-lists",
@@ -2101,6 +2589,7 @@ async fn trigger_sync_mailing_lists_create(
 }
 
 /** Listen for triggering a function run of sync journal clubs. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-journal-clubs",
@@ -2124,6 +2613,7 @@ async fn trigger_sync_journal_clubs_create(
 }
 
 /** Listen for triggering a function run of sync api tokens. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/sync-api-tokens",
@@ -2148,6 +2638,7 @@ async fn trigger_sync_api_tokens_create(
 
 /** Listen for triggering a cleanup of all in-progress sagas, we typically run this when the server
  * is shutting down. */
+#[request_metrics]
 #[endpoint {
     method = POST,
     path = "/run/cleanup",
@@ -2179,6 +2670,11 @@ async fn trigger_cleanup_create(rqctx: Arc<RequestContext<Context>>) -> Result<H
 
                 f.update(&ctx.db).await.map_err(handle_anyhow_err_as_http_err)?;
                 info!("set saga `{}` `{}` as `{}`", f.name, f.saga_id, f.status);
+
+                // Stop the command if we are running one for it.
+                if let Err(e) = crate::sagas::kill_cmd(&ctx.running, &saga.id.0) {
+                    warn!("stopping the command for saga `{}` failed: {}", f.saga_id, e);
+                }
             }
         }
     }
@@ -2187,13 +2683,118 @@ async fn trigger_cleanup_create(rqctx: Arc<RequestContext<Context>>) -> Result<H
     Ok(HttpResponseAccepted(()))
 }
 
+/**
+ * Check a webhook came from the provider before we handle it, by its signature or the shared
+ * secret in the request, and save it so it can be replayed if handling it fails.
+ *
+ * The event is saved with the company of the secret, the handlers make sure what the payload
+ * names is for that company.
+ */
+async fn receive_webhook(
+    rqctx: &Arc<RequestContext<Context>>,
+    provider: WebhookProvider,
+    body: &[u8],
+) -> Result<WebhookEvent, HttpError> {
+    let (endpoint, headers, query) = {
+        let req = rqctx.request.lock().await;
+        (
+            req.uri().path().to_string(),
+            req.headers().clone(),
+            req.uri().query().unwrap_or_default().to_string(),
+        )
+    };
+    let db = &rqctx.context().db;
+
+    let cio_company_id = match WebhookSecret::authenticate(db, provider, &headers, &query, body) {
+        Ok(WebhookAuthentication::Verified(secret)) => secret.cio_company_id,
+        // We only take webhooks we can't check if we were told to for the provider, the
+        // handlers get the company from the payload then.
+        Ok(WebhookAuthentication::NoSecrets) if provider.accepts_unverified() => {
+            warn!(
+                "accepting unverified {} webhook: no company has a secret for {}, set one with `webhooky set-webhook-secret`",
+                provider, provider
+            );
+            0
+        }
+        Ok(WebhookAuthentication::NoSecrets) => {
+            warn!(
+                "rejected {} webhook: no company has a secret for {}, set one with `webhooky set-webhook-secret`",
+                provider, provider
+            );
+            cio_api::metrics::record_webhook_event(&provider.to_string(), "rejected");
+
+            sentry::end_session();
+            return Err(HttpError::for_client_error(
+                None,
+                http::StatusCode::UNAUTHORIZED,
+                format!("the {} webhook could not be verified", provider),
+            ));
+        }
+        Ok(WebhookAuthentication::Rejected) => {
+            cio_api::metrics::record_webhook_event(&provider.to_string(), "rejected");
+
+            // The reason is logged, don't tell whoever sent it.
+            sentry::end_session();
+            return Err(HttpError::for_client_error(
+                None,
+                http::StatusCode::UNAUTHORIZED,
+                format!("the {} webhook could not be verified", provider),
+            ));
+        }
+        Err(e) => return Err(handle_anyhow_err_as_http_err(e)),
+    };
+
+    WebhookEvent::record(db, provider, &endpoint, &headers, body, cio_company_id).map_err(handle_anyhow_err_as_http_err)
+}
+
+/// Check the request has the `METRICS_TOKEN` as a bearer token. Besides `/metrics` this guards
+/// the endpoints that show what we stored or run jobs, nobody outside should reach those.
+async fn require_token(rqctx: &Arc<RequestContext<Context>>) -> Result<(), HttpError> {
+    if !cio_api::metrics::authorized(rqctx.request.lock().await.headers()) {
+        return Err(HttpError::for_client_error(
+            None,
+            http::StatusCode::UNAUTHORIZED,
+            "a valid metrics token is required".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
 fn handle_anyhow_err_as_http_err(err: anyhow::Error) -> HttpError {
     // Send to sentry.
     sentry_anyhow::capture_anyhow(&anyhow::anyhow!("{:?}", err));
     sentry::end_session();
 
-    // We use the debug formatting here so we get the stack trace.
-    return HttpError::for_internal_error(format!("{:?}", err));
+    // We use the debug formatting here so we get the stack trace, but only in the internal
+    // message. Clients get the `CioError` itself, or the reason for the status code.
+    let message = format!("{:?}", err);
+    let client_error = |status_code: http::StatusCode, cio_err: &CioError| HttpError {
+        status_code,
+        error_code: None,
+        external_message: cio_err.to_string(),
+        internal_message: message.clone(),
+    };
+    match CioError::find(&err) {
+        Some(e @ CioError::NotFound(_)) => client_error(http::StatusCode::NOT_FOUND, e),
+        Some(e @ CioError::UniqueViolation(_)) | Some(e @ CioError::Conflict(_)) => {
+            client_error(http::StatusCode::CONFLICT, e)
+        }
+        Some(e @ CioError::Forbidden(_)) => client_error(http::StatusCode::FORBIDDEN, e),
+        // The company has not set up the provider, retrying won't help.
+        Some(e @ CioError::NotConnected(_)) => client_error(http::StatusCode::PRECONDITION_FAILED, e),
+        Some(e @ CioError::RateLimited(_)) => client_error(http::StatusCode::TOO_MANY_REQUESTS, e),
+        Some(CioError::DatabaseUnavailable(_)) | Some(CioError::MirrorUnavailable(_)) => {
+            HttpError::for_unavail(None, message)
+        }
+        Some(CioError::Upstream(_, _)) => HttpError {
+            status_code: http::StatusCode::BAD_GATEWAY,
+            error_code: None,
+            external_message: http::StatusCode::BAD_GATEWAY.canonical_reason().unwrap().to_string(),
+            internal_message: message,
+        },
+        None => HttpError::for_internal_error(message),
+    }
 }
 
 async fn start_job(address: &str, job: &str) -> Result<()> {
@@ -2238,3 +2839,12 @@ async fn do_job(address: &str, job: &str) {
         }
     }
 }
+
+/// Run a job for only one company, for the companies that run it on their own schedule.
+async fn do_company_job(api_context: &Context, job: &str, cio_company_id: i32) {
+    let args = vec!["--only-company-id".to_string(), cio_company_id.to_string()];
+    if let Err(e) = crate::handlers_cron::handle_reexec_cmd_with_args(api_context, job, &args, true).await {
+        warn!("starting job `{}` for company `{}` failed: {}", job, cio_company_id, e);
+        sentry_anyhow::capture_anyhow(&e);
+    }
+}